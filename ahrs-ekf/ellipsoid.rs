@@ -0,0 +1,563 @@
+// Hard and soft iron magnetometer calibration by ellipsoid fitting, no_std
+// port of the fit from ahrs-ekf-mag-cal.ipynb. Sums are done in f64.
+// `EllipsoidFit` keeps samples and fits once, `StreamingFit` updates per
+// sample in constant memory. Mount `solve.rs` as `crate::solve` next to it.
+
+use libm::{cbrt, fabs, sqrt};
+
+use crate::solve::solve;
+
+/// Relative distance to already collected samples below which new sample is
+/// dropped, keeps the buffer from filling up while the board is still
+const MIN_SPACING: f32 = 0.1;
+const JACOBI_SWEEPS: usize = 50;
+
+/// Magnetometer correction: calibrated sample is `a_1 * (sample - b)`
+#[derive(Clone, Copy, Debug)]
+pub struct MagCalibration {
+    /// Soft iron correction, row-major, unit determinant
+    pub a_1: [f32; 9],
+    /// Hard iron offset
+    pub b: [f32; 3],
+    /// RMS of relative deviation of calibrated samples from the sphere
+    pub residual: f32,
+}
+
+impl MagCalibration {
+    /// No correction
+    pub const fn identity() -> Self {
+        MagCalibration {
+            a_1: [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+            b: [0.0; 3],
+            residual: 0.0,
+        }
+    }
+}
+
+/// Collects up to `N` magnetometer samples spread over the ellipsoid and
+/// fits general quadric `x'Qx + 2n'x = 1` through them.
+pub struct EllipsoidFit<const N: usize> {
+    samples: [[f32; 3]; N],
+    len: usize,
+    min: [f32; 3],
+    max: [f32; 3],
+}
+
+impl<const N: usize> EllipsoidFit<N> {
+    pub const fn new() -> Self {
+        EllipsoidFit {
+            samples: [[0.0; 3]; N],
+            len: 0,
+            min: [f32::MAX; 3],
+            max: [f32::MIN; 3],
+        }
+    }
+
+    /// Drop collected samples
+    pub fn reset(&mut self) {
+        *self = EllipsoidFit::new();
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Offer sample, keeps it unless buffer is full or it is closer than
+    /// `MIN_SPACING` of the seen span to one of the kept samples
+    pub fn push(&mut self, s: [f32; 3]) -> bool {
+        let mut span: f32 = 0.0;
+        for (i, &v) in s.iter().enumerate() {
+            self.min[i] = self.min[i].min(v);
+            self.max[i] = self.max[i].max(v);
+            span = span.max(self.max[i] - self.min[i]);
+        }
+        if self.is_full() {
+            return false;
+        }
+        let limit = span * MIN_SPACING;
+        let crowded = self.samples[..self.len].iter().any(|k| {
+            let d = [k[0] - s[0], k[1] - s[1], k[2] - s[2]];
+            d[0] * d[0] + d[1] * d[1] + d[2] * d[2] < limit * limit
+        });
+        if crowded {
+            return false;
+        }
+        self.samples[self.len] = s;
+        self.len += 1;
+        true
+    }
+
+    /// Fit calibration to collected samples, `None` if they don't describe
+    /// an ellipsoid (too few, degenerate coverage)
+    pub fn fit(&self) -> Option<MagCalibration> {
+        let samples = &self.samples[..self.len];
+        if samples.len() < 9 {
+            return None;
+        }
+        // Center and scale to keep fourth powers in range
+        let mut mean = [0.0f64; 3];
+        for s in samples {
+            for i in 0..3 {
+                mean[i] += s[i] as f64 / samples.len() as f64;
+            }
+        }
+        let mut scale: f64 = 0.0;
+        for s in samples {
+            for i in 0..3 {
+                scale = scale.max(fabs(s[i] as f64 - mean[i]));
+            }
+        }
+        if scale == 0.0 {
+            return None;
+        }
+        let point = |s: &[f32; 3]| {
+            [
+                (s[0] as f64 - mean[0]) / scale,
+                (s[1] as f64 - mean[1]) / scale,
+                (s[2] as f64 - mean[2]) / scale,
+            ]
+        };
+
+        let mut ata = [[0.0f64; 9]; 9];
+        let mut atb = [0.0f64; 9];
+        for s in samples {
+            let r = design(point(s));
+            for i in 0..9 {
+                atb[i] += r[i];
+                for j in 0..9 {
+                    ata[i][j] += r[i] * r[j];
+                }
+            }
+        }
+        let v = solve(ata, atb)?;
+        let (mut cal, _) = calibration(&v, &mean, scale)?;
+        cal.residual = residual(&cal, samples);
+        Some(cal)
+    }
+}
+
+/// Fits the same quadric as `EllipsoidFit` sample by sample in constant
+/// memory by accumulating normal equations. Isolated glitches are removed by
+/// per axis median of three, once there is a fit samples too far from it
+/// are rejected. Samples are counted in `BINS` direction bins around the
+/// center, each bin takes at most `BIN_CAP` of them so that holding the board
+/// still doesn't outweigh the rest of the sphere.
+pub struct StreamingFit {
+    ata: [[f64; 9]; 9],
+    atb: [f64; 9],
+    len: u32,
+    rejected: u32,
+    rejected_run: u32,
+    last: [[f32; 3]; 2],
+    seen: u32,
+    min: [f32; 3],
+    max: [f32; 3],
+    bins: [u8; BINS],
+    fit: Option<Fitted>,
+}
+
+#[derive(Clone, Copy)]
+struct Fitted {
+    cal: MagCalibration,
+    /// Quadric over samples shifted by `t` and divided by `scale`
+    v: [f64; 9],
+    k: f64,
+    t: [f64; 3],
+    scale: f64,
+}
+
+/// Cube faces split into 3x3 cells
+const BINS: usize = 6 * 9;
+const BIN_CAP: u8 = 24;
+/// Samples in a bin for it to count as covered
+const BIN_MIN: u8 = 4;
+const MIN_FIT_SAMPLES: u32 = 32;
+const REFIT_EVERY: u32 = 16;
+/// Largest relative deviation from the current fit of an accepted sample,
+/// applied once the fit has seen `GATE_COVERAGE` of the sphere
+const GATE: f64 = 0.2;
+const GATE_COVERAGE: f32 = 0.4;
+/// Rejections in a row that discard the fit instead of the samples
+const MAX_REJECTED_RUN: u32 = 25;
+/// Fraction of covered bins and residual at which calibration is done
+pub const DONE_COVERAGE: f32 = 0.75;
+pub const DONE_RESIDUAL: f32 = 0.02;
+
+impl StreamingFit {
+    pub const fn new() -> Self {
+        StreamingFit {
+            ata: [[0.0; 9]; 9],
+            atb: [0.0; 9],
+            len: 0,
+            rejected: 0,
+            rejected_run: 0,
+            last: [[0.0; 3]; 2],
+            seen: 0,
+            min: [f32::MAX; 3],
+            max: [f32::MIN; 3],
+            bins: [0; BINS],
+            fit: None,
+        }
+    }
+
+    /// Drop everything accumulated
+    pub fn reset(&mut self) {
+        *self = StreamingFit::new();
+    }
+
+    /// Samples in the fit
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    /// Samples rejected as outliers
+    pub fn rejected(&self) -> u32 {
+        self.rejected
+    }
+
+    /// Offer raw sample, true if (delayed by one) sample went into the fit
+    pub fn push(&mut self, raw: [f32; 3]) -> bool {
+        if raw.iter().any(|v| !v.is_finite()) {
+            self.rejected += 1;
+            return false;
+        }
+        let [a, b] = self.last;
+        self.last = [b, raw];
+        self.seen += 1;
+        if self.seen < 3 {
+            return false;
+        }
+        let s = [0, 1, 2].map(|i| median(a[i], b[i], raw[i]));
+        let r = design(s.map(|v| v as f64));
+        match &self.fit {
+            Some(f) if self.coverage() >= GATE_COVERAGE => {
+                let y = [0, 1, 2].map(|i| (s[i] as f64 - f.t[i]) / f.scale);
+                let rv: f64 =
+                    design(y).iter().zip(&f.v).map(|(r, v)| r * v).sum();
+                let off = fabs((rv - 1.0) / (2.0 * f.k));
+                // Not a number is an outlier too
+                if off >= GATE || off.is_nan() {
+                    self.rejected += 1;
+                    self.rejected_run += 1;
+                    if self.rejected_run < MAX_REJECTED_RUN {
+                        return false;
+                    }
+                    // It's the fit that is off, wait for the next one
+                    self.fit = None;
+                }
+            }
+            _ => {}
+        }
+        self.rejected_run = 0;
+        for (i, &v) in s.iter().enumerate() {
+            self.min[i] = self.min[i].min(v);
+            self.max[i] = self.max[i].max(v);
+        }
+        let bin = self.bin(&s);
+        if self.bins[bin] >= BIN_CAP {
+            return false;
+        }
+        self.bins[bin] += 1;
+        for i in 0..9 {
+            self.atb[i] += r[i];
+            for j in 0..9 {
+                self.ata[i][j] += r[i] * r[j];
+            }
+        }
+        self.len += 1;
+        if self.len >= MIN_FIT_SAMPLES && self.len.is_multiple_of(REFIT_EVERY) {
+            self.refit();
+        }
+        true
+    }
+
+    /// Latest fit, its residual is estimated from the normal equations
+    pub fn calibration(&self) -> Option<MagCalibration> {
+        self.fit.map(|f| f.cal)
+    }
+
+    /// Fraction of direction bins with enough samples
+    pub fn coverage(&self) -> f32 {
+        let covered = self.bins.iter().filter(|&&n| n >= BIN_MIN).count();
+        covered as f32 / BINS as f32
+    }
+
+    /// Coverage and residual thresholds are met
+    pub fn is_done(&self) -> bool {
+        match &self.fit {
+            Some(f) => {
+                self.coverage() >= DONE_COVERAGE
+                    && f.cal.residual <= DONE_RESIDUAL
+            }
+            None => false,
+        }
+    }
+
+    fn refit(&mut self) {
+        // Quadric through the origin can't be written as `= 1`, so move the
+        // origin into the ellipsoid and scale to keep fourth powers in range
+        let t = self.center().map(|v| v as f64);
+        let mut scale: f64 = 0.0;
+        for i in 0..3 {
+            scale = scale.max((self.max[i] - self.min[i]) as f64 / 2.0);
+        }
+        if scale <= 0.0 || scale.is_nan() {
+            return;
+        }
+        let (l, e) = shift(&t, scale);
+        let n = self.len as f64;
+        let mut lb = [0.0; 9];
+        let mut l_ata = [[0.0; 9]; 9];
+        for (i, row) in l.iter().enumerate() {
+            for (k, &lik) in row.iter().enumerate() {
+                lb[i] += lik * self.atb[k];
+                for (j, sum) in l_ata[i].iter_mut().enumerate() {
+                    *sum += lik * self.ata[k][j];
+                }
+            }
+        }
+        // Sums of shifted rows r' = Lr + e
+        let mut ata = [[0.0; 9]; 9];
+        let mut atb = [0.0; 9];
+        for i in 0..9 {
+            atb[i] = lb[i] + n * e[i];
+            for j in 0..9 {
+                for k in 0..9 {
+                    ata[i][j] += l_ata[i][k] * l[j][k];
+                }
+                ata[i][j] += lb[i] * e[j] + e[i] * lb[j] + n * e[i] * e[j];
+            }
+        }
+
+        let v = match solve(ata, atb) {
+            Some(v) => v,
+            None => return,
+        };
+        let (mut cal, k) = match calibration(&v, &t, scale) {
+            Some(fit) => fit,
+            None => return,
+        };
+        // Sum of squared r'v - 1 expanded over the sums, r'v - 1 is
+        // k(|u|² - 1) for calibrated unit sample u
+        let mut se = n;
+        for i in 0..9 {
+            se -= 2.0 * v[i] * atb[i];
+            for j in 0..9 {
+                se += v[i] * ata[i][j] * v[j];
+            }
+        }
+        cal.residual = (sqrt(se.max(0.0) / n) / (2.0 * k)) as f32;
+        self.fit = Some(Fitted {
+            cal,
+            v,
+            k,
+            t,
+            scale,
+        });
+    }
+
+    /// Fitted center, or middle of the seen range before there is a fit
+    fn center(&self) -> [f32; 3] {
+        match &self.fit {
+            Some(f) => f.cal.b,
+            None => [0, 1, 2].map(|i| (self.min[i] + self.max[i]) / 2.0),
+        }
+    }
+
+    /// Direction bin of `s` around the center
+    fn bin(&self, s: &[f32; 3]) -> usize {
+        let c = self.center();
+        let u = [0, 1, 2].map(|i| (s[i] - c[i]) as f64);
+        let mut axis = 0;
+        for i in 1..3 {
+            if fabs(u[i]) > fabs(u[axis]) {
+                axis = i;
+            }
+        }
+        let m = fabs(u[axis]);
+        if m == 0.0 {
+            return 0;
+        }
+        let face = axis * 2 + (u[axis] < 0.0) as usize;
+        let cell = |v: f64| (((v / m + 1.0) * 1.5) as usize).min(2);
+        let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+        face * 9 + cell(u[a]) * 3 + cell(u[b])
+    }
+}
+
+/// `L` and `e` such that `design((x - t) / scale) = L design(x) + e`
+fn shift(t: &[f64; 3], scale: f64) -> ([[f64; 9]; 9], [f64; 9]) {
+    let a = 1.0 / scale;
+    let b = t.map(|t| -t / scale);
+    let mut l = [[0.0; 9]; 9];
+    let mut e = [0.0; 9];
+    // Row of y_i y_j, off-diagonal products are doubled in the design
+    for i in 0..3 {
+        for j in i..3 {
+            let (row, f) = if i == j { (i, 1.0) } else { (6 - i - j, 2.0) };
+            // y_i y_j = a² x_i x_j + a b_j x_i + a b_i x_j + b_i b_j
+            l[row][row] = a * a;
+            l[row][6 + i] += f * a * b[j] / 2.0;
+            l[row][6 + j] += f * a * b[i] / 2.0;
+            e[row] = f * b[i] * b[j];
+        }
+        // 2 y_i = a 2x_i + 2 b_i
+        l[6 + i][6 + i] = a;
+        e[6 + i] = 2.0 * b[i];
+    }
+    (l, e)
+}
+
+fn median(a: f32, b: f32, c: f32) -> f32 {
+    a.max(b).min(a.min(b).max(c))
+}
+
+/// Least squares row of `[x², y², z², 2yz, 2xz, 2xy, 2x, 2y, 2z] = 1`
+fn design([x, y, z]: [f64; 3]) -> [f64; 9] {
+    [
+        x * x,
+        y * y,
+        z * z,
+        2.0 * y * z,
+        2.0 * x * z,
+        2.0 * x * y,
+        2.0 * x,
+        2.0 * y,
+        2.0 * z,
+    ]
+}
+
+/// Calibration from quadric `v` fitted to samples shifted by `mean` and
+/// divided by `scale`, residual is left zero. Also gives `k` of
+/// `(x - c)'Q(x - c) = k`.
+fn calibration(
+    v: &[f64; 9],
+    mean: &[f64; 3],
+    scale: f64,
+) -> Option<(MagCalibration, f64)> {
+    let q = [[v[0], v[5], v[4]], [v[5], v[1], v[3]], [v[4], v[3], v[2]]];
+    let n = [v[6], v[7], v[8]];
+
+    // Center c = -Q^-1 n, then (x - c)'Q(x - c) = c'Qc + 1
+    let q_inv = invert3(&q)?;
+    let c = mul3(&q_inv, &n).map(|v| -v);
+    let k = dot3(&c, &mul3(&q, &c)) + 1.0;
+    if k <= 0.0 || k.is_nan() {
+        return None;
+    }
+
+    // Soft iron is square root of Q / k, scaled to unit determinant
+    let m = q.map(|row| row.map(|v| v / k));
+    let (l, vecs) = eigen3(m);
+    if l.iter().any(|&l| l <= 0.0 || l.is_nan()) {
+        return None;
+    }
+    let root = l.map(sqrt);
+    let det = cbrt(root[0] * root[1] * root[2]);
+    let mut a_1 = [0.0f32; 9];
+    for i in 0..3 {
+        for j in 0..3 {
+            let mut s = 0.0;
+            for e in 0..3 {
+                s += vecs[i][e] * root[e] * vecs[j][e];
+            }
+            a_1[i * 3 + j] = (s / det) as f32;
+        }
+    }
+    let b = [0, 1, 2].map(|i| (mean[i] + c[i] * scale) as f32);
+    let cal = MagCalibration {
+        a_1,
+        b,
+        residual: 0.0,
+    };
+    Some((cal, k))
+}
+
+/// RMS of relative deviation of calibrated sample magnitudes from their mean
+fn residual(cal: &MagCalibration, samples: &[[f32; 3]]) -> f32 {
+    let norm = |s: &[f32; 3]| {
+        let v = calibrate(cal, s);
+        sqrt(v.iter().map(|v| v * v).sum())
+    };
+    let mean: f64 =
+        samples.iter().map(norm).sum::<f64>() / samples.len() as f64;
+    let se: f64 = samples
+        .iter()
+        .map(|s| (norm(s) - mean) / mean)
+        .map(|e| e * e)
+        .sum();
+    sqrt(se / samples.len() as f64) as f32
+}
+
+fn calibrate(cal: &MagCalibration, s: &[f32; 3]) -> [f64; 3] {
+    let sb = [0, 1, 2].map(|i| (s[i] - cal.b[i]) as f64);
+    [0, 1, 2].map(|i| {
+        (0..3)
+            .map(|j| cal.a_1[i * 3 + j] as f64 * sb[j])
+            .sum::<f64>()
+    })
+}
+
+fn invert3(m: &[[f64; 3]; 3]) -> Option<[[f64; 3]; 3]> {
+    let mut inv = [[0.0; 3]; 3];
+    for (i, row) in inv.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            // Cofactor of (j, i), indices wrap to keep the sign
+            let (r0, r1) = ((j + 1) % 3, (j + 2) % 3);
+            let (c0, c1) = ((i + 1) % 3, (i + 2) % 3);
+            *v = m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+        }
+    }
+    let det = m[0][0] * inv[0][0] + m[0][1] * inv[1][0] + m[0][2] * inv[2][0];
+    if fabs(det) <= 1e-12 || det.is_nan() {
+        return None;
+    }
+    Some(inv.map(|row| row.map(|v| v / det)))
+}
+
+fn mul3(m: &[[f64; 3]; 3], v: &[f64; 3]) -> [f64; 3] {
+    m.map(|row| dot3(&row, v))
+}
+
+fn dot3(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// Eigenvalues and eigenvectors (columns) of symmetric `m`, cyclic Jacobi
+fn eigen3(mut m: [[f64; 3]; 3]) -> ([f64; 3], [[f64; 3]; 3]) {
+    let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    for _ in 0..JACOBI_SWEEPS {
+        let off = m[0][1] * m[0][1] + m[0][2] * m[0][2] + m[1][2] * m[1][2];
+        if off < 1e-30 {
+            break;
+        }
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if m[p][q] == 0.0 {
+                continue;
+            }
+            let theta = (m[q][q] - m[p][p]) / (2.0 * m[p][q]);
+            let sign = if theta < 0.0 { -1.0 } else { 1.0 };
+            let t = sign / (fabs(theta) + sqrt(theta * theta + 1.0));
+            let c = 1.0 / sqrt(t * t + 1.0);
+            let s = t * c;
+            for row in m.iter_mut() {
+                let (mkp, mkq) = (row[p], row[q]);
+                row[p] = c * mkp - s * mkq;
+                row[q] = s * mkp + c * mkq;
+            }
+            let (mp, mq) = (m[p], m[q]);
+            m[p] = [0, 1, 2].map(|k| c * mp[k] - s * mq[k]);
+            m[q] = [0, 1, 2].map(|k| s * mp[k] + c * mq[k]);
+            for row in v.iter_mut() {
+                let (vkp, vkq) = (row[p], row[q]);
+                row[p] = c * vkp - s * vkq;
+                row[q] = s * vkp + c * vkq;
+            }
+        }
+    }
+    ([m[0][0], m[1][1], m[2][2]], v)
+}
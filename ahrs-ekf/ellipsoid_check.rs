@@ -0,0 +1,211 @@
+// Fits ellipsoid to simulated magnetometer samples with known hard and soft
+// iron distortion, at once and streaming with glitches, and checks the
+// correction undoes it:
+//     rustc --edition 2021 -O ellipsoid_check.rs && ./ellipsoid_check
+// Panics on mismatch.
+
+#![allow(dead_code)]
+
+mod ellipsoid {
+    // libm on the target, std on the host
+    mod libm {
+        pub fn sqrt(x: f64) -> f64 {
+            x.sqrt()
+        }
+        pub fn cbrt(x: f64) -> f64 {
+            x.cbrt()
+        }
+        pub fn fabs(x: f64) -> f64 {
+            x.abs()
+        }
+    }
+    include!("ellipsoid.rs");
+}
+mod solve;
+
+use ellipsoid::{EllipsoidFit, MagCalibration, StreamingFit};
+
+const SAMPLES: usize = 5000;
+// Give up on streaming fit after this many
+const STREAM_SAMPLES: usize = 20000;
+const FIELD: f64 = 300.0;
+const NOISE: f64 = 1.0;
+const MAX_RESIDUAL: f32 = 0.01;
+const MAX_OFFSET_ERROR: f64 = 2.0;
+const MAX_SHAPE_ERROR: f64 = 0.01;
+
+/// Soft iron distortion, row-major
+const SOFT: [f64; 9] =
+    [1.15, -0.12, 0.04, -0.12, 1.05, -0.03, 0.04, -0.03, 1.02];
+const HARD: [f64; 3] = [-171.5, 440.1, -197.9];
+
+/// xorshift64*, good enough to scatter directions
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let v = self.0.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        (v >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in [-a, a)
+    fn sym(&mut self, a: f64) -> f64 {
+        (2.0 * self.next() - 1.0) * a
+    }
+
+    /// Random unit vector
+    fn direction(&mut self) -> [f64; 3] {
+        loop {
+            let v = [self.sym(1.0), self.sym(1.0), self.sym(1.0)];
+            let n = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+            if n > 0.1 && n <= 1.0 {
+                return [v[0] / n, v[1] / n, v[2] / n];
+            }
+        }
+    }
+}
+
+fn mul(m: &[f64; 9], v: &[f64; 3]) -> [f64; 3] {
+    [0, 1, 2].map(|i| (0..3).map(|j| m[i * 3 + j] * v[j]).sum())
+}
+
+/// Correction must undo `SOFT` up to rotation and scale and remove `HARD`
+fn check(cal: &MagCalibration) {
+    if cal.residual > MAX_RESIDUAL {
+        panic!("residual {} over {}", cal.residual, MAX_RESIDUAL);
+    }
+    for (i, (&b, &hard)) in cal.b.iter().zip(HARD.iter()).enumerate() {
+        let err = (b as f64 - hard).abs();
+        if err > MAX_OFFSET_ERROR {
+            panic!("b[{}] is {}, expected {}", i, b, hard);
+        }
+    }
+    // a_1 * SOFT should be a scaled rotation, so its Gram matrix is k² I
+    let a_1 = cal.a_1.map(|v| v as f64);
+    let mut c = [0.0; 9];
+    for i in 0..3 {
+        for j in 0..3 {
+            c[i * 3 + j] =
+                (0..3).map(|k| a_1[i * 3 + k] * SOFT[k * 3 + j]).sum();
+        }
+    }
+    let mut gram = [0.0; 9];
+    for i in 0..3 {
+        for j in 0..3 {
+            gram[i * 3 + j] = (0..3).map(|k| c[k * 3 + i] * c[k * 3 + j]).sum();
+        }
+    }
+    let k2 = (gram[0] + gram[4] + gram[8]) / 3.0;
+    for i in 0..3 {
+        for j in 0..3 {
+            let expected = if i == j { 1.0 } else { 0.0 };
+            let err = (gram[i * 3 + j] / k2 - expected).abs();
+            if err > MAX_SHAPE_ERROR {
+                panic!("shape error {} at ({}, {})", err, i, j);
+            }
+        }
+    }
+}
+
+/// Distorted noisy sample of field along `d`
+fn sample(rng: &mut Rng, d: &[f64; 3]) -> [f32; 3] {
+    let m = mul(&SOFT, &d.map(|v| v * FIELD));
+    [0, 1, 2].map(|i| (m[i] + HARD[i] + rng.sym(NOISE)) as f32)
+}
+
+/// Hand rotating the board: direction wanders a little every sample,
+/// `spin` keeps it turning around z
+fn wander(rng: &mut Rng, d: &[f64; 3], spin: f64) -> [f64; 3] {
+    let v = [
+        d[0] - spin * d[1] + rng.sym(0.05),
+        d[1] + spin * d[0] + rng.sym(0.05),
+        d[2] + rng.sym(0.05),
+    ];
+    let n = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    v.map(|v| v / n)
+}
+
+fn streaming(rng: &mut Rng) {
+    let mut fit = StreamingFit::new();
+    let mut d = [0.0, 0.0, 1.0];
+    let mut n = 0;
+    while !fit.is_done() {
+        n += 1;
+        if n > STREAM_SAMPLES {
+            panic!(
+                "streaming fit not done after {} samples, coverage {}",
+                STREAM_SAMPLES,
+                fit.coverage()
+            );
+        }
+        d = wander(rng, &d, 0.02);
+        let mut s = sample(rng, &d);
+        // Lone glitches and a couple of two sample bursts
+        if n % 211 == 0 {
+            s = [4912.0, 4912.0, 4912.0];
+        }
+        if n % 1009 == 0 || n % 1009 == 1 {
+            s = [0.0; 3];
+        }
+        if n == 77 {
+            s = [f32::NAN; 3];
+        }
+        fit.push(s);
+    }
+    let cal = fit.calibration().unwrap();
+    println!(
+        "streaming done after {} samples, {} in fit, {} rejected, \
+         coverage {}",
+        n,
+        fit.len(),
+        fit.rejected(),
+        fit.coverage()
+    );
+    println!("{:?}", cal);
+    check(&cal);
+
+    // Turning only around z never covers the sphere
+    let mut flat = StreamingFit::new();
+    let mut d = [1.0, 0.0, 0.0];
+    for _ in 0..STREAM_SAMPLES {
+        d = wander(rng, &d, 0.05);
+        d = [d[0], d[1], d[2] * 0.1];
+        flat.push(sample(rng, &d));
+    }
+    if flat.is_done() {
+        panic!("streaming fit done on planar samples");
+    }
+}
+
+fn main() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    let mut fit = EllipsoidFit::<200>::new();
+    for _ in 0..SAMPLES {
+        let d = rng.direction();
+        fit.push(sample(&mut rng, &d));
+    }
+    println!("kept {} of {} samples", fit.len(), SAMPLES);
+    let cal: MagCalibration = fit.fit().expect("fit failed");
+    println!("{:?}", cal);
+    check(&cal);
+
+    // Too few samples and a flat cloud must not produce a calibration
+    let mut flat = EllipsoidFit::<200>::new();
+    for _ in 0..SAMPLES {
+        let d = rng.direction();
+        let n = (d[0] * d[0] + d[1] * d[1]).sqrt();
+        flat.push([(d[0] / n * FIELD) as f32, (d[1] / n * FIELD) as f32, 0.0]);
+    }
+    if flat.fit().is_some() {
+        panic!("fit succeeded on planar samples");
+    }
+    if EllipsoidFit::<8>::new().fit().is_some() {
+        panic!("fit succeeded without samples");
+    }
+
+    streaming(&mut rng);
+    println!("ellipsoid ok");
+}
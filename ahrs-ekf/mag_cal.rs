@@ -0,0 +1,166 @@
+// Fits hard and soft iron magnetometer correction to a capture of ahrs-ekf
+// output, lines of `[dt, accel, gyro, cal, state, mag]`. Takes the COBS
+// framed capture as is or text from `cobs/unframe`:
+//     rustc --edition 2021 -O mag_cal.rs && ./mag_cal capture.bin [field]
+// `field` picks the column to fit, raw `mag` (5) by default. Prints residuals,
+// sphere coverage and constant to paste into main.rs.
+
+#![allow(dead_code)]
+
+mod ellipsoid {
+    // libm on the target, std on the host
+    mod libm {
+        pub fn sqrt(x: f64) -> f64 {
+            x.sqrt()
+        }
+        pub fn cbrt(x: f64) -> f64 {
+            x.cbrt()
+        }
+        pub fn fabs(x: f64) -> f64 {
+            x.abs()
+        }
+    }
+    include!("ellipsoid.rs");
+}
+mod solve;
+#[path = "../cobs/capture.rs"]
+mod capture;
+#[path = "../cobs/crc.rs"]
+mod crc;
+#[path = "../cobs/decode.rs"]
+mod decode;
+
+use ellipsoid::{EllipsoidFit, MagCalibration};
+
+const MAG_FIELD: usize = 5;
+const MAX_SAMPLES: usize = 4096;
+// Equal area bins: bands of equal height in z times longitude sectors
+const BANDS: usize = 6;
+const SECTORS: usize = 12;
+
+/// Splits `[a, [b, c], d]` into top level fields `a`, `[b, c]`, `d`
+fn fields(line: &str) -> Option<Vec<&str>> {
+    let inner = line.trim().strip_prefix('[')?.strip_suffix(']')?;
+    let mut fields = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in inner.char_indices() {
+        match c {
+            '[' | '(' | '{' => depth += 1,
+            ']' | ')' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                fields.push(inner[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    fields.push(inner[start..].trim());
+    Some(fields)
+}
+
+fn vec3(field: &str) -> Option<[f32; 3]> {
+    let inner = field.strip_prefix('[')?.strip_suffix(']')?;
+    let v: Vec<f32> = inner
+        .split(',')
+        .map(|v| v.trim().parse())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    match v[..] {
+        [x, y, z] => Some([x, y, z]),
+        _ => None,
+    }
+}
+
+fn apply(cal: &MagCalibration, s: &[f32; 3]) -> [f64; 3] {
+    let sb = [0, 1, 2].map(|i| (s[i] - cal.b[i]) as f64);
+    [0, 1, 2].map(|i| (0..3).map(|j| cal.a_1[i * 3 + j] as f64 * sb[j]).sum())
+}
+
+fn norm(v: &[f64; 3]) -> f64 {
+    (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt()
+}
+
+/// RMS and worst relative deviation of magnitudes from their mean
+fn spread(vs: &[[f64; 3]]) -> (f64, f64) {
+    let mean = vs.iter().map(norm).sum::<f64>() / vs.len() as f64;
+    let errs: Vec<f64> = vs.iter().map(|v| (norm(v) - mean) / mean).collect();
+    let rms =
+        (errs.iter().map(|e| e * e).sum::<f64>() / errs.len() as f64).sqrt();
+    (rms, errs.iter().fold(0.0, |w: f64, e| w.max(e.abs())))
+}
+
+/// Fraction of equal area sphere bins hit by calibrated directions
+fn coverage(vs: &[[f64; 3]]) -> f64 {
+    let mut hit = [[false; SECTORS]; BANDS];
+    for v in vs {
+        let n = norm(v);
+        let z = (v[2] / n).clamp(-1.0, 1.0);
+        let band = (((z + 1.0) / 2.0 * BANDS as f64) as usize).min(BANDS - 1);
+        let lon = v[1].atan2(v[0]) + std::f64::consts::PI;
+        let sector = ((lon / std::f64::consts::TAU * SECTORS as f64) as usize)
+            .min(SECTORS - 1);
+        hit[band][sector] = true;
+    }
+    let covered = hit.iter().flatten().filter(|&&h| h).count();
+    covered as f64 / (BANDS * SECTORS) as f64
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let path = args.get(1).expect("usage: mag_cal capture.bin [field]");
+    let field: usize = args.get(2).map_or(MAG_FIELD, |f| f.parse().unwrap());
+    let bytes = std::fs::read(path).expect("can't read capture");
+    let (capture, broken) = capture::text(&bytes);
+    if broken > 0 {
+        eprintln!("{} broken frames skipped", broken);
+    }
+
+    let samples: Vec<[f32; 3]> = capture
+        .lines()
+        .filter_map(fields)
+        .filter_map(|f| f.get(field).and_then(|f| vec3(f)))
+        .collect();
+    if samples.is_empty() {
+        eprintln!("no samples in {}, is it ahrs-ekf text output?", path);
+        std::process::exit(1);
+    }
+    let mut fit = Box::new(EllipsoidFit::<MAX_SAMPLES>::new());
+    for s in &samples {
+        fit.push(*s);
+    }
+    println!(
+        "// {} samples, {} spread enough to fit",
+        samples.len(),
+        fit.len()
+    );
+    let cal = match fit.fit() {
+        Some(cal) => cal,
+        None => {
+            eprintln!("samples don't describe an ellipsoid, rotate more");
+            std::process::exit(1);
+        }
+    };
+
+    let raw: Vec<[f64; 3]> =
+        samples.iter().map(|s| s.map(|v| v as f64)).collect();
+    let calibrated: Vec<[f64; 3]> =
+        samples.iter().map(|s| apply(&cal, s)).collect();
+    let (raw_rms, raw_worst) = spread(&raw);
+    let (rms, worst) = spread(&calibrated);
+    println!(
+        "// magnitude spread: raw RMS {:.4}, worst {:.4}; \
+         calibrated RMS {:.4}, worst {:.4}",
+        raw_rms, raw_worst, rms, worst
+    );
+    println!(
+        "// sphere coverage {:.0}% of {} bins",
+        coverage(&calibrated) * 100.0,
+        BANDS * SECTORS
+    );
+    println!("const MAG_CAL: MagCalibration = MagCalibration {{");
+    println!("    a_1: {:?},", cal.a_1);
+    println!("    b: {:?},", cal.b);
+    println!("    residual: {:?},", cal.residual);
+    println!("}};");
+}
@@ -5,6 +5,7 @@
 use core::fmt::Write;
 use core::intrinsics;
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use asm_delay::AsmDelay;
 use cortex_m_rt::{entry, exception, ExceptionFrame};
@@ -13,14 +14,52 @@ use hal::gpio;
 use hal::prelude::*;
 use hal::serial;
 use hal::time::Bps;
+use heapless::spsc::{Producer, Queue};
 
 use mpu9250::Mpu9250;
 
-static mut L: Option<hal::serial::Tx<hal::pac::USART2>> = None;
+#[allow(dead_code)]
+mod ellipsoid;
+use ellipsoid::{MagCalibration, StreamingFit};
+#[allow(dead_code)]
+#[path = "../temp_calib/bias.rs"]
+mod bias;
+mod solve;
+use bias::{BiasModel, Compensate, MODEL_LEN};
+#[path = "../cobs/mod.rs"]
+mod cobs;
+use cobs::FrameWriter;
+#[path = "../mavlink/mod.rs"]
+mod mavlink;
+use mavlink::MAV_STATE_ACTIVE;
+use mavlink::{AttitudeQuaternion, Heartbeat, Interval, Link, RawImu};
+#[path = "../shell/mod.rs"]
+mod shell;
+use shell::{Args, Command, Error, Flags, Shell};
+#[path = "../storage/mod.rs"]
+mod storage;
+use storage::stm32::InternalFlash;
+use storage::{records, Store};
+
+static mut L: Option<FrameWriter<hal::serial::Tx<hal::pac::USART2>>> = None;
 static mut RX: Option<hal::serial::Rx<hal::pac::USART2>> = None;
-static mut QUIET: bool = true;
+static QUIET: AtomicBool = AtomicBool::new(false);
 static mut NOW_MS: u32 = 0;
-const TURN_QUIET: u8 = 'q' as u8;
+static mut RECALIBRATE: bool = false;
+static MAVLINK: AtomicBool = AtomicBool::new(false);
+// Bytes from the RX interrupt for the shell in the main loop
+static mut INPUT: Queue<u8, 64> = Queue::new();
+static mut P: Option<Producer<'static, u8, 64>> = None;
+static COMMANDS: [Command<Flags<2>>; 1] = [Command {
+    name: "calibrate",
+    usage: "",
+    help: "start magnetometer calibration over",
+    run: calibrate,
+}];
+const BAUD: u32 = 460800;
+// Correction until on-board fit completes or stored one is loaded, paste
+// `mag_cal.rs` output here
+const MAG_CAL: MagCalibration = MagCalibration::identity();
 
 #[entry]
 fn main() -> ! {
@@ -48,16 +87,18 @@ fn main() -> ! {
     let mut serial =
         device
             .USART2
-            .serial((gpioa.pa2, gpioa.pa15), Bps(460800), clocks);
+            .serial((gpioa.pa2, gpioa.pa15), Bps(BAUD), clocks);
     let ser_int = serial.get_interrupt();
     serial.listen(serial::Event::Rxne);
-    let (mut tx, rx) = serial.split();
-    writeln!(tx, "tx ok").unwrap();
+    let (tx, rx) = serial.split();
     unsafe {
-        L = Some(tx);
+        L = Some(FrameWriter::start(tx).unwrap());
         RX = Some(rx);
     };
+    let (p, mut input) = unsafe { INPUT.split() };
+    unsafe { P = Some(p) };
     let l = unsafe { extract(&mut L) };
+    writeln!(l, "tx ok").unwrap();
     writeln!(l, "logger ok").unwrap();
     // SPI1
     let ncs = gpiob.pb0.output().push_pull();
@@ -79,14 +120,36 @@ fn main() -> ! {
         }
     };
     writeln!(l, "mpu ok").unwrap();
-    let accel_biases: [f32; 3] = match mpu.calibrate_at_rest(&mut delay) {
-        Ok(ab) => ab,
+    let mut store = match Store::new(unsafe { InternalFlash::new() }) {
+        Ok(store) => Some(store),
         Err(e) => {
-            writeln!(l, "Mpu calib error: {:?}", e).unwrap();
-            panic!("mpu err");
+            writeln!(l, "storage unavailable: {:?}", e).unwrap();
+            None
+        }
+    };
+    // Temperature model from temp-calib replaces calibration at rest
+    let bias_model = match store
+        .as_ref()
+        .and_then(|s| s.read_f32s::<MODEL_LEN>(records::TEMP_BIAS))
+    {
+        Some(v) => {
+            let model = BiasModel::from_f32s(&v);
+            writeln!(l, "bias model loaded: {:?}", model).unwrap();
+            model
+        }
+        None => {
+            let calibrated: Result<[f32; 3], _> =
+                mpu.calibrate_at_rest(&mut delay);
+            match calibrated {
+                Ok(ab) => writeln!(l, "calibration ok: {:?}", ab).unwrap(),
+                Err(e) => {
+                    writeln!(l, "Mpu calib error: {:?}", e).unwrap();
+                    panic!("mpu err");
+                }
+            }
+            BiasModel::zero()
         }
     };
-    writeln!(l, "calibration ok: {:?}", accel_biases).unwrap();
     let mut syst = core.SYST;
     unsafe { cortex_m::interrupt::enable() };
     let reload = clocks.sysclk().0 / 8000 - 1;
@@ -100,49 +163,64 @@ fn main() -> ! {
 
     write!(l, "{} {}\r\n", clocks.sysclk().0, reload).unwrap();
 
-    // // EEPROM this
-    // let mag_offs = [
-    //     0., 0., 0.,
-    //     // Dev1
-    //     // 400.149575,
-    //     // -75.52005,
-    //     // -145.13623,
-
-    //     // // Dev2
-    //     // 505.4114185,
-    //     // 503.12718,
-    //     // 291.256415,
-    // ];
-
     let mut marg = ahrs::MargEkf::new();
 
-    // magic
-    let a_1 = [
-        0.8584555038527345,
-        0.10586940235249466,
-        -0.03836484610343783,
-        0.10586940235249466,
-        0.9443944364324307,
-        0.026852422933079816,
-        -0.03836484610343783,
-        0.026852422933079816,
-        0.9564666809032134,
-    ];
-    let b = [-171.46636520969122, 440.13276013298366, -197.88863777137766];
-    // end of magic
+    let mut mag_cal = MAG_CAL;
+    let mut mag_fit = StreamingFit::new();
+    let stored = store.as_ref().and_then(|s| s.read_f32s::<12>(records::MAG));
+    let mut fitting = stored.is_none();
+    if let Some(v) = stored {
+        mag_cal.a_1.copy_from_slice(&v[..9]);
+        mag_cal.b.copy_from_slice(&v[9..]);
+        writeln!(l, "mag calibration loaded: {:?}", mag_cal).unwrap();
+    } else {
+        writeln!(l, "rotate the board to calibrate magnetometer").unwrap();
+    }
 
+    writeln!(l, "`set mavlink on` to switch to MAVLink, `help` for more")
+        .unwrap();
+    let mut link = Link::new(1, 1, BAUD, prev_t_ms);
+    let mut heartbeat = Interval::new(1000, prev_t_ms);
     let mut reads = 0;
+    let mut flags = Flags::new(["quiet", "mavlink"], [&QUIET, &MAVLINK]);
+    let mut shell = Shell::<Flags<2>, 64>::new(&COMMANDS);
     loop {
+        shell.drain(|| input.dequeue(), &mut flags, l).unwrap();
         let t_ms = now_ms();
         let dt_ms = t_ms.wrapping_sub(prev_t_ms);
         prev_t_ms = t_ms;
         match mpu.all::<[f32; 3]>() {
-            Ok(meas) => {
+            Ok(mut meas) => {
+                meas.compensate(&bias_model);
                 let gyro = meas.gyro;
 
                 let accel = meas.accel;
                 let mag = [meas.mag[0], meas.mag[1], meas.mag[2]];
-                let cal = calibrated_sample(&mag, &a_1, &b);
+                if unsafe { RECALIBRATE } {
+                    unsafe { RECALIBRATE = false };
+                    fitting = true;
+                    mag_fit.reset();
+                    writeln!(l, "rotate the board to calibrate magnetometer")
+                        .unwrap();
+                }
+                // Adopted and saved only once it covers enough of the
+                // sphere with a small residual, same as mpu-calib
+                if fitting && mag_fit.push(mag) && mag_fit.is_done() {
+                    if let Some(fitted) = mag_fit.calibration() {
+                        fitting = false;
+                        mag_cal = fitted;
+                        write!(l, "mag calibration: {:?}\r\n", mag_cal)
+                            .unwrap();
+                        let saved = match store.as_mut() {
+                            Some(s) => save_mag_cal(s, &mag_cal),
+                            None => Ok(()),
+                        };
+                        if let Err(e) = saved {
+                            write!(l, "can't save: {:?}\r\n", e).unwrap();
+                        }
+                    }
+                }
+                let cal = calibrated_sample(&mag, &mag_cal.a_1, &mag_cal.b);
 
                 marg.predict(
                     gyro[0],
@@ -152,17 +230,53 @@ fn main() -> ! {
                 );
                 marg.update(accel, cal);
 
-                write!(
-                    l,
-                    "[{}, {:?}, {:?}, {:?}, {:?}, {:?}]\r\n",
-                    dt_ms, accel, gyro, cal, marg.state, meas.mag
-                )
-                .unwrap();
+                if MAVLINK.load(Ordering::Relaxed) {
+                    let mut write = |f: &[u8]| l.write_raw(f);
+                    if heartbeat.due(t_ms) {
+                        let hb = Heartbeat::quadrotor(MAV_STATE_ACTIVE);
+                        link.send(t_ms, &hb, &mut write).unwrap();
+                    }
+                    // State starts with the quaternion, w first
+                    let s = &marg.state;
+                    let att = AttitudeQuaternion {
+                        time_boot_ms: t_ms,
+                        q: [s[0], s[1], s[2], s[3]],
+                        rollspeed: gyro[0],
+                        pitchspeed: gyro[1],
+                        yawspeed: gyro[2],
+                    };
+                    let imu = RawImu::from_si(
+                        t_ms as u64 * 1000,
+                        accel,
+                        gyro,
+                        meas.mag,
+                        meas.temp,
+                    );
+                    // 85 bytes each 20 ms, under a tenth of the link
+                    link.send(t_ms, &att, &mut write).unwrap();
+                    link.send(t_ms, &imu, &mut write).unwrap();
+                } else if !QUIET.load(Ordering::Relaxed) {
+                    write!(
+                        l,
+                        "[{}, {:?}, {:?}, {:?}, {:?}, {:?}]\r\n",
+                        dt_ms, accel, gyro, cal, marg.state, meas.mag
+                    )
+                    .unwrap();
+                }
 
                 while now_ms() < t_ms + 20 {}
                 reads += 1;
                 if reads >= 100 {
                     reads = 0;
+                    if fitting && !MAVLINK.load(Ordering::Relaxed) {
+                        write!(
+                            l,
+                            "mag coverage: {}, residual: {:?}\r\n",
+                            mag_fit.coverage(),
+                            mag_fit.calibration().map(|c| c.residual)
+                        )
+                        .unwrap();
+                    }
                     if is_high {
                         let _ = beeper.set_low();
                         is_high = false;
@@ -173,7 +287,7 @@ fn main() -> ! {
                 }
             }
             Err(e) => {
-                write!(l, "Err: {:?}; {:?}", t_ms, e).unwrap();
+                write!(l, "Err: {:?}; {:?}\r\n", t_ms, e).unwrap();
             }
         }
     }
@@ -189,17 +303,10 @@ unsafe fn extract<T>(opt: &'static mut Option<T>) -> &'static mut T {
 #[interrupt]
 fn USART2_EXTI26() {
     let rx = unsafe { extract(&mut RX) };
-    let l = unsafe { extract(&mut L) };
+    let p = unsafe { extract(&mut P) };
     match rx.read() {
         Ok(b) => {
-            if b == TURN_QUIET {
-                unsafe {
-                    QUIET = !QUIET;
-                }
-            } else {
-                // echo byte as is
-                write!(l, "{}", b as char).unwrap();
-            }
+            let _ = p.enqueue(b);
         }
         Err(nb::Error::WouldBlock) => {}
         Err(nb::Error::Other(e)) => match e {
@@ -212,13 +319,22 @@ fn USART2_EXTI26() {
             serial::Error::Noise => {
                 rx.clear_noise_error();
             }
-            _ => {
-                write!(l, "read error: {:?}", e).unwrap();
-            }
+            _ => {}
         },
     };
 }
 
+fn calibrate(
+    _: &mut Flags<2>,
+    args: &mut Args,
+    out: &mut dyn Write,
+) -> Result<(), Error> {
+    args.end()?;
+    unsafe { RECALIBRATE = true };
+    write!(out, "calibrating\r\n")?;
+    Ok(())
+}
+
 fn now_ms() -> u32 {
     unsafe { core::ptr::read_volatile(&NOW_MS as *const u32) }
 }
@@ -231,14 +347,14 @@ unsafe fn SysTick() {
 #[exception]
 unsafe fn HardFault(ef: &ExceptionFrame) -> ! {
     let l = extract(&mut L);
-    write!(l, "hard fault at {:?}", ef).unwrap();
+    write!(l, "hard fault at {:?}\r\n", ef).unwrap();
     panic!("HardFault at {:#?}", ef);
 }
 
 #[exception]
 unsafe fn DefaultHandler(irqn: i16) {
     let l = extract(&mut L);
-    write!(l, "Interrupt: {}", irqn).unwrap();
+    write!(l, "Interrupt: {}\r\n", irqn).unwrap();
 }
 
 #[panic_handler]
@@ -273,12 +389,23 @@ fn panic(panic_info: &PanicInfo) -> ! {
                     write!(l, "panic occured, no info available").unwrap();
                 }
             }
+            let _ = l.end_frame();
         }
         None => {}
     }
     intrinsics::abort()
 }
 
+fn save_mag_cal(
+    store: &mut Store<InternalFlash>,
+    cal: &MagCalibration,
+) -> Result<(), storage::Error> {
+    let mut v = [0.0; 12];
+    v[..9].copy_from_slice(&cal.a_1);
+    v[9..].copy_from_slice(&cal.b);
+    store.write_f32s(records::MAG, &v)
+}
+
 pub fn calibrated_sample(
     sample: &[f32; 3],
     a_1: &[f32; 9],
@@ -0,0 +1,51 @@
+// Dense linear solver shared by the calibration fits, no_std and without
+// libm: `ellipsoid.rs`, `temp_calib/bias.rs` and `altitude/fit_range.rs`
+// mount it as `crate::solve`.
+
+/// Solves `a * x = b` with Gaussian elimination and partial pivoting,
+/// `None` if `a` is singular or not a number
+pub fn solve<const N: usize>(
+    mut a: [[f64; N]; N],
+    mut b: [f64; N],
+) -> Option<[f64; N]> {
+    for col in 0..N {
+        let mut pivot = col;
+        for row in col + 1..N {
+            if abs(a[row][col]) > abs(a[pivot][col]) {
+                pivot = row;
+            }
+        }
+        let largest = abs(a[pivot][col]);
+        if largest <= 1e-12 || largest.is_nan() {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        let (top, below) = a.split_at_mut(col + 1);
+        let top = &top[col];
+        for (row, ar) in below.iter_mut().enumerate() {
+            let f = ar[col] / top[col];
+            for (arc, &tc) in ar[col..].iter_mut().zip(&top[col..]) {
+                *arc -= f * tc;
+            }
+            b[col + 1 + row] -= f * b[col];
+        }
+    }
+    let mut x = [0.0; N];
+    for row in (0..N).rev() {
+        let mut s = b[row];
+        for c in row + 1..N {
+            s -= a[row][c] * x[c];
+        }
+        x[row] = s / a[row][row];
+    }
+    Some(x)
+}
+
+fn abs(x: f64) -> f64 {
+    if x < 0.0 {
+        -x
+    } else {
+        x
+    }
+}
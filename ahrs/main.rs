@@ -6,6 +6,7 @@
 use core::fmt::Write;
 use core::intrinsics;
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use cortex_m::peripheral::syst::SystClkSource;
 use cortex_m_rt::{entry, exception, ExceptionFrame};
@@ -13,16 +14,45 @@ use hal::pac::interrupt;
 use hal::prelude::*;
 use hal::time::Bps;
 use hal::{delay, serial};
+use heapless::spsc::{Producer, Queue};
 use nb;
 
 use dcmimu::DCMIMU;
 use mpu9250::{self, Mpu9250};
 
-static mut L: Option<hal::serial::Tx<hal::pac::USART1>> = None;
+#[allow(dead_code)]
+#[path = "../temp_calib/bias.rs"]
+mod bias;
+#[path = "../ahrs-ekf/solve.rs"]
+mod solve;
+use bias::{BiasModel, Compensate, MODEL_LEN};
+#[path = "../cobs/mod.rs"]
+mod cobs;
+use cobs::FrameWriter;
+#[path = "../mavlink/mod.rs"]
+mod mavlink;
+use mavlink::MAV_STATE_ACTIVE;
+use mavlink::{AttitudeQuaternion, Heartbeat, Interval, Link, RawImu};
+#[path = "../shell/mod.rs"]
+mod shell;
+use shell::{Command, Flags, Shell};
+#[path = "../storage/mod.rs"]
+mod storage;
+use storage::stm32::InternalFlash;
+use storage::{records, Store};
+
+static mut L: Option<FrameWriter<hal::serial::Tx<hal::pac::USART1>>> = None;
 static mut RX: Option<hal::serial::Rx<hal::pac::USART1>> = None;
-static mut QUIET: bool = false;
-const TURN_QUIET: u8 = 'q' as u8;
+static QUIET: AtomicBool = AtomicBool::new(false);
+static MAVLINK: AtomicBool = AtomicBool::new(false);
+// Bytes from the RX interrupt for the shell in the main loop
+static mut INPUT: Queue<u8, 64> = Queue::new();
+static mut P: Option<Producer<'static, u8, 64>> = None;
+static COMMANDS: [Command<Flags<2>>; 0] = [];
 static mut NOW_MS: u32 = 0;
+const BAUD: u32 = 115200;
+// Attitude and raw IMU at 50 Hz take 4250 of 11520 bytes/s
+const MAVLINK_PERIOD_MS: u32 = 20;
 
 #[entry]
 fn main() -> ! {
@@ -43,16 +73,16 @@ fn main() -> ! {
     let mut serial =
         device
             .USART1
-            .serial((gpioa.pa9, gpioa.pa10), Bps(115200), clocks);
+            .serial((gpioa.pa9, gpioa.pa10), Bps(BAUD), clocks);
     let ser_int = serial.get_interrupt();
     serial.listen(serial::Event::Rxne);
-    let (mut tx, rx) = serial.split();
-    // COBS frame
-    tx.write(0x00).unwrap();
+    let (tx, rx) = serial.split();
     unsafe {
-        L = Some(tx);
+        L = Some(FrameWriter::start(tx).unwrap());
         RX = Some(rx);
     };
+    let (p, mut input) = unsafe { INPUT.split() };
+    unsafe { P = Some(p) };
     let l = unsafe { extract(&mut L) };
     write!(l, "logger ok\r\n").unwrap();
     let mut delay = delay::Delay::new(core.SYST, clocks);
@@ -74,11 +104,26 @@ fn main() -> ! {
     )
     .expect("mpu error");
     write!(l, "mpu ok\r\n").unwrap();
-    let mut accel_biases: [f32; 3] =
-        mpu.calibrate_at_rest(&mut delay).expect("calib error");
-    // Correct axis for gravity;
-    accel_biases[2] -= mpu9250::G;
-    write!(l, "calibration ok: {:?}\r\n", accel_biases).unwrap();
+    let stored = Store::new(unsafe { InternalFlash::new() })
+        .ok()
+        .and_then(|s| s.read_f32s::<MODEL_LEN>(records::TEMP_BIAS));
+    // Temperature model from temp-calib replaces calibration at rest
+    let mut accel_biases = [0.0; 3];
+    let bias_model = match stored {
+        Some(v) => {
+            let model = BiasModel::from_f32s(&v);
+            write!(l, "bias model loaded: {:?}\r\n", model).unwrap();
+            model
+        }
+        None => {
+            accel_biases =
+                mpu.calibrate_at_rest(&mut delay).expect("calib error");
+            // Correct axis for gravity;
+            accel_biases[2] -= mpu9250::G;
+            write!(l, "calibration ok: {:?}\r\n", accel_biases).unwrap();
+            BiasModel::zero()
+        }
+    };
 
     let mut dcmimu = DCMIMU::new();
     let mut syst = delay.free();
@@ -87,15 +132,18 @@ fn main() -> ! {
     unsafe { cortex_m::peripheral::NVIC::unmask(ser_int) };
 
     let mut prev_t_ms = now_ms();
-    write!(
-        l,
-        "All ok, now: {:?}; Press 'q' to toggle logging!\r\n",
-        prev_t_ms
-    )
-    .unwrap();
+    write!(l, "All ok, now: {:?}; `help` lists commands\r\n", prev_t_ms)
+        .unwrap();
+    let mut link = Link::new(1, 1, BAUD, prev_t_ms);
+    let mut heartbeat = Interval::new(1000, prev_t_ms);
+    let mut stream = Interval::new(MAVLINK_PERIOD_MS, prev_t_ms);
+    let mut flags = Flags::new(["quiet", "mavlink"], [&QUIET, &MAVLINK]);
+    let mut shell = Shell::<Flags<2>, 64>::new(&COMMANDS);
     loop {
+        shell.drain(|| input.dequeue(), &mut flags, l).unwrap();
         match mpu.all::<[f32; 3]>() {
-            Ok(meas) => {
+            Ok(mut meas) => {
+                meas.compensate(&bias_model);
                 let gyro = meas.gyro;
                 let accel = [
                     meas.accel[0] - accel_biases[0],
@@ -111,7 +159,32 @@ fn main() -> ! {
                     (accel[0], accel[1], accel[2]),
                     dt_s,
                 );
-                if unsafe { !QUIET } {
+                if MAVLINK.load(Ordering::Relaxed) {
+                    let mut write = |f: &[u8]| l.write_raw(f);
+                    if heartbeat.due(t_ms) {
+                        let hb = Heartbeat::quadrotor(MAV_STATE_ACTIVE);
+                        link.send(t_ms, &hb, &mut write).unwrap();
+                    }
+                    if stream.due(t_ms) {
+                        let att = AttitudeQuaternion {
+                            time_boot_ms: t_ms,
+                            q: quat(dcm.roll, dcm.pitch, dcm.yaw),
+                            rollspeed: gyro[0],
+                            pitchspeed: gyro[1],
+                            yawspeed: gyro[2],
+                        };
+                        // No magnetometer in IMU mode
+                        let imu = RawImu::from_si(
+                            t_ms as u64 * 1000,
+                            accel,
+                            gyro,
+                            [0.; 3],
+                            meas.temp,
+                        );
+                        link.send(t_ms, &att, &mut write).unwrap();
+                        link.send(t_ms, &imu, &mut write).unwrap();
+                    }
+                } else if !QUIET.load(Ordering::Relaxed) {
                     write!(
                         l,
                         "IMU: dt={}s; roll={}; yaw={}; pitch={}\r\n",
@@ -130,6 +203,19 @@ fn main() -> ! {
     }
 }
 
+/// w, x, y, z from Euler angles, rotation order yaw, pitch, roll
+fn quat(roll: f32, pitch: f32, yaw: f32) -> [f32; 4] {
+    let (sr, cr) = (libm::sinf(roll / 2.), libm::cosf(roll / 2.));
+    let (sp, cp) = (libm::sinf(pitch / 2.), libm::cosf(pitch / 2.));
+    let (sy, cy) = (libm::sinf(yaw / 2.), libm::cosf(yaw / 2.));
+    [
+        cr * cp * cy + sr * sp * sy,
+        sr * cp * cy - cr * sp * sy,
+        cr * sp * cy + sr * cp * sy,
+        cr * cp * sy - sr * sp * cy,
+    ]
+}
+
 fn rad_to_degrees(r: f32) -> f32 {
     (r * 180.) / 3.14159265359
 }
@@ -144,17 +230,10 @@ unsafe fn extract<T>(opt: &'static mut Option<T>) -> &'static mut T {
 #[interrupt]
 fn USART1_EXTI25() {
     let rx = unsafe { extract(&mut RX) };
-    let l = unsafe { extract(&mut L) };
+    let p = unsafe { extract(&mut P) };
     match rx.read() {
         Ok(b) => {
-            if b == TURN_QUIET {
-                unsafe {
-                    QUIET = !QUIET;
-                }
-            } else {
-                // echo byte as is
-                write!(l, "{}", b as char).unwrap();
-            }
+            let _ = p.enqueue(b);
         }
         Err(nb::Error::WouldBlock) => {}
         Err(nb::Error::Other(e)) => match e {
@@ -167,9 +246,7 @@ fn USART1_EXTI25() {
             serial::Error::Noise => {
                 rx.clear_noise_error();
             }
-            _ => {
-                write!(l, "read error: {:?}", e).unwrap();
-            }
+            _ => {}
         },
     };
 }
@@ -233,6 +310,7 @@ fn panic(panic_info: &PanicInfo) -> ! {
                     write!(l, "panic occured, no info available").unwrap();
                 }
             }
+            let _ = l.end_frame();
         }
         None => {}
     }
@@ -10,8 +10,8 @@ use nalgebra as na;
 ///
 /// References:
 /// * http://www.engineeringtoolbox.com/air-altitude-pressure-d_462.html
-fn asl_to_baro(h: f32) -> f32 {
-    101325.0 * (1.0 - 2.25577e-5 * h).powf(5.25588)
+pub fn asl_to_baro(h: f32) -> f32 {
+    101325.0 * powf(1.0 - 2.25577e-5 * h, 5.25588)
 }
 
 /// Convert pressure to above the sea level
@@ -20,88 +20,408 @@ fn asl_to_baro(h: f32) -> f32 {
 /// * h: altitude above sea level (m)
 /// Input:
 /// * p: air pressure
-fn baro_to_asl(p: f32) -> f32 {
-    (1.0 - (p / 101325.0).powf(0.190295)) * 44330.0
+pub fn baro_to_asl(p: f32) -> f32 {
+    (1.0 - powf(p / 101325.0, 0.190295)) * 44330.0
 }
 
-/// Ground-truth AGL to rangefinder measurement
-///
-/// TBD:
-/// * Measure real function
+/// Derivative of `asl_to_baro`
 ///
+/// Returns:
+/// * dp/dh: change of air pressure per meter (Pa/m)
 /// Input:
-/// * h: above the ground level
+/// * h: altitude above sea level (m)
+pub fn dbaro_dasl(h: f32) -> f32 {
+    -101325.0 * 5.25588 * 2.25577e-5 * powf(1.0 - 2.25577e-5 * h, 4.25588)
+}
+
+/// Ground-truth AGL to rangefinder measurement
 ///
-/// Output:
-/// * h': corresponding rangefinder measurement
-fn agl_to_range(agl: f32) -> f32 {
-    0.933 * agl - 2.894
-    // h
+/// Reading (m) is a polynomial of height above the ground (m),
+/// `c[0] + c[1] * h + c[2] * h^2 + c[3] * h^3`. Fit coefficients for a
+/// particular sensor with `fit_range.rs`.
+#[derive(Clone, Copy, Debug)]
+pub struct RangeModel {
+    c: [f32; 4],
 }
 
-/// Class for fusing range and barometric sensors
+impl RangeModel {
+    /// Polynomial with coefficients `c` in increasing order of power
+    pub const fn polynomial(c: [f32; 4]) -> Self {
+        RangeModel { c }
+    }
+
+    /// Straight line mapping
+    pub const fn linear(gain: f32, offset: f32) -> Self {
+        RangeModel::polynomial([offset, gain, 0.0, 0.0])
+    }
+
+    /// Sensor that reads true height, e.g. VL53L0X
+    pub const fn identity() -> Self {
+        RangeModel::linear(1.0, 0.0)
+    }
+
+    /// MB1242 sonar, empirically determined:
+    /// see http://diydrones.com/profiles/blogs/altitude-hold-with-mb1242-sonar
+    pub const fn mb1242() -> Self {
+        RangeModel::linear(0.933, -2.894)
+    }
+
+    /// Rangefinder measurement at `agl` meters above the ground
+    pub fn range(&self, agl: f32) -> f32 {
+        let c = &self.c;
+        c[0] + agl * (c[1] + agl * (c[2] + agl * c[3]))
+    }
+
+    /// Derivative of `range` at `agl`
+    pub fn slope(&self, agl: f32) -> f32 {
+        let c = &self.c;
+        c[1] + agl * (2.0 * c[2] + agl * 3.0 * c[3])
+    }
+}
+
+/// Chi-square 99.9% quantile for one degree of freedom, default gate
+pub const CHI2_GATE: f32 = 10.83;
+/// Rejections in a row after which measurement is fused regardless, so the
+/// filter can't lock itself out after a real jump
+const MAX_REJECTS: u32 = 5;
+/// VL53L0X reports this or larger when there is no target
+const RANGE_NO_TARGET_MM: u16 = 8190;
+/// Barometer samples averaged for the ground baseline, 2 s of BMP280 with
+/// 250 ms standby
+pub const ZERO_SAMPLES: u32 = 8;
+/// Acceleration (m/s^2) above which the sensor is not stationary and
+/// baseline averaging starts over
+const STILL_ACCEL: f32 = 1.0;
+
+const BARO: usize = 0;
+const RANGE: usize = 1;
+
+/// Counters of one measurement channel
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChannelStats {
+    /// Measurements fused into the state
+    pub accepted: u32,
+    /// Measurements rejected by the innovation gate
+    pub rejected: u32,
+    /// Measurements skipped because innovation covariance was degenerate
+    pub singular: u32,
+    /// Measurements fused past the gate after `MAX_REJECTS` rejections
+    pub forced: u32,
+    /// Readings without a valid measurement (no target, out of range)
+    pub dropouts: u32,
+    consecutive: u32,
+}
+
+/// Running average of ground pressure, deviations from the first reading
+/// are summed to keep f32 accuracy
+struct Zeroing {
+    first: f32,
+    sum: f32,
+    count: u32,
+    samples: u32,
+}
+
+/// Class for fusing range, barometric and accelerometer sensors
+///
+/// State is altitude above the sea level (m), vertical velocity (m/s) and
+/// accelerometer bias (m/s^2). Earth frame vertical acceleration (m/s^2,
+/// gravity removed, up is positive) drives the state transition.
+///
+/// Sensors tick at their own rates: call `predict` with every accelerometer
+/// sample and `update_baro`/`update_range` whenever a reading arrives.
+/// Filter starts by averaging `ZERO_SAMPLES` barometer readings while
+/// stationary to find the ground baseline pressure, `rezero` repeats that on
+/// demand. Measurements are not fused until the baseline is known.
+///
+/// Each measurement is rejected when its squared Mahalanobis distance
+/// exceeds the gate. Degenerate innovation covariance skips the measurement
+/// instead of panicking.
 pub struct ASL_EKF {
     baseline_pressure: f32,
-    p_pre: na::Matrix1<f32>, // Previous prediction noise covariance
-    x: na::Matrix1<f32>,     // Matrix of n states, where n = 1
-    p_post: na::Matrix1<f32>, // Matrix of n multiplied by pval
-    q: na::Matrix1<f32>,     // Matrix of size n
-    r: na::Matrix2<f32>,     // Two observations
-    i: na::Matrix1<f32>,     // of size n
+    zeroing: Option<Zeroing>,
+    pval: f32,                // Initial state variance
+    x: na::Vector3<f32>,      // altitude, velocity, accelerometer bias
+    p: na::Matrix3<f32>,      // State covariance
+    accel_noise: f32,         // Acceleration noise density
+    bias_noise: f32,          // Bias random walk
+    r: [f32; 2],              // Barometer and rangefinder variances
+    range_limits: (f32, f32), // Trusted rangefinder span (m)
+    range_model: RangeModel,  // Height above the ground to range reading
+    i: na::Matrix3<f32>,      // of size n
+    gate: Option<f32>,        // Chi-square threshold, None fuses everything
+    stats: [ChannelStats; 2],
 }
 
 impl ASL_EKF {
     /// Create new fusor with default (large measurement covariance) settings.
     pub fn new() -> Self {
         let pval = 0.1;
-        let qval = 1e-4;
-        let rval = 0.5;
-        ASL_EKF {
-            p_pre: na::Matrix1::zeros(),
-            x: na::Matrix1::zeros(),
-            p_post: na::Matrix1::identity() * pval,
-            q: na::Matrix1::identity() * qval,
-            r: na::Matrix2::identity() * rval,
-            i: na::Matrix1::identity(),
-            baseline_pressure: 97420.0,
+        let accel_noise = 0.5;
+        let bias_noise = 1e-3;
+        let baro_rval = 16.0;
+        let range_rval = 0.5;
+        let baseline_pressure = 101325.0;
+        let mut ekf = ASL_EKF {
+            zeroing: None,
+            pval,
+            x: na::Vector3::new(baro_to_asl(baseline_pressure), 0.0, 0.0),
+            p: na::Matrix3::identity() * pval,
+            accel_noise,
+            bias_noise,
+            r: [baro_rval, range_rval],
+            range_limits: (0.03, 2.0),
+            range_model: RangeModel::identity(),
+            i: na::Matrix3::identity(),
+            gate: Some(CHI2_GATE),
+            stats: [ChannelStats::default(); 2],
+            baseline_pressure,
+        };
+        ekf.rezero(ZERO_SAMPLES);
+        ekf
+    }
+
+    /// Start over ground baseline estimation from next `samples` barometer
+    /// readings, sensor must stay still until `is_zeroing` returns false
+    pub fn rezero(&mut self, samples: u32) {
+        self.zeroing = Some(Zeroing {
+            first: 0.0,
+            sum: 0.0,
+            count: 0,
+            samples: samples.max(1),
+        });
+    }
+
+    /// Whether ground baseline estimation is still in progress
+    pub fn is_zeroing(&self) -> bool {
+        self.zeroing.is_some()
+    }
+
+    /// Ground baseline pressure (Pa)
+    pub fn baseline(&self) -> f32 {
+        self.baseline_pressure
+    }
+
+    /// Estimated height above the ground baseline (m)
+    pub fn agl(&self) -> f32 {
+        self.x[0] - baro_to_asl(self.baseline_pressure)
+    }
+
+    /// Accumulate barometer reading `p` into baseline average, resets state
+    /// to the ground once enough readings are in
+    fn zero(&mut self, p: f32) {
+        let done = match self.zeroing.as_mut() {
+            None => return,
+            Some(z) => {
+                if z.count == 0 {
+                    z.first = p;
+                }
+                z.sum += p - z.first;
+                z.count += 1;
+                if z.count < z.samples {
+                    return;
+                }
+                z.first + z.sum / z.count as f32
+            }
+        };
+        self.zeroing = None;
+        self.baseline_pressure = done;
+        let bias_variance = self.p[(2, 2)];
+        self.x = na::Vector3::new(baro_to_asl(done), 0.0, self.x[2]);
+        self.p = na::Matrix3::identity() * self.pval;
+        self.p[(2, 2)] = bias_variance;
+        for stats in self.stats.iter_mut() {
+            stats.consecutive = 0;
         }
     }
 
-    /// State transition step
-    pub fn step(&mut self, z: na::Vector2<f32>) -> na::Matrix1<f32> {
-        let (new_x, f) = self.f(self.x);
+    /// Set innovation gate threshold, `None` disables gating
+    pub fn set_gate(&mut self, gate: Option<f32>) {
+        self.gate = gate;
+    }
+
+    /// Set acceleration noise density and accelerometer bias random walk
+    pub fn set_process_noise(&mut self, accel_noise: f32, bias_noise: f32) {
+        self.accel_noise = accel_noise;
+        self.bias_noise = bias_noise;
+    }
+
+    /// Set initial state variance, used from the next re-zero on
+    pub fn set_initial_variance(&mut self, pval: f32) {
+        self.pval = pval;
+    }
+
+    /// Set barometer measurement variance (Pa^2)
+    pub fn set_baro_noise(&mut self, rval: f32) {
+        self.r[BARO] = rval;
+    }
+
+    /// Set rangefinder measurement variance (m^2)
+    pub fn set_range_noise(&mut self, rval: f32) {
+        self.r[RANGE] = rval;
+    }
+
+    /// Set span of rangefinder readings (m) to trust, others are dropouts
+    pub fn set_range_limits(&mut self, min: f32, max: f32) {
+        self.range_limits = (min, max);
+    }
+
+    /// Set mapping from height above the ground to rangefinder reading
+    pub fn set_range_model(&mut self, model: RangeModel) {
+        self.range_model = model;
+    }
+
+    /// Barometer channel counters
+    pub fn baro_stats(&self) -> ChannelStats {
+        self.stats[BARO]
+    }
+
+    /// Rangefinder channel counters
+    pub fn range_stats(&self) -> ChannelStats {
+        self.stats[RANGE]
+    }
+
+    /// Current state: altitude, velocity, accelerometer bias
+    pub fn state(&self) -> na::Vector3<f32> {
+        self.x
+    }
+
+    /// Prediction step
+    ///
+    /// Input:
+    /// * dt: time since previous prediction (s)
+    /// * accel: earth frame vertical acceleration (m/s^2)
+    pub fn predict(&mut self, dt: f32, accel: f32) -> na::Vector3<f32> {
+        if let Some(z) = self.zeroing.as_mut() {
+            // Hold the state on the ground, moving sensor spoils the average
+            if libm::fabsf(accel - self.x[2]) > STILL_ACCEL {
+                z.count = 0;
+                z.sum = 0.0;
+            }
+            return self.x;
+        }
+        let (new_x, f) = self.f(self.x, accel, dt);
         self.x = new_x;
-        self.p_pre = f * self.p_post * f.transpose() + self.q;
-        let (h, h_big) = self.h(self.x);
-        let a = self.p_pre * h_big.transpose();
-        let b = ((h_big * self.p_pre) * h_big.transpose() + self.r)
-            .try_inverse()
-            .unwrap();
-        let g_big = a * b;
-        self.x += g_big * (z - h/*.transpose()*/)/*.transpose()*/;
-        self.p_post = (self.i - g_big * h_big) * self.p_pre;
+        self.p = f * self.p * f.transpose() + self.q(dt);
         self.x
     }
 
+    /// Prediction step for a setup without an accelerometer: zero
+    /// acceleration, motion goes into process noise. Nothing tells whether
+    /// the sensor moves, keep it still while `is_zeroing`.
+    ///
+    /// Input:
+    /// * dt: time since previous prediction (s)
+    pub fn predict_unmeasured(&mut self, dt: f32) -> na::Vector3<f32> {
+        if self.zeroing.is_some() {
+            return self.x;
+        }
+        self.predict(dt, 0.0)
+    }
+
+    /// Barometer update
+    ///
+    /// Input:
+    /// * p: air pressure (Pa)
+    pub fn update_baro(&mut self, p: f32) -> na::Vector3<f32> {
+        if self.zeroing.is_some() {
+            self.zero(p);
+            return self.x;
+        }
+        let (h, h_big) = self.h_baro(self.x);
+        self.fuse(BARO, p, h, h_big);
+        self.x
+    }
+
+    /// Rangefinder update
+    ///
+    /// Input:
+    /// * mm: rangefinder reading (mm), VL53L0X no target values and
+    ///   readings outside of range limits count as dropouts
+    pub fn update_range(&mut self, mm: u16) -> na::Vector3<f32> {
+        if self.zeroing.is_some() {
+            return self.x;
+        }
+        let range = mm as f32 / 1000.0;
+        let (min, max) = self.range_limits;
+        if mm >= RANGE_NO_TARGET_MM || range < min || range > max {
+            self.stats[RANGE].dropouts += 1;
+            return self.x;
+        }
+        let (h, h_big) = self.h_range(self.x);
+        self.fuse(RANGE, range, h, h_big);
+        self.x
+    }
+
+    /// Fuse single measurement `z` of channel `c` with expected value `h`
+    /// and Jacobian row `h_big`
+    fn fuse(&mut self, c: usize, z: f32, h: f32, h_big: na::RowVector3<f32>) {
+        let a = self.p * h_big.transpose();
+        let s = (h_big * a)[0] + self.r[c];
+        if !s.is_finite() || s <= f32::EPSILON {
+            self.stats[c].singular += 1;
+            return;
+        }
+        let innovation = z - h;
+        let stats = &mut self.stats[c];
+        match self.gate {
+            Some(gate) if innovation * innovation / s > gate => {
+                if stats.consecutive < MAX_REJECTS {
+                    stats.rejected += 1;
+                    stats.consecutive += 1;
+                    return;
+                }
+                stats.forced += 1;
+            }
+            _ => stats.accepted += 1,
+        }
+        stats.consecutive = 0;
+        let g_big = a / s;
+        self.x += g_big * innovation;
+        self.p = (self.i - g_big * h_big) * self.p;
+    }
+
     /// State transition function
     pub fn f(
         &self,
-        x: na::Matrix1<f32>,
-    ) -> (na::Matrix1<f32>, na::Matrix1<f32>) {
-        (x, na::Matrix1::identity())
+        x: na::Vector3<f32>,
+        accel: f32,
+        dt: f32,
+    ) -> (na::Vector3<f32>, na::Matrix3<f32>) {
+        let a = accel - x[2];
+        let new_x = na::Vector3::new(
+            x[0] + x[1] * dt + 0.5 * a * dt * dt,
+            x[1] + a * dt,
+            x[2],
+        );
+        #[rustfmt::skip]
+        let f = na::Matrix3::new(
+            1.0, dt,  -0.5 * dt * dt,
+            0.0, 1.0, -dt,
+            0.0, 0.0, 1.0,
+        );
+        (new_x, f)
     }
 
-    pub fn h(
-        &self,
-        x: na::Matrix1<f32>,
-    ) -> (na::Matrix2x1<f32>, na::Vector2<f32>) {
+    /// Process noise: white acceleration and bias random walk over `dt`
+    fn q(&self, dt: f32) -> na::Matrix3<f32> {
+        let g = na::Vector3::new(0.5 * dt * dt, dt, 0.0);
+        let mut q = g * g.transpose() * (self.accel_noise * self.accel_noise);
+        q[(2, 2)] = self.bias_noise * self.bias_noise * dt;
+        q
+    }
+
+    /// Barometer measurement expected at state `x` and its Jacobian
+    pub fn h_baro(&self, x: na::Vector3<f32>) -> (f32, na::RowVector3<f32>) {
         let asl = x[0];
-        let s = agl_to_range(asl - baro_to_asl(self.baseline_pressure));
-        let b = asl_to_baro(asl);
-        let h = na::Matrix2x1::new(b, s);
-        let dpdx = -0.120131 * powf(1.0 - 2.2577e-7 * x[0], 4.25588);
-        let dsdx = 0.933;
-        let h_big = na::Vector2::new(dpdx, dsdx);
-        (h, h_big)
+        let dpdx = dbaro_dasl(asl);
+        (asl_to_baro(asl), na::RowVector3::new(dpdx, 0.0, 0.0))
+    }
+
+    /// Rangefinder measurement expected at state `x` and its Jacobian
+    pub fn h_range(&self, x: na::Vector3<f32>) -> (f32, na::RowVector3<f32>) {
+        let agl = x[0] - baro_to_asl(self.baseline_pressure);
+        let s = self.range_model.range(agl);
+        let dsdx = self.range_model.slope(agl);
+        (s, na::RowVector3::new(dsdx, 0.0, 0.0))
     }
 }
@@ -0,0 +1,101 @@
+// Fits rangefinder model for `altitude::RangeModel` from logged pairs of true
+// height above the ground (m) and rangefinder reading (mm), one
+// `height,reading` pair per line:
+//     rustc -O fit_range.rs && ./fit_range pairs.csv [degree]
+// Prints coefficients ready to paste into `RangeModel::polynomial`.
+
+#[path = "../ahrs-ekf/solve.rs"]
+mod solve;
+
+use solve::solve;
+
+const MAX_DEGREE: usize = 3;
+const TERMS: usize = MAX_DEGREE + 1;
+const SLOPE_CHECKS: usize = 20;
+
+fn eval(c: &[f64], h: f64) -> f64 {
+    c.iter().rev().fold(0.0, |acc, v| acc * h + v)
+}
+
+fn slope(c: &[f64], h: f64) -> f64 {
+    (1..c.len())
+        .map(|k| k as f64 * c[k] * h.powi(k as i32 - 1))
+        .sum()
+}
+
+/// Least squares polynomial of `degree` through `(height, range)` pairs
+fn fit(pairs: &[(f64, f64)], degree: usize) -> Option<Vec<f64>> {
+    let n = degree + 1;
+    let mut a = [[0.0; TERMS]; TERMS];
+    let mut b = [0.0; TERMS];
+    for &(h, r) in pairs {
+        for (i, (ai, bi)) in a[..n].iter_mut().zip(&mut b[..n]).enumerate() {
+            *bi += h.powi(i as i32) * r;
+            for (j, aij) in ai[..n].iter_mut().enumerate() {
+                *aij += h.powi((i + j) as i32);
+            }
+        }
+    }
+    // Terms above `degree` solve to zero
+    for (i, ai) in a.iter_mut().enumerate().skip(n) {
+        ai[i] = 1.0;
+    }
+    solve(a, b).map(|x| x[..n].to_vec())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let path = args.get(1).expect("usage: fit_range pairs.csv [degree]");
+    let degree: usize = args.get(2).map_or(1, |d| d.parse().expect("degree"));
+    if degree > MAX_DEGREE {
+        panic!("RangeModel holds polynomials up to degree {}", MAX_DEGREE);
+    }
+    let log = std::fs::read_to_string(path).expect("can't read pairs");
+    let pairs: Vec<(f64, f64)> = log
+        .lines()
+        .filter(|l| !l.trim().starts_with('#'))
+        .filter_map(|l| {
+            let mut fields = l.split(',').map(|f| f.trim().parse::<f64>());
+            match (fields.next(), fields.next()) {
+                (Some(Ok(h)), Some(Ok(mm))) => Some((h, mm / 1000.0)),
+                _ => None,
+            }
+        })
+        .collect();
+    if pairs.len() <= degree {
+        panic!("need more than {} pairs, got {}", degree, pairs.len());
+    }
+    let c = fit(&pairs, degree).expect("heights don't span the model");
+
+    let residuals: Vec<f64> =
+        pairs.iter().map(|&(h, r)| r - eval(&c, h)).collect();
+    let rms = (residuals.iter().map(|e| e * e).sum::<f64>()
+        / residuals.len() as f64)
+        .sqrt();
+    let worst = residuals.iter().fold(0.0f64, |w, e| w.max(e.abs()));
+    let (lo, hi) = pairs
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(lo, hi), &(h, _)| {
+            (lo.min(h), hi.max(h))
+        });
+    // EKF relies on reading growing with height
+    for i in 0..=SLOPE_CHECKS {
+        let h = lo + (hi - lo) * i as f64 / SLOPE_CHECKS as f64;
+        if slope(&c, h) <= 0.0 {
+            println!("// warning: model is not increasing at {:.3} m", h);
+        }
+    }
+    println!(
+        "// {} pairs over {:.3}..{:.3} m, residual RMS {:.4} m, worst {:.4} m",
+        pairs.len(),
+        lo,
+        hi,
+        rms,
+        worst
+    );
+    let mut coeffs = [0.0f32; MAX_DEGREE + 1];
+    for (v, c) in coeffs.iter_mut().zip(c.iter()) {
+        *v = *c as f32;
+    }
+    println!("RangeModel::polynomial({:?})", coeffs);
+}
@@ -1,30 +1,362 @@
 mod altitude;
+#[path = "../cobs/capture.rs"]
+mod capture;
+#[allow(dead_code)]
+#[path = "../cobs/crc.rs"]
+mod crc;
+#[allow(dead_code)]
+#[path = "../cobs/decode.rs"]
+mod decode;
 use libm;
-use nalgebra as na;
 use rand;
 
 const LOOPSIZE: i32 = 5000;
+// Sample period, s
+const DT: f32 = 0.01;
+// Climbs to `HOVER` over `CLIMB` samples, then swings up and down by
+// `AMPLITUDE` and back, staying within reach of the sonar, m
+const HOVER: f32 = 3.5;
+const CLIMB: i32 = 200;
+const AMPLITUDE: f32 = 1.5;
+// Share of sonar readings that are late multipath echoes, near the far end
+// of the sonar range
+const ECHOES: f32 = 0.1;
+// Longest lag to look for, samples
+const MAX_LAG: usize = 500;
+// Samples to skip before measuring errors
+const SETTLE: usize = 500;
+// Samples to stay on the ground while filter finds the baseline
+const STILL: i32 = 300;
+// BMP280 standby time of 250 ms, in samples
+const BARO_EVERY: i32 = 25;
+// VL53L0X timing budget of 200 ms, in samples
+const RANGE_EVERY: i32 = 20;
+// VL53L0X reading without target, mm
+const NO_TARGET: u16 = 8190;
+// Replay against altitude logged on the target, m. The target times
+// predictions in cycles, the log only has milliseconds.
+const REPLAY_TOLERANCE: f32 = 0.05;
 
-//ground-truth AGL to sonar measurement, empirically determined:
-// see http://diydrones.com/profiles/blogs/altitude-hold-with-mb1242-sonar
-fn sonarfun(agl: f32) -> f32 {
-    0.933 * agl - 2.894
+/// Uniform noise in [-a, a)
+fn noise(a: f32) -> f32 {
+    (rand::random::<f32>() * 2. - 1.) * a
 }
 
-fn main() {
+/// Finds delay (in samples) of `estimate` against `truth` with the smallest
+/// RMS error, returns delay and that error
+fn lag(estimate: &[f32], truth: &[f32]) -> (usize, f32) {
+    (0..MAX_LAG)
+        .map(|l| {
+            let n = estimate.len() - l;
+            let se: f32 = (0..n)
+                .map(|i| estimate[i + l] - truth[i])
+                .map(|e| e * e)
+                .sum();
+            (l, libm::sqrtf(se / n as f32))
+        })
+        .fold((0, f32::MAX), |best, c| if c.1 < best.1 { c } else { best })
+}
+
+fn rms(estimate: &[f32], truth: &[f32]) -> f32 {
+    let se: f32 = estimate
+        .iter()
+        .zip(truth.iter())
+        .map(|(e, t)| (e - t) * (e - t))
+        .sum();
+    libm::sqrtf(se / truth.len() as f32)
+}
+
+/// The filter before velocity and accelerometer bias: altitude alone as a
+/// random walk, growing `q` per prediction, on a known baseline and
+/// without gating. Kept to compare against.
+struct SingleState {
+    ground: f32,
+    x: f32,
+    p: f32,
+    q: f32,
+    baro_noise: f32,
+    range_noise: f32,
+    range_model: altitude::RangeModel,
+    range_limits: (f32, f32),
+}
+
+impl SingleState {
+    fn new(baseline: f32, range_model: altitude::RangeModel) -> Self {
+        let ground = altitude::baro_to_asl(baseline);
+        SingleState {
+            ground,
+            x: ground,
+            p: 0.1,
+            q: 1e-4,
+            baro_noise: 16.0,
+            range_noise: 0.01,
+            range_model,
+            range_limits: (0.2, 7.65),
+        }
+    }
+
+    fn agl(&self) -> f32 {
+        self.x - self.ground
+    }
+
+    fn predict(&mut self) {
+        self.p += self.q;
+    }
+
+    fn update_baro(&mut self, pa: f32) {
+        let h = altitude::asl_to_baro(self.x);
+        self.fuse(pa, h, altitude::dbaro_dasl(self.x), self.baro_noise);
+    }
+
+    /// Same dropouts as `ASL_EKF::update_range`
+    fn update_range(&mut self, mm: u16) {
+        let range = mm as f32 / 1000.0;
+        let (min, max) = self.range_limits;
+        if mm >= NO_TARGET || range < min || range > max {
+            return;
+        }
+        let agl = self.agl();
+        let h = self.range_model.range(agl);
+        let slope = self.range_model.slope(agl);
+        self.fuse(range, h, slope, self.range_noise);
+    }
+
+    fn fuse(&mut self, z: f32, h: f32, dh: f32, r: f32) {
+        let k = self.p * dh / (dh * self.p * dh + r);
+        self.x += k * (z - h);
+        self.p *= 1.0 - k * dh;
+    }
+}
+
+/// Runs the filter over telemetry recorded by `altitude-fusion` and compares
+/// with altitude it estimated on the target, panics when they differ by
+/// more than `REPLAY_TOLERANCE`. Takes the COBS framed capture as is or text
+/// from `cobs/unframe`. `# params` and `# rezero` lines take effect after
+/// the next prediction, the target logs them after it too.
+fn replay(path: &str) {
+    let bytes = std::fs::read(path).expect("can't read log");
+    let (log, broken) = capture::text(&bytes);
     let mut ekf = altitude::ASL_EKF::new();
-    let baro_base = 97420.0;
-
-    for i in 0..LOOPSIZE {
-        //  Model up-and-down motion with a sine wave
-        let count = i as f32;
-        let sine = libm::sinf(count / (LOOPSIZE as f32) * 2.0 * 3.141592);
-        let baro = baro_base + sine * 20.;
-
-        // Add noise to simulated sonar at random intervals
-        let sonar: f32 = sonarfun(50. * (1. - sine))
-            + if rand::random::<f32>() > 0.9 { 50. } else { 0. };
-        let fused = ekf.step(na::Vector2::new(baro, sonar));
-        println!("Pressure: {}, height: {}, fused: {}", baro, sonar, fused[0]);
+    let mut params: Option<[f32; 5]> = None;
+    let mut rezero = false;
+    let mut last_ms = None;
+    let mut worst: f32 = 0.;
+    let mut samples = 0;
+    let mut skipped = 0;
+    for line in log.lines() {
+        let line = line.trim();
+        if line == "# rezero" {
+            rezero = true;
+            continue;
+        }
+        // pval,accel_noise,bias_noise,baro_noise,range_noise
+        if let Some(values) = line.strip_prefix("# params ") {
+            let v: Vec<f32> = values
+                .split(',')
+                .map(|v| v.parse().expect("bad params line"))
+                .collect();
+            params = Some(v.try_into().expect("bad params line"));
+            continue;
+        }
+        // ms,pressure,range_mm,agl,velocity; raw fields are empty when
+        // sensor had no reading
+        let fields: Vec<&str> = line.split(',').collect();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (ms, logged): (u32, f32) = match fields[..] {
+            [ms, _, _, agl, _] => match (ms.parse(), agl.parse()) {
+                (Ok(ms), Ok(agl)) => (ms, agl),
+                _ => {
+                    skipped += 1;
+                    continue;
+                }
+            },
+            _ => {
+                skipped += 1;
+                continue;
+            }
+        };
+        // Timestamps wrap around, a reset makes them go back
+        let dt = last_ms.map_or(0, |last| ms.wrapping_sub(last) as i32);
+        last_ms = Some(ms);
+        if dt > 0 {
+            ekf.predict_unmeasured(dt as f32 / 1000.);
+        }
+        if let Some(v) = params.take() {
+            ekf.set_initial_variance(v[0]);
+            ekf.set_process_noise(v[1], v[2]);
+            ekf.set_baro_noise(v[3]);
+            ekf.set_range_noise(v[4]);
+        }
+        if rezero {
+            rezero = false;
+            ekf.rezero(altitude::ZERO_SAMPLES);
+        }
+        if let Ok(p) = fields[1].parse() {
+            ekf.update_baro(p);
+        }
+        if let Ok(mm) = fields[2].parse() {
+            ekf.update_range(mm);
+        }
+        println!("{},{},{}", ms, ekf.agl(), logged);
+        worst = worst.max((ekf.agl() - logged).abs());
+        samples += 1;
+    }
+    println!("Replayed {} samples, worst difference {} m", samples, worst);
+    println!("Skipped {} lines, {} broken frames", skipped, broken);
+    println!("Baseline: {} Pa", ekf.baseline());
+    println!("Barometer: {:?}", ekf.baro_stats());
+    println!("Rangefinder: {:?}", ekf.range_stats());
+    assert!(samples > 0, "no samples in {}", path);
+    assert!(
+        worst <= REPLAY_TOLERANCE,
+        "replay differs from the target by {} m",
+        worst
+    );
+}
+
+fn simulate(baro_base: f32) {
+    let mut ekf = altitude::ASL_EKF::new();
+    let mut ungated = altitude::ASL_EKF::new();
+    ungated.set_gate(None);
+    // Simulate MB1242 sonar
+    let sonar_model = altitude::RangeModel::mb1242();
+    let mut single = SingleState::new(baro_base, sonar_model);
+    for f in [&mut ekf, &mut ungated] {
+        f.set_range_model(sonar_model);
+        f.set_range_limits(0.2, 7.65);
+        // Sonar is good to a few centimeters when it hears the ground
+        f.set_range_noise(0.01);
+        // Accelerometer and barometer noise the simulation adds below
+        f.set_initial_variance(0.1);
+        f.set_process_noise(0.5, 1e-3);
+        f.set_baro_noise(16.0);
+    }
+    let ground = altitude::baro_to_asl(baro_base);
+    let omega = 2.0 * 3.141592 / (LOOPSIZE as f32 * DT);
+
+    let mut truth = Vec::new();
+    let mut fused_agl = Vec::new();
+    let mut ungated_agl = Vec::new();
+    let mut single_agl = Vec::new();
+    let mut baro_agl = Vec::new();
+    let mut baro = altitude::asl_to_baro(ground);
+    let climb_omega = 3.141592 / (CLIMB as f32 * DT);
+    for i in 0..STILL + CLIMB + LOOPSIZE {
+        // Half a cosine wave up from the ground, then up-and-down motion
+        // with a full one; `accel` is the second derivative of `agl`
+        let (agl, accel) = if i < STILL {
+            (0., 0.)
+        } else if i < STILL + CLIMB {
+            let cosine = libm::cosf(climb_omega * (i - STILL) as f32 * DT);
+            let half = HOVER / 2.;
+            (
+                half * (1. - cosine),
+                half * climb_omega * climb_omega * cosine,
+            )
+        } else {
+            let t = (i - STILL - CLIMB) as f32 * DT;
+            let cosine = libm::cosf(omega * t);
+            (
+                HOVER + AMPLITUDE * (1. - cosine),
+                AMPLITUDE * omega * omega * cosine,
+            )
+        };
+        if i == STILL {
+            assert!(!ekf.is_zeroing(), "still zeroing when the climb starts");
+        }
+        let accel = accel + noise(0.5);
+        let mut fused = ekf.predict(DT, accel);
+        ungated.predict(DT, accel);
+        single.predict();
+
+        if i % BARO_EVERY == 0 {
+            baro = altitude::asl_to_baro(ground + agl) + noise(4.);
+            fused = ekf.update_baro(baro);
+            ungated.update_baro(baro);
+            single.update_baro(baro);
+        }
+
+        if i % RANGE_EVERY == 0 {
+            // Echoes at random intervals, still within sonar range
+            let sonar = if rand::random::<f32>() < ECHOES {
+                6. + rand::random::<f32>() * 1.6
+            } else {
+                sonar_model.range(agl)
+            };
+            // No target beyond the sensor range and at random
+            let mm = if sonar < 0. || sonar * 1000. >= NO_TARGET as f32 {
+                NO_TARGET
+            } else if rand::random::<f32>() > 0.95 {
+                NO_TARGET
+            } else {
+                (sonar * 1000.) as u16
+            };
+            fused = ekf.update_range(mm);
+            ungated.update_range(mm);
+            single.update_range(mm);
+            println!("Range: {} mm, truth: {}", mm, agl);
+        }
+
+        println!(
+            "Pressure: {}, truth: {}, fused: {}, velocity: {}",
+            baro,
+            agl,
+            ekf.agl(),
+            fused[1]
+        );
+        truth.push(agl);
+        fused_agl.push(ekf.agl());
+        ungated_agl.push(ungated.agl());
+        single_agl.push(single.agl());
+        baro_agl.push(altitude::baro_to_asl(baro) - ground);
+    }
+
+    let truth = &truth[SETTLE..];
+    let fused_agl = &fused_agl[SETTLE..];
+    let ungated_agl = &ungated_agl[SETTLE..];
+    let single_agl = &single_agl[SETTLE..];
+    let baro_agl = &baro_agl[SETTLE..];
+    let (fused_lag, _) = lag(fused_agl, truth);
+    let (single_lag, _) = lag(single_agl, truth);
+    let (baro_lag, _) = lag(baro_agl, truth);
+    println!(
+        "Fused: RMS error {} m, lag {} ms",
+        rms(fused_agl, truth),
+        fused_lag as f32 * DT * 1000.
+    );
+    println!(
+        "Fused without gating: RMS error {} m",
+        rms(ungated_agl, truth)
+    );
+    println!(
+        "Single state: RMS error {} m, lag {} ms",
+        rms(single_agl, truth),
+        single_lag as f32 * DT * 1000.
+    );
+    println!(
+        "Barometer only: RMS error {} m, lag {} ms",
+        rms(baro_agl, truth),
+        baro_lag as f32 * DT * 1000.
+    );
+    println!(
+        "Baseline: {} Pa, estimated {} Pa",
+        baro_base,
+        ekf.baseline()
+    );
+    println!("Accelerometer bias: {} m/s^2", ekf.state()[2]);
+    println!("Barometer: {:?}", ekf.baro_stats());
+    println!("Rangefinder: {:?}", ekf.range_stats());
+}
+
+/// Simulates flight over the ground at given pressure, `altitude [Pa]`, or
+/// replays recorded telemetry, `altitude replay <log>`
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(|a| a.as_str()) {
+        Some("replay") => replay(args.get(2).expect("log file")),
+        Some(base) => simulate(base.parse().expect("baseline pressure in Pa")),
+        None => simulate(97420.0),
     }
 }
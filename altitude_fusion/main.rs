@@ -0,0 +1,394 @@
+#![deny(warnings)]
+#![no_std]
+#![no_main]
+#![feature(core_intrinsics)]
+
+use core::fmt::Write;
+use core::intrinsics;
+use core::panic::PanicInfo;
+
+use cortex_m_rt::{entry, exception, ExceptionFrame};
+use hal::pac::interrupt;
+use hal::prelude::*;
+use hal::serial;
+use hal::time::Bps;
+use heapless::spsc::{Producer, Queue};
+
+use bmp280::{self, BMP280};
+use shared_bus::CortexMBusManager as SharedBus;
+
+#[path = "../altitude/altitude.rs"]
+#[allow(dead_code)]
+mod altitude;
+
+#[path = "../cobs/mod.rs"]
+mod cobs;
+use cobs::FrameWriter;
+#[path = "../mavlink/mod.rs"]
+mod mavlink;
+use mavlink::MAV_STATE_ACTIVE;
+use mavlink::{DistanceSensor, Heartbeat, Interval, Link, ScaledPressure};
+use mavlink::{MAV_DISTANCE_SENSOR_LASER, MAV_SENSOR_ROTATION_PITCH_270};
+#[path = "../shell/mod.rs"]
+mod shell;
+use shell::{Args, Command, Error, Registry, Shell, Spec, Stored};
+#[path = "../storage/mod.rs"]
+mod storage;
+use storage::stm32::InternalFlash;
+use storage::{records, Store};
+
+static mut L: Option<FrameWriter<hal::serial::Tx<hal::pac::USART1>>> = None;
+static mut RX: Option<hal::serial::Rx<hal::pac::USART1>> = None;
+static mut QUIET: bool = false;
+static mut REZERO: bool = false;
+// Bytes from the RX interrupt for the shell in the main loop
+static mut INPUT: Queue<u8, 64> = Queue::new();
+static mut P: Option<Producer<'static, u8, 64>> = None;
+static COMMANDS: [Command<Tuning>; 4] = [
+    Command {
+        name: "quiet",
+        usage: "",
+        help: "toggle verbosity",
+        run: quiet,
+    },
+    Command {
+        name: "zero",
+        usage: "",
+        help: "re-zero altitude, keep still for 2 s",
+        run: zero,
+    },
+    Tuning::SAVE,
+    Tuning::DEFAULTS,
+];
+// Filter tuning, `save` keeps it in flash under ALTITUDE
+const PVAL: usize = 0;
+const ACCEL_NOISE: usize = 1;
+const BIAS_NOISE: usize = 2;
+const BARO_NOISE: usize = 3;
+const RANGE_NOISE: usize = 4;
+const MAVLINK: usize = 5;
+static PARAMS: [Spec; 6] = [
+    Spec::f32("pval", 0.1, 1e-6, 1e3),
+    Spec::f32("accel_noise", 0.5, 1e-6, 100.0),
+    Spec::f32("bias_noise", 1e-3, 1e-9, 10.0),
+    Spec::f32("baro_noise", 16.0, 1e-3, 1e4),
+    Spec::f32("range_noise", 0.5, 1e-6, 100.0),
+    Spec::bool("mavlink", false),
+];
+
+const SYSCLK_HZ: u32 = 64_000_000;
+// Pressure at 4 Hz, range at 5 Hz and a heartbeat take 255 of 11520
+// bytes/s in MAVLink mode
+const BAUD: u32 = 115200;
+// BMP280 standby time, ms
+const BARO_PERIOD_MS: u32 = 250;
+// VL53L0X timing budget, us
+const RANGE_BUDGET_US: u32 = 200_000;
+// VL53L0X reach, cm
+const RANGE_MIN_CM: u16 = 3;
+const RANGE_MAX_CM: u16 = 200;
+// Rangefinder reading vs height, paste `altitude/fit_range.rs` output here
+const RANGE_MODEL: altitude::RangeModel = altitude::RangeModel::identity();
+
+#[entry]
+fn main() -> ! {
+    let device = hal::pac::Peripherals::take().unwrap();
+    let mut core = cortex_m::Peripherals::take().unwrap();
+    let mut rcc = device.RCC.constrain();
+    let mut flash = device.FLASH.constrain();
+    let clocks = rcc
+        .cfgr
+        .sysclk(SYSCLK_HZ.hz())
+        .pclk1(32.mhz())
+        .pclk2(32.mhz())
+        .freeze(&mut flash.acr);
+    let gpioa = device.GPIOA.split(&mut rcc.ahb);
+    let gpiob = device.GPIOB.split(&mut rcc.ahb);
+    let store = Store::new(unsafe { InternalFlash::new() }).ok();
+    let mut params = Registry::new(&PARAMS);
+    let stored = store.as_ref().and_then(|s| s.read_f32s(records::ALTITUDE));
+    let mut serial =
+        device
+            .USART1
+            .serial((gpioa.pa9, gpioa.pa10), Bps(BAUD), clocks);
+    let ser_int = serial.get_interrupt();
+    serial.listen(serial::Event::Rxne);
+    let (tx, rx) = serial.split();
+    unsafe {
+        L = Some(FrameWriter::start(tx).unwrap());
+        RX = Some(rx);
+    };
+    let (p, mut input) = unsafe { INPUT.split() };
+    unsafe { P = Some(p) };
+    let l = unsafe { extract(&mut L) };
+    write!(l, "\r\nAltitude fusion\r\n").unwrap();
+    if let Some(v) = stored {
+        let rejected = params.load_f32s(&v);
+        write!(l, "params loaded, {} rejected\r\n", rejected).unwrap();
+    }
+    let mut tuning =
+        Tuning::new(params, store, |s, v| s.write_f32s(records::ALTITUDE, v));
+    // I2C
+    let i2c = device.I2C1.i2c((gpiob.pb6, gpiob.pb7), 400.khz(), clocks);
+    let bus = SharedBus::new(i2c);
+    write!(l, "i2c shared\r\n").unwrap();
+    // bmp
+    let mut bmp = BMP280::new(bus.acquire()).expect("bmp error");
+    bmp.reset();
+    bmp.set_config(bmp280::Config {
+        t_sb: bmp280::Standby::ms250,
+        filter: bmp280::Filter::c8,
+    });
+    bmp.set_control(bmp280::Control {
+        osrs_t: bmp280::Oversampling::x1,
+        osrs_p: bmp280::Oversampling::x4,
+        mode: bmp280::PowerMode::Normal,
+    });
+    write!(l, "bmp ok\r\n").unwrap();
+    // vl53l0x
+    let mut tof = vl53l0x::VL53L0x::new(bus.acquire()).expect("vl");
+    tof.set_measurement_timing_budget(RANGE_BUDGET_US)
+        .expect("timbudg");
+    tof.start_continuous(0).expect("start cont");
+    write!(l, "vl53l0x ok\r\n").unwrap();
+    // timing
+    core.DCB.enable_trace();
+    core.DWT.enable_cycle_counter();
+    // done
+    unsafe { cortex_m::interrupt::enable() };
+    unsafe { cortex_m::peripheral::NVIC::unmask(ser_int) };
+
+    let mut ekf = altitude::ASL_EKF::new();
+    ekf.set_range_model(RANGE_MODEL);
+    let mut applied = None;
+    write!(
+        l,
+        "All ok; `quiet` toggles verbosity, `zero` re-zeroes!\r\n"
+    )
+    .unwrap();
+    // Without an accelerometer only the user knows it stays still
+    write!(l, "Keep still for 2 s after start and `zero`\r\n").unwrap();
+    write!(
+        l,
+        "`set mavlink on` to switch to MAVLink, `help` for more\r\n"
+    )
+    .unwrap();
+    write!(l, "# ms,pressure,range_mm,agl,velocity\r\n").unwrap();
+    let ticks_per_ms = SYSCLK_HZ / 1000;
+    let mut last = cortex_m::peripheral::DWT::get_cycle_count();
+    let mut ticks: u32 = 0;
+    let mut now_ms: u32 = 0;
+    let mut last_baro_ms: u32 = 0;
+    let mut shell = Shell::<Tuning, 64>::new(&COMMANDS);
+    let mut link = Link::new(1, 1, BAUD, now_ms);
+    let mut heartbeat = Interval::new(1000, now_ms);
+    loop {
+        shell.drain(|| input.dequeue(), &mut tuning, l).unwrap();
+        // Blocks for up to the timing budget
+        let range = tof.read_range_continuous_millimeters();
+        let cycles = cortex_m::peripheral::DWT::get_cycle_count();
+        let elapsed = cycles.wrapping_sub(last);
+        last = cycles;
+        ticks += elapsed;
+        now_ms += ticks / ticks_per_ms;
+        ticks %= ticks_per_ms;
+        // No accelerometer on the bus, motion goes into process noise
+        ekf.predict_unmeasured(elapsed as f32 / SYSCLK_HZ as f32);
+
+        let generation = tuning.params.generation();
+        if applied != Some(generation) {
+            applied = Some(generation);
+            let v = tuning.params.to_f32s();
+            ekf.set_initial_variance(v[PVAL]);
+            ekf.set_process_noise(v[ACCEL_NOISE], v[BIAS_NOISE]);
+            ekf.set_baro_noise(v[BARO_NOISE]);
+            ekf.set_range_noise(v[RANGE_NOISE]);
+            // `altitude` replays logs with the same tuning
+            write!(
+                l,
+                "# params {},{},{},{},{}\r\n",
+                v[PVAL],
+                v[ACCEL_NOISE],
+                v[BIAS_NOISE],
+                v[BARO_NOISE],
+                v[RANGE_NOISE]
+            )
+            .unwrap();
+        }
+
+        if unsafe { REZERO } {
+            unsafe { REZERO = false };
+            ekf.rezero(altitude::ZERO_SAMPLES);
+            write!(l, "# rezero\r\n").unwrap();
+        }
+
+        let baro_due = now_ms.wrapping_sub(last_baro_ms) >= BARO_PERIOD_MS;
+        let pressure = if baro_due {
+            last_baro_ms = now_ms;
+            let p = bmp.pressure() as f32;
+            ekf.update_baro(p);
+            Some(p)
+        } else {
+            None
+        };
+        let range = match range {
+            Ok(mm) => {
+                ekf.update_range(mm);
+                Some(mm)
+            }
+            Err(e) => {
+                write!(l, "# range error: {:?}\r\n", e).unwrap();
+                None
+            }
+        };
+
+        if tuning.params.bool(MAVLINK) {
+            let mut write = |f: &[u8]| l.write_raw(f);
+            if heartbeat.due(now_ms) {
+                let hb = Heartbeat::quadrotor(MAV_STATE_ACTIVE);
+                link.send(now_ms, &hb, &mut write).unwrap();
+            }
+            if let Some(p) = pressure {
+                let sp = ScaledPressure {
+                    time_boot_ms: now_ms,
+                    press_abs: p / 100.,
+                    press_diff: 0.,
+                    temperature: (bmp.temp() * 100.) as i16,
+                };
+                link.send(now_ms, &sp, &mut write).unwrap();
+            }
+            if let Some(mm) = range {
+                let ds = distance_sensor(now_ms, mm);
+                link.send(now_ms, &ds, &mut write).unwrap();
+            }
+        } else if unsafe { !QUIET } {
+            write!(l, "{},", now_ms).unwrap();
+            if let Some(p) = pressure {
+                write!(l, "{}", p).unwrap();
+            }
+            write!(l, ",").unwrap();
+            if let Some(mm) = range {
+                write!(l, "{}", mm).unwrap();
+            }
+            write!(l, ",{},{}\r\n", ekf.agl(), ekf.state()[1]).unwrap();
+        }
+    }
+}
+
+/// Rangefinder looks down, out of reach reads as `max_distance + 1`
+fn distance_sensor(now_ms: u32, mm: u16) -> DistanceSensor {
+    let cm = mm / 10;
+    DistanceSensor {
+        time_boot_ms: now_ms,
+        min_distance: RANGE_MIN_CM,
+        max_distance: RANGE_MAX_CM,
+        current_distance: cm.min(RANGE_MAX_CM + 1),
+        sensor_type: MAV_DISTANCE_SENSOR_LASER,
+        id: 0,
+        orientation: MAV_SENSOR_ROTATION_PITCH_270,
+        covariance: 255,
+    }
+}
+
+unsafe fn extract<T>(opt: &'static mut Option<T>) -> &'static mut T {
+    match opt {
+        Some(ref mut x) => &mut *x,
+        None => panic!("extract"),
+    }
+}
+
+#[interrupt]
+fn USART1_EXTI25() {
+    let rx = unsafe { extract(&mut RX) };
+    let p = unsafe { extract(&mut P) };
+    match rx.read() {
+        Ok(b) => {
+            let _ = p.enqueue(b);
+        }
+        Err(nb::Error::WouldBlock) => {}
+        Err(nb::Error::Other(e)) => match e {
+            serial::Error::Overrun => {
+                rx.clear_overrun_error();
+            }
+            serial::Error::Framing => {
+                rx.clear_framing_error();
+            }
+            serial::Error::Noise => {
+                rx.clear_noise_error();
+            }
+            _ => {}
+        },
+    };
+}
+
+/// Shell context, parameters and the store `save` writes to
+type Tuning = Stored<Store<InternalFlash>, storage::Error, 6>;
+
+fn quiet(
+    _: &mut Tuning,
+    args: &mut Args,
+    _: &mut dyn Write,
+) -> Result<(), Error> {
+    args.end()?;
+    unsafe { QUIET = !QUIET };
+    Ok(())
+}
+
+fn zero(
+    _: &mut Tuning,
+    args: &mut Args,
+    _: &mut dyn Write,
+) -> Result<(), Error> {
+    args.end()?;
+    unsafe { REZERO = true };
+    Ok(())
+}
+
+#[exception]
+fn HardFault(ef: &ExceptionFrame) -> ! {
+    panic!("HardFault at {:#?}", ef);
+}
+
+#[exception]
+fn DefaultHandler(irqn: i16) {
+    panic!("Unhandled exception (IRQn = {})", irqn);
+}
+
+#[panic_handler]
+fn panic(panic_info: &PanicInfo) -> ! {
+    match unsafe { &mut L } {
+        Some(ref mut l) => {
+            let payload = panic_info.payload().downcast_ref::<&str>();
+            match (panic_info.location(), payload) {
+                (Some(location), Some(msg)) => {
+                    write!(
+                        l,
+                        "\r\npanic in file '{}' at line {}: {:?}\r\n",
+                        location.file(),
+                        location.line(),
+                        msg
+                    )
+                    .unwrap();
+                }
+                (Some(location), None) => {
+                    write!(
+                        l,
+                        "panic in file '{}' at line {}",
+                        location.file(),
+                        location.line()
+                    )
+                    .unwrap();
+                }
+                (None, Some(msg)) => {
+                    write!(l, "panic: {:?}", msg).unwrap();
+                }
+                (None, None) => {
+                    write!(l, "panic occured, no info available").unwrap();
+                }
+            }
+            let _ = l.end_frame();
+        }
+        None => {}
+    }
+    unsafe { intrinsics::abort() }
+}
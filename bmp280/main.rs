@@ -13,6 +13,10 @@ use hal::prelude::*;
 use hal::serial;
 use hal::time::Bps;
 
+#[path = "../cobs/mod.rs"]
+mod cobs;
+use cobs::FrameWriter;
+
 #[entry]
 fn main() -> ! {
     let device = hal::pac::Peripherals::take().unwrap();
@@ -32,10 +36,8 @@ fn main() -> ! {
             .USART1
             .serial((gpioa.pa9, gpioa.pa10), Bps(115200), clocks);
     serial.listen(serial::Event::Rxne);
-    let (mut tx, mut rx) = serial.split();
-    // COBS frame
-    tx.write(0x00).unwrap();
-    let mut l = tx;
+    let (tx, mut rx) = serial.split();
+    let mut l = FrameWriter::start(tx).unwrap();
     write!(l, "\r\nBMP280 demo\r\n").unwrap();
 
     // i2c
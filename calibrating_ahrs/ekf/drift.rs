@@ -0,0 +1,134 @@
+// Runs f64 and f32 filters side by side on the same simulated flight and
+// reports how far they drift apart:
+//     rustc -O drift.rs && ./drift
+
+mod double {
+    type Float = f64;
+    include!("generated.rs");
+    include!("quat_ekf.rs");
+}
+
+mod single {
+    // libm on the target, std on the host
+    mod libm {
+        pub fn sqrtf(x: f32) -> f32 {
+            x.sqrt()
+        }
+        pub fn fabsf(x: f32) -> f32 {
+            x.abs()
+        }
+        #[allow(unused)]
+        pub fn powf(x: f32, y: f32) -> f32 {
+            x.powf(y)
+        }
+    }
+    type Float = f32;
+    include!("generated_f32.rs");
+    include!("quat_ekf.rs");
+}
+
+const STEPS: usize = 200_000;
+const REPORT_EVERY: usize = 20_000;
+const DT: f64 = 0.02;
+const MAX_QUAT_DIFF: f64 = 1e-4;
+const MAX_BIAS_DIFF: f64 = 1e-4;
+const RETUNE_P: f64 = 0.05;
+const RETUNE_Q: f64 = 0.0005;
+const RETUNE_R: f64 = 0.2;
+
+/// Hamilton product
+fn mul(a: [f64; 4], b: [f64; 4]) -> [f64; 4] {
+    [
+        a[0] * b[0] - a[1] * b[1] - a[2] * b[2] - a[3] * b[3],
+        a[0] * b[1] + a[1] * b[0] + a[2] * b[3] - a[3] * b[2],
+        a[0] * b[2] - a[1] * b[3] + a[2] * b[0] + a[3] * b[1],
+        a[0] * b[3] + a[1] * b[2] - a[2] * b[1] + a[3] * b[0],
+    ]
+}
+
+fn conj(q: [f64; 4]) -> [f64; 4] {
+    [q[0], -q[1], -q[2], -q[3]]
+}
+
+/// Rotates earth frame vector `v` to the body frame of `q`
+fn to_body(q: [f64; 4], v: [f64; 3]) -> [f64; 3] {
+    let r = mul(mul(conj(q), [0.0, v[0], v[1], v[2]]), q);
+    [r[1], r[2], r[3]]
+}
+
+/// Angular difference between two attitudes, rad
+fn angle(a: [f64; 4], b: [f64; 4]) -> f64 {
+    let d = mul(conj(a), b);
+    let v = (d[1] * d[1] + d[2] * d[2] + d[3] * d[3]).sqrt();
+    2.0 * v.atan2(d[0].abs())
+}
+
+fn main() {
+    let mut ekf64 = double::QuatEkf::new();
+    let mut ekf32 = single::QuatEkf::new();
+    let mut truth = [1.0, 0.0, 0.0, 0.0];
+    let bias = [0.01, -0.02, 0.005];
+    let gravity = [0.0, 0.0, 9.81];
+    let field = [20.0, 0.0, 40.0];
+
+    let mut max_quat: f64 = 0.0;
+    let mut max_bias: f64 = 0.0;
+    let mut max_cov: f64 = 0.0;
+    for i in 0..STEPS {
+        let t = i as f64 * DT;
+        let w = [
+            0.3 * (0.01 * t).sin(),
+            0.2 * (0.013 * t).cos(),
+            0.1 * (0.007 * t).sin(),
+        ];
+        let half = [0.0, w[0] * DT / 2.0, w[1] * DT / 2.0, w[2] * DT / 2.0];
+        let dq = [1.0, half[1], half[2], half[3]];
+        truth = mul(truth, dq);
+        let n = truth.iter().map(|v| v * v).sum::<f64>().sqrt();
+        truth.iter_mut().for_each(|v| *v /= n);
+
+        // Retune half way, as the serial parameter registry does
+        if i == STEPS / 2 {
+            ekf64.set_noise(RETUNE_Q, RETUNE_R);
+            ekf64.reset_covariance(RETUNE_P);
+            ekf32.set_noise(RETUNE_Q as f32, RETUNE_R as f32);
+            ekf32.reset_covariance(RETUNE_P as f32);
+        }
+
+        let gyro = [w[0] + bias[0], w[1] + bias[1], w[2] + bias[2]];
+        let accel = to_body(truth, gravity);
+        let mag = to_body(truth, field);
+
+        ekf64.predict(gyro, DT);
+        ekf64.update(accel, mag);
+        let f = |v: [f64; 3]| [v[0] as f32, v[1] as f32, v[2] as f32];
+        ekf32.predict(f(gyro), DT as f32);
+        ekf32.update(f(accel), f(mag));
+
+        let q32 = ekf32.quat().map(|v| v as f64);
+        max_quat = max_quat.max(angle(ekf64.quat(), q32));
+        for (a, b) in ekf64.bias().iter().zip(ekf32.bias().iter()) {
+            max_bias = max_bias.max((a - *b as f64).abs());
+        }
+        for (a, b) in ekf64.p.iter().zip(ekf32.p.iter()) {
+            max_cov = max_cov.max((a - *b as f64).abs());
+        }
+
+        if (i + 1) % REPORT_EVERY == 0 {
+            println!(
+                "{:>7} steps: attitude {:.3e} rad, bias {:.3e} rad/s, \
+                 P {:.3e}, f64 error {:.3e} rad",
+                i + 1,
+                max_quat,
+                max_bias,
+                max_cov,
+                angle(ekf64.quat(), truth)
+            );
+        }
+    }
+
+    if max_quat > MAX_QUAT_DIFF || max_bias > MAX_BIAS_DIFF {
+        panic!("f32 drifted away from f64");
+    }
+    println!("drift ok");
+}
@@ -1,6 +1,16 @@
 from sympy import *
+from sympy.printing.precedence import precedence
+from sympy.printing.rust import RustCodePrinter
+import re
+import sys
+# Generates generated.rs (f64, std) and generated_f32.rs (f32, no_std, libm):
+#   python3 gen_ekf.py > generated.rs && rustfmt generated.rs
+#   python3 gen_ekf.py f32 > generated_f32.rs && rustfmt generated_f32.rs
+
 # Our state is [q, b], where q is quatenion and b are biases
 state_len = 7
+# Observation is [accel, mag]
+obs_len = 6
 x = IndexedBase('x', shape=(state_len,))
 # Without an input our state changed only by biases
 # So we repeat our quat
@@ -19,26 +29,16 @@ def q2m(q):
 def i2l(i, start, stop):
     return [i[j] for j in range(start, stop)]
 
-# Convert indexed base to Matrix
-def i2m(i):
-    s = i.shape
-    assert len(s) == 2, "Works only for 2D case"
-    m = []
-    for r in range(s[0]):
-        tr = []
-        for c in range(s[1]):
-            tr.append(i[r, c])
-        m.append(tr)
-    return Matrix(m)
+# Convert flat indexed base to rows x cols Matrix (row-major)
+def f2m(i, rows, cols):
+    return Matrix(rows, cols, lambda r, c: i[r * cols + c])
 
 # no interaction between q and b
 Z3x4 = zeros(3, 4)
 # we assume constant angular speed between measurements
-dt = symbols("d_t")
+dt = symbols("dT")
 # Estimated quaternion
 q = Quaternion(*i2l(x, 0, 4))
-# Estimated bias
-b = Matrix(i2l(x, 4, 6))
 # State transition matrix
 A = Matrix(BlockMatrix([[I4, (-dt / 2.0) * q2m(q)], [Z3x4, I3]]))
 # Measured angular velocity control our attitude
@@ -52,28 +52,25 @@ B = BlockMatrix([[q2m(q)], [Z3x3]])
 x_m = Matrix(i2l(x, 0, state_len))
 # Next state
 nx = A * x_m + (dt / 2.0) * Matrix(B) * w_m
+# Transition Jacobian, A alone misses angular velocity and the bias terms
+# it brings. Quaternion normalization below is left out of it.
+F_m = nx.jacobian(x_m)
 
-# Output our state transition equation
-output = MatrixSymbol('nx', state_len, 1)
 # Normalize quaternion to account for the limited precision
 def norm_q(q):
     mag = (q[0]**2 + q[1]**2 + q[2]**2 + q[3]**2)**0.5
     return [q[i] / mag for i in range(4)]
 
 nx[0:4, :] = norm_q(nx[0:4])
-# print("// State transition")
-# print(rust_code(nx, assign_to=output, contract=False))
 
-# We keep it symbolical for code generation, initialize to I7
-P = IndexedBase('P', shape=(state_len, state_len))
-P_m = i2m(P)
-Q = IndexedBase('Q', shape=(state_len, state_len))
-Q_m = i2m(Q)
+# Covariance is kept symbolical and flat for code generation
+P = IndexedBase('P', shape=(state_len * state_len,))
+P_m = f2m(P, state_len, state_len)
+Q = IndexedBase('Q', shape=(state_len * state_len,))
+Q_m = f2m(Q, state_len, state_len)
 
-nP = A * P * A.transpose() + Q
-output = MatrixSymbol('np', state_len, state_len)
-# print("// Error transition")
-# print(ccode(nP, assign_to=output, contract=False))
+# Error transition
+nP = F_m * P_m * F_m.transpose() + Q_m
 
 # Get homogeneous rotation matrix
 def q2hrm(q):
@@ -91,20 +88,298 @@ def q2hrm(q):
 def gen_jac(q, r):
     return (q2hrm(q) * Matrix(i2l(r, 0, 3))).jacobian(Matrix([q.a, q.b, q.c, q.d]))
 
-# r = IndexedBase('r', shape=(3,))
-# output = MatrixSymbol('j', 3, 4)
-# print(ccode(gen_jac(q, r), assign_to=output, contract=False))
-
 # Now we have everything to predict accelerometer and magnetometer values
-ar = Matrix(i2l(IndexedBase('a_r', shape=(3,)), 0, 3))
-mr = Matrix(i2l(IndexedBase('m_r', shape=(3,)), 0, 3))
+a_r = IndexedBase('a_r', shape=(3,))
+m_r = IndexedBase('m_r', shape=(3,))
+ar = Matrix(i2l(a_r, 0, 3))
+mr = Matrix(i2l(m_r, 0, 3))
 C_a = gen_jac(q, ar)
 C_m = gen_jac(q, mr)
-C = BlockMatrix([[C_a, Z3x3], [C_m, Z3x3]])
-output = MatrixSymbol('C', 6, 7)
 # Convertion matrix
-print(ccode(C, assign_to=output, contract=False))
+C = Matrix(BlockMatrix([[C_a, Z3x3], [C_m, Z3x3]]))
+# Expected observation
+y = Matrix(BlockMatrix([[q2hrm(q) * ar], [q2hrm(q) * mr]]))
+
+# Body to earth frame, used to build magnetic reference
+v = IndexedBase('v', shape=(3,))
+rv = q2hrm(q).transpose() * Matrix(i2l(v, 0, 3))
+
+
+# sympy's rust printer casts factors of float products and loses parentheses
+# around them, so print products and sums as plain code
+class Printer(RustCodePrinter):
+    def _print_Mul(self, expr):
+        return super(RustCodePrinter, self)._print_Mul(expr)
+
+    def _print_Add(self, expr, order=None):
+        return super(RustCodePrinter, self)._print_Add(expr, order)
+
+
+# core has no float powers and roots, so spell them out with libm
+class NoStdPrinter(Printer):
+    def _print_Pow(self, expr):
+        b = self.parenthesize(expr.base, precedence(expr))
+        e = expr.exp
+        if e.is_Integer and e > 0:
+            return "(" + " * ".join([b] * int(e)) + ")"
+        if e == 0.5:
+            return "libm::sqrtf(%s)" % self._print(expr.base)
+        if e == -0.5:
+            return "(1.0 / libm::sqrtf(%s))" % self._print(expr.base)
+        return "libm::powf(%s, %s)" % (self._print(expr.base), self._print(e))
+
+
+variants = {
+    "f64": {
+        "F": "f64",
+        "printer": Printer,
+        "sqrt": "f64::sqrt",
+        "abs": "f64::abs",
+        "eps": "1e-12",
+        "cse": False,
+    },
+    "f32": {
+        "F": "f32",
+        "printer": NoStdPrinter,
+        "sqrt": "libm::sqrtf",
+        "abs": "libm::fabsf",
+        "eps": "1e-6",
+        "cse": True,
+    },
+}
+name = sys.argv[1] if len(sys.argv) > 1 else "f64"
+variant = variants[name]
+F = variant["F"]
+
+
+# Rust won't multiply floats by integer literals, so turn those into floats,
+# leaving indices and powi arguments alone
+def code(e):
+    c = variant["printer"]({"contract": False}).doprint(e)
+    return re.sub(r"(?<![\w.\[])(?<!powi\()(\d+)(?![\w.\]])", r"\1.0", c)
+
+
+# Drops parentheses around the whole expression
+def unwrap(c):
+    if not (c.startswith("(") and c.endswith(")")):
+        return c
+    depth = 0
+    for i, ch in enumerate(c):
+        depth += {"(": 1, ")": -1}.get(ch, 0)
+        if depth == 0 and i < len(c) - 1:
+            return c
+    return c[1:-1]
+
+
+# Factors out common subexpressions of `exprs` if variant asks for it,
+# returns `let` statements and remaining expressions
+def factor(exprs):
+    if not variant["cse"]:
+        return "", exprs
+    temps, reduced = cse(exprs, symbols=numbered_symbols("t"))
+    lets = "".join("    let %s = %s;\n" % (t, unwrap(code(e))) for t, e in temps)
+    return lets, reduced
+
+
+def flat(name, m, exprs):
+    rows, cols = m.shape
+    lines = []
+    for r in range(rows):
+        for c in range(cols):
+            e = exprs[r * cols + c]
+            if e != 0:
+                lines.append("    %s[%d] = %s;" % (name, r * cols + c, code(e)))
+    return "\n".join(lines)
+
+
+def array(exprs):
+    return "[\n" + ",\n".join("        " + code(e) for e in exprs) + ",\n    ]"
+
+
+if name == "f64":
+    print("// Generated by gen_ekf.py, do not edit.")
+else:
+    print("// Generated by gen_ekf.py %s, do not edit." % name)
+print()
+lets, reduced = factor(list(nx) + list(nP))
+print("/// State transition: propagates state `x` and covariance `P` with")
+print("/// measured angular velocity `w` over `dT` seconds")
+print("#[allow(non_snake_case)]")
+print("fn predict(x: [{F}; 7], w: [{F}; 3], P: [{F}; 49], Q: [{F}; 49], "
+      "dT: {F}) -> ([{F}; 7], [{F}; 49]) {{".format(F=F))
+print(lets, end="")
+print("    let nx = %s;" % array(reduced[:state_len]))
+print("    let mut np: [%s; 49] = [0.0; 49];" % F)
+print(flat("np", nP, reduced[state_len:]))
+print("    (nx, np)")
+print("}")
+print()
+lets, reduced = factor(list(F_m))
+print("/// Transition Jacobian used by `predict` (7x7, row-major)")
+print("#[allow(non_snake_case, dead_code)]")
+print("fn transition(x: [{F}; 7], w: [{F}; 3], dT: {F}) -> [{F}; 49] {{"
+      .format(F=F))
+print(lets, end="")
+print("    let mut A: [%s; 49] = [0.0; 49];" % F)
+print(flat("A", F_m, reduced))
+print("    A")
+print("}")
+print()
+lets, reduced = factor(list(y) + list(C))
+print("/// Expected observation `y` and its Jacobian `C` (6x7, row-major)")
+print("/// for accelerometer reference `a_r` and magnetometer reference `m_r`")
+print("#[allow(non_snake_case)]")
+print("fn observe(x: [{F}; 7], a_r: [{F}; 3], m_r: [{F}; 3]) "
+      "-> ([{F}; 6], [{F}; 42]) {{".format(F=F))
+print(lets, end="")
+print("    let y = %s;" % array(reduced[:obs_len]))
+print("    let mut C: [%s; 42] = [0.0; 42];" % F)
+print(flat("C", C, reduced[obs_len:]))
+print("    (y, C)")
+print("}")
+print()
+lets, reduced = factor(list(rv))
+print("/// Rotates body frame vector `v` to the earth frame")
+print("fn to_earth(x: [{F}; 7], v: [{F}; 3]) -> [{F}; 3] {{".format(F=F))
+print(lets, end="")
+print("    %s" % array(reduced))
+print("}")
+print("""
+/// Inverts 6x6 row-major matrix with Gauss-Jordan elimination
+fn invert6(m: [{F}; 36]) -> Option<[{F}; 36]> {{
+    let mut a = m;
+    let mut inv = [0.0; 36];
+    for i in 0..6 {{
+        inv[i * 6 + i] = 1.0;
+    }}
+    for c in 0..6 {{
+        let mut pivot = c;
+        for r in c + 1..6 {{
+            if {abs}(a[r * 6 + c]) > {abs}(a[pivot * 6 + c]) {{
+                pivot = r;
+            }}
+        }}
+        if {abs}(a[pivot * 6 + c]) < {eps} {{
+            return None;
+        }}
+        for k in 0..6 {{
+            a.swap(c * 6 + k, pivot * 6 + k);
+            inv.swap(c * 6 + k, pivot * 6 + k);
+        }}
+        let d = a[c * 6 + c];
+        for k in 0..6 {{
+            a[c * 6 + k] /= d;
+            inv[c * 6 + k] /= d;
+        }}
+        for r in 0..6 {{
+            if r != c {{
+                let f = a[r * 6 + c];
+                for k in 0..6 {{
+                    a[r * 6 + k] -= f * a[c * 6 + k];
+                    inv[r * 6 + k] -= f * inv[c * 6 + k];
+                }}
+            }}
+        }}
+    }}
+    Some(inv)
+}}
+
+/// Measurement update with raw `accel` and `mag` samples and measurement
+/// noise `R` (6x6, row-major).
+///
+/// Both samples are normalized, so only their directions matter.
+/// Accelerometer reference is gravity (0, 0, 1), magnetometer reference is
+/// the measured field rotated to the earth frame with its horizontal part
+/// pointing north, so mag corrects only the heading.
+///
+/// Returns state and covariance unchanged if either sample is zero or
+/// innovation covariance is singular.
+#[allow(non_snake_case)]
+fn update(
+    x: [{F}; 7],
+    P: [{F}; 49],
+    accel: [{F}; 3],
+    mag: [{F}; 3],
+    R: [{F}; 36],
+) -> ([{F}; 7], [{F}; 49]) {{
+    let an = {sqrt}(
+        accel[0] * accel[0] + accel[1] * accel[1] + accel[2] * accel[2],
+    );
+    let mn = {sqrt}(mag[0] * mag[0] + mag[1] * mag[1] + mag[2] * mag[2]);
+    if an == 0.0 || mn == 0.0 {{
+        return (x, P);
+    }}
+    let z = [
+        accel[0] / an,
+        accel[1] / an,
+        accel[2] / an,
+        mag[0] / mn,
+        mag[1] / mn,
+        mag[2] / mn,
+    ];
+    let me = to_earth(x, [z[3], z[4], z[5]]);
+    let m_r = [{sqrt}(me[0] * me[0] + me[1] * me[1]), 0.0, me[2]];
+    let (y, C) = observe(x, [0.0, 0.0, 1.0], m_r);
+
+    // P * C^T, 7x6
+    let mut PCt = [0.0; 42];
+    for i in 0..7 {{
+        for j in 0..6 {{
+            for k in 0..7 {{
+                PCt[i * 6 + j] += P[i * 7 + k] * C[j * 7 + k];
+            }}
+        }}
+    }}
+    // Innovation covariance S = C * P * C^T + R, 6x6
+    let mut S = R;
+    for i in 0..6 {{
+        for j in 0..6 {{
+            for k in 0..7 {{
+                S[i * 6 + j] += C[i * 7 + k] * PCt[k * 6 + j];
+            }}
+        }}
+    }}
+    let Si = match invert6(S) {{
+        Some(si) => si,
+        None => return (x, P),
+    }};
+    // Kalman gain K = P * C^T * S^-1, 7x6
+    let mut K = [0.0; 42];
+    for i in 0..7 {{
+        for j in 0..6 {{
+            for k in 0..6 {{
+                K[i * 6 + j] += PCt[i * 6 + k] * Si[k * 6 + j];
+            }}
+        }}
+    }}
+
+    let mut nx = x;
+    for i in 0..7 {{
+        for j in 0..6 {{
+            nx[i] += K[i * 6 + j] * (z[j] - y[j]);
+        }}
+    }}
+    let qn = {sqrt}(nx[0] * nx[0] + nx[1] * nx[1] + nx[2] * nx[2] + nx[3] * nx[3]);
+    for v in nx.iter_mut().take(4) {{
+        *v /= qn;
+    }}
 
-y = BlockMatrix([[q2hrm(q) * ar], [q2hrm(q) * mr]])
-output = MatrixSymbol('y', 6, 1)
-print(rust_code(y, assign_to=output, contract=False))
+    // P = (I - K * C) * P
+    let mut KC = [0.0; 49];
+    for i in 0..7 {{
+        for j in 0..7 {{
+            for k in 0..6 {{
+                KC[i * 7 + j] += K[i * 6 + k] * C[k * 7 + j];
+            }}
+        }}
+    }}
+    let mut np = P;
+    for i in 0..7 {{
+        for j in 0..7 {{
+            for k in 0..7 {{
+                np[i * 7 + j] -= KC[i * 7 + k] * P[k * 7 + j];
+            }}
+        }}
+    }}
+    (nx, np)
+}}""".format(**variant))
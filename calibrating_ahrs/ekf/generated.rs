@@ -1,5 +1,15 @@
-fn predict(x: [f64; 7], w: [f64; 3], P: [f64; 49], Q: [f64; 49], dT: f64) -> ([f64; 7], [f64; 49]) {
-    let d_t = dT;
+// Generated by gen_ekf.py, do not edit.
+
+/// State transition: propagates state `x` and covariance `P` with
+/// measured angular velocity `w` over `dT` seconds
+#[allow(non_snake_case)]
+fn predict(
+    x: [f64; 7],
+    w: [f64; 3],
+    P: [f64; 49],
+    Q: [f64; 49],
+    dT: f64,
+) -> ([f64; 7], [f64; 49]) {
     let nx = [
         ((0.5 * dT * w[0] * x[0] - 0.5 * dT * w[1] * x[3]
             + 0.5 * dT * w[2] * x[2]
@@ -159,512 +169,1135 @@ fn predict(x: [f64; 7], w: [f64; 3], P: [f64; 49], Q: [f64; 49], dT: f64) -> ([f
         x[5],
         x[6],
     ];
-    println!("{:?}", nx);
     let mut np: [f64; 49] = [0.0; 49];
     np[0] = 0.5
-        * d_t
-        * (0.5 * d_t * P[32] * x[1]
-            + 0.5 * d_t * P[39] * x[2]
-            + 0.5 * d_t * P[46] * x[3]
+        * dT
+        * (0.5 * dT * P[32] * x[1]
+            + 0.5 * dT * P[39] * x[2]
+            + 0.5 * dT * P[46] * x[3]
+            + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[11]
+            + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[18]
+            + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[25]
             + P[4])
         * x[1]
         + 0.5
-            * d_t
-            * (0.5 * d_t * P[33] * x[1]
-                + 0.5 * d_t * P[40] * x[2]
-                + 0.5 * d_t * P[47] * x[3]
+            * dT
+            * (0.5 * dT * P[33] * x[1]
+                + 0.5 * dT * P[40] * x[2]
+                + 0.5 * dT * P[47] * x[3]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[12]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[19]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[26]
                 + P[5])
             * x[2]
         + 0.5
-            * d_t
-            * (0.5 * d_t * P[34] * x[1]
-                + 0.5 * d_t * P[41] * x[2]
-                + 0.5 * d_t * P[48] * x[3]
+            * dT
+            * (0.5 * dT * P[34] * x[1]
+                + 0.5 * dT * P[41] * x[2]
+                + 0.5 * dT * P[48] * x[3]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[13]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[20]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[27]
                 + P[6])
             * x[3]
-        + 0.5 * d_t * P[28] * x[1]
-        + 0.5 * d_t * P[35] * x[2]
-        + 0.5 * d_t * P[42] * x[3]
+        + 0.5 * dT * P[28] * x[1]
+        + 0.5 * dT * P[35] * x[2]
+        + 0.5 * dT * P[42] * x[3]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4])
+            * (0.5 * dT * P[29] * x[1]
+                + 0.5 * dT * P[36] * x[2]
+                + 0.5 * dT * P[43] * x[3]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[8]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[15]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[22]
+                + P[1])
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[7]
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5])
+            * (0.5 * dT * P[30] * x[1]
+                + 0.5 * dT * P[37] * x[2]
+                + 0.5 * dT * P[44] * x[3]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[9]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[16]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[23]
+                + P[2])
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[14]
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6])
+            * (0.5 * dT * P[31] * x[1]
+                + 0.5 * dT * P[38] * x[2]
+                + 0.5 * dT * P[45] * x[3]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[10]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[17]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[24]
+                + P[3])
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[21]
         + P[0]
         + Q[0];
     np[1] = -0.5
-        * d_t
-        * (0.5 * d_t * P[32] * x[1]
-            + 0.5 * d_t * P[39] * x[2]
-            + 0.5 * d_t * P[46] * x[3]
+        * dT
+        * (0.5 * dT * P[32] * x[1]
+            + 0.5 * dT * P[39] * x[2]
+            + 0.5 * dT * P[46] * x[3]
+            + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[11]
+            + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[18]
+            + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[25]
             + P[4])
         * x[0]
         + 0.5
-            * d_t
-            * (0.5 * d_t * P[33] * x[1]
-                + 0.5 * d_t * P[40] * x[2]
-                + 0.5 * d_t * P[47] * x[3]
+            * dT
+            * (0.5 * dT * P[33] * x[1]
+                + 0.5 * dT * P[40] * x[2]
+                + 0.5 * dT * P[47] * x[3]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[12]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[19]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[26]
                 + P[5])
             * x[3]
         - 0.5
-            * d_t
-            * (0.5 * d_t * P[34] * x[1]
-                + 0.5 * d_t * P[41] * x[2]
-                + 0.5 * d_t * P[48] * x[3]
+            * dT
+            * (0.5 * dT * P[34] * x[1]
+                + 0.5 * dT * P[41] * x[2]
+                + 0.5 * dT * P[48] * x[3]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[13]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[20]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[27]
                 + P[6])
             * x[2]
-        + 0.5 * d_t * P[29] * x[1]
-        + 0.5 * d_t * P[36] * x[2]
-        + 0.5 * d_t * P[43] * x[3]
+        + 0.5 * dT * P[29] * x[1]
+        + 0.5 * dT * P[36] * x[2]
+        + 0.5 * dT * P[43] * x[3]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[8]
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4])
+            * (0.5 * dT * P[28] * x[1]
+                + 0.5 * dT * P[35] * x[2]
+                + 0.5 * dT * P[42] * x[3]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[7]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[14]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[21]
+                + P[0])
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5])
+            * (0.5 * dT * P[31] * x[1]
+                + 0.5 * dT * P[38] * x[2]
+                + 0.5 * dT * P[45] * x[3]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[10]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[17]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[24]
+                + P[3])
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[15]
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[22]
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6])
+            * (0.5 * dT * P[30] * x[1]
+                + 0.5 * dT * P[37] * x[2]
+                + 0.5 * dT * P[44] * x[3]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[9]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[16]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[23]
+                + P[2])
         + P[1]
         + Q[1];
     np[2] = -0.5
-        * d_t
-        * (0.5 * d_t * P[32] * x[1]
-            + 0.5 * d_t * P[39] * x[2]
-            + 0.5 * d_t * P[46] * x[3]
+        * dT
+        * (0.5 * dT * P[32] * x[1]
+            + 0.5 * dT * P[39] * x[2]
+            + 0.5 * dT * P[46] * x[3]
+            + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[11]
+            + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[18]
+            + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[25]
             + P[4])
         * x[3]
         - 0.5
-            * d_t
-            * (0.5 * d_t * P[33] * x[1]
-                + 0.5 * d_t * P[40] * x[2]
-                + 0.5 * d_t * P[47] * x[3]
+            * dT
+            * (0.5 * dT * P[33] * x[1]
+                + 0.5 * dT * P[40] * x[2]
+                + 0.5 * dT * P[47] * x[3]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[12]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[19]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[26]
                 + P[5])
             * x[0]
         + 0.5
-            * d_t
-            * (0.5 * d_t * P[34] * x[1]
-                + 0.5 * d_t * P[41] * x[2]
-                + 0.5 * d_t * P[48] * x[3]
+            * dT
+            * (0.5 * dT * P[34] * x[1]
+                + 0.5 * dT * P[41] * x[2]
+                + 0.5 * dT * P[48] * x[3]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[13]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[20]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[27]
                 + P[6])
             * x[1]
-        + 0.5 * d_t * P[30] * x[1]
-        + 0.5 * d_t * P[37] * x[2]
-        + 0.5 * d_t * P[44] * x[3]
+        + 0.5 * dT * P[30] * x[1]
+        + 0.5 * dT * P[37] * x[2]
+        + 0.5 * dT * P[44] * x[3]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[9]
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4])
+            * (0.5 * dT * P[31] * x[1]
+                + 0.5 * dT * P[38] * x[2]
+                + 0.5 * dT * P[45] * x[3]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[10]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[17]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[24]
+                + P[3])
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[16]
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5])
+            * (0.5 * dT * P[28] * x[1]
+                + 0.5 * dT * P[35] * x[2]
+                + 0.5 * dT * P[42] * x[3]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[7]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[14]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[21]
+                + P[0])
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6])
+            * (0.5 * dT * P[29] * x[1]
+                + 0.5 * dT * P[36] * x[2]
+                + 0.5 * dT * P[43] * x[3]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[8]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[15]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[22]
+                + P[1])
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[23]
         + P[2]
         + Q[2];
     np[3] = 0.5
-        * d_t
-        * (0.5 * d_t * P[32] * x[1]
-            + 0.5 * d_t * P[39] * x[2]
-            + 0.5 * d_t * P[46] * x[3]
+        * dT
+        * (0.5 * dT * P[32] * x[1]
+            + 0.5 * dT * P[39] * x[2]
+            + 0.5 * dT * P[46] * x[3]
+            + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[11]
+            + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[18]
+            + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[25]
             + P[4])
         * x[2]
         - 0.5
-            * d_t
-            * (0.5 * d_t * P[33] * x[1]
-                + 0.5 * d_t * P[40] * x[2]
-                + 0.5 * d_t * P[47] * x[3]
+            * dT
+            * (0.5 * dT * P[33] * x[1]
+                + 0.5 * dT * P[40] * x[2]
+                + 0.5 * dT * P[47] * x[3]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[12]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[19]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[26]
                 + P[5])
             * x[1]
         - 0.5
-            * d_t
-            * (0.5 * d_t * P[34] * x[1]
-                + 0.5 * d_t * P[41] * x[2]
-                + 0.5 * d_t * P[48] * x[3]
+            * dT
+            * (0.5 * dT * P[34] * x[1]
+                + 0.5 * dT * P[41] * x[2]
+                + 0.5 * dT * P[48] * x[3]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[13]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[20]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[27]
                 + P[6])
             * x[0]
-        + 0.5 * d_t * P[31] * x[1]
-        + 0.5 * d_t * P[38] * x[2]
-        + 0.5 * d_t * P[45] * x[3]
+        + 0.5 * dT * P[31] * x[1]
+        + 0.5 * dT * P[38] * x[2]
+        + 0.5 * dT * P[45] * x[3]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4])
+            * (0.5 * dT * P[30] * x[1]
+                + 0.5 * dT * P[37] * x[2]
+                + 0.5 * dT * P[44] * x[3]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[9]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[16]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[23]
+                + P[2])
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[10]
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[17]
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5])
+            * (0.5 * dT * P[29] * x[1]
+                + 0.5 * dT * P[36] * x[2]
+                + 0.5 * dT * P[43] * x[3]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[8]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[15]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[22]
+                + P[1])
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[24]
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6])
+            * (0.5 * dT * P[28] * x[1]
+                + 0.5 * dT * P[35] * x[2]
+                + 0.5 * dT * P[42] * x[3]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[7]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[14]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[21]
+                + P[0])
         + P[3]
         + Q[3];
-    np[4] = 0.5 * d_t * P[32] * x[1]
-        + 0.5 * d_t * P[39] * x[2]
-        + 0.5 * d_t * P[46] * x[3]
+    np[4] = 0.5 * dT * P[32] * x[1]
+        + 0.5 * dT * P[39] * x[2]
+        + 0.5 * dT * P[46] * x[3]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[11]
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[18]
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[25]
         + P[4]
         + Q[4];
-    np[5] = 0.5 * d_t * P[33] * x[1]
-        + 0.5 * d_t * P[40] * x[2]
-        + 0.5 * d_t * P[47] * x[3]
+    np[5] = 0.5 * dT * P[33] * x[1]
+        + 0.5 * dT * P[40] * x[2]
+        + 0.5 * dT * P[47] * x[3]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[12]
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[19]
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[26]
         + P[5]
         + Q[5];
-    np[6] = 0.5 * d_t * P[34] * x[1]
-        + 0.5 * d_t * P[41] * x[2]
-        + 0.5 * d_t * P[48] * x[3]
+    np[6] = 0.5 * dT * P[34] * x[1]
+        + 0.5 * dT * P[41] * x[2]
+        + 0.5 * dT * P[48] * x[3]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[13]
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[20]
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[27]
         + P[6]
         + Q[6];
     np[7] = 0.5
-        * d_t
-        * (-0.5 * d_t * P[32] * x[0] + 0.5 * d_t * P[39] * x[3]
-            - 0.5 * d_t * P[46] * x[2]
+        * dT
+        * (-0.5 * dT * P[32] * x[0] + 0.5 * dT * P[39] * x[3]
+            - 0.5 * dT * P[46] * x[2]
+            + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[4]
+            + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[25]
+            + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[18]
             + P[11])
         * x[1]
         + 0.5
-            * d_t
-            * (-0.5 * d_t * P[33] * x[0] + 0.5 * d_t * P[40] * x[3]
-                - 0.5 * d_t * P[47] * x[2]
+            * dT
+            * (-0.5 * dT * P[33] * x[0] + 0.5 * dT * P[40] * x[3]
+                - 0.5 * dT * P[47] * x[2]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[5]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[26]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[19]
                 + P[12])
             * x[2]
         + 0.5
-            * d_t
-            * (-0.5 * d_t * P[34] * x[0] + 0.5 * d_t * P[41] * x[3]
-                - 0.5 * d_t * P[48] * x[2]
+            * dT
+            * (-0.5 * dT * P[34] * x[0] + 0.5 * dT * P[41] * x[3]
+                - 0.5 * dT * P[48] * x[2]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[6]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[27]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[20]
                 + P[13])
             * x[3]
-        - 0.5 * d_t * P[28] * x[0]
-        + 0.5 * d_t * P[35] * x[3]
-        - 0.5 * d_t * P[42] * x[2]
+        - 0.5 * dT * P[28] * x[0]
+        + 0.5 * dT * P[35] * x[3]
+        - 0.5 * dT * P[42] * x[2]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4])
+            * (-0.5 * dT * P[29] * x[0] + 0.5 * dT * P[36] * x[3]
+                - 0.5 * dT * P[43] * x[2]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[1]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[22]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[15]
+                + P[8])
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[0]
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5])
+            * (-0.5 * dT * P[30] * x[0] + 0.5 * dT * P[37] * x[3]
+                - 0.5 * dT * P[44] * x[2]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[2]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[23]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[16]
+                + P[9])
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[21]
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6])
+            * (-0.5 * dT * P[31] * x[0] + 0.5 * dT * P[38] * x[3]
+                - 0.5 * dT * P[45] * x[2]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[3]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[24]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[17]
+                + P[10])
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[14]
         + P[7]
         + Q[7];
     np[8] = -0.5
-        * d_t
-        * (-0.5 * d_t * P[32] * x[0] + 0.5 * d_t * P[39] * x[3]
-            - 0.5 * d_t * P[46] * x[2]
+        * dT
+        * (-0.5 * dT * P[32] * x[0] + 0.5 * dT * P[39] * x[3]
+            - 0.5 * dT * P[46] * x[2]
+            + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[4]
+            + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[25]
+            + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[18]
             + P[11])
         * x[0]
         + 0.5
-            * d_t
-            * (-0.5 * d_t * P[33] * x[0] + 0.5 * d_t * P[40] * x[3]
-                - 0.5 * d_t * P[47] * x[2]
+            * dT
+            * (-0.5 * dT * P[33] * x[0] + 0.5 * dT * P[40] * x[3]
+                - 0.5 * dT * P[47] * x[2]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[5]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[26]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[19]
                 + P[12])
             * x[3]
         - 0.5
-            * d_t
-            * (-0.5 * d_t * P[34] * x[0] + 0.5 * d_t * P[41] * x[3]
-                - 0.5 * d_t * P[48] * x[2]
+            * dT
+            * (-0.5 * dT * P[34] * x[0] + 0.5 * dT * P[41] * x[3]
+                - 0.5 * dT * P[48] * x[2]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[6]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[27]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[20]
                 + P[13])
             * x[2]
-        - 0.5 * d_t * P[29] * x[0]
-        + 0.5 * d_t * P[36] * x[3]
-        - 0.5 * d_t * P[43] * x[2]
+        - 0.5 * dT * P[29] * x[0]
+        + 0.5 * dT * P[36] * x[3]
+        - 0.5 * dT * P[43] * x[2]
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4])
+            * (-0.5 * dT * P[28] * x[0] + 0.5 * dT * P[35] * x[3]
+                - 0.5 * dT * P[42] * x[2]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[0]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[21]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[14]
+                + P[7])
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[1]
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5])
+            * (-0.5 * dT * P[31] * x[0] + 0.5 * dT * P[38] * x[3]
+                - 0.5 * dT * P[45] * x[2]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[3]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[24]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[17]
+                + P[10])
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[22]
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6])
+            * (-0.5 * dT * P[30] * x[0] + 0.5 * dT * P[37] * x[3]
+                - 0.5 * dT * P[44] * x[2]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[2]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[23]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[16]
+                + P[9])
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[15]
         + P[8]
         + Q[8];
     np[9] = -0.5
-        * d_t
-        * (-0.5 * d_t * P[32] * x[0] + 0.5 * d_t * P[39] * x[3]
-            - 0.5 * d_t * P[46] * x[2]
+        * dT
+        * (-0.5 * dT * P[32] * x[0] + 0.5 * dT * P[39] * x[3]
+            - 0.5 * dT * P[46] * x[2]
+            + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[4]
+            + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[25]
+            + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[18]
             + P[11])
         * x[3]
         - 0.5
-            * d_t
-            * (-0.5 * d_t * P[33] * x[0] + 0.5 * d_t * P[40] * x[3]
-                - 0.5 * d_t * P[47] * x[2]
+            * dT
+            * (-0.5 * dT * P[33] * x[0] + 0.5 * dT * P[40] * x[3]
+                - 0.5 * dT * P[47] * x[2]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[5]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[26]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[19]
                 + P[12])
             * x[0]
         + 0.5
-            * d_t
-            * (-0.5 * d_t * P[34] * x[0] + 0.5 * d_t * P[41] * x[3]
-                - 0.5 * d_t * P[48] * x[2]
+            * dT
+            * (-0.5 * dT * P[34] * x[0] + 0.5 * dT * P[41] * x[3]
+                - 0.5 * dT * P[48] * x[2]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[6]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[27]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[20]
                 + P[13])
             * x[1]
-        - 0.5 * d_t * P[30] * x[0]
-        + 0.5 * d_t * P[37] * x[3]
-        - 0.5 * d_t * P[44] * x[2]
+        - 0.5 * dT * P[30] * x[0]
+        + 0.5 * dT * P[37] * x[3]
+        - 0.5 * dT * P[44] * x[2]
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4])
+            * (-0.5 * dT * P[31] * x[0] + 0.5 * dT * P[38] * x[3]
+                - 0.5 * dT * P[45] * x[2]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[3]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[24]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[17]
+                + P[10])
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[2]
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[23]
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5])
+            * (-0.5 * dT * P[28] * x[0] + 0.5 * dT * P[35] * x[3]
+                - 0.5 * dT * P[42] * x[2]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[0]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[21]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[14]
+                + P[7])
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6])
+            * (-0.5 * dT * P[29] * x[0] + 0.5 * dT * P[36] * x[3]
+                - 0.5 * dT * P[43] * x[2]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[1]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[22]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[15]
+                + P[8])
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[16]
         + P[9]
         + Q[9];
     np[10] = 0.5
-        * d_t
-        * (-0.5 * d_t * P[32] * x[0] + 0.5 * d_t * P[39] * x[3]
-            - 0.5 * d_t * P[46] * x[2]
+        * dT
+        * (-0.5 * dT * P[32] * x[0] + 0.5 * dT * P[39] * x[3]
+            - 0.5 * dT * P[46] * x[2]
+            + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[4]
+            + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[25]
+            + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[18]
             + P[11])
         * x[2]
         - 0.5
-            * d_t
-            * (-0.5 * d_t * P[33] * x[0] + 0.5 * d_t * P[40] * x[3]
-                - 0.5 * d_t * P[47] * x[2]
+            * dT
+            * (-0.5 * dT * P[33] * x[0] + 0.5 * dT * P[40] * x[3]
+                - 0.5 * dT * P[47] * x[2]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[5]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[26]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[19]
                 + P[12])
             * x[1]
         - 0.5
-            * d_t
-            * (-0.5 * d_t * P[34] * x[0] + 0.5 * d_t * P[41] * x[3]
-                - 0.5 * d_t * P[48] * x[2]
+            * dT
+            * (-0.5 * dT * P[34] * x[0] + 0.5 * dT * P[41] * x[3]
+                - 0.5 * dT * P[48] * x[2]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[6]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[27]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[20]
                 + P[13])
             * x[0]
-        - 0.5 * d_t * P[31] * x[0]
-        + 0.5 * d_t * P[38] * x[3]
-        - 0.5 * d_t * P[45] * x[2]
+        - 0.5 * dT * P[31] * x[0]
+        + 0.5 * dT * P[38] * x[3]
+        - 0.5 * dT * P[45] * x[2]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4])
+            * (-0.5 * dT * P[30] * x[0] + 0.5 * dT * P[37] * x[3]
+                - 0.5 * dT * P[44] * x[2]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[2]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[23]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[16]
+                + P[9])
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[3]
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[24]
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5])
+            * (-0.5 * dT * P[29] * x[0] + 0.5 * dT * P[36] * x[3]
+                - 0.5 * dT * P[43] * x[2]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[1]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[22]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[15]
+                + P[8])
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6])
+            * (-0.5 * dT * P[28] * x[0] + 0.5 * dT * P[35] * x[3]
+                - 0.5 * dT * P[42] * x[2]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[0]
+                + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[21]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[14]
+                + P[7])
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[17]
         + P[10]
         + Q[10];
-    np[11] = -0.5 * d_t * P[32] * x[0] + 0.5 * d_t * P[39] * x[3]
-        - 0.5 * d_t * P[46] * x[2]
+    np[11] = -0.5 * dT * P[32] * x[0] + 0.5 * dT * P[39] * x[3]
+        - 0.5 * dT * P[46] * x[2]
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[4]
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[25]
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[18]
         + P[11]
         + Q[11];
-    np[12] = -0.5 * d_t * P[33] * x[0] + 0.5 * d_t * P[40] * x[3]
-        - 0.5 * d_t * P[47] * x[2]
+    np[12] = -0.5 * dT * P[33] * x[0] + 0.5 * dT * P[40] * x[3]
+        - 0.5 * dT * P[47] * x[2]
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[5]
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[26]
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[19]
         + P[12]
         + Q[12];
-    np[13] = -0.5 * d_t * P[34] * x[0] + 0.5 * d_t * P[41] * x[3]
-        - 0.5 * d_t * P[48] * x[2]
+    np[13] = -0.5 * dT * P[34] * x[0] + 0.5 * dT * P[41] * x[3]
+        - 0.5 * dT * P[48] * x[2]
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[6]
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[27]
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[20]
         + P[13]
         + Q[13];
     np[14] = 0.5
-        * d_t
-        * (-0.5 * d_t * P[32] * x[3] - 0.5 * d_t * P[39] * x[0]
-            + 0.5 * d_t * P[46] * x[1]
+        * dT
+        * (-0.5 * dT * P[32] * x[3] - 0.5 * dT * P[39] * x[0]
+            + 0.5 * dT * P[46] * x[1]
+            + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[25]
+            + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[4]
+            + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[11]
             + P[18])
         * x[1]
         + 0.5
-            * d_t
-            * (-0.5 * d_t * P[33] * x[3] - 0.5 * d_t * P[40] * x[0]
-                + 0.5 * d_t * P[47] * x[1]
+            * dT
+            * (-0.5 * dT * P[33] * x[3] - 0.5 * dT * P[40] * x[0]
+                + 0.5 * dT * P[47] * x[1]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[26]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[5]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[12]
                 + P[19])
             * x[2]
         + 0.5
-            * d_t
-            * (-0.5 * d_t * P[34] * x[3] - 0.5 * d_t * P[41] * x[0]
-                + 0.5 * d_t * P[48] * x[1]
+            * dT
+            * (-0.5 * dT * P[34] * x[3] - 0.5 * dT * P[41] * x[0]
+                + 0.5 * dT * P[48] * x[1]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[27]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[6]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[13]
                 + P[20])
             * x[3]
-        - 0.5 * d_t * P[28] * x[3]
-        - 0.5 * d_t * P[35] * x[0]
-        + 0.5 * d_t * P[42] * x[1]
+        - 0.5 * dT * P[28] * x[3]
+        - 0.5 * dT * P[35] * x[0]
+        + 0.5 * dT * P[42] * x[1]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4])
+            * (-0.5 * dT * P[29] * x[3] - 0.5 * dT * P[36] * x[0]
+                + 0.5 * dT * P[43] * x[1]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[22]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[1]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[8]
+                + P[15])
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[21]
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5])
+            * (-0.5 * dT * P[30] * x[3] - 0.5 * dT * P[37] * x[0]
+                + 0.5 * dT * P[44] * x[1]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[23]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[2]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[9]
+                + P[16])
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[0]
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6])
+            * (-0.5 * dT * P[31] * x[3] - 0.5 * dT * P[38] * x[0]
+                + 0.5 * dT * P[45] * x[1]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[24]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[3]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[10]
+                + P[17])
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[7]
         + P[14]
         + Q[14];
     np[15] = -0.5
-        * d_t
-        * (-0.5 * d_t * P[32] * x[3] - 0.5 * d_t * P[39] * x[0]
-            + 0.5 * d_t * P[46] * x[1]
+        * dT
+        * (-0.5 * dT * P[32] * x[3] - 0.5 * dT * P[39] * x[0]
+            + 0.5 * dT * P[46] * x[1]
+            + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[25]
+            + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[4]
+            + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[11]
             + P[18])
         * x[0]
         + 0.5
-            * d_t
-            * (-0.5 * d_t * P[33] * x[3] - 0.5 * d_t * P[40] * x[0]
-                + 0.5 * d_t * P[47] * x[1]
+            * dT
+            * (-0.5 * dT * P[33] * x[3] - 0.5 * dT * P[40] * x[0]
+                + 0.5 * dT * P[47] * x[1]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[26]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[5]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[12]
                 + P[19])
             * x[3]
         - 0.5
-            * d_t
-            * (-0.5 * d_t * P[34] * x[3] - 0.5 * d_t * P[41] * x[0]
-                + 0.5 * d_t * P[48] * x[1]
+            * dT
+            * (-0.5 * dT * P[34] * x[3] - 0.5 * dT * P[41] * x[0]
+                + 0.5 * dT * P[48] * x[1]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[27]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[6]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[13]
                 + P[20])
             * x[2]
-        - 0.5 * d_t * P[29] * x[3]
-        - 0.5 * d_t * P[36] * x[0]
-        + 0.5 * d_t * P[43] * x[1]
+        - 0.5 * dT * P[29] * x[3]
+        - 0.5 * dT * P[36] * x[0]
+        + 0.5 * dT * P[43] * x[1]
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4])
+            * (-0.5 * dT * P[28] * x[3] - 0.5 * dT * P[35] * x[0]
+                + 0.5 * dT * P[42] * x[1]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[21]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[0]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[7]
+                + P[14])
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[22]
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5])
+            * (-0.5 * dT * P[31] * x[3] - 0.5 * dT * P[38] * x[0]
+                + 0.5 * dT * P[45] * x[1]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[24]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[3]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[10]
+                + P[17])
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[1]
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[8]
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6])
+            * (-0.5 * dT * P[30] * x[3] - 0.5 * dT * P[37] * x[0]
+                + 0.5 * dT * P[44] * x[1]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[23]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[2]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[9]
+                + P[16])
         + P[15]
         + Q[15];
     np[16] = -0.5
-        * d_t
-        * (-0.5 * d_t * P[32] * x[3] - 0.5 * d_t * P[39] * x[0]
-            + 0.5 * d_t * P[46] * x[1]
+        * dT
+        * (-0.5 * dT * P[32] * x[3] - 0.5 * dT * P[39] * x[0]
+            + 0.5 * dT * P[46] * x[1]
+            + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[25]
+            + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[4]
+            + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[11]
             + P[18])
         * x[3]
         - 0.5
-            * d_t
-            * (-0.5 * d_t * P[33] * x[3] - 0.5 * d_t * P[40] * x[0]
-                + 0.5 * d_t * P[47] * x[1]
+            * dT
+            * (-0.5 * dT * P[33] * x[3] - 0.5 * dT * P[40] * x[0]
+                + 0.5 * dT * P[47] * x[1]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[26]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[5]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[12]
                 + P[19])
             * x[0]
         + 0.5
-            * d_t
-            * (-0.5 * d_t * P[34] * x[3] - 0.5 * d_t * P[41] * x[0]
-                + 0.5 * d_t * P[48] * x[1]
+            * dT
+            * (-0.5 * dT * P[34] * x[3] - 0.5 * dT * P[41] * x[0]
+                + 0.5 * dT * P[48] * x[1]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[27]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[6]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[13]
                 + P[20])
             * x[1]
-        - 0.5 * d_t * P[30] * x[3]
-        - 0.5 * d_t * P[37] * x[0]
-        + 0.5 * d_t * P[44] * x[1]
+        - 0.5 * dT * P[30] * x[3]
+        - 0.5 * dT * P[37] * x[0]
+        + 0.5 * dT * P[44] * x[1]
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4])
+            * (-0.5 * dT * P[31] * x[3] - 0.5 * dT * P[38] * x[0]
+                + 0.5 * dT * P[45] * x[1]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[24]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[3]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[10]
+                + P[17])
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[23]
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5])
+            * (-0.5 * dT * P[28] * x[3] - 0.5 * dT * P[35] * x[0]
+                + 0.5 * dT * P[42] * x[1]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[21]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[0]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[7]
+                + P[14])
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[2]
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6])
+            * (-0.5 * dT * P[29] * x[3] - 0.5 * dT * P[36] * x[0]
+                + 0.5 * dT * P[43] * x[1]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[22]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[1]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[8]
+                + P[15])
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[9]
         + P[16]
         + Q[16];
     np[17] = 0.5
-        * d_t
-        * (-0.5 * d_t * P[32] * x[3] - 0.5 * d_t * P[39] * x[0]
-            + 0.5 * d_t * P[46] * x[1]
+        * dT
+        * (-0.5 * dT * P[32] * x[3] - 0.5 * dT * P[39] * x[0]
+            + 0.5 * dT * P[46] * x[1]
+            + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[25]
+            + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[4]
+            + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[11]
             + P[18])
         * x[2]
         - 0.5
-            * d_t
-            * (-0.5 * d_t * P[33] * x[3] - 0.5 * d_t * P[40] * x[0]
-                + 0.5 * d_t * P[47] * x[1]
+            * dT
+            * (-0.5 * dT * P[33] * x[3] - 0.5 * dT * P[40] * x[0]
+                + 0.5 * dT * P[47] * x[1]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[26]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[5]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[12]
                 + P[19])
             * x[1]
         - 0.5
-            * d_t
-            * (-0.5 * d_t * P[34] * x[3] - 0.5 * d_t * P[41] * x[0]
-                + 0.5 * d_t * P[48] * x[1]
+            * dT
+            * (-0.5 * dT * P[34] * x[3] - 0.5 * dT * P[41] * x[0]
+                + 0.5 * dT * P[48] * x[1]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[27]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[6]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[13]
                 + P[20])
             * x[0]
-        - 0.5 * d_t * P[31] * x[3]
-        - 0.5 * d_t * P[38] * x[0]
-        + 0.5 * d_t * P[45] * x[1]
+        - 0.5 * dT * P[31] * x[3]
+        - 0.5 * dT * P[38] * x[0]
+        + 0.5 * dT * P[45] * x[1]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4])
+            * (-0.5 * dT * P[30] * x[3] - 0.5 * dT * P[37] * x[0]
+                + 0.5 * dT * P[44] * x[1]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[23]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[2]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[9]
+                + P[16])
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[24]
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5])
+            * (-0.5 * dT * P[29] * x[3] - 0.5 * dT * P[36] * x[0]
+                + 0.5 * dT * P[43] * x[1]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[22]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[1]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[8]
+                + P[15])
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[3]
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[10]
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6])
+            * (-0.5 * dT * P[28] * x[3] - 0.5 * dT * P[35] * x[0]
+                + 0.5 * dT * P[42] * x[1]
+                + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[21]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[0]
+                + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[7]
+                + P[14])
         + P[17]
         + Q[17];
-    np[18] = -0.5 * d_t * P[32] * x[3] - 0.5 * d_t * P[39] * x[0]
-        + 0.5 * d_t * P[46] * x[1]
+    np[18] = -0.5 * dT * P[32] * x[3] - 0.5 * dT * P[39] * x[0]
+        + 0.5 * dT * P[46] * x[1]
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[25]
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[4]
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[11]
         + P[18]
         + Q[18];
-    np[19] = -0.5 * d_t * P[33] * x[3] - 0.5 * d_t * P[40] * x[0]
-        + 0.5 * d_t * P[47] * x[1]
+    np[19] = -0.5 * dT * P[33] * x[3] - 0.5 * dT * P[40] * x[0]
+        + 0.5 * dT * P[47] * x[1]
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[26]
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[5]
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[12]
         + P[19]
         + Q[19];
-    np[20] = -0.5 * d_t * P[34] * x[3] - 0.5 * d_t * P[41] * x[0]
-        + 0.5 * d_t * P[48] * x[1]
+    np[20] = -0.5 * dT * P[34] * x[3] - 0.5 * dT * P[41] * x[0]
+        + 0.5 * dT * P[48] * x[1]
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[27]
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[6]
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[13]
         + P[20]
         + Q[20];
     np[21] = 0.5
-        * d_t
-        * (0.5 * d_t * P[32] * x[2]
-            - 0.5 * d_t * P[39] * x[1]
-            - 0.5 * d_t * P[46] * x[0]
+        * dT
+        * (0.5 * dT * P[32] * x[2]
+            - 0.5 * dT * P[39] * x[1]
+            - 0.5 * dT * P[46] * x[0]
+            + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[18]
+            + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[11]
+            + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[4]
             + P[25])
         * x[1]
         + 0.5
-            * d_t
-            * (0.5 * d_t * P[33] * x[2]
-                - 0.5 * d_t * P[40] * x[1]
-                - 0.5 * d_t * P[47] * x[0]
+            * dT
+            * (0.5 * dT * P[33] * x[2]
+                - 0.5 * dT * P[40] * x[1]
+                - 0.5 * dT * P[47] * x[0]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[19]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[12]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[5]
                 + P[26])
             * x[2]
         + 0.5
-            * d_t
-            * (0.5 * d_t * P[34] * x[2]
-                - 0.5 * d_t * P[41] * x[1]
-                - 0.5 * d_t * P[48] * x[0]
+            * dT
+            * (0.5 * dT * P[34] * x[2]
+                - 0.5 * dT * P[41] * x[1]
+                - 0.5 * dT * P[48] * x[0]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[20]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[13]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[6]
                 + P[27])
             * x[3]
-        + 0.5 * d_t * P[28] * x[2]
-        - 0.5 * d_t * P[35] * x[1]
-        - 0.5 * d_t * P[42] * x[0]
+        + 0.5 * dT * P[28] * x[2]
+        - 0.5 * dT * P[35] * x[1]
+        - 0.5 * dT * P[42] * x[0]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4])
+            * (0.5 * dT * P[29] * x[2]
+                - 0.5 * dT * P[36] * x[1]
+                - 0.5 * dT * P[43] * x[0]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[15]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[8]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[1]
+                + P[22])
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[14]
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5])
+            * (0.5 * dT * P[30] * x[2]
+                - 0.5 * dT * P[37] * x[1]
+                - 0.5 * dT * P[44] * x[0]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[16]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[9]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[2]
+                + P[23])
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[7]
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6])
+            * (0.5 * dT * P[31] * x[2]
+                - 0.5 * dT * P[38] * x[1]
+                - 0.5 * dT * P[45] * x[0]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[17]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[10]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[3]
+                + P[24])
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[0]
         + P[21]
         + Q[21];
     np[22] = -0.5
-        * d_t
-        * (0.5 * d_t * P[32] * x[2]
-            - 0.5 * d_t * P[39] * x[1]
-            - 0.5 * d_t * P[46] * x[0]
+        * dT
+        * (0.5 * dT * P[32] * x[2]
+            - 0.5 * dT * P[39] * x[1]
+            - 0.5 * dT * P[46] * x[0]
+            + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[18]
+            + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[11]
+            + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[4]
             + P[25])
         * x[0]
         + 0.5
-            * d_t
-            * (0.5 * d_t * P[33] * x[2]
-                - 0.5 * d_t * P[40] * x[1]
-                - 0.5 * d_t * P[47] * x[0]
+            * dT
+            * (0.5 * dT * P[33] * x[2]
+                - 0.5 * dT * P[40] * x[1]
+                - 0.5 * dT * P[47] * x[0]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[19]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[12]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[5]
                 + P[26])
             * x[3]
         - 0.5
-            * d_t
-            * (0.5 * d_t * P[34] * x[2]
-                - 0.5 * d_t * P[41] * x[1]
-                - 0.5 * d_t * P[48] * x[0]
+            * dT
+            * (0.5 * dT * P[34] * x[2]
+                - 0.5 * dT * P[41] * x[1]
+                - 0.5 * dT * P[48] * x[0]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[20]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[13]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[6]
                 + P[27])
             * x[2]
-        + 0.5 * d_t * P[29] * x[2]
-        - 0.5 * d_t * P[36] * x[1]
-        - 0.5 * d_t * P[43] * x[0]
+        + 0.5 * dT * P[29] * x[2]
+        - 0.5 * dT * P[36] * x[1]
+        - 0.5 * dT * P[43] * x[0]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[15]
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4])
+            * (0.5 * dT * P[28] * x[2]
+                - 0.5 * dT * P[35] * x[1]
+                - 0.5 * dT * P[42] * x[0]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[14]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[7]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[0]
+                + P[21])
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5])
+            * (0.5 * dT * P[31] * x[2]
+                - 0.5 * dT * P[38] * x[1]
+                - 0.5 * dT * P[45] * x[0]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[17]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[10]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[3]
+                + P[24])
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[8]
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6])
+            * (0.5 * dT * P[30] * x[2]
+                - 0.5 * dT * P[37] * x[1]
+                - 0.5 * dT * P[44] * x[0]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[16]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[9]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[2]
+                + P[23])
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[1]
         + P[22]
         + Q[22];
     np[23] = -0.5
-        * d_t
-        * (0.5 * d_t * P[32] * x[2]
-            - 0.5 * d_t * P[39] * x[1]
-            - 0.5 * d_t * P[46] * x[0]
+        * dT
+        * (0.5 * dT * P[32] * x[2]
+            - 0.5 * dT * P[39] * x[1]
+            - 0.5 * dT * P[46] * x[0]
+            + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[18]
+            + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[11]
+            + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[4]
             + P[25])
         * x[3]
         - 0.5
-            * d_t
-            * (0.5 * d_t * P[33] * x[2]
-                - 0.5 * d_t * P[40] * x[1]
-                - 0.5 * d_t * P[47] * x[0]
+            * dT
+            * (0.5 * dT * P[33] * x[2]
+                - 0.5 * dT * P[40] * x[1]
+                - 0.5 * dT * P[47] * x[0]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[19]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[12]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[5]
                 + P[26])
             * x[0]
         + 0.5
-            * d_t
-            * (0.5 * d_t * P[34] * x[2]
-                - 0.5 * d_t * P[41] * x[1]
-                - 0.5 * d_t * P[48] * x[0]
+            * dT
+            * (0.5 * dT * P[34] * x[2]
+                - 0.5 * dT * P[41] * x[1]
+                - 0.5 * dT * P[48] * x[0]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[20]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[13]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[6]
                 + P[27])
             * x[1]
-        + 0.5 * d_t * P[30] * x[2]
-        - 0.5 * d_t * P[37] * x[1]
-        - 0.5 * d_t * P[44] * x[0]
+        + 0.5 * dT * P[30] * x[2]
+        - 0.5 * dT * P[37] * x[1]
+        - 0.5 * dT * P[44] * x[0]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[16]
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4])
+            * (0.5 * dT * P[31] * x[2]
+                - 0.5 * dT * P[38] * x[1]
+                - 0.5 * dT * P[45] * x[0]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[17]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[10]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[3]
+                + P[24])
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5])
+            * (0.5 * dT * P[28] * x[2]
+                - 0.5 * dT * P[35] * x[1]
+                - 0.5 * dT * P[42] * x[0]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[14]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[7]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[0]
+                + P[21])
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[9]
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6])
+            * (0.5 * dT * P[29] * x[2]
+                - 0.5 * dT * P[36] * x[1]
+                - 0.5 * dT * P[43] * x[0]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[15]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[8]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[1]
+                + P[22])
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[2]
         + P[23]
         + Q[23];
     np[24] = 0.5
-        * d_t
-        * (0.5 * d_t * P[32] * x[2]
-            - 0.5 * d_t * P[39] * x[1]
-            - 0.5 * d_t * P[46] * x[0]
+        * dT
+        * (0.5 * dT * P[32] * x[2]
+            - 0.5 * dT * P[39] * x[1]
+            - 0.5 * dT * P[46] * x[0]
+            + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[18]
+            + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[11]
+            + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[4]
             + P[25])
         * x[2]
         - 0.5
-            * d_t
-            * (0.5 * d_t * P[33] * x[2]
-                - 0.5 * d_t * P[40] * x[1]
-                - 0.5 * d_t * P[47] * x[0]
+            * dT
+            * (0.5 * dT * P[33] * x[2]
+                - 0.5 * dT * P[40] * x[1]
+                - 0.5 * dT * P[47] * x[0]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[19]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[12]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[5]
                 + P[26])
             * x[1]
         - 0.5
-            * d_t
-            * (0.5 * d_t * P[34] * x[2]
-                - 0.5 * d_t * P[41] * x[1]
-                - 0.5 * d_t * P[48] * x[0]
+            * dT
+            * (0.5 * dT * P[34] * x[2]
+                - 0.5 * dT * P[41] * x[1]
+                - 0.5 * dT * P[48] * x[0]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[20]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[13]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[6]
                 + P[27])
             * x[0]
-        + 0.5 * d_t * P[31] * x[2]
-        - 0.5 * d_t * P[38] * x[1]
-        - 0.5 * d_t * P[45] * x[0]
+        + 0.5 * dT * P[31] * x[2]
+        - 0.5 * dT * P[38] * x[1]
+        - 0.5 * dT * P[45] * x[0]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4])
+            * (0.5 * dT * P[30] * x[2]
+                - 0.5 * dT * P[37] * x[1]
+                - 0.5 * dT * P[44] * x[0]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[16]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[9]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[2]
+                + P[23])
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[17]
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5])
+            * (0.5 * dT * P[29] * x[2]
+                - 0.5 * dT * P[36] * x[1]
+                - 0.5 * dT * P[43] * x[0]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[15]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[8]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[1]
+                + P[22])
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[10]
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6])
+            * (0.5 * dT * P[28] * x[2]
+                - 0.5 * dT * P[35] * x[1]
+                - 0.5 * dT * P[42] * x[0]
+                + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[14]
+                + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[7]
+                + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[0]
+                + P[21])
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[3]
         + P[24]
         + Q[24];
-    np[25] = 0.5 * d_t * P[32] * x[2]
-        - 0.5 * d_t * P[39] * x[1]
-        - 0.5 * d_t * P[46] * x[0]
+    np[25] = 0.5 * dT * P[32] * x[2]
+        - 0.5 * dT * P[39] * x[1]
+        - 0.5 * dT * P[46] * x[0]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[18]
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[11]
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[4]
         + P[25]
         + Q[25];
-    np[26] = 0.5 * d_t * P[33] * x[2]
-        - 0.5 * d_t * P[40] * x[1]
-        - 0.5 * d_t * P[47] * x[0]
+    np[26] = 0.5 * dT * P[33] * x[2]
+        - 0.5 * dT * P[40] * x[1]
+        - 0.5 * dT * P[47] * x[0]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[19]
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[12]
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[5]
         + P[26]
         + Q[26];
-    np[27] = 0.5 * d_t * P[34] * x[2]
-        - 0.5 * d_t * P[41] * x[1]
-        - 0.5 * d_t * P[48] * x[0]
+    np[27] = 0.5 * dT * P[34] * x[2]
+        - 0.5 * dT * P[41] * x[1]
+        - 0.5 * dT * P[48] * x[0]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[20]
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[13]
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[6]
         + P[27]
         + Q[27];
-    np[28] = 0.5 * d_t * P[32] * x[1]
-        + 0.5 * d_t * P[33] * x[2]
-        + 0.5 * d_t * P[34] * x[3]
+    np[28] = 0.5 * dT * P[32] * x[1]
+        + 0.5 * dT * P[33] * x[2]
+        + 0.5 * dT * P[34] * x[3]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[29]
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[30]
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[31]
         + P[28]
         + Q[28];
-    np[29] = -0.5 * d_t * P[32] * x[0] + 0.5 * d_t * P[33] * x[3]
-        - 0.5 * d_t * P[34] * x[2]
+    np[29] = -0.5 * dT * P[32] * x[0] + 0.5 * dT * P[33] * x[3]
+        - 0.5 * dT * P[34] * x[2]
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[28]
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[31]
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[30]
         + P[29]
         + Q[29];
-    np[30] = -0.5 * d_t * P[32] * x[3] - 0.5 * d_t * P[33] * x[0]
-        + 0.5 * d_t * P[34] * x[1]
+    np[30] = -0.5 * dT * P[32] * x[3] - 0.5 * dT * P[33] * x[0]
+        + 0.5 * dT * P[34] * x[1]
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[31]
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[28]
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[29]
         + P[30]
         + Q[30];
-    np[31] = 0.5 * d_t * P[32] * x[2]
-        - 0.5 * d_t * P[33] * x[1]
-        - 0.5 * d_t * P[34] * x[0]
+    np[31] = 0.5 * dT * P[32] * x[2]
+        - 0.5 * dT * P[33] * x[1]
+        - 0.5 * dT * P[34] * x[0]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[30]
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[29]
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[28]
         + P[31]
         + Q[31];
     np[32] = P[32] + Q[32];
     np[33] = P[33] + Q[33];
     np[34] = P[34] + Q[34];
-    np[35] = 0.5 * d_t * P[39] * x[1]
-        + 0.5 * d_t * P[40] * x[2]
-        + 0.5 * d_t * P[41] * x[3]
+    np[35] = 0.5 * dT * P[39] * x[1]
+        + 0.5 * dT * P[40] * x[2]
+        + 0.5 * dT * P[41] * x[3]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[36]
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[37]
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[38]
         + P[35]
         + Q[35];
-    np[36] = -0.5 * d_t * P[39] * x[0] + 0.5 * d_t * P[40] * x[3]
-        - 0.5 * d_t * P[41] * x[2]
+    np[36] = -0.5 * dT * P[39] * x[0] + 0.5 * dT * P[40] * x[3]
+        - 0.5 * dT * P[41] * x[2]
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[35]
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[38]
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[37]
         + P[36]
         + Q[36];
-    np[37] = -0.5 * d_t * P[39] * x[3] - 0.5 * d_t * P[40] * x[0]
-        + 0.5 * d_t * P[41] * x[1]
+    np[37] = -0.5 * dT * P[39] * x[3] - 0.5 * dT * P[40] * x[0]
+        + 0.5 * dT * P[41] * x[1]
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[38]
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[35]
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[36]
         + P[37]
         + Q[37];
-    np[38] = 0.5 * d_t * P[39] * x[2]
-        - 0.5 * d_t * P[40] * x[1]
-        - 0.5 * d_t * P[41] * x[0]
+    np[38] = 0.5 * dT * P[39] * x[2]
+        - 0.5 * dT * P[40] * x[1]
+        - 0.5 * dT * P[41] * x[0]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[37]
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[36]
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[35]
         + P[38]
         + Q[38];
     np[39] = P[39] + Q[39];
     np[40] = P[40] + Q[40];
     np[41] = P[41] + Q[41];
-    np[42] = 0.5 * d_t * P[46] * x[1]
-        + 0.5 * d_t * P[47] * x[2]
-        + 0.5 * d_t * P[48] * x[3]
+    np[42] = 0.5 * dT * P[46] * x[1]
+        + 0.5 * dT * P[47] * x[2]
+        + 0.5 * dT * P[48] * x[3]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[43]
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[44]
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[45]
         + P[42]
         + Q[42];
-    np[43] = -0.5 * d_t * P[46] * x[0] + 0.5 * d_t * P[47] * x[3]
-        - 0.5 * d_t * P[48] * x[2]
+    np[43] = -0.5 * dT * P[46] * x[0] + 0.5 * dT * P[47] * x[3]
+        - 0.5 * dT * P[48] * x[2]
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[42]
+        + (-0.5 * dT * w[1] + 0.5 * dT * x[5]) * P[45]
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[44]
         + P[43]
         + Q[43];
-    np[44] = -0.5 * d_t * P[46] * x[3] - 0.5 * d_t * P[47] * x[0]
-        + 0.5 * d_t * P[48] * x[1]
+    np[44] = -0.5 * dT * P[46] * x[3] - 0.5 * dT * P[47] * x[0]
+        + 0.5 * dT * P[48] * x[1]
+        + (0.5 * dT * w[0] - 0.5 * dT * x[4]) * P[45]
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[42]
+        + (-0.5 * dT * w[2] + 0.5 * dT * x[6]) * P[43]
         + P[44]
         + Q[44];
-    np[45] = 0.5 * d_t * P[46] * x[2]
-        - 0.5 * d_t * P[47] * x[1]
-        - 0.5 * d_t * P[48] * x[0]
+    np[45] = 0.5 * dT * P[46] * x[2]
+        - 0.5 * dT * P[47] * x[1]
+        - 0.5 * dT * P[48] * x[0]
+        + (-0.5 * dT * w[0] + 0.5 * dT * x[4]) * P[44]
+        + (0.5 * dT * w[1] - 0.5 * dT * x[5]) * P[43]
+        + (0.5 * dT * w[2] - 0.5 * dT * x[6]) * P[42]
         + P[45]
         + Q[45];
     np[46] = P[46] + Q[46];
@@ -672,3 +1305,270 @@ fn predict(x: [f64; 7], w: [f64; 3], P: [f64; 49], Q: [f64; 49], dT: f64) -> ([f
     np[48] = P[48] + Q[48];
     (nx, np)
 }
+
+/// Transition Jacobian used by `predict` (7x7, row-major)
+#[allow(non_snake_case, dead_code)]
+fn transition(x: [f64; 7], w: [f64; 3], dT: f64) -> [f64; 49] {
+    let mut A: [f64; 49] = [0.0; 49];
+    A[0] = 1.0;
+    A[1] = -0.5 * dT * w[0] + 0.5 * dT * x[4];
+    A[2] = -0.5 * dT * w[1] + 0.5 * dT * x[5];
+    A[3] = -0.5 * dT * w[2] + 0.5 * dT * x[6];
+    A[4] = 0.5 * dT * x[1];
+    A[5] = 0.5 * dT * x[2];
+    A[6] = 0.5 * dT * x[3];
+    A[7] = 0.5 * dT * w[0] - 0.5 * dT * x[4];
+    A[8] = 1.0;
+    A[9] = 0.5 * dT * w[2] - 0.5 * dT * x[6];
+    A[10] = -0.5 * dT * w[1] + 0.5 * dT * x[5];
+    A[11] = -0.5 * dT * x[0];
+    A[12] = 0.5 * dT * x[3];
+    A[13] = -0.5 * dT * x[2];
+    A[14] = 0.5 * dT * w[1] - 0.5 * dT * x[5];
+    A[15] = -0.5 * dT * w[2] + 0.5 * dT * x[6];
+    A[16] = 1.0;
+    A[17] = 0.5 * dT * w[0] - 0.5 * dT * x[4];
+    A[18] = -0.5 * dT * x[3];
+    A[19] = -0.5 * dT * x[0];
+    A[20] = 0.5 * dT * x[1];
+    A[21] = 0.5 * dT * w[2] - 0.5 * dT * x[6];
+    A[22] = 0.5 * dT * w[1] - 0.5 * dT * x[5];
+    A[23] = -0.5 * dT * w[0] + 0.5 * dT * x[4];
+    A[24] = 1.0;
+    A[25] = 0.5 * dT * x[2];
+    A[26] = -0.5 * dT * x[1];
+    A[27] = -0.5 * dT * x[0];
+    A[32] = 1.0;
+    A[40] = 1.0;
+    A[48] = 1.0;
+    A
+}
+
+/// Expected observation `y` and its Jacobian `C` (6x7, row-major)
+/// for accelerometer reference `a_r` and magnetometer reference `m_r`
+#[allow(non_snake_case)]
+fn observe(x: [f64; 7], a_r: [f64; 3], m_r: [f64; 3]) -> ([f64; 6], [f64; 42]) {
+    let y = [
+        (-2.0 * x[0] * x[2] + 2.0 * x[1] * x[3]) * a_r[2]
+            + (2.0 * x[0] * x[3] + 2.0 * x[1] * x[2]) * a_r[1]
+            + ((x[0]).powi(2) + (x[1]).powi(2)
+                - (x[2]).powi(2)
+                - (x[3]).powi(2))
+                * a_r[0],
+        (2.0 * x[0] * x[1] + 2.0 * x[2] * x[3]) * a_r[2]
+            + (-2.0 * x[0] * x[3] + 2.0 * x[1] * x[2]) * a_r[0]
+            + ((x[0]).powi(2) - (x[1]).powi(2) + (x[2]).powi(2)
+                - (x[3]).powi(2))
+                * a_r[1],
+        (-2.0 * x[0] * x[1] + 2.0 * x[2] * x[3]) * a_r[1]
+            + (2.0 * x[0] * x[2] + 2.0 * x[1] * x[3]) * a_r[0]
+            + ((x[0]).powi(2) - (x[1]).powi(2) - (x[2]).powi(2)
+                + (x[3]).powi(2))
+                * a_r[2],
+        (-2.0 * x[0] * x[2] + 2.0 * x[1] * x[3]) * m_r[2]
+            + (2.0 * x[0] * x[3] + 2.0 * x[1] * x[2]) * m_r[1]
+            + ((x[0]).powi(2) + (x[1]).powi(2)
+                - (x[2]).powi(2)
+                - (x[3]).powi(2))
+                * m_r[0],
+        (2.0 * x[0] * x[1] + 2.0 * x[2] * x[3]) * m_r[2]
+            + (-2.0 * x[0] * x[3] + 2.0 * x[1] * x[2]) * m_r[0]
+            + ((x[0]).powi(2) - (x[1]).powi(2) + (x[2]).powi(2)
+                - (x[3]).powi(2))
+                * m_r[1],
+        (-2.0 * x[0] * x[1] + 2.0 * x[2] * x[3]) * m_r[1]
+            + (2.0 * x[0] * x[2] + 2.0 * x[1] * x[3]) * m_r[0]
+            + ((x[0]).powi(2) - (x[1]).powi(2) - (x[2]).powi(2)
+                + (x[3]).powi(2))
+                * m_r[2],
+    ];
+    let mut C: [f64; 42] = [0.0; 42];
+    C[0] = 2.0 * a_r[0] * x[0] + 2.0 * a_r[1] * x[3] - 2.0 * a_r[2] * x[2];
+    C[1] = 2.0 * a_r[0] * x[1] + 2.0 * a_r[1] * x[2] + 2.0 * a_r[2] * x[3];
+    C[2] = -2.0 * a_r[0] * x[2] + 2.0 * a_r[1] * x[1] - 2.0 * a_r[2] * x[0];
+    C[3] = -2.0 * a_r[0] * x[3] + 2.0 * a_r[1] * x[0] + 2.0 * a_r[2] * x[1];
+    C[7] = -2.0 * a_r[0] * x[3] + 2.0 * a_r[1] * x[0] + 2.0 * a_r[2] * x[1];
+    C[8] = 2.0 * a_r[0] * x[2] - 2.0 * a_r[1] * x[1] + 2.0 * a_r[2] * x[0];
+    C[9] = 2.0 * a_r[0] * x[1] + 2.0 * a_r[1] * x[2] + 2.0 * a_r[2] * x[3];
+    C[10] = -2.0 * a_r[0] * x[0] - 2.0 * a_r[1] * x[3] + 2.0 * a_r[2] * x[2];
+    C[14] = 2.0 * a_r[0] * x[2] - 2.0 * a_r[1] * x[1] + 2.0 * a_r[2] * x[0];
+    C[15] = 2.0 * a_r[0] * x[3] - 2.0 * a_r[1] * x[0] - 2.0 * a_r[2] * x[1];
+    C[16] = 2.0 * a_r[0] * x[0] + 2.0 * a_r[1] * x[3] - 2.0 * a_r[2] * x[2];
+    C[17] = 2.0 * a_r[0] * x[1] + 2.0 * a_r[1] * x[2] + 2.0 * a_r[2] * x[3];
+    C[21] = 2.0 * m_r[0] * x[0] + 2.0 * m_r[1] * x[3] - 2.0 * m_r[2] * x[2];
+    C[22] = 2.0 * m_r[0] * x[1] + 2.0 * m_r[1] * x[2] + 2.0 * m_r[2] * x[3];
+    C[23] = -2.0 * m_r[0] * x[2] + 2.0 * m_r[1] * x[1] - 2.0 * m_r[2] * x[0];
+    C[24] = -2.0 * m_r[0] * x[3] + 2.0 * m_r[1] * x[0] + 2.0 * m_r[2] * x[1];
+    C[28] = -2.0 * m_r[0] * x[3] + 2.0 * m_r[1] * x[0] + 2.0 * m_r[2] * x[1];
+    C[29] = 2.0 * m_r[0] * x[2] - 2.0 * m_r[1] * x[1] + 2.0 * m_r[2] * x[0];
+    C[30] = 2.0 * m_r[0] * x[1] + 2.0 * m_r[1] * x[2] + 2.0 * m_r[2] * x[3];
+    C[31] = -2.0 * m_r[0] * x[0] - 2.0 * m_r[1] * x[3] + 2.0 * m_r[2] * x[2];
+    C[35] = 2.0 * m_r[0] * x[2] - 2.0 * m_r[1] * x[1] + 2.0 * m_r[2] * x[0];
+    C[36] = 2.0 * m_r[0] * x[3] - 2.0 * m_r[1] * x[0] - 2.0 * m_r[2] * x[1];
+    C[37] = 2.0 * m_r[0] * x[0] + 2.0 * m_r[1] * x[3] - 2.0 * m_r[2] * x[2];
+    C[38] = 2.0 * m_r[0] * x[1] + 2.0 * m_r[1] * x[2] + 2.0 * m_r[2] * x[3];
+    (y, C)
+}
+
+/// Rotates body frame vector `v` to the earth frame
+fn to_earth(x: [f64; 7], v: [f64; 3]) -> [f64; 3] {
+    [
+        (2.0 * x[0] * x[2] + 2.0 * x[1] * x[3]) * v[2]
+            + (-2.0 * x[0] * x[3] + 2.0 * x[1] * x[2]) * v[1]
+            + ((x[0]).powi(2) + (x[1]).powi(2)
+                - (x[2]).powi(2)
+                - (x[3]).powi(2))
+                * v[0],
+        (-2.0 * x[0] * x[1] + 2.0 * x[2] * x[3]) * v[2]
+            + (2.0 * x[0] * x[3] + 2.0 * x[1] * x[2]) * v[0]
+            + ((x[0]).powi(2) - (x[1]).powi(2) + (x[2]).powi(2)
+                - (x[3]).powi(2))
+                * v[1],
+        (2.0 * x[0] * x[1] + 2.0 * x[2] * x[3]) * v[1]
+            + (-2.0 * x[0] * x[2] + 2.0 * x[1] * x[3]) * v[0]
+            + ((x[0]).powi(2) - (x[1]).powi(2) - (x[2]).powi(2)
+                + (x[3]).powi(2))
+                * v[2],
+    ]
+}
+
+/// Inverts 6x6 row-major matrix with Gauss-Jordan elimination
+fn invert6(m: [f64; 36]) -> Option<[f64; 36]> {
+    let mut a = m;
+    let mut inv = [0.0; 36];
+    for i in 0..6 {
+        inv[i * 6 + i] = 1.0;
+    }
+    for c in 0..6 {
+        let mut pivot = c;
+        for r in c + 1..6 {
+            if f64::abs(a[r * 6 + c]) > f64::abs(a[pivot * 6 + c]) {
+                pivot = r;
+            }
+        }
+        if f64::abs(a[pivot * 6 + c]) < 1e-12 {
+            return None;
+        }
+        for k in 0..6 {
+            a.swap(c * 6 + k, pivot * 6 + k);
+            inv.swap(c * 6 + k, pivot * 6 + k);
+        }
+        let d = a[c * 6 + c];
+        for k in 0..6 {
+            a[c * 6 + k] /= d;
+            inv[c * 6 + k] /= d;
+        }
+        for r in 0..6 {
+            if r != c {
+                let f = a[r * 6 + c];
+                for k in 0..6 {
+                    a[r * 6 + k] -= f * a[c * 6 + k];
+                    inv[r * 6 + k] -= f * inv[c * 6 + k];
+                }
+            }
+        }
+    }
+    Some(inv)
+}
+
+/// Measurement update with raw `accel` and `mag` samples and measurement
+/// noise `R` (6x6, row-major).
+///
+/// Both samples are normalized, so only their directions matter.
+/// Accelerometer reference is gravity (0, 0, 1), magnetometer reference is
+/// the measured field rotated to the earth frame with its horizontal part
+/// pointing north, so mag corrects only the heading.
+///
+/// Returns state and covariance unchanged if either sample is zero or
+/// innovation covariance is singular.
+#[allow(non_snake_case)]
+fn update(
+    x: [f64; 7],
+    P: [f64; 49],
+    accel: [f64; 3],
+    mag: [f64; 3],
+    R: [f64; 36],
+) -> ([f64; 7], [f64; 49]) {
+    let an = f64::sqrt(
+        accel[0] * accel[0] + accel[1] * accel[1] + accel[2] * accel[2],
+    );
+    let mn = f64::sqrt(mag[0] * mag[0] + mag[1] * mag[1] + mag[2] * mag[2]);
+    if an == 0.0 || mn == 0.0 {
+        return (x, P);
+    }
+    let z = [
+        accel[0] / an,
+        accel[1] / an,
+        accel[2] / an,
+        mag[0] / mn,
+        mag[1] / mn,
+        mag[2] / mn,
+    ];
+    let me = to_earth(x, [z[3], z[4], z[5]]);
+    let m_r = [f64::sqrt(me[0] * me[0] + me[1] * me[1]), 0.0, me[2]];
+    let (y, C) = observe(x, [0.0, 0.0, 1.0], m_r);
+
+    // P * C^T, 7x6
+    let mut PCt = [0.0; 42];
+    for i in 0..7 {
+        for j in 0..6 {
+            for k in 0..7 {
+                PCt[i * 6 + j] += P[i * 7 + k] * C[j * 7 + k];
+            }
+        }
+    }
+    // Innovation covariance S = C * P * C^T + R, 6x6
+    let mut S = R;
+    for i in 0..6 {
+        for j in 0..6 {
+            for k in 0..7 {
+                S[i * 6 + j] += C[i * 7 + k] * PCt[k * 6 + j];
+            }
+        }
+    }
+    let Si = match invert6(S) {
+        Some(si) => si,
+        None => return (x, P),
+    };
+    // Kalman gain K = P * C^T * S^-1, 7x6
+    let mut K = [0.0; 42];
+    for i in 0..7 {
+        for j in 0..6 {
+            for k in 0..6 {
+                K[i * 6 + j] += PCt[i * 6 + k] * Si[k * 6 + j];
+            }
+        }
+    }
+
+    let mut nx = x;
+    for i in 0..7 {
+        for j in 0..6 {
+            nx[i] += K[i * 6 + j] * (z[j] - y[j]);
+        }
+    }
+    let qn = f64::sqrt(
+        nx[0] * nx[0] + nx[1] * nx[1] + nx[2] * nx[2] + nx[3] * nx[3],
+    );
+    for v in nx.iter_mut().take(4) {
+        *v /= qn;
+    }
+
+    // P = (I - K * C) * P
+    let mut KC = [0.0; 49];
+    for i in 0..7 {
+        for j in 0..7 {
+            for k in 0..6 {
+                KC[i * 7 + j] += K[i * 6 + k] * C[k * 7 + j];
+            }
+        }
+    }
+    let mut np = P;
+    for i in 0..7 {
+        for j in 0..7 {
+            for k in 0..7 {
+                np[i * 7 + j] -= KC[i * 7 + k] * P[k * 7 + j];
+            }
+        }
+    }
+    (nx, np)
+}
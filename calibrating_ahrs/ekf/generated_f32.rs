@@ -0,0 +1,628 @@
+// Generated by gen_ekf.py f32, do not edit.
+
+/// State transition: propagates state `x` and covariance `P` with
+/// measured angular velocity `w` over `dT` seconds
+#[allow(non_snake_case)]
+fn predict(
+    x: [f32; 7],
+    w: [f32; 3],
+    P: [f32; 49],
+    Q: [f32; 49],
+    dT: f32,
+) -> ([f32; 7], [f32; 49]) {
+    let t0 = 0.5 * dT;
+    let t1 = t0 * w[0];
+    let t2 = t0 * w[1];
+    let t3 = t0 * w[2];
+    let t4 = t0 * x[4];
+    let t5 = t0 * x[5];
+    let t6 = t0 * x[6];
+    let t7 = -t1 * x[1] - t2 * x[2] - t3 * x[3]
+        + t4 * x[1]
+        + t5 * x[2]
+        + t6 * x[3]
+        + x[0];
+    let t8 = t1 * x[0] - t2 * x[3] + t3 * x[2] - t4 * x[0] + t5 * x[3]
+        - t6 * x[2]
+        + x[1];
+    let t9 =
+        -t1 * x[2] + t2 * x[1] + t3 * x[0] + t4 * x[2] - t5 * x[1] - t6 * x[0]
+            + x[3];
+    let t10 = t1 * x[3] + t2 * x[0] - t3 * x[1] - t4 * x[3] - t5 * x[0]
+        + t6 * x[1]
+        + x[2];
+    let t11 =
+        1.0 / libm::sqrtf((t10 * t10) + (t7 * t7) + (t8 * t8) + (t9 * t9));
+    let t12 = -0.5 * dT * x[4] + t1;
+    let t13 = -t12;
+    let t14 = t0 * x[1];
+    let t15 = t0 * x[2];
+    let t16 = t0 * x[3];
+    let t17 = -0.5 * dT * x[5] + t2;
+    let t18 = -t17;
+    let t19 = -0.5 * dT * x[6] + t3;
+    let t20 = -t19;
+    let t21 = t13 * P[8]
+        + t14 * P[29]
+        + t15 * P[36]
+        + t16 * P[43]
+        + t18 * P[15]
+        + t20 * P[22]
+        + P[1];
+    let t22 = t13 * P[9]
+        + t14 * P[30]
+        + t15 * P[37]
+        + t16 * P[44]
+        + t18 * P[16]
+        + t20 * P[23]
+        + P[2];
+    let t23 = t13 * P[10]
+        + t14 * P[31]
+        + t15 * P[38]
+        + t16 * P[45]
+        + t18 * P[17]
+        + t20 * P[24]
+        + P[3];
+    let t24 = t14 * P[32];
+    let t25 = t15 * P[39];
+    let t26 = t16 * P[46];
+    let t27 = t13 * P[11] + t18 * P[18] + t20 * P[25] + t24 + t25 + t26 + P[4];
+    let t28 = t14 * P[33];
+    let t29 = t15 * P[40];
+    let t30 = t16 * P[47];
+    let t31 = t13 * P[12] + t18 * P[19] + t20 * P[26] + t28 + t29 + t30 + P[5];
+    let t32 = t14 * P[34];
+    let t33 = t15 * P[41];
+    let t34 = t16 * P[48];
+    let t35 = t13 * P[13] + t18 * P[20] + t20 * P[27] + t32 + t33 + t34 + P[6];
+    let t36 = t13 * P[7]
+        + t14 * P[28]
+        + t15 * P[35]
+        + t16 * P[42]
+        + t18 * P[14]
+        + t20 * P[21]
+        + P[0];
+    let t37 = t0 * x[0];
+    let t38 =
+        t12 * P[1] - t15 * P[43] + t16 * P[36] + t18 * P[22] + t19 * P[15]
+            - t37 * P[29]
+            + P[8];
+    let t39 =
+        t12 * P[2] - t15 * P[44] + t16 * P[37] + t18 * P[23] + t19 * P[16]
+            - t37 * P[30]
+            + P[9];
+    let t40 =
+        t12 * P[3] - t15 * P[45] + t16 * P[38] + t18 * P[24] + t19 * P[17]
+            - t37 * P[31]
+            + P[10];
+    let t41 = -t37 * P[32];
+    let t42 = t16 * P[39];
+    let t43 = t15 * P[46];
+    let t44 = t12 * P[4] + t18 * P[25] + t19 * P[18] + t41 + t42 - t43 + P[11];
+    let t45 = -t37 * P[33];
+    let t46 = t16 * P[40];
+    let t47 = t15 * P[47];
+    let t48 = t12 * P[5] + t18 * P[26] + t19 * P[19] + t45 + t46 - t47 + P[12];
+    let t49 = -t37 * P[34];
+    let t50 = t16 * P[41];
+    let t51 = -t15 * P[48];
+    let t52 = t12 * P[6] + t18 * P[27] + t19 * P[20] + t49 + t50 + t51 + P[13];
+    let t53 =
+        t12 * P[0] - t15 * P[42] + t16 * P[35] + t18 * P[21] + t19 * P[14]
+            - t37 * P[28]
+            + P[7];
+    let t54 = t12 * P[22] + t14 * P[43] - t16 * P[29] + t17 * P[1] + t20 * P[8]
+        - t37 * P[36]
+        + P[15];
+    let t55 = t12 * P[23] + t14 * P[44] - t16 * P[30] + t17 * P[2] + t20 * P[9]
+        - t37 * P[37]
+        + P[16];
+    let t56 =
+        t12 * P[24] + t14 * P[45] - t16 * P[31] + t17 * P[3] + t20 * P[10]
+            - t37 * P[38]
+            + P[17];
+    let t57 = -t16 * P[32];
+    let t58 = -t37 * P[39];
+    let t59 = t14 * P[46];
+    let t60 = t12 * P[25] + t17 * P[4] + t20 * P[11] + t57 + t58 + t59 + P[18];
+    let t61 = t16 * P[33];
+    let t62 = -t37 * P[40];
+    let t63 = t14 * P[47];
+    let t64 = t12 * P[26] + t17 * P[5] + t20 * P[12] - t61 + t62 + t63 + P[19];
+    let t65 = t16 * P[34];
+    let t66 = -t37 * P[41];
+    let t67 = t14 * P[48];
+    let t68 = t12 * P[27] + t17 * P[6] + t20 * P[13] - t65 + t66 + t67 + P[20];
+    let t69 = t12 * P[21] + t14 * P[42] - t16 * P[28] + t17 * P[0] + t20 * P[7]
+        - t37 * P[35]
+        + P[14];
+    let t70 = t13 * P[15] - t14 * P[36] + t15 * P[29] + t17 * P[8] + t19 * P[1]
+        - t37 * P[43]
+        + P[22];
+    let t71 = t13 * P[16] - t14 * P[37] + t15 * P[30] + t17 * P[9] + t19 * P[2]
+        - t37 * P[44]
+        + P[23];
+    let t72 =
+        t13 * P[17] - t14 * P[38] + t15 * P[31] + t17 * P[10] + t19 * P[3]
+            - t37 * P[45]
+            + P[24];
+    let t73 = t15 * P[32];
+    let t74 = t14 * P[39];
+    let t75 = -t37 * P[46];
+    let t76 = t13 * P[18] + t17 * P[11] + t19 * P[4] + t73 - t74 + t75 + P[25];
+    let t77 = t15 * P[33];
+    let t78 = -t14 * P[40];
+    let t79 = -t37 * P[47];
+    let t80 = t13 * P[19] + t17 * P[12] + t19 * P[5] + t77 + t78 + t79 + P[26];
+    let t81 = t15 * P[34];
+    let t82 = t14 * P[41];
+    let t83 = -t37 * P[48];
+    let t84 = t13 * P[20] + t17 * P[13] + t19 * P[6] + t81 - t82 + t83 + P[27];
+    let t85 = t13 * P[14] - t14 * P[35] + t15 * P[28] + t17 * P[7] + t19 * P[0]
+        - t37 * P[42]
+        + P[21];
+    let nx = [t11 * t7, t11 * t8, t10 * t11, t11 * t9, x[4], x[5], x[6]];
+    let mut np: [f32; 49] = [0.0; 49];
+    np[0] = t13 * t21
+        + t14 * t27
+        + t15 * t31
+        + t16 * t35
+        + t18 * t22
+        + t20 * t23
+        + t36
+        + Q[0];
+    np[1] = t12 * t36 - t15 * t35 + t16 * t31 + t18 * t23 + t19 * t22 + t21
+        - t27 * t37
+        + Q[1];
+    np[2] = t12 * t23 + t14 * t35 - t16 * t27 + t17 * t36 + t20 * t21 + t22
+        - t31 * t37
+        + Q[2];
+    np[3] = t13 * t22 - t14 * t31 + t15 * t27 + t17 * t21 + t19 * t36 + t23
+        - t35 * t37
+        + Q[3];
+    np[4] = t27 + Q[4];
+    np[5] = t31 + Q[5];
+    np[6] = t35 + Q[6];
+    np[7] = t13 * t38
+        + t14 * t44
+        + t15 * t48
+        + t16 * t52
+        + t18 * t39
+        + t20 * t40
+        + t53
+        + Q[7];
+    np[8] = t12 * t53 - t15 * t52 + t16 * t48 + t18 * t40 + t19 * t39
+        - t37 * t44
+        + t38
+        + Q[8];
+    np[9] = t12 * t40 + t14 * t52 - t16 * t44 + t17 * t53 + t20 * t38
+        - t37 * t48
+        + t39
+        + Q[9];
+    np[10] = t13 * t39 - t14 * t48 + t15 * t44 + t17 * t38 + t19 * t53
+        - t37 * t52
+        + t40
+        + Q[10];
+    np[11] = t44 + Q[11];
+    np[12] = t48 + Q[12];
+    np[13] = t52 + Q[13];
+    np[14] = t13 * t54
+        + t14 * t60
+        + t15 * t64
+        + t16 * t68
+        + t18 * t55
+        + t20 * t56
+        + t69
+        + Q[14];
+    np[15] = t12 * t69 - t15 * t68 + t16 * t64 + t18 * t56 + t19 * t55
+        - t37 * t60
+        + t54
+        + Q[15];
+    np[16] = t12 * t56 + t14 * t68 - t16 * t60 + t17 * t69 + t20 * t54
+        - t37 * t64
+        + t55
+        + Q[16];
+    np[17] = t13 * t55 - t14 * t64 + t15 * t60 + t17 * t54 + t19 * t69
+        - t37 * t68
+        + t56
+        + Q[17];
+    np[18] = t60 + Q[18];
+    np[19] = t64 + Q[19];
+    np[20] = t68 + Q[20];
+    np[21] = t13 * t70
+        + t14 * t76
+        + t15 * t80
+        + t16 * t84
+        + t18 * t71
+        + t20 * t72
+        + t85
+        + Q[21];
+    np[22] = t12 * t85 - t15 * t84 + t16 * t80 + t18 * t72 + t19 * t71
+        - t37 * t76
+        + t70
+        + Q[22];
+    np[23] = t12 * t72 + t14 * t84 - t16 * t76 + t17 * t85 + t20 * t70
+        - t37 * t80
+        + t71
+        + Q[23];
+    np[24] = t13 * t71 - t14 * t80 + t15 * t76 + t17 * t70 + t19 * t85
+        - t37 * t84
+        + t72
+        + Q[24];
+    np[25] = t76 + Q[25];
+    np[26] = t80 + Q[26];
+    np[27] = t84 + Q[27];
+    np[28] = t13 * P[29]
+        + t18 * P[30]
+        + t20 * P[31]
+        + t24
+        + t65
+        + t77
+        + P[28]
+        + Q[28];
+    np[29] = t12 * P[28] + t18 * P[31] + t19 * P[30] + t41 + t61 - t81
+        + P[29]
+        + Q[29];
+    np[30] = t12 * P[31]
+        + t17 * P[28]
+        + t20 * P[29]
+        + t32
+        + t45
+        + t57
+        + P[30]
+        + Q[30];
+    np[31] = t13 * P[30] + t17 * P[29] + t19 * P[28] - t28
+        + t49
+        + t73
+        + P[31]
+        + Q[31];
+    np[32] = P[32] + Q[32];
+    np[33] = P[33] + Q[33];
+    np[34] = P[34] + Q[34];
+    np[35] = t13 * P[36]
+        + t18 * P[37]
+        + t20 * P[38]
+        + t29
+        + t50
+        + t74
+        + P[35]
+        + Q[35];
+    np[36] = t12 * P[35] + t18 * P[38] + t19 * P[37] - t33
+        + t46
+        + t58
+        + P[36]
+        + Q[36];
+    np[37] = t12 * P[38] + t17 * P[35] + t20 * P[36] - t42
+        + t62
+        + t82
+        + P[37]
+        + Q[37];
+    np[38] = t13 * P[37]
+        + t17 * P[36]
+        + t19 * P[35]
+        + t25
+        + t66
+        + t78
+        + P[38]
+        + Q[38];
+    np[39] = P[39] + Q[39];
+    np[40] = P[40] + Q[40];
+    np[41] = P[41] + Q[41];
+    np[42] = t13 * P[43]
+        + t18 * P[44]
+        + t20 * P[45]
+        + t34
+        + t47
+        + t59
+        + P[42]
+        + Q[42];
+    np[43] = t12 * P[42]
+        + t18 * P[45]
+        + t19 * P[44]
+        + t30
+        + t51
+        + t75
+        + P[43]
+        + Q[43];
+    np[44] = t12 * P[45] + t17 * P[42] + t20 * P[43] - t26
+        + t67
+        + t79
+        + P[44]
+        + Q[44];
+    np[45] = t13 * P[44] + t17 * P[43] + t19 * P[42] + t43 - t63
+        + t83
+        + P[45]
+        + Q[45];
+    np[46] = P[46] + Q[46];
+    np[47] = P[47] + Q[47];
+    np[48] = P[48] + Q[48];
+    (nx, np)
+}
+
+/// Transition Jacobian used by `predict` (7x7, row-major)
+#[allow(non_snake_case, dead_code)]
+fn transition(x: [f32; 7], w: [f32; 3], dT: f32) -> [f32; 49] {
+    let t0 = 0.5 * dT;
+    let t1 = -0.5 * dT * x[4] + t0 * w[0];
+    let t2 = -t1;
+    let t3 = -0.5 * dT * x[5] + t0 * w[1];
+    let t4 = -t3;
+    let t5 = -0.5 * dT * x[6] + t0 * w[2];
+    let t6 = -t5;
+    let t7 = t0 * x[1];
+    let t8 = t0 * x[2];
+    let t9 = t0 * x[3];
+    let t10 = -t0 * x[0];
+    let mut A: [f32; 49] = [0.0; 49];
+    A[0] = 1.0;
+    A[1] = t2;
+    A[2] = t4;
+    A[3] = t6;
+    A[4] = t7;
+    A[5] = t8;
+    A[6] = t9;
+    A[7] = t1;
+    A[8] = 1.0;
+    A[9] = t5;
+    A[10] = t4;
+    A[11] = t10;
+    A[12] = t9;
+    A[13] = -t8;
+    A[14] = t3;
+    A[15] = t6;
+    A[16] = 1.0;
+    A[17] = t1;
+    A[18] = -t9;
+    A[19] = t10;
+    A[20] = t7;
+    A[21] = t5;
+    A[22] = t3;
+    A[23] = t2;
+    A[24] = 1.0;
+    A[25] = t8;
+    A[26] = -t7;
+    A[27] = t10;
+    A[32] = 1.0;
+    A[40] = 1.0;
+    A[48] = 1.0;
+    A
+}
+
+/// Expected observation `y` and its Jacobian `C` (6x7, row-major)
+/// for accelerometer reference `a_r` and magnetometer reference `m_r`
+#[allow(non_snake_case)]
+fn observe(x: [f32; 7], a_r: [f32; 3], m_r: [f32; 3]) -> ([f32; 6], [f32; 42]) {
+    let t0 = 2.0 * x[0];
+    let t1 = t0 * x[2];
+    let t2 = -t1 + 2.0 * x[1] * x[3];
+    let t3 = t0 * x[3];
+    let t4 = 2.0 * x[1];
+    let t5 = t3 + t4 * x[2];
+    let t6 = x[1] * x[1];
+    let t7 = x[2] * x[2];
+    let t8 = -t7;
+    let t9 = x[0] * x[0];
+    let t10 = x[3] * x[3];
+    let t11 = -t10 + t9;
+    let t12 = t11 + t6 + t8;
+    let t13 = t0 * x[1];
+    let t14 = 2.0 * x[2];
+    let t15 = t13 + t14 * x[3];
+    let t16 = -t3 + 2.0 * x[1] * x[2];
+    let t17 = -t6;
+    let t18 = t11 + t17 + t7;
+    let t19 = -t13 + 2.0 * x[2] * x[3];
+    let t20 = t1 + t4 * x[3];
+    let t21 = t10 + t17 + t8 + t9;
+    let t22 = 2.0 * x[3];
+    let t23 = t0 * a_r[0] - t14 * a_r[2] + t22 * a_r[1];
+    let t24 = t14 * a_r[1] + t22 * a_r[2] + t4 * a_r[0];
+    let t25 = t0 * a_r[2] + t14 * a_r[0] - 2.0 * a_r[1] * x[1];
+    let t26 = t0 * a_r[1] - t22 * a_r[0] + t4 * a_r[2];
+    let t27 = t0 * m_r[0] - t14 * m_r[2] + t22 * m_r[1];
+    let t28 = t14 * m_r[1] + t22 * m_r[2] + t4 * m_r[0];
+    let t29 = t0 * m_r[2] + t14 * m_r[0] - 2.0 * m_r[1] * x[1];
+    let t30 = t0 * m_r[1] - t22 * m_r[0] + t4 * m_r[2];
+    let y = [
+        t12 * a_r[0] + t2 * a_r[2] + t5 * a_r[1],
+        t15 * a_r[2] + t16 * a_r[0] + t18 * a_r[1],
+        t19 * a_r[1] + t20 * a_r[0] + t21 * a_r[2],
+        t12 * m_r[0] + t2 * m_r[2] + t5 * m_r[1],
+        t15 * m_r[2] + t16 * m_r[0] + t18 * m_r[1],
+        t19 * m_r[1] + t20 * m_r[0] + t21 * m_r[2],
+    ];
+    let mut C: [f32; 42] = [0.0; 42];
+    C[0] = t23;
+    C[1] = t24;
+    C[2] = -t25;
+    C[3] = t26;
+    C[7] = t26;
+    C[8] = t25;
+    C[9] = t24;
+    C[10] = -t23;
+    C[14] = t25;
+    C[15] = -t26;
+    C[16] = t23;
+    C[17] = t24;
+    C[21] = t27;
+    C[22] = t28;
+    C[23] = -t29;
+    C[24] = t30;
+    C[28] = t30;
+    C[29] = t29;
+    C[30] = t28;
+    C[31] = -t27;
+    C[35] = t29;
+    C[36] = -t30;
+    C[37] = t27;
+    C[38] = t28;
+    (y, C)
+}
+
+/// Rotates body frame vector `v` to the earth frame
+fn to_earth(x: [f32; 7], v: [f32; 3]) -> [f32; 3] {
+    let t0 = 2.0 * x[0];
+    let t1 = t0 * x[2];
+    let t2 = 2.0 * x[1];
+    let t3 = t0 * x[3];
+    let t4 = x[1] * x[1];
+    let t5 = x[2] * x[2];
+    let t6 = -t5;
+    let t7 = x[0] * x[0];
+    let t8 = x[3] * x[3];
+    let t9 = t7 - t8;
+    let t10 = t0 * x[1];
+    let t11 = -t4;
+    [
+        (t1 + t2 * x[3]) * v[2]
+            + (-t3 + 2.0 * x[1] * x[2]) * v[1]
+            + (t4 + t6 + t9) * v[0],
+        (-t10 + 2.0 * x[2] * x[3]) * v[2]
+            + (t2 * x[2] + t3) * v[0]
+            + (t11 + t5 + t9) * v[1],
+        (-t1 + 2.0 * x[1] * x[3]) * v[0]
+            + (t10 + 2.0 * x[2] * x[3]) * v[1]
+            + (t11 + t6 + t7 + t8) * v[2],
+    ]
+}
+
+/// Inverts 6x6 row-major matrix with Gauss-Jordan elimination
+fn invert6(m: [f32; 36]) -> Option<[f32; 36]> {
+    let mut a = m;
+    let mut inv = [0.0; 36];
+    for i in 0..6 {
+        inv[i * 6 + i] = 1.0;
+    }
+    for c in 0..6 {
+        let mut pivot = c;
+        for r in c + 1..6 {
+            if libm::fabsf(a[r * 6 + c]) > libm::fabsf(a[pivot * 6 + c]) {
+                pivot = r;
+            }
+        }
+        if libm::fabsf(a[pivot * 6 + c]) < 1e-6 {
+            return None;
+        }
+        for k in 0..6 {
+            a.swap(c * 6 + k, pivot * 6 + k);
+            inv.swap(c * 6 + k, pivot * 6 + k);
+        }
+        let d = a[c * 6 + c];
+        for k in 0..6 {
+            a[c * 6 + k] /= d;
+            inv[c * 6 + k] /= d;
+        }
+        for r in 0..6 {
+            if r != c {
+                let f = a[r * 6 + c];
+                for k in 0..6 {
+                    a[r * 6 + k] -= f * a[c * 6 + k];
+                    inv[r * 6 + k] -= f * inv[c * 6 + k];
+                }
+            }
+        }
+    }
+    Some(inv)
+}
+
+/// Measurement update with raw `accel` and `mag` samples and measurement
+/// noise `R` (6x6, row-major).
+///
+/// Both samples are normalized, so only their directions matter.
+/// Accelerometer reference is gravity (0, 0, 1), magnetometer reference is
+/// the measured field rotated to the earth frame with its horizontal part
+/// pointing north, so mag corrects only the heading.
+///
+/// Returns state and covariance unchanged if either sample is zero or
+/// innovation covariance is singular.
+#[allow(non_snake_case)]
+fn update(
+    x: [f32; 7],
+    P: [f32; 49],
+    accel: [f32; 3],
+    mag: [f32; 3],
+    R: [f32; 36],
+) -> ([f32; 7], [f32; 49]) {
+    let an = libm::sqrtf(
+        accel[0] * accel[0] + accel[1] * accel[1] + accel[2] * accel[2],
+    );
+    let mn = libm::sqrtf(mag[0] * mag[0] + mag[1] * mag[1] + mag[2] * mag[2]);
+    if an == 0.0 || mn == 0.0 {
+        return (x, P);
+    }
+    let z = [
+        accel[0] / an,
+        accel[1] / an,
+        accel[2] / an,
+        mag[0] / mn,
+        mag[1] / mn,
+        mag[2] / mn,
+    ];
+    let me = to_earth(x, [z[3], z[4], z[5]]);
+    let m_r = [libm::sqrtf(me[0] * me[0] + me[1] * me[1]), 0.0, me[2]];
+    let (y, C) = observe(x, [0.0, 0.0, 1.0], m_r);
+
+    // P * C^T, 7x6
+    let mut PCt = [0.0; 42];
+    for i in 0..7 {
+        for j in 0..6 {
+            for k in 0..7 {
+                PCt[i * 6 + j] += P[i * 7 + k] * C[j * 7 + k];
+            }
+        }
+    }
+    // Innovation covariance S = C * P * C^T + R, 6x6
+    let mut S = R;
+    for i in 0..6 {
+        for j in 0..6 {
+            for k in 0..7 {
+                S[i * 6 + j] += C[i * 7 + k] * PCt[k * 6 + j];
+            }
+        }
+    }
+    let Si = match invert6(S) {
+        Some(si) => si,
+        None => return (x, P),
+    };
+    // Kalman gain K = P * C^T * S^-1, 7x6
+    let mut K = [0.0; 42];
+    for i in 0..7 {
+        for j in 0..6 {
+            for k in 0..6 {
+                K[i * 6 + j] += PCt[i * 6 + k] * Si[k * 6 + j];
+            }
+        }
+    }
+
+    let mut nx = x;
+    for i in 0..7 {
+        for j in 0..6 {
+            nx[i] += K[i * 6 + j] * (z[j] - y[j]);
+        }
+    }
+    let qn = libm::sqrtf(
+        nx[0] * nx[0] + nx[1] * nx[1] + nx[2] * nx[2] + nx[3] * nx[3],
+    );
+    for v in nx.iter_mut().take(4) {
+        *v /= qn;
+    }
+
+    // P = (I - K * C) * P
+    let mut KC = [0.0; 49];
+    for i in 0..7 {
+        for j in 0..7 {
+            for k in 0..6 {
+                KC[i * 7 + j] += K[i * 6 + k] * C[k * 7 + j];
+            }
+        }
+    }
+    let mut np = P;
+    for i in 0..7 {
+        for j in 0..7 {
+            for k in 0..7 {
+                np[i * 7 + j] -= KC[i * 7 + k] * P[k * 7 + j];
+            }
+        }
+    }
+    (nx, np)
+}
@@ -0,0 +1,231 @@
+// Checks generated Jacobians against central finite differences and basic
+// invariants of `predict` over randomized states:
+//     rustc -O jacobian.rs && ./jacobian
+// Run it after every regeneration of generated.rs, it panics on mismatch.
+
+#![allow(dead_code)]
+
+include!("generated.rs");
+
+const TRIALS: usize = 1000;
+const EPS: f64 = 1e-6;
+const JACOBIAN_TOL: f64 = 1e-7;
+const NORM_TOL: f64 = 1e-12;
+const SYMMETRY_TOL: f64 = 1e-12;
+
+/// xorshift64*, good enough to scatter test states
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let v = self.0.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        (v >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in [-a, a)
+    fn sym(&mut self, a: f64) -> f64 {
+        (2.0 * self.next() - 1.0) * a
+    }
+
+    fn state(&mut self) -> [f64; 7] {
+        let mut x = [0.0; 7];
+        for v in x.iter_mut().take(4) {
+            *v = self.sym(1.0);
+        }
+        let n = (x[0] * x[0] + x[1] * x[1] + x[2] * x[2] + x[3] * x[3]).sqrt();
+        for v in x.iter_mut().take(4) {
+            *v /= n;
+        }
+        for v in x.iter_mut().skip(4) {
+            *v = self.sym(0.1);
+        }
+        x
+    }
+
+    fn vec3(&mut self, a: f64) -> [f64; 3] {
+        [self.sym(a), self.sym(a), self.sym(a)]
+    }
+
+    /// Random symmetric positive definite 7x7 matrix
+    fn covariance(&mut self) -> [f64; 49] {
+        let mut l = [0.0; 49];
+        for v in l.iter_mut() {
+            *v = self.sym(0.1);
+        }
+        let mut p = [0.0; 49];
+        for i in 0..7 {
+            for j in 0..7 {
+                for k in 0..7 {
+                    p[i * 7 + j] += l[i * 7 + k] * l[j * 7 + k];
+                }
+            }
+            p[i * 7 + i] += 1e-3;
+        }
+        p
+    }
+}
+
+/// Keeps track of the worst error of one check
+struct Check {
+    name: &'static str,
+    tol: f64,
+    worst: f64,
+}
+
+impl Check {
+    fn new(name: &'static str, tol: f64) -> Self {
+        Check {
+            name,
+            tol,
+            worst: 0.0,
+        }
+    }
+
+    fn compare(&mut self, got: f64, expected: f64, what: &dyn Fn() -> String) {
+        let err = (got - expected).abs();
+        if err > self.tol || err.is_nan() {
+            panic!(
+                "{}: {} is {}, expected {} (error {:e})",
+                self.name,
+                what(),
+                got,
+                expected,
+                err
+            );
+        }
+        self.worst = self.worst.max(err);
+    }
+
+    fn report(&self) {
+        println!("{} ok, worst error {:e}", self.name, self.worst);
+    }
+}
+
+/// Jacobian of quaternion normalization at `n`, rest of the state passes
+/// through
+fn normalization(nx: [f64; 7]) -> [f64; 49] {
+    let n =
+        (nx[0] * nx[0] + nx[1] * nx[1] + nx[2] * nx[2] + nx[3] * nx[3]).sqrt();
+    let mut d = [0.0; 49];
+    for i in 0..7 {
+        d[i * 7 + i] = 1.0;
+    }
+    for i in 0..4 {
+        for j in 0..4 {
+            let delta = if i == j { 1.0 } else { 0.0 };
+            d[i * 7 + j] = (delta - nx[i] * nx[j] / (n * n)) / n;
+        }
+    }
+    d
+}
+
+/// Next state before quaternion normalization, independent of generated code
+fn raw_transition(x: [f64; 7], w: [f64; 3], dt: f64) -> [f64; 7] {
+    let v = [w[0] - x[4], w[1] - x[5], w[2] - x[6]];
+    let h = dt / 2.0;
+    [
+        x[0] + h * (-x[1] * v[0] - x[2] * v[1] - x[3] * v[2]),
+        x[1] + h * (x[0] * v[0] + x[2] * v[2] - x[3] * v[1]),
+        x[2] + h * (x[0] * v[1] - x[1] * v[2] + x[3] * v[0]),
+        x[3] + h * (x[0] * v[2] + x[1] * v[1] - x[2] * v[0]),
+        x[4],
+        x[5],
+        x[6],
+    ]
+}
+
+fn check_transition(rng: &mut Rng, jac: &mut Check, raw: &mut Check) {
+    let x = rng.state();
+    let w = rng.vec3(2.0);
+    let dt = 0.001 + 0.05 * rng.next();
+    let zero = [0.0; 49];
+    let a = transition(x, w, dt);
+    // predict normalizes quaternion, so chain its Jacobian
+    let d = normalization(raw_transition(x, w, dt));
+    for col in 0..7 {
+        let (mut xp, mut xm) = (x, x);
+        xp[col] += EPS;
+        xm[col] -= EPS;
+        let (np, _) = predict(xp, w, zero, zero, dt);
+        let (nm, _) = predict(xm, w, zero, zero, dt);
+        let (rp, rm) = (raw_transition(xp, w, dt), raw_transition(xm, w, dt));
+        for row in 0..7 {
+            let fd = (np[row] - nm[row]) / (2.0 * EPS);
+            let mut chained = 0.0;
+            for k in 0..7 {
+                chained += d[row * 7 + k] * a[k * 7 + col];
+            }
+            jac.compare(chained, fd, &|| format!("dnx[{}]/dx[{}]", row, col));
+            let fd = (rp[row] - rm[row]) / (2.0 * EPS);
+            raw.compare(a[row * 7 + col], fd, &|| {
+                format!("A[{}][{}]", row, col)
+            });
+        }
+    }
+}
+
+fn check_observation(rng: &mut Rng, check: &mut Check) {
+    let x = rng.state();
+    let a_r = rng.vec3(1.0);
+    let m_r = rng.vec3(1.0);
+    let (_, c) = observe(x, a_r, m_r);
+    for col in 0..7 {
+        let (mut xp, mut xm) = (x, x);
+        xp[col] += EPS;
+        xm[col] -= EPS;
+        let (yp, _) = observe(xp, a_r, m_r);
+        let (ym, _) = observe(xm, a_r, m_r);
+        for row in 0..6 {
+            let fd = (yp[row] - ym[row]) / (2.0 * EPS);
+            check.compare(c[row * 7 + col], fd, &|| {
+                format!("C[{}][{}]", row, col)
+            });
+        }
+    }
+}
+
+fn check_predict(rng: &mut Rng, norm: &mut Check, symmetry: &mut Check) {
+    let mut x = rng.state();
+    // predict should also repair slightly off quaternions
+    let scale = 1.0 + rng.sym(0.01);
+    for v in x.iter_mut().take(4) {
+        *v *= scale;
+    }
+    let w = rng.vec3(2.0);
+    let dt = 0.001 + 0.05 * rng.next();
+    let p = rng.covariance();
+    let q = rng.covariance();
+    let (nx, np) = predict(x, w, p, q, dt);
+    let n =
+        (nx[0] * nx[0] + nx[1] * nx[1] + nx[2] * nx[2] + nx[3] * nx[3]).sqrt();
+    norm.compare(n, 1.0, &|| "|q|".to_string());
+    for i in 0..7 {
+        for j in i + 1..7 {
+            symmetry.compare(np[i * 7 + j], np[j * 7 + i], &|| {
+                format!("P[{}][{}]", i, j)
+            });
+        }
+    }
+}
+
+fn main() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    let mut transition = Check::new("transition Jacobian", JACOBIAN_TOL);
+    let mut raw = Check::new("unnormalized transition", JACOBIAN_TOL);
+    let mut observation = Check::new("observation Jacobian", JACOBIAN_TOL);
+    let mut norm = Check::new("predict quaternion norm", NORM_TOL);
+    let mut symmetry = Check::new("predict P symmetry", SYMMETRY_TOL);
+    for _ in 0..TRIALS {
+        check_transition(&mut rng, &mut transition, &mut raw);
+        check_observation(&mut rng, &mut observation);
+        check_predict(&mut rng, &mut norm, &mut symmetry);
+    }
+    transition.report();
+    raw.report();
+    observation.report();
+    norm.report();
+    symmetry.report();
+}
@@ -0,0 +1,5 @@
+//! Quaternion EKF in single precision for the Cortex-M4F FPU.
+type Float = f32;
+
+include!("generated_f32.rs");
+include!("quat_ekf.rs");
@@ -0,0 +1,83 @@
+/// Quaternion EKF with gyro bias estimation.
+///
+/// Thin wrapper around generated `predict` and `update`, keeps state
+/// `[q0, q1, q2, q3, bx, by, bz]` and row-major covariances.
+/// Includer picks precision with `Float` alias and matching generated code.
+pub struct QuatEkf {
+    pub x: [Float; 7],
+    pub p: [Float; 49],
+    q: [Float; 49],
+    r: [Float; 36],
+}
+
+/// Square row-major matrix with `val` on the diagonal
+fn diag<const N: usize>(n: usize, val: Float) -> [Float; N] {
+    let mut m = [0.0; N];
+    for i in 0..n {
+        m[i * n + i] = val;
+    }
+    m
+}
+
+impl Default for QuatEkf {
+    fn default() -> Self {
+        QuatEkf::new()
+    }
+}
+
+impl QuatEkf {
+    /// Create new filter with default noise settings.
+    pub fn new() -> Self {
+        let pval = 0.01;
+        let qval = 0.001;
+        let rval = 0.1;
+        QuatEkf::with_noise(pval, qval, rval)
+    }
+
+    /// Create new filter with diagonal initial covariance `pval`, process
+    /// noise `qval` and measurement noise `rval`.
+    pub fn with_noise(pval: Float, qval: Float, rval: Float) -> Self {
+        QuatEkf {
+            x: [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            p: diag(7, pval),
+            q: diag(7, qval),
+            r: diag(6, rval),
+        }
+    }
+
+    /// Replace process noise `qval` and measurement noise `rval`, state is
+    /// kept
+    pub fn set_noise(&mut self, qval: Float, rval: Float) {
+        self.q = diag(7, qval);
+        self.r = diag(6, rval);
+    }
+
+    /// Restart covariance from `pval` on the diagonal, state is kept
+    pub fn reset_covariance(&mut self, pval: Float) {
+        self.p = diag(7, pval);
+    }
+
+    /// Propagate state with gyro sample `w` (rad/s) over `dt` seconds
+    pub fn predict(&mut self, w: [Float; 3], dt: Float) {
+        let (nx, np) = predict(self.x, w, self.p, self.q, dt);
+        self.x = nx;
+        self.p = np;
+    }
+
+    /// Correct state with accelerometer and magnetometer samples
+    pub fn update(&mut self, accel: [Float; 3], mag: [Float; 3]) {
+        let (nx, np) = update(self.x, self.p, accel, mag, self.r);
+        self.x = nx;
+        self.p = np;
+    }
+
+    /// Estimated attitude quaternion
+    pub fn quat(&self) -> [Float; 4] {
+        [self.x[0], self.x[1], self.x[2], self.x[3]]
+    }
+
+    /// Estimated gyro biases
+    pub fn bias(&self) -> [Float; 3] {
+        [self.x[4], self.x[5], self.x[6]]
+    }
+}
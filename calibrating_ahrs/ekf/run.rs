@@ -1,5 +1,200 @@
+type Float = f64;
 
-include!{"generated.rs"}
+include! {"generated.rs"}
+include! {"quat_ekf.rs"}
+
+// Reference outputs, covariances and update computed with 40 digit
+// arithmetic
+const PREDICT_X1: [f64; 7] = [
+    0.9999998958034055,
+    -9.454221014902264e-05,
+    0.00021971076710688392,
+    0.0003888214594861238,
+    0.0,
+    0.0,
+    0.0,
+];
+const PREDICT_P1: [f64; 49] = [
+    0.011000002083932215,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.011001002083932215,
+    0.0,
+    0.0,
+    -0.0001,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.011001002083932215,
+    0.0,
+    0.0,
+    -0.0001,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.011001002083932215,
+    0.0,
+    0.0,
+    -0.0001,
+    0.0,
+    -0.0001,
+    0.0,
+    0.0,
+    0.011,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    -0.0001,
+    0.0,
+    0.0,
+    0.011,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    -0.0001,
+    0.0,
+    0.0,
+    0.011,
+];
+const PREDICT_X2: [f64; 7] = [
+    -0.12969501539456232,
+    0.6648596298800524,
+    -0.7235394247896452,
+    -0.13278394598984058,
+    -0.03246229,
+    -0.02853964,
+    0.04783659,
+];
+const PREDICT_P2: [f64; 49] = [
+    0.005013456712507746,
+    1.1996175881618606e-05,
+    7.834058054551934e-06,
+    -0.0007930255357657702,
+    0.003899848677053645,
+    -0.002729303003833869,
+    -0.0005396632290202583,
+    1.1996175881618606e-05,
+    0.004938014898798418,
+    0.0008712093382576136,
+    3.324877805950275e-05,
+    0.0004783884895993975,
+    -0.0005261668695147293,
+    0.00384268101734529,
+    7.834058054551934e-06,
+    0.0008712093382576136,
+    0.004957889462772786,
+    -1.3562260775304598e-05,
+    0.0005647334496059942,
+    0.0003882924425216217,
+    0.003915608483181211,
+    -0.0007930255357657702,
+    3.324877805950275e-05,
+    -1.3562260775304598e-05,
+    0.005036809377761406,
+    -0.0038425382405701336,
+    -0.002982307636891488,
+    0.0004948302642393546,
+    0.003899848677053645,
+    0.0004783884895993975,
+    0.0005647334496059942,
+    -0.0038425382405701336,
+    0.105907537,
+    0.000119448297,
+    4.87245479e-06,
+    -0.002729303003833869,
+    -0.0005261668695147293,
+    0.0003882924425216217,
+    -0.002982307636891488,
+    0.000119448297,
+    0.104576969,
+    -4.93875434e-05,
+    -0.0005396632290202583,
+    0.00384268101734529,
+    0.003915608483181211,
+    0.0004948302642393546,
+    4.87245479e-06,
+    -4.93875434e-05,
+    0.105927215,
+];
+const UPDATE_X: [f64; 7] = [
+    -0.14258032067740656,
+    0.6665831393744867,
+    -0.7180426035268772,
+    -0.1405439076499781,
+    -0.034172803956775244,
+    -0.011087363188270112,
+    0.05928219884052802,
+];
+const UPDATE_P: [f64; 49] = [
+    0.0030036323143104718,
+    0.00015362945125622457,
+    0.000146210404648966,
+    -0.00046646177441670475,
+    0.0023867474807678897,
+    -0.0016093313660516626,
+    -0.00013351566831789428,
+    0.00015362945125622457,
+    0.0036037491453875382,
+    0.0010954663367326472,
+    -5.9901378182257355e-05,
+    0.0005386128874878678,
+    -0.00035270819838221574,
+    0.0030632511368513977,
+    0.000146210404648966,
+    0.0010954663367326472,
+    0.003525633702890264,
+    -7.525950956959117e-05,
+    0.0005812666559992647,
+    0.0001439317211224589,
+    0.0031082778257600497,
+    -0.00046646177441670475,
+    -5.9901378182257355e-05,
+    -7.525950956959117e-05,
+    0.00299057942742544,
+    -0.00225920874632158,
+    -0.0018134655470304999,
+    0.00018726437024175428,
+    0.0023867474807678897,
+    0.0005386128874878678,
+    0.0005812666559992647,
+    -0.00225920874632158,
+    0.10384838459285464,
+    5.633473271788163e-05,
+    0.0003347014670195172,
+    -0.0016093313660516626,
+    -0.00035270819838221574,
+    0.0001439317211224589,
+    -0.0018134655470304999,
+    5.633473271788163e-05,
+    0.1029859023903479,
+    -0.00010445931571233553,
+    -0.00013351566831789428,
+    0.0030632511368513977,
+    0.0031082778257600497,
+    0.00018726437024175428,
+    0.0003347014670195172,
+    -0.00010445931571233553,
+    0.104799618026851,
+];
+
+/// Panics if `got` differs from `expected` by more than `tol`
+fn check(name: &str, got: &[f64], expected: &[f64], tol: f64) {
+    for (i, (g, e)) in got.iter().zip(expected.iter()).enumerate() {
+        if (g - e).abs() > tol {
+            panic!("{}[{}]: got {}, expected {}", name, i, g, e);
+        }
+    }
+    println!("{} ok", name);
+}
 
 fn main() {
     let mut x: [f64; 7] = [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
@@ -18,9 +213,8 @@ fn main() {
         0.001, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.001,
     ];
     let (nx, np) = predict(x, w, p, q, d_t);
-    println!("{:?}", nx);
-    println!("{:?}", np);
-    println!("---------------------");
+    check("predict x", &nx, &PREDICT_X1, 1e-12);
+    check("predict P", &np, &PREDICT_P1, 1e-12);
     x = [
         -0.12277918,
         0.66605548,
@@ -83,6 +277,26 @@ fn main() {
         1.04927215e-01,
     ];
     let (nx2, np2) = predict(x, w, p, q, d_t);
-    println!("{:?}", nx2);
-    println!("{:?}", np2);
+    check("predict x2", &nx2, &PREDICT_X2, 1e-12);
+    check("predict P2", &np2, &PREDICT_P2, 1e-12);
+
+    let accel = [0.3, -0.2, 9.6];
+    let mag = [20.0, -5.0, 40.0];
+    let mut r = [0.0; 36];
+    for i in 0..6 {
+        r[i * 6 + i] = 0.1;
+    }
+    let (ux, up) = update(x, p, accel, mag, r);
+    check("update x", &ux, &UPDATE_X, 1e-9);
+    check("update P", &up, &UPDATE_P, 1e-9);
+
+    // Filter at rest and level should stay level and learn gyro bias
+    let mut ekf = QuatEkf::new();
+    let bias = [0.01, -0.02, 0.005];
+    for _ in 0..2000 {
+        ekf.predict(bias, d_t);
+        ekf.update([0.0, 0.0, 9.81], [20.0, 0.0, 40.0]);
+    }
+    check("QuatEkf quat", &ekf.quat(), &[1.0, 0.0, 0.0, 0.0], 1e-3);
+    check("QuatEkf bias", &ekf.bias(), &bias, 1e-3);
 }
@@ -1,11 +1,31 @@
 #![no_std]
 #![no_main]
-#![feature(unboxed_closures)]
 
 #[allow(unused)]
 use panic_abort;
 
 use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+mod ekf;
+#[allow(dead_code)]
+#[path = "../temp_calib/bias.rs"]
+mod bias;
+#[path = "../ahrs-ekf/solve.rs"]
+mod solve;
+#[path = "../cobs/mod.rs"]
+mod cobs;
+#[path = "../dma/mod.rs"]
+mod dma;
+#[allow(dead_code)]
+#[path = "../wonca/imu.rs"]
+mod imu;
+#[path = "../mavlink/mod.rs"]
+mod mavlink;
+#[path = "../shell/mod.rs"]
+mod shell;
+#[path = "../storage/mod.rs"]
+mod storage;
 
 use hal::gpio::{
     self, AltFn, HighSpeed, Input, LowSpeed, Output, PullNone, PullUp,
@@ -14,13 +34,21 @@ use hal::gpio::{
 use hal::prelude::*;
 use hal::spi::Spi;
 use hal::time::Bps;
-use heapless::consts::*;
-use heapless::Vec;
 use rtic::cyccnt::U32Ext as _;
 
 use asm_delay::{AsmDelay, CyclesToTime};
 use mpu9250::{MargMeasurements, Mpu9250, MpuConfig};
-use ryu;
+use telemetry::{Attitude, Estimator, Health, Level, Message, RawMarg};
+
+use bias::{BiasModel, Compensate, MODEL_LEN};
+use dma::{DmaTelemetry, Drain, Priority, Queue, Stream};
+use ekf::QuatEkf;
+use imu::{ImuCal, IMU_LEN};
+use mavlink::MAV_STATE_ACTIVE;
+use mavlink::{frame_len, AttitudeQuaternion, Heartbeat, RawImu};
+use shell::{Args, Command, Error, Registry, Shell, Spec, Stored};
+use storage::stm32::InternalFlash;
+use storage::{records, Store};
 
 type SpiT = hal::pac::SPI1;
 type SCLPin<B> = gpio::PA5<PullNone, B>;
@@ -38,15 +66,154 @@ type Dev = mpu9250::SpiDevice<SPI, NcsPinT>;
 type MPU9250 = mpu9250::Mpu9250<Dev, mpu9250::Marg>;
 
 type USART = hal::pac::USART2;
-type TxUsart = hal::serial::Tx<USART>;
-type TxCh = hal::dma::dma1::C7;
-type TxBuffer = Vec<u8, U256>;
-type TxReady = (&'static mut TxBuffer, TxCh, TxUsart);
-type TxBusy =
-    hal::dma::Transfer<hal::dma::R, &'static mut TxBuffer, TxCh, TxUsart>;
-static mut BUFFER: TxBuffer = Vec(heapless::i::Vec::new());
+type RxUsart = hal::serial::Rx<USART>;
+
+// Sampling period, default and shortest one `period_ms` can be set to
+const FAST_MS: u32 = 20;
+const MIN_FAST_MS: u32 = 10;
+// At 64 MHz
+const CYCLES_PER_MS: u32 = 64_000;
+const BAUD: u32 = 460800;
+// Attitude and raw IMU every cycle and a heartbeat once a second have to
+// fit the link, each frame a queue slot
+const MAVLINK_CYCLE: usize =
+    frame_len::<AttitudeQuaternion>() + frame_len::<RawImu>();
+const _: () = assert!(
+    MAVLINK_CYCLE * (1000 / MIN_FAST_MS) as usize + frame_len::<Heartbeat>()
+        < mavlink::bytes_per_s(BAUD) as usize
+);
+const _: () = assert!(
+    frame_len::<AttitudeQuaternion>() <= SLOT_LEN
+        && frame_len::<RawImu>() <= SLOT_LEN
+        && frame_len::<Heartbeat>() <= SLOT_LEN
+);
+
+// Telemetry queue: tasks push, DMA interrupt sends. A slot takes a line
+// of shell reply as a log message. Samples get half the low slots, a burst
+// of 8 events gets through them.
+const SLOT_LEN: usize = 144;
+const _: () = assert!(SLOT_LEN <= cobs::max_payload(dma::CAPACITY));
+type TeleQueue = Queue<4, 8, SLOT_LEN>;
+type TeleDrain = Drain<4, 8, SLOT_LEN>;
+static QUEUE: TeleQueue = Queue::new();
+const RAW: Stream = Stream::framed(0, Priority::Low);
+const ATTITUDE: Stream = Stream::framed(1, Priority::Low);
+const HEALTH: Stream = Stream::framed(2, Priority::Low);
+// Shell replies, faults and interrupts
+const EVENTS: Stream = Stream::framed(3, Priority::High);
+const MAV: Stream = Stream::raw(4, Priority::Low);
+static STREAMS: [(&str, Stream); 5] = [
+    ("raw", RAW),
+    ("attitude", ATTITUDE),
+    ("health", HEALTH),
+    ("events", EVENTS),
+    ("mavlink", MAV),
+];
+
+// Tuning, `set` over serial, `save` keeps it in flash under FILTER
+const PVAL: usize = 0;
+const QVAL: usize = 1;
+const RVAL: usize = 2;
+const DIVISOR: usize = 3;
+const DLPF: usize = 4;
+const PERIOD_MS: usize = 5;
+// MAVLink for a ground station instead of telemetry messages
+const MAVLINK: usize = 6;
+static PARAMS: [Spec; 7] = [
+    Spec::f32("pval", 0.01, 1e-9, 10.0),
+    Spec::f32("qval", 0.001, 1e-9, 10.0),
+    Spec::f32("rval", 0.1, 1e-9, 10.0),
+    Spec::u32("divisor", 3, 0, 255),
+    Spec::u32("dlpf", 2, 0, 7),
+    Spec::u32("period_ms", FAST_MS, MIN_FAST_MS, 100),
+    Spec::bool("mavlink", false),
+];
+// `mavlink` as the sampling task last applied it, for text senders
+static MAVLINK_ON: AtomicBool = AtomicBool::new(false);
+static COMMANDS: [Command<Tuning>; 3] = [
+    Tuning::SAVE,
+    Tuning::DEFAULTS,
+    Command {
+        name: "stats",
+        usage: "",
+        help: "telemetry sent and dropped per stream",
+        run: stats,
+    },
+];
+
+/// Shell context, parameters and the store `save` writes to
+pub type Tuning = Stored<Store<InternalFlash>, storage::Error, 7>;
+
+fn stats(
+    _: &mut Tuning,
+    args: &mut Args,
+    out: &mut dyn Write,
+) -> Result<(), Error> {
+    args.end()?;
+    for (name, stream) in STREAMS.iter() {
+        let s = QUEUE.stats(*stream);
+        write!(out, "{}: {} sent, {} dropped\r\n", name, s.sent, s.dropped)?;
+    }
+    Ok(())
+}
 
-const FAST: u32 = 1_280_000;
+/// What the sampling task runs with, read from the registry each cycle
+#[derive(Clone, Copy, PartialEq)]
+pub struct Tuned {
+    pval: f32,
+    qval: f32,
+    rval: f32,
+    divisor: u8,
+    dlpf: u32,
+    period_ms: u32,
+    mavlink: bool,
+}
+
+impl Tuned {
+    fn of(params: &Registry<7>) -> Self {
+        Tuned {
+            pval: params.f32(PVAL),
+            qval: params.f32(QVAL),
+            rval: params.f32(RVAL),
+            divisor: params.u32(DIVISOR) as u8,
+            dlpf: params.u32(DLPF),
+            period_ms: params.u32(PERIOD_MS),
+            mavlink: params.bool(MAVLINK),
+        }
+    }
+
+    fn gyro_rate(&self) -> mpu9250::GyroTempDataRate {
+        let dlpf = match self.dlpf {
+            0 => mpu9250::Dlpf::_0,
+            1 => mpu9250::Dlpf::_1,
+            2 => mpu9250::Dlpf::_2,
+            3 => mpu9250::Dlpf::_3,
+            4 => mpu9250::Dlpf::_4,
+            5 => mpu9250::Dlpf::_5,
+            6 => mpu9250::Dlpf::_6,
+            _ => mpu9250::Dlpf::_7,
+        };
+        mpu9250::GyroTempDataRate::DlpfConf(dlpf)
+    }
+
+    /// Applies what changed since `self` was in effect
+    fn apply(&mut self, new: Tuned, ekf: &mut QuatEkf, mpu: &mut MPU9250) {
+        if new.pval != self.pval {
+            ekf.reset_covariance(new.pval);
+        }
+        if new.qval != self.qval || new.rval != self.rval {
+            ekf.set_noise(new.qval, new.rval);
+        }
+        if new.divisor != self.divisor {
+            let _ = mpu.sample_rate_divisor(new.divisor);
+        }
+        if new.dlpf != self.dlpf {
+            let _ = mpu.gyro_temp_data_rate(new.gyro_rate());
+        }
+        MAVLINK_ON.store(new.mavlink, Ordering::Relaxed);
+        *self = new;
+    }
+}
 
 pub trait Chrono: Sized {
     type Time;
@@ -92,65 +259,135 @@ impl Chrono for DwtClock {
     fn split_time_ms(&mut self) -> f32 {
         let dwt = unsafe { &(*cortex_m::peripheral::DWT::ptr()) };
         let now: u32 = dwt.cyccnt.read();
-        let duration = now - self.last;
+        let duration = now.wrapping_sub(self.last);
         self.last = now;
         self.cc.to_ms(duration)
     }
 }
 
-enum TransferState {
-    Ready(TxReady),
-    MaybeBusy(TxBusy),
+/// Encodes messages back to back into frame payload, the ones that don't
+/// fit are left out whole
+fn encode_messages(payload: &mut [u8], msgs: &[Message]) -> usize {
+    let mut len = 0;
+    for msg in msgs {
+        if let Ok(n) = telemetry::append(payload, len, msg) {
+            len = n;
+        }
+    }
+    len
 }
 
-pub struct DmaTelemetry {
-    state: TransferState,
+/// Queues messages as one frame and wakes the DMA interrupt to send it
+fn send(stream: Stream, msgs: &[Message]) {
+    QUEUE.push(stream, |b| encode_messages(b, msgs));
+    rtic::pend(hal::pac::Interrupt::DMA1_CH7);
 }
 
-impl DmaTelemetry {
-    fn with_state(ns: TransferState) -> Self {
-        DmaTelemetry { state: ns }
+/// Queues a MAVLink frame, it goes out as is
+fn push_mavlink<M: mavlink::Message>(enc: &mut mavlink::Encoder, msg: &M) {
+    QUEUE.push(MAV, |b| enc.encode(msg, b).unwrap_or(0));
+    rtic::pend(hal::pac::Interrupt::DMA1_CH7);
+}
+
+fn mavlink_on() -> bool {
+    MAVLINK_ON.load(Ordering::Relaxed)
+}
+
+/// Text goes nowhere in MAVLink mode
+fn log(level: Level, text: &str) {
+    if !mavlink_on() {
+        send(EVENTS, &[Message::log(level, text)]);
     }
 }
 
-impl DmaTelemetry {
-    pub fn create(ch: TxCh, tx: TxUsart) -> Self {
-        let bf = unsafe { &mut BUFFER };
-        let state = TransferState::Ready((bf, ch, tx));
-        DmaTelemetry::with_state(state)
+// Postcard puts variant, level and text length in front of log text
+const LOG_OVERHEAD: usize = 4;
+/// Longest reply line a log message carries in a slot
+const REPLY_LINE: usize = SLOT_LEN - LOG_OVERHEAD;
+
+/// Shell reply as log messages, one per line, packed into as few frames
+/// as fit. Lines longer than `REPLY_LINE` are cut and a warning follows.
+struct Reply {
+    level: Level,
+    line: [u8; REPLY_LINE],
+    len: usize,
+    cut: bool,
+    frame: [u8; SLOT_LEN],
+    frame_len: usize,
+}
+
+impl Reply {
+    const fn new() -> Self {
+        Reply::at(Level::Info)
     }
 
-    fn send<F>(self, mut buffer_filler: F) -> Self
-    where
-        F: for<'a> FnMut<(&'a mut TxBuffer,), Output = ()>,
-    {
-        let ns = match self.state {
-            TransferState::Ready((mut buffer, ch, tx)) => {
-                buffer_filler(&mut buffer);
-                TransferState::MaybeBusy(tx.write_all(ch, buffer))
-            }
-            TransferState::MaybeBusy(transfer) => {
-                if transfer.is_done() {
-                    let (buffer, ch, tx) = transfer.wait();
-                    buffer.clear();
-                    TransferState::Ready((buffer, ch, tx))
-                } else {
-                    TransferState::MaybeBusy(transfer)
-                }
-            }
-        };
+    /// Lines logged at `level`
+    const fn at(level: Level) -> Self {
+        Reply {
+            level,
+            line: [0; REPLY_LINE],
+            len: 0,
+            cut: false,
+            frame: [0; SLOT_LEN],
+            frame_len: 0,
+        }
+    }
+
+    fn end_line(&mut self) {
+        if self.len > 0 {
+            // Only whole chars go in
+            let line = &self.line[..self.len];
+            let text = core::str::from_utf8(line).unwrap_or("");
+            let msg = Message::log(self.level, text);
+            self.frame_len = pack(&mut self.frame, self.frame_len, &msg);
+            self.len = 0;
+        }
+        if self.cut {
+            self.cut = false;
+            let msg = Message::log(Level::Warn, "reply truncated");
+            self.frame_len = pack(&mut self.frame, self.frame_len, &msg);
+        }
+    }
+
+    /// Sends what's left, text without a line end too
+    fn finish(mut self) {
+        self.end_line();
+        if self.frame_len > 0 && !mavlink_on() {
+            QUEUE.push_bytes(EVENTS, &self.frame[..self.frame_len]);
+            rtic::pend(hal::pac::Interrupt::DMA1_CH7);
+        }
+    }
+}
 
-        match ns {
-            TransferState::MaybeBusy(_) => DmaTelemetry::with_state(ns),
-            TransferState::Ready(_) => {
-                DmaTelemetry::with_state(ns).send(buffer_filler)
+impl Write for Reply {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for c in s.chars() {
+            let n = c.len_utf8();
+            if self.len + n <= REPLY_LINE {
+                c.encode_utf8(&mut self.line[self.len..]);
+                self.len += n;
+            } else {
+                self.cut = true;
+            }
+            if c == '\n' {
+                self.end_line();
             }
         }
+        Ok(())
     }
 }
 
-fn fill_with_str(buffer: &mut TxBuffer, arg: &str) {
-    buffer.extend_from_slice(arg.as_bytes()).unwrap();
+/// Appends `msg` to frame payload `buf` of `len` bytes, sends the frame
+/// first when `msg` doesn't fit. Returns the new length.
+fn pack(buf: &mut [u8; SLOT_LEN], len: usize, msg: &Message) -> usize {
+    if let Ok(n) = telemetry::append(buf, len, msg) {
+        return n;
+    }
+    if len > 0 && !mavlink_on() {
+        QUEUE.push_bytes(EVENTS, &buf[..len]);
+        rtic::pend(hal::pac::Interrupt::DMA1_CH7);
+    }
+    telemetry::append(buf, 0, msg).unwrap_or(0)
 }
 
 #[rtic::app(device = hal::pac,
@@ -167,13 +404,39 @@ mod app {
             hal::gpio::PA0<PullUp, Input>,
             hal::exti::EXTI1,
         >,
-        tele: Option<DmaTelemetry>,
+        tuning: Tuning,
+        #[task_local]
+        tele: DmaTelemetry,
+        #[task_local]
+        drain: TeleDrain,
+        #[task_local]
+        applied: Tuned,
+        #[task_local]
+        rx: RxUsart,
+        #[task_local]
+        shell: Shell<Tuning, 64>,
         #[task_local]
         timer: DwtClock,
         #[task_local]
         mpu: MPU9250,
         #[task_local]
         previous_sample: MargMeasurements<[f32; 3]>,
+        #[task_local]
+        ekf: QuatEkf,
+        #[task_local]
+        bias_model: Option<BiasModel>,
+        #[task_local]
+        imu_cal: Option<ImuCal>,
+        #[task_local]
+        health: Health,
+        #[task_local]
+        mav: mavlink::Encoder,
+        #[task_local]
+        failing: bool,
+        #[task_local]
+        events_dropped: u32,
+        #[task_local]
+        elapsed_s: f32,
     }
 
     #[init()]
@@ -189,11 +452,12 @@ mod app {
         let gpioa = device.GPIOA.split(&mut rcc.ahb);
         let gpiob = device.GPIOB.split(&mut rcc.ahb);
 
-        let serial =
+        let mut serial =
             device
                 .USART2
-                .serial((gpioa.pa2, gpioa.pa15), Bps(460800), clocks);
-        let (mut tx, _) = serial.split();
+                .serial((gpioa.pa2, gpioa.pa15), Bps(BAUD), clocks);
+        serial.listen(hal::serial::Event::Rxne);
+        let (mut tx, rx) = serial.split();
         write!(tx, "init...\r\n").unwrap();
         let mut syscfg = device.SYSCFG.constrain(&mut rcc.apb2);
         write!(tx, "syscfg...\r\n").unwrap();
@@ -217,15 +481,25 @@ mod app {
         );
         write!(tx, "spi...\r\n").unwrap();
 
+        let store = Store::new(unsafe { InternalFlash::new() }).ok();
+        let mut params = Registry::new(&PARAMS);
+        if let Some(v) =
+            store.as_ref().and_then(|s| s.read_f32s(records::FILTER))
+        {
+            let rejected = params.load_f32s(&v);
+            write!(tx, "params loaded, {} rejected\r\n", rejected).unwrap();
+        }
+        let tuned = Tuned::of(&params);
+        MAVLINK_ON.store(tuned.mavlink, Ordering::Relaxed);
+
         let mut delay = AsmDelay::new(clocks.sysclk());
-        let gyro_rate = mpu9250::GyroTempDataRate::DlpfConf(mpu9250::Dlpf::_2);
         let mpu = Mpu9250::marg_with_reinit(
             spi,
             ncs,
             &mut delay,
             &mut MpuConfig::marg()
-                .gyro_temp_data_rate(gyro_rate)
-                .sample_rate_divisor(3),
+                .gyro_temp_data_rate(tuned.gyro_rate())
+                .sample_rate_divisor(tuned.divisor),
             |spi, ncs| {
                 let (dev_spi, (scl, miso, mosi)) = spi.free();
                 let new_spi = dev_spi.spi(
@@ -240,25 +514,46 @@ mod app {
         .unwrap();
         write!(tx, "mpu...\r\n").unwrap();
 
+        let bias_model = store
+            .as_ref()
+            .and_then(|s| s.read_f32s::<MODEL_LEN>(records::TEMP_BIAS))
+            .map(|v| BiasModel::from_f32s(&v));
+        write!(tx, "bias model: {:?}\r\n", bias_model).unwrap();
+        let imu_cal = store
+            .as_ref()
+            .and_then(|s| s.read_f32s::<IMU_LEN>(records::IMU))
+            .map(|v| ImuCal::from_f32s(&v));
+        write!(tx, "imu calibration: {:?}\r\n", imu_cal).unwrap();
+
         let mut led = gpiob.pb3.output().pull_type(PullNone);
         let _ = led.set_high();
         write!(tx, "led...\r\n").unwrap();
 
         let dma_channels = device.DMA1.split(&mut rcc.ahb);
         write!(tx, "dma...\r\n").unwrap();
-        let tele = DmaTelemetry::create(dma_channels.7, tx);
-        let new_tele = tele.send(|b| fill_with_str(b, "Dma ok!\r\n"));
+        let mut tx_ch = dma_channels.7;
+        tx_ch.listen(hal::dma::Event::TransferComplete);
+        let tele = DmaTelemetry::create(tx_ch, tx);
+        log(Level::Info, "Dma ok!");
 
         ctx.core.DWT.enable_cycle_counter();
 
-        calibrate::schedule(ctx.start + FAST.cycles()).unwrap();
+        let period = tuned.period_ms * CYCLES_PER_MS;
+        calibrate::schedule(ctx.start + period.cycles()).unwrap();
 
         let timer = DwtClock::new(CyclesToTime::new(clocks.sysclk()));
 
         init::LateResources {
             led,
             extih: handle,
-            tele: Some(new_tele),
+            tele,
+            drain: QUEUE.take_drain().unwrap(),
+            tuning: Tuning::new(params, store, |s, v| {
+                s.write_f32s(records::FILTER, v)
+            }),
+            applied: tuned,
+            rx,
+            shell: Shell::new(&COMMANDS),
             mpu,
             timer,
             previous_sample: MargMeasurements {
@@ -267,62 +562,191 @@ mod app {
                 mag: [0., 0., 0.],
                 temp: 0.,
             },
+            ekf: QuatEkf::with_noise(tuned.pval, tuned.qval, tuned.rval),
+            bias_model,
+            imu_cal,
+            health: Health {
+                uptime_ms: 0,
+                samples: 0,
+                sensor_errors: 0,
+            },
+            mav: mavlink::Encoder::new(1, 1),
+            failing: false,
+            events_dropped: 0,
+            elapsed_s: 0.,
         }
     }
 
-    #[task(resources = [tele, previous_sample, mpu, timer])]
+    #[task(resources = [
+        previous_sample,
+        mpu,
+        timer,
+        ekf,
+        bias_model,
+        imu_cal,
+        health,
+        mav,
+        tuning,
+        applied,
+        failing,
+        events_dropped,
+        elapsed_s,
+    ])]
     fn calibrate(mut ctx: calibrate::Context) {
         let timer = ctx.resources.timer;
         let mpu = ctx.resources.mpu;
         let previous = ctx.resources.previous_sample;
+        let ekf = ctx.resources.ekf;
+        let bias_model = ctx.resources.bias_model;
+        let imu_cal = ctx.resources.imu_cal;
+        let health = ctx.resources.health;
+        let mav = ctx.resources.mav;
+        let applied = ctx.resources.applied;
+        let failing = ctx.resources.failing;
+        let events_dropped = ctx.resources.events_dropped;
+        let elapsed_s = ctx.resources.elapsed_s;
+
+        let tuned = ctx.resources.tuning.lock(|t| Tuned::of(&t.params));
+        applied.apply(tuned, ekf, mpu);
+        // Health once a second, whatever the period
+        let before = health.uptime_ms;
+        health.uptime_ms = before.wrapping_add(tuned.period_ms);
+        let report = before / 1000 != health.uptime_ms / 1000;
+        // Events only drop with the whole queue full, say so once a second
+        let dropped = QUEUE.stats(EVENTS).dropped;
+        if report && dropped != *events_dropped {
+            let lost = dropped.wrapping_sub(*events_dropped);
+            let mut warning = Reply::at(Level::Warn);
+            let _ = write!(warning, "{} events dropped\n", lost);
+            warning.finish();
+            *events_dropped = dropped;
+        }
+        // Since the last sample the filter saw, repeated ones are skipped
+        *elapsed_s += timer.split_time_s();
+        let sample = match mpu.all::<[f32; 3]>() {
+            Ok(sample) => {
+                *failing = false;
+                sample
+            }
+            Err(_) => {
+                health.sensor_errors += 1;
+                // Once per streak, not to crowd out other events
+                if !*failing {
+                    log(Level::Error, "sensor read failed");
+                }
+                *failing = true;
+                *previous
+            }
+        };
+        if sample.accel != previous.accel
+            || sample.gyro != previous.gyro
+            || sample.mag != previous.mag
+        {
+            // Filter gets compensated sample, telemetry keeps raw one
+            let mut compensated = sample;
+            if let Some(model) = bias_model {
+                compensated.compensate(model);
+            }
+            // Temperature model knows biases better, wonca adds scales
+            if let Some(cal) = imu_cal {
+                cal.correct(
+                    &mut compensated.accel,
+                    &mut compensated.gyro,
+                    bias_model.is_some(),
+                );
+            }
+            let dt_s = *elapsed_s;
+            *elapsed_s = 0.;
+            ekf.predict(compensated.gyro, dt_s);
+            ekf.update(compensated.accel, compensated.mag);
+            *previous = sample;
+            health.samples += 1;
+            let raw = Message::RawMarg(RawMarg {
+                accel: sample.accel,
+                gyro: sample.gyro,
+                mag: sample.mag,
+                temp: sample.temp,
+                dt_s,
+            });
+            let attitude = Message::Attitude(Attitude {
+                estimator: Estimator::QuatEkf,
+                quat: Some(ekf.quat()),
+                ypr: None,
+                gyro_bias: Some(ekf.bias()),
+            });
+            let t_ms = health.uptime_ms;
+            if tuned.mavlink {
+                if report {
+                    let hb = Heartbeat::quadrotor(MAV_STATE_ACTIVE);
+                    push_mavlink(mav, &hb);
+                }
+                let att = AttitudeQuaternion {
+                    time_boot_ms: t_ms,
+                    q: ekf.quat(),
+                    rollspeed: compensated.gyro[0],
+                    pitchspeed: compensated.gyro[1],
+                    yawspeed: compensated.gyro[2],
+                };
+                let imu = RawImu::from_si(
+                    t_ms as u64 * 1000,
+                    sample.accel,
+                    sample.gyro,
+                    sample.mag,
+                    sample.temp,
+                );
+                push_mavlink(mav, &att);
+                push_mavlink(mav, &imu);
+            } else {
+                send(RAW, &[raw]);
+                send(ATTITUDE, &[attitude]);
+                if report {
+                    send(HEALTH, &[Message::Health(*health)]);
+                }
+            }
+        }
 
-        ctx.resources.tele.lock(|maybe_tele| {
-            let dt_s = timer.split_time_s();
-            let sample = mpu.all::<[f32; 3]>().unwrap_or(*previous);
-            if sample.accel != previous.accel
-                || sample.gyro != previous.gyro
-                || sample.mag != previous.mag
-            {
-                if let Some(tele) = maybe_tele.take() {
-                    let new_tele = tele.send(|buffer| {
-                        // ax,ay,az,gx,gy,gz,mx,my,mz,temp,dt_s
-                        let flts = sample
-                            .accel
-                            .iter()
-                            .chain(sample.gyro.iter())
-                            .chain(sample.mag.iter())
-                            .chain(core::iter::once(&sample.temp))
-                            .chain(core::iter::once(&dt_s));
-                        // ignore errors around buffer manipulation
-                        for f in flts {
-                            let _ = buffer.extend_from_slice(
-                                ryu::Buffer::new().format(*f).as_bytes(),
-                            );
-                            let _ = buffer.push(b';');
-                        }
-                        let _ = buffer.push(b'\r');
-                        let _ = buffer.push(b'\n');
-                    });
-
-                    *maybe_tele = Some(new_tele);
+        let period = tuned.period_ms * CYCLES_PER_MS;
+        calibrate::schedule(ctx.scheduled + period.cycles()).unwrap();
+    }
+
+    #[task(binds = USART2_EXTI26, resources = [tuning, rx, shell])]
+    fn serial_rx(mut ctx: serial_rx::Context) {
+        let rx = ctx.resources.rx;
+        let shell = ctx.resources.shell;
+        let b = match rx.read() {
+            Ok(b) => b,
+            Err(nb::Error::WouldBlock) => return,
+            Err(nb::Error::Other(e)) => {
+                match e {
+                    hal::serial::Error::Overrun => rx.clear_overrun_error(),
+                    hal::serial::Error::Framing => rx.clear_framing_error(),
+                    hal::serial::Error::Noise => rx.clear_noise_error(),
+                    _ => {}
                 }
+                return;
             }
+        };
+        let mut reply = Reply::new();
+        ctx.resources.tuning.lock(|tuning| {
+            // Reply never refuses text
+            let _ = shell.push(b, tuning, &mut reply);
         });
-
-        calibrate::schedule(ctx.scheduled + FAST.cycles()).unwrap();
+        reply.finish();
     }
 
-    #[task(binds=EXTI0, resources = [led, tele, extih])]
+    #[task(binds=EXTI0, resources = [led, extih])]
     fn handle_interrupt(mut ctx: handle_interrupt::Context) {
         ctx.resources.led.lock(|led| {
             let _ = led.set_low();
         });
-        ctx.resources.tele.lock(|maybe_tele| {
-            if let Some(tele) = maybe_tele.take() {
-                let new_tele = tele.send(|b| fill_with_str(b, "interrupt!\n"));
-                *maybe_tele = Some(new_tele);
-            }
-        });
+        log(Level::Info, "interrupt!");
         ctx.resources.extih.lock(|extih| extih.unpend());
     }
+
+    /// Transfer done or messages queued, keeps DMA busy while there are.
+    /// Above the tasks pushing, slots free up as soon as DMA takes them.
+    #[task(binds = DMA1_CH7, priority = 2, resources = [tele, drain])]
+    fn send_telemetry(ctx: send_telemetry::Context) {
+        ctx.resources.tele.send_queued(ctx.resources.drain);
+    }
 }
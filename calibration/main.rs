@@ -15,7 +15,16 @@ use hal::time::Bps;
 
 use mpu9250::Mpu9250;
 
-static mut L: Option<hal::serial::Tx<hal::pac::USART2>> = None;
+#[path = "../storage/mod.rs"]
+mod storage;
+use storage::stm32::InternalFlash;
+use storage::{records, Store};
+
+#[path = "../cobs/mod.rs"]
+mod cobs;
+use cobs::FrameWriter;
+
+static mut L: Option<FrameWriter<hal::serial::Tx<hal::pac::USART2>>> = None;
 static mut RX: Option<hal::serial::Rx<hal::pac::USART2>> = None;
 static mut QUIET: bool = true;
 static mut NOW_MS: u32 = 0;
@@ -41,13 +50,13 @@ fn main() -> ! {
             .serial((gpioa.pa2, gpioa.pa15), Bps(460800), clocks);
     let ser_int = serial.get_interrupt();
     serial.listen(serial::Event::Rxne);
-    let (mut tx, rx) = serial.split();
-    writeln!(tx, "tx ok").unwrap();
+    let (tx, rx) = serial.split();
     unsafe {
-        L = Some(tx);
+        L = Some(FrameWriter::start(tx).unwrap());
         RX = Some(rx);
     };
     let l = unsafe { extract(&mut L) };
+    writeln!(l, "tx ok").unwrap();
     writeln!(l, "logger ok").unwrap();
     // SPI1
     let ncs = gpiob.pb0.output().push_pull();
@@ -92,20 +101,15 @@ fn main() -> ! {
 
     write!(l, "{} {}\r\n", clocks.sysclk().0, reload).unwrap();
 
-    // EEPROM this
-    let mag_offs = [
-        0., 0.,
-        0.,
-        // Dev1
-        // 400.149575,
-        // -75.52005,
-        // -145.13623,
-
-        // // Dev2
-        // 505.4114185,
-        // 503.12718,
-        // 291.256415,
-    ];
+    // Hard iron offsets of the magnetometer calibration saved by ahrs-ekf
+    let stored = Store::new(unsafe { InternalFlash::new() })
+        .ok()
+        .and_then(|s| s.read_f32s::<12>(records::MAG));
+    let mag_offs = match stored {
+        Some(v) => [v[9], v[10], v[11]],
+        None => [0.; 3],
+    };
+    write!(l, "mag offsets: {:?}\r\n", mag_offs).unwrap();
 
     loop {
         let t_ms = now_ms();
@@ -130,7 +134,7 @@ fn main() -> ! {
                 while now_ms() < t_ms + 100 {}
             }
             Err(e) => {
-                write!(l, "Err: {:?}; {:?}", t_ms, e).unwrap();
+                write!(l, "Err: {:?}; {:?}\r\n", t_ms, e).unwrap();
             }
         }
     }
@@ -170,7 +174,7 @@ fn USART2_EXTI26() {
                 rx.clear_noise_error();
             }
             _ => {
-                write!(l, "read error: {:?}", e).unwrap();
+                write!(l, "read error: {:?}\r\n", e).unwrap();
             }
         },
     };
@@ -188,14 +192,14 @@ unsafe fn SysTick() {
 #[exception]
 unsafe fn HardFault(ef: &ExceptionFrame) -> ! {
     let l = extract(&mut L);
-    write!(l, "hard fault at {:?}", ef).unwrap();
+    write!(l, "hard fault at {:?}\r\n", ef).unwrap();
     panic!("HardFault at {:#?}", ef);
 }
 
 #[exception]
 unsafe fn DefaultHandler(irqn: i16) {
     let l = extract(&mut L);
-    write!(l, "Interrupt: {}", irqn).unwrap();
+    write!(l, "Interrupt: {}\r\n", irqn).unwrap();
 }
 
 #[panic_handler]
@@ -230,6 +234,7 @@ fn panic(panic_info: &PanicInfo) -> ! {
                     write!(l, "panic occured, no info available").unwrap();
                 }
             }
+            let _ = l.end_frame();
         }
         None => {}
     }
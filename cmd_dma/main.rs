@@ -1,6 +1,5 @@
 #![no_std]
 #![no_main]
-#![feature(unboxed_closures)]
 
 #[allow(unused)]
 use panic_abort;
@@ -11,127 +10,117 @@ use rtic::app;
 use hal::gpio::{LowSpeed, Output, PullNone, PushPull};
 use hal::prelude::*;
 use hal::time::Bps;
-use heapless::consts::*;
 use heapless::spsc::{Consumer, Producer, Queue};
-use heapless::Vec;
-
-type USART = hal::pac::USART2;
-type TxUsart = hal::serial::Tx<USART>;
-type TxCh = hal::dma::dma1::C7;
-type TxBuffer = Vec<u8, U256>;
-type TxReady = (&'static mut TxBuffer, TxCh, TxUsart);
-type TxBusy =
-    hal::dma::Transfer<hal::dma::R, &'static mut TxBuffer, TxCh, TxUsart>;
-static mut BUFFER: TxBuffer = Vec(heapless::i::Vec::new());
-static mut QUEUE: Queue<u8, U16> = Queue(heapless::i::Queue::new());
-
-enum TransferState {
-    Ready(TxReady),
-    MaybeBusy(TxBusy),
-}
+use heapless::String;
 
-pub struct DmaTelemetry {
-    state: TransferState,
-}
+#[path = "../cobs/mod.rs"]
+mod cobs;
+#[path = "../dma/mod.rs"]
+mod dma;
+#[path = "../shell/mod.rs"]
+mod shell;
 
-impl DmaTelemetry {
-    fn with_state(ns: TransferState) -> Self {
-        DmaTelemetry { state: ns }
-    }
+use dma::{DmaReceiver, DmaTelemetry};
+use shell::{Args, Command, Error, Params, Shell, Value};
+
+// Frames from the DMA interrupts for the shell in idle, holds one byte
+// less than its size
+static mut QUEUE: Queue<u8, 128> = Queue::new();
+
+const LINE_SIZE: usize = 128;
+
+pub struct Settings {
+    repeat: u32,
+    upper: bool,
 }
 
-impl DmaTelemetry {
-    pub fn create(ch: TxCh, tx: TxUsart) -> Self {
-        let bf = unsafe { &mut BUFFER };
-        let state = TransferState::Ready((bf, ch, tx));
-        DmaTelemetry::with_state(state)
+impl Params for Settings {
+    fn names(&self) -> &[&'static str] {
+        &["repeat", "upper"]
     }
 
-    fn send<F>(self, mut buffer_filler: F) -> Self
-    where
-        F: for<'a> FnMut<(&'a mut TxBuffer,), Output = ()>,
-    {
-        let ns = match self.state {
-            TransferState::Ready((mut buffer, ch, tx)) => {
-                buffer_filler(&mut buffer);
-                TransferState::MaybeBusy(tx.write_all(ch, buffer))
-            }
-            TransferState::MaybeBusy(transfer) => {
-                if transfer.is_done() {
-                    let (buffer, ch, tx) = transfer.wait();
-                    buffer.clear();
-                    TransferState::Ready((buffer, ch, tx))
-                } else {
-                    TransferState::MaybeBusy(transfer)
-                }
-            }
-        };
+    fn get(&self, name: &str) -> Option<Value> {
+        match name {
+            "repeat" => Some(Value::U32(self.repeat)),
+            "upper" => Some(Value::Bool(self.upper)),
+            _ => None,
+        }
+    }
 
-        match ns {
-            TransferState::MaybeBusy(_) => DmaTelemetry::with_state(ns),
-            TransferState::Ready(_) => {
-                DmaTelemetry::with_state(ns).send(buffer_filler)
+    fn set(&mut self, name: &str, value: Value) -> Result<(), Error> {
+        match (name, value) {
+            ("repeat", Value::U32(v)) if (1..=4).contains(&v) => {
+                self.repeat = v
             }
+            ("repeat", _) => return Err(Error::OutOfRange),
+            ("upper", Value::Bool(v)) => self.upper = v,
+            _ => return Err(Error::UnknownParam),
         }
+        Ok(())
     }
 }
 
-const BUFFER_SIZE: usize = 512;
-const CR: u8 = '\r' as u8;
-const LF: u8 = '\n' as u8;
-
-pub struct Cmd {
-    buffer: [u8; BUFFER_SIZE],
-    pos: usize,
-}
-
-impl Cmd {
-    #[inline]
-    const fn new() -> Cmd {
-        Cmd {
-            buffer: [0; BUFFER_SIZE],
-            pos: 0,
+fn echo(
+    settings: &mut Settings,
+    args: &mut Args,
+    out: &mut dyn Write,
+) -> Result<(), Error> {
+    for _ in 0..settings.repeat {
+        for word in args.clone() {
+            for c in word.chars() {
+                let c = if settings.upper {
+                    c.to_ascii_uppercase()
+                } else {
+                    c
+                };
+                out.write_char(c)?;
+            }
+            out.write_char(' ')?;
         }
+        out.write_str("\r\n")?;
     }
+    Ok(())
+}
 
-    #[inline]
-    fn push(&mut self, b: u8) -> Option<&[u8]> {
-        if b == CR || b == LF {
-            if self.pos == 0 {
-                None
-            } else {
-                let result = &self.buffer[0..self.pos];
-                self.pos = 0;
-                Some(result)
-            }
-        } else {
-            self.buffer[self.pos] = b;
-            self.pos = (self.pos + 1) & (BUFFER_SIZE - 1);
-            None
+static COMMANDS: [Command<Settings>; 1] = [Command {
+    name: "echo",
+    usage: "<words>",
+    help: "say words back `repeat` times",
+    run: echo,
+}];
+
+/// Bytes of a received frame for the shell
+fn enqueue(p: &mut Producer<'static, u8, 128>, frame: &[u8]) {
+    for &b in frame {
+        // Shell is behind, the rest of the frame is lost
+        if p.enqueue(b).is_err() {
+            break;
         }
     }
 }
 
-fn fill_with_bytes(buffer: &mut TxBuffer, arg: &[u8]) {
-    buffer.extend_from_slice(arg).unwrap();
-}
+#[app(device = hal::pac, peripherals = true)]
+mod app {
+    use super::*;
 
-fn fill_with_str(buffer: &mut TxBuffer, arg: &str) {
-    buffer.extend_from_slice(arg.as_bytes()).unwrap();
-}
+    #[local]
+    struct Local {
+        led: hal::gpio::PA5<PullNone, Output<PushPull, LowSpeed>>,
+        tele: DmaTelemetry,
+        c: Consumer<'static, u8, 128>,
+    }
+
+    // Both handlers run at the same priority
+    #[shared]
+    struct Shared {
+        #[lock_free]
+        rx: DmaReceiver,
+        #[lock_free]
+        p: Producer<'static, u8, 128>,
+    }
 
-#[app(device = hal::pac)]
-const APP: () = {
-    static mut LED: hal::gpio::PA5<PullNone, Output<PushPull, LowSpeed>> = ();
-    static mut TELE: Option<DmaTelemetry> = ();
-    static mut RX: hal::serial::Rx<USART> = ();
-    static mut CMD: Cmd = ();
-    static mut P: Producer<'static, u8, U16> = ();
-    static mut C: Consumer<'static, u8, U16> = ();
-
-    #[init]
-    fn init(ctx: init::Context) -> init::LateResources {
-        cortex_m_semihosting::hprintln!("init").unwrap();
+    #[init()]
+    fn init(ctx: init::Context) -> (Shared, Local, init::Monotonics) {
         let device: hal::pac::Peripherals = ctx.device;
         let mut rcc = device.RCC.constrain();
         let mut flash = device.FLASH.constrain();
@@ -141,65 +130,59 @@ const APP: () = {
             .pclk1(32.mhz())
             .freeze(&mut flash.acr);
         let gpioa = device.GPIOA.split(&mut rcc.ahb);
-        let mut serial =
+        let serial =
             device
                 .USART2
                 .serial((gpioa.pa2, gpioa.pa15), Bps(460800), clocks);
-        cortex_m_semihosting::hprintln!("serial").unwrap();
-        serial.listen(hal::serial::Event::Rxne);
-        cortex_m_semihosting::hprintln!("listen").unwrap();
         let (mut tx, rx) = serial.split();
         write!(tx, "init...\r\n").unwrap();
         let dma_channels = device.DMA1.split(&mut rcc.ahb);
-        write!(tx, "dma...\r\n").unwrap();
-        let tele = DmaTelemetry::create(dma_channels.7, tx);
-        let new_tele = tele.send(|b| fill_with_str(b, "Dma ok!\r\n"));
+        let mut tele = DmaTelemetry::create(dma_channels.7, tx);
+        tele.write_frame(b"Dma ok!\r\n");
         let mut led = gpioa.pa5.output().pull_type(PullNone);
         let _ = led.set_low();
-        cortex_m_semihosting::hprintln!("init done").unwrap();
+        let rx = DmaReceiver::create(dma_channels.6, rx);
 
         let (p, c) = unsafe { QUEUE.split() };
-        init::LateResources {
-            LED: led,
-            RX: rx,
-            CMD: Cmd::new(),
-            TELE: Some(new_tele),
-            P: p,
-            C: c,
-        }
+        (Shared { rx, p }, Local { led, tele, c }, init::Monotonics())
     }
 
-    #[idle(resources=[C, CMD, TELE])]
+    #[idle(local = [tele, c])]
     fn idle(ctx: idle::Context) -> ! {
-        let cmd = ctx.resources.CMD;
+        let tele = ctx.local.tele;
+        let mut shell = Shell::<Settings, LINE_SIZE>::new(&COMMANDS);
+        let mut settings = Settings {
+            repeat: 1,
+            upper: false,
+        };
+        let mut reply: String<256> = String::new();
         loop {
-            if let Some(byte) = ctx.resources.C.dequeue() {
-                if let Some(word) = cmd.push(byte) {
-                    let maybe_tele = ctx.resources.TELE.take();
-                    if let Some(tele) = maybe_tele {
-                        let new_tele = tele.send(|b| fill_with_bytes(b, word));
-                        *ctx.resources.TELE = Some(new_tele);
-                    }
+            // Sends replies that piled up during the previous transfer
+            tele.poll();
+            if let Some(byte) = ctx.local.c.dequeue() {
+                // Reply is cut off where it doesn't fit
+                let _ = shell.push(byte, &mut settings, &mut reply);
+                if reply.is_empty() {
+                    continue;
                 }
+                tele.write_frame(reply.as_bytes());
+                reply.clear();
             }
         }
     }
 
-    #[interrupt(binds=USART2_EXTI26, resources = [LED, RX, P])]
-    fn handle_rx(ctx: handle_rx::Context) {
-        let led = ctx.resources.LED;
-        let rx = ctx.resources.RX;
+    /// Half or full buffer
+    #[task(binds = DMA1_CH6, shared = [rx, p])]
+    fn handle_dma(ctx: handle_dma::Context) {
+        let p = ctx.shared.p;
+        ctx.shared.rx.poll(|frame| enqueue(p, frame));
+    }
 
-        let _ = led.toggle();
-        match rx.read() {
-            Ok(b) => {
-                if let Err(e) = ctx.resources.P.enqueue(b) {
-                    cortex_m_semihosting::hprintln!("err: {:?}", e).unwrap();
-                }
-            }
-            Err(e) => {
-                cortex_m_semihosting::hprintln!("err: {:?}", e).unwrap();
-            }
-        }
+    /// Line went idle, sender is done with a command
+    #[task(binds = USART2_EXTI26, local = [led], shared = [rx, p])]
+    fn handle_idle(ctx: handle_idle::Context) {
+        let _ = ctx.local.led.toggle();
+        let p = ctx.shared.p;
+        ctx.shared.rx.poll(|frame| enqueue(p, frame));
     }
-};
+}
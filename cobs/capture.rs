@@ -0,0 +1,27 @@
+// Text from a capture of framed board output, for host tools. Needs std,
+// mount it next to `crc.rs` and `decode.rs`.
+
+use super::decode::Decoder;
+
+// Larger than any DMA buffer in the tree
+const MAX_FRAME: usize = 4096;
+
+/// Payloads of the COBS frames in `bytes` one after another, and how many
+/// frames were broken. A capture without zeros has no frames, it's taken as
+/// text `unframe` already decoded.
+pub fn text(bytes: &[u8]) -> (String, usize) {
+    if !bytes.contains(&0) {
+        return (String::from_utf8_lossy(bytes).into_owned(), 0);
+    }
+    let mut dec = Decoder::<MAX_FRAME>::new();
+    let mut out = Vec::new();
+    let mut broken = 0;
+    for &b in bytes {
+        match dec.push(b) {
+            None => {}
+            Some(Ok(payload)) => out.extend_from_slice(payload),
+            Some(Err(_)) => broken += 1,
+        }
+    }
+    (String::from_utf8_lossy(&out).into_owned(), broken)
+}
@@ -0,0 +1,208 @@
+// Round trips frames through both encoders and the decoder, corrupts the
+// stream and checks the decoder recovers at the next frame:
+//     rustc --edition 2021 -O check.rs && ./check
+// Panics on failure.
+
+#![allow(dead_code)]
+
+#[path = "crc.rs"]
+mod crc;
+#[path = "decode.rs"]
+mod decode;
+#[path = "encode.rs"]
+mod encode;
+
+use crc::crc16;
+use decode::{Decoder, Error};
+use encode::{encode_in_place, frame_len, max_payload, Encoder};
+
+const MAX: usize = 1024;
+
+/// xorshift64*
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() >> 33) as usize % n
+    }
+
+    /// Random bytes, `zeros` in 256 of them are zero
+    fn payload(&mut self, len: usize, zeros: usize) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                if self.below(256) < zeros {
+                    0
+                } else {
+                    1 + self.below(255) as u8
+                }
+            })
+            .collect()
+    }
+}
+
+fn in_place(payload: &[u8]) -> Vec<u8> {
+    let mut buf = vec![0xaa; frame_len(payload.len())];
+    buf[..payload.len()].copy_from_slice(payload);
+    let n = encode_in_place(&mut buf, payload.len()).expect("doesn't fit");
+    buf.truncate(n);
+    buf
+}
+
+fn streamed(payload: &[u8]) -> Vec<u8> {
+    let mut enc = Encoder::new();
+    let mut out = Vec::new();
+    let mut sink = |b| -> Result<(), ()> {
+        out.push(b);
+        Ok(())
+    };
+    for b in payload {
+        enc.push(*b, &mut sink).unwrap();
+    }
+    enc.finish(&mut sink).unwrap();
+    out
+}
+
+fn decode_all(
+    dec: &mut Decoder<MAX>,
+    stream: &[u8],
+) -> Vec<Result<Vec<u8>, Error>> {
+    stream
+        .iter()
+        .filter_map(|b| dec.push(*b).map(|r| r.map(|p| p.to_vec())))
+        .collect()
+}
+
+fn crc() {
+    assert_eq!(crc16(b"123456789"), 0x29b1);
+    assert_eq!(crc16(&[]), 0xffff);
+    println!("crc ok");
+}
+
+fn roundtrip() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    let mut dec = Decoder::<MAX>::new();
+    let lens = (0..600).chain([762, 1016, MAX - 2]);
+    for len in lens {
+        for zeros in [0, 1, 16, 128, 256] {
+            let payload = rng.payload(len, zeros);
+            let frame = in_place(&payload);
+            assert_eq!(frame, streamed(&payload), "encoders differ at {}", len);
+            assert_eq!(frame.last(), Some(&0));
+            assert!(!frame[..frame.len() - 1].contains(&0), "zero inside");
+            assert!(frame.len() <= frame_len(len));
+            assert_eq!(decode_all(&mut dec, &frame), vec![Ok(payload)]);
+        }
+    }
+    // Worst case is exact
+    let frame = in_place(&[0xff; 252]);
+    assert_eq!(frame.len(), frame_len(252));
+    println!("roundtrip ok");
+}
+
+fn capacity() {
+    assert_eq!(max_payload(256), 251);
+    for cap in 4..600 {
+        let len = max_payload(cap);
+        assert!(frame_len(len) <= cap && frame_len(len + 1) > cap);
+        let mut buf = vec![0x55; cap];
+        assert!(encode_in_place(&mut buf, len).is_some());
+        assert_eq!(encode_in_place(&mut buf, len + 1), None);
+    }
+    println!("capacity ok");
+}
+
+fn stream_of(rng: &mut Rng, frames: usize) -> (Vec<Vec<u8>>, Vec<u8>) {
+    let payloads: Vec<Vec<u8>> = (0..frames)
+        .map(|_| {
+            let len = rng.below(300);
+            rng.payload(len, 8)
+        })
+        .collect();
+    // Leading zero like the binaries send on start
+    let mut stream = vec![0];
+    for p in &payloads {
+        stream.extend(in_place(p));
+    }
+    (payloads, stream)
+}
+
+/// Every kind of damage costs at most the frame it hits and the one after
+fn resync() {
+    let mut rng = Rng(42);
+    let mut dec = Decoder::<MAX>::new();
+    let (payloads, stream) = stream_of(&mut rng, 400);
+    let ok = decode_all(&mut dec, &stream);
+    assert_eq!(ok, payloads.iter().cloned().map(Ok).collect::<Vec<_>>());
+
+    let mut lost_total = 0;
+    for round in 0..2000 {
+        let mut bad = stream.clone();
+        let at = 1 + rng.below(bad.len() - 1);
+        // Frame ends taken out merge frames, each costs one more
+        let mut merged = 0;
+        match round % 4 {
+            0 => bad[at] ^= 1 << rng.below(8),
+            1 => merged = (bad.remove(at) == 0) as usize,
+            2 => bad.insert(at, rng.below(256) as u8),
+            _ => {
+                let n = rng.below(40).min(bad.len() - at);
+                merged = bad.drain(at..at + n).filter(|b| *b == 0).count();
+            }
+        }
+        // Start mid-stream too, receiver powered up late
+        let from = if round % 7 == 0 { rng.below(at) } else { 0 };
+        dec.reset();
+        let good: Vec<Vec<u8>> = decode_all(&mut dec, &bad[from..])
+            .into_iter()
+            .filter_map(|r| r.ok())
+            .collect();
+        // Decoded frames are real ones in order
+        let mut it = payloads.iter();
+        for g in &good {
+            assert!(it.any(|p| p == g), "bogus frame in round {}", round);
+        }
+        // Frames ended before the start are not lost, the one cut is
+        let ends = stream[..from].iter().filter(|b| **b == 0).count();
+        let skipped = ends.saturating_sub(1);
+        let lost = payloads.len() - skipped - good.len();
+        let allowed = 2 + merged + (from > 0) as usize;
+        assert!(lost <= allowed, "lost {} frames in round {}", lost, round);
+        lost_total += lost;
+    }
+    println!("resync ok, {} frames lost in 2000 corruptions", lost_total);
+}
+
+fn errors() {
+    let mut dec = Decoder::<16>::new();
+    let mut push_all = |bytes: &[u8]| -> Vec<Result<Vec<u8>, Error>> {
+        bytes
+            .iter()
+            .filter_map(|b| dec.push(*b).map(|r| r.map(|p| p.to_vec())))
+            .collect()
+    };
+    assert_eq!(push_all(&[0, 0, 0]), vec![]);
+    assert_eq!(push_all(&in_place(&[7; 20])), vec![Err(Error::Overflow)]);
+    assert_eq!(push_all(&[5, 1, 2, 0]), vec![Err(Error::Truncated)]);
+    assert_eq!(push_all(&[2, 1, 0]), vec![Err(Error::Short)]);
+    let mut frame = in_place(b"hello");
+    frame[2] ^= 0x20;
+    assert_eq!(push_all(&frame), vec![Err(Error::Crc)]);
+    assert_eq!(push_all(&in_place(b"hello")), vec![Ok(b"hello".to_vec())]);
+    assert_eq!(push_all(&in_place(b"")), vec![Ok(vec![])]);
+    println!("errors ok");
+}
+
+fn main() {
+    crc();
+    roundtrip();
+    capacity();
+    resync();
+    errors();
+}
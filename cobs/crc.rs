@@ -0,0 +1,22 @@
+//! CRC-16/CCITT-FALSE, bitwise to keep it out of flash tables.
+
+const POLY: u16 = 0x1021;
+pub const INIT: u16 = 0xffff;
+/// Bytes the CRC adds to every frame
+pub const CRC_LEN: usize = 2;
+
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(INIT, |crc, b| update(crc, *b))
+}
+
+pub fn update(mut crc: u16, b: u8) -> u16 {
+    crc ^= (b as u16) << 8;
+    for _ in 0..8 {
+        crc = if crc & 0x8000 != 0 {
+            (crc << 1) ^ POLY
+        } else {
+            crc << 1
+        };
+    }
+    crc
+}
@@ -0,0 +1,102 @@
+//! Streaming decoder, byte by byte from a serial port or a capture.
+
+use super::crc::{self, CRC_LEN};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /// Frame didn't fit the buffer and was dropped
+    Overflow,
+    /// Zero came in the middle of a block, bytes were lost
+    Truncated,
+    /// Shorter than the CRC
+    Short,
+    Crc,
+}
+
+/// Collects one frame of up to `N` decoded bytes (payload and CRC). Any
+/// error drops the frame, decoding resumes at the next one.
+pub struct Decoder<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    /// Data bytes left in the current block
+    left: u8,
+    /// Zero is implied before the next block
+    zero: bool,
+    started: bool,
+    overflow: bool,
+}
+
+impl<const N: usize> Decoder<N> {
+    pub const fn new() -> Self {
+        Decoder {
+            buf: [0; N],
+            len: 0,
+            left: 0,
+            zero: false,
+            started: false,
+            overflow: false,
+        }
+    }
+
+    /// Drops the partial frame
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.left = 0;
+        self.zero = false;
+        self.started = false;
+        self.overflow = false;
+    }
+
+    /// Feeds a received byte, returns payload or error at the end of each
+    /// frame. Empty frames (repeated zeros) are skipped.
+    pub fn push(&mut self, b: u8) -> Option<Result<&[u8], Error>> {
+        if b == 0 {
+            return self.end();
+        }
+        self.started = true;
+        if self.left == 0 {
+            if self.zero {
+                self.append(0);
+            }
+            self.left = b - 1;
+            self.zero = b != 0xff;
+        } else {
+            self.append(b);
+            self.left -= 1;
+        }
+        None
+    }
+
+    fn append(&mut self, b: u8) {
+        if self.len < N {
+            self.buf[self.len] = b;
+            self.len += 1;
+        } else {
+            self.overflow = true;
+        }
+    }
+
+    fn end(&mut self) -> Option<Result<&[u8], Error>> {
+        let (started, overflow, left, len) =
+            (self.started, self.overflow, self.left, self.len);
+        self.reset();
+        if !started {
+            return None;
+        }
+        if overflow {
+            return Some(Err(Error::Overflow));
+        }
+        if left != 0 {
+            return Some(Err(Error::Truncated));
+        }
+        if len < CRC_LEN {
+            return Some(Err(Error::Short));
+        }
+        let n = len - CRC_LEN;
+        let expected = u16::from_be_bytes([self.buf[n], self.buf[n + 1]]);
+        if crc::crc16(&self.buf[..n]) != expected {
+            return Some(Err(Error::Crc));
+        }
+        Some(Ok(&self.buf[..n]))
+    }
+}
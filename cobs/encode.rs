@@ -0,0 +1,124 @@
+//! Encoders: in place for DMA buffers, streaming for blocking writers.
+
+use super::crc::{self, CRC_LEN};
+
+/// Longest run of non-zero bytes a block holds
+const BLOCK: usize = 254;
+
+/// Frame length for `len` payload bytes at worst: CRC, COBS overhead and
+/// the trailing zero
+pub const fn frame_len(len: usize) -> usize {
+    let n = len + CRC_LEN;
+    n + 1 + n / BLOCK + 1
+}
+
+/// Longest payload that always fits `capacity` bytes once framed
+pub const fn max_payload(capacity: usize) -> usize {
+    let mut len = capacity;
+    while len > 0 && frame_len(len) > capacity {
+        len -= 1;
+    }
+    len
+}
+
+/// Frames the `len` payload bytes at the start of `buf`, which has to hold
+/// `frame_len(len)`. Returns frame length, `None` if it doesn't fit.
+pub fn encode_in_place(buf: &mut [u8], len: usize) -> Option<usize> {
+    if frame_len(len) > buf.len() {
+        return None;
+    }
+    let crc = crc::crc16(&buf[..len]);
+    let n = len + CRC_LEN;
+    buf[len..n].copy_from_slice(&crc.to_be_bytes());
+    // Move data past the overhead, output never overtakes input then
+    let shift = 1 + n / BLOCK;
+    buf.copy_within(0..n, shift);
+    let mut code_at = 0;
+    let mut out = 1;
+    for i in shift..shift + n {
+        let b = buf[i];
+        if b != 0 {
+            buf[out] = b;
+            out += 1;
+        }
+        if b == 0 || out - code_at == BLOCK + 1 {
+            buf[code_at] = (out - code_at) as u8;
+            code_at = out;
+            out += 1;
+        }
+    }
+    buf[code_at] = (out - code_at) as u8;
+    buf[out] = 0;
+    Some(out + 1)
+}
+
+/// Streaming encoder, frames bytes as they come and passes encoded ones to
+/// `out`. Holds one block.
+pub struct Encoder {
+    block: [u8; BLOCK],
+    len: usize,
+    crc: u16,
+}
+
+impl Encoder {
+    pub const fn new() -> Self {
+        Encoder {
+            block: [0; BLOCK],
+            len: 0,
+            crc: crc::INIT,
+        }
+    }
+
+    /// Adds a payload byte
+    pub fn push<E>(
+        &mut self,
+        b: u8,
+        out: &mut impl FnMut(u8) -> Result<(), E>,
+    ) -> Result<(), E> {
+        self.crc = crc::update(self.crc, b);
+        self.put(b, out)
+    }
+
+    /// Appends the CRC and terminates the frame
+    pub fn finish<E>(
+        &mut self,
+        out: &mut impl FnMut(u8) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let crc = self.crc;
+        self.crc = crc::INIT;
+        for b in crc.to_be_bytes() {
+            self.put(b, out)?;
+        }
+        self.flush(out)?;
+        out(0)
+    }
+
+    fn put<E>(
+        &mut self,
+        b: u8,
+        out: &mut impl FnMut(u8) -> Result<(), E>,
+    ) -> Result<(), E> {
+        if b == 0 {
+            return self.flush(out);
+        }
+        self.block[self.len] = b;
+        self.len += 1;
+        if self.len == BLOCK {
+            self.flush(out)?;
+        }
+        Ok(())
+    }
+
+    fn flush<E>(
+        &mut self,
+        out: &mut impl FnMut(u8) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let len = self.len;
+        self.len = 0;
+        out(len as u8 + 1)?;
+        for b in &self.block[..len] {
+            out(*b)?;
+        }
+        Ok(())
+    }
+}
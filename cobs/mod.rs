@@ -0,0 +1,22 @@
+//! COBS framing for serial telemetry.
+//!
+//! Each frame is the payload followed by its CRC16 (big endian), COBS
+//! encoded and terminated by a zero byte. No zeros occur inside a frame, so
+//! a receiver that lost bytes drops the broken frame and picks up again at
+//! the next zero.
+//!
+//! Include with `#[path = "../cobs/mod.rs"] mod cobs;`. `check.rs` tests
+//! encoder and decoder on the host, `unframe.rs` decodes captured output.
+
+// Binaries use only some of it
+#![allow(dead_code, unused_imports)]
+
+mod crc;
+mod decode;
+mod encode;
+mod writer;
+
+pub use crc::crc16;
+pub use decode::{Decoder, Error};
+pub use encode::{encode_in_place, frame_len, max_payload, Encoder};
+pub use writer::FrameWriter;
@@ -0,0 +1,77 @@
+// Decodes framed output captured from a board, prints payloads as text and
+// reports broken frames:
+//     rustc --edition 2021 -O unframe.rs && ./unframe capture.bin
+// Reads stdin without a file, so it also works on a port:
+//     stty -F /dev/ttyUSB0 460800 raw && ./unframe < /dev/ttyUSB0
+// `--hex` prints payloads in hex for binary telemetry.
+
+#![allow(dead_code)]
+
+#[path = "crc.rs"]
+mod crc;
+#[path = "decode.rs"]
+mod decode;
+
+use std::io::{self, BufReader, Read, Write};
+
+use decode::{Decoder, Error};
+
+// Larger than any DMA buffer in the tree
+const MAX_FRAME: usize = 4096;
+
+fn main() -> io::Result<()> {
+    let mut hex = false;
+    let mut path = None;
+    for arg in std::env::args().skip(1) {
+        if arg == "--hex" {
+            hex = true;
+        } else {
+            path = Some(arg);
+        }
+    }
+    let input: Box<dyn Read> = match path {
+        Some(path) => Box::new(std::fs::File::open(path)?),
+        None => Box::new(io::stdin()),
+    };
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut dec = Decoder::<MAX_FRAME>::new();
+    let mut frames = 0;
+    let mut errors = [0usize; 4];
+    for b in BufReader::new(input).bytes() {
+        match dec.push(b?) {
+            None => {}
+            Some(Ok(payload)) => {
+                frames += 1;
+                if hex {
+                    for b in payload {
+                        write!(out, "{:02x}", b)?;
+                    }
+                    writeln!(out)?;
+                } else {
+                    out.write_all(payload)?;
+                }
+                out.flush()?;
+            }
+            Some(Err(e)) => {
+                let i = match e {
+                    Error::Overflow => 0,
+                    Error::Truncated => 1,
+                    Error::Short => 2,
+                    Error::Crc => 3,
+                };
+                errors[i] += 1;
+                eprintln!(
+                    "frame {}: {:?}",
+                    frames + errors.iter().sum::<usize>(),
+                    e
+                );
+            }
+        }
+    }
+    eprintln!(
+        "{} frames, dropped: {} overflow, {} truncated, {} short, {} crc",
+        frames, errors[0], errors[1], errors[2], errors[3]
+    );
+    Ok(())
+}
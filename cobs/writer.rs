@@ -0,0 +1,85 @@
+//! Framing blocking writer for serial `Tx`.
+
+use core::fmt;
+
+use ehal::serial;
+
+use super::encode::Encoder;
+
+/// Wraps a blocking serial writer. Text written through `core::fmt::Write`
+/// goes out one frame per line, a frame ends after each `\n`.
+pub struct FrameWriter<W> {
+    w: W,
+    enc: Encoder,
+    /// Bytes went out since the last frame ended
+    open: bool,
+}
+
+impl<W: serial::Write<u8>> FrameWriter<W> {
+    pub fn new(w: W) -> Self {
+        FrameWriter {
+            w,
+            enc: Encoder::new(),
+            open: false,
+        }
+    }
+
+    /// Like `new`, first sending a zero that ends whatever the receiver
+    /// caught before reset so the first frame decodes
+    pub fn start(mut w: W) -> Result<Self, W::Error> {
+        nb::block!(w.write(0))?;
+        Ok(Self::new(w))
+    }
+
+    /// Sends `payload` as a frame of its own, ending any started one first
+    pub fn write_frame(&mut self, payload: &[u8]) -> Result<(), W::Error> {
+        self.end_frame()?;
+        let w = &mut self.w;
+        for b in payload {
+            self.enc.push(*b, &mut |b| nb::block!(w.write(b)))?;
+        }
+        self.enc.finish(&mut |b| nb::block!(w.write(b)))
+    }
+
+    /// Ends the frame started by text without a trailing newline, if any
+    pub fn end_frame(&mut self) -> Result<(), W::Error> {
+        if !self.open {
+            return Ok(());
+        }
+        self.open = false;
+        let w = &mut self.w;
+        self.enc.finish(&mut |b| nb::block!(w.write(b)))
+    }
+
+    /// Sends bytes as they are, for protocols framed on their own like
+    /// MAVLink. Ends any started frame first.
+    pub fn write_raw(&mut self, bytes: &[u8]) -> Result<(), W::Error> {
+        self.end_frame()?;
+        for b in bytes {
+            nb::block!(self.w.write(*b))?;
+        }
+        Ok(())
+    }
+
+    pub fn free(self) -> W {
+        self.w
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), W::Error> {
+        for b in bytes {
+            let w = &mut self.w;
+            self.enc.push(*b, &mut |b| nb::block!(w.write(b)))?;
+            self.open = true;
+            if *b == b'\n' {
+                self.end_frame()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: serial::Write<u8>> fmt::Write for FrameWriter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
@@ -0,0 +1,57 @@
+// Queues text past the ring size under both overflow policies, checks
+// what's left to send and drop counts:
+//     rustc --edition 2021 -O check.rs && ./check
+// Panics on failure.
+
+#![allow(dead_code)]
+
+#[path = "ring.rs"]
+mod ring;
+
+use ring::{Overflow, Ring};
+
+fn drain<const N: usize>(ring: &mut Ring<N>) -> Vec<u8> {
+    let mut out = Vec::new();
+    while let Some(b) = ring.peek() {
+        out.push(b);
+        ring.pop();
+    }
+    out
+}
+
+fn newest() {
+    let mut ring = Ring::<8>::new(Overflow::DropNewest);
+    assert_eq!(ring.peek(), None);
+    ring.push(b"hello");
+    assert_eq!(ring.peek(), Some(b'h'), "peek keeps it");
+    ring.pop();
+    ring.pop();
+    // Wraps around the end
+    ring.push(b"world");
+    assert_eq!(ring.len(), 8);
+    ring.push(b"lost");
+    assert_eq!(drain(&mut ring), b"lloworld");
+    assert_eq!(ring.dropped(), 4);
+    println!("drop newest ok");
+}
+
+fn oldest() {
+    let mut ring = Ring::<8>::new(Overflow::DropOldest);
+    ring.push(b"hello");
+    ring.pop();
+    ring.push(b"world");
+    assert_eq!(ring.dropped(), 1);
+    assert_eq!(drain(&mut ring), b"lloworld");
+    // Longer than the ring, only its end is left
+    ring.push(b"abc");
+    ring.push(b"0123456789");
+    assert_eq!(ring.dropped(), 1 + 2 + 3);
+    assert_eq!(drain(&mut ring), b"23456789");
+    assert!(ring.is_empty());
+    println!("drop oldest ok");
+}
+
+fn main() {
+    newest();
+    oldest();
+}
@@ -0,0 +1,42 @@
+//! Text console on USART2 that never waits for the port.
+//!
+//! `print!` and `println!` copy text into a RAM ring as COBS frames, one
+//! per line, the TXE interrupt sends it a byte at a time. When text doesn't
+//! fit, `Overflow` picks what goes: the oldest queued bytes or the new ones,
+//! `dropped` counts them either way. The receiver drops frames cut that way
+//! and picks up at the next one. `flush` sends what's left waiting for the
+//! port, for panic and fault handlers.
+//!
+//! Include with `#[macro_use] #[path = "../console/mod.rs"] mod console;`,
+//! call `console::init` with the TX half, unmask the USART2 interrupt and
+//! call `console::on_interrupt` from its handler. `check.rs` tests the ring
+//! on the host.
+
+// Binaries use only some of it
+#![allow(dead_code, unused_imports, unused_macros)]
+
+#[path = "../cobs/mod.rs"]
+mod cobs;
+mod ring;
+mod usart;
+
+pub use ring::{Overflow, Ring};
+pub use usart::{
+    dropped, flush, init, on_interrupt, write, Stdout, CONSOLE_LEN,
+};
+
+macro_rules! print {
+    ($($tt:tt)*) => ({
+        let _ = core::fmt::Write::write_fmt(
+            &mut crate::console::Stdout,
+            format_args!($($tt)*),
+        );
+    });
+}
+
+macro_rules! println {
+    ($($tt:tt)*) => ({
+        print!($($tt)*);
+        print!("\r\n");
+    });
+}
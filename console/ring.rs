@@ -0,0 +1,87 @@
+//! Byte ring for text waiting to go out, no hardware involved.
+
+/// What goes when text doesn't fit
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overflow {
+    /// Old text makes room, the latest output survives
+    DropOldest,
+    /// New text is cut, what's queued goes out whole
+    DropNewest,
+}
+
+pub struct Ring<const N: usize> {
+    bytes: [u8; N],
+    /// Oldest byte
+    head: usize,
+    len: usize,
+    overflow: Overflow,
+    dropped: u32,
+}
+
+impl<const N: usize> Ring<N> {
+    pub const fn new(overflow: Overflow) -> Self {
+        Ring {
+            bytes: [0; N],
+            head: 0,
+            len: 0,
+            overflow,
+            dropped: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Bytes lost to a full ring so far
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /// Queues `bytes`, dropping what doesn't fit as `Overflow` says
+    pub fn push(&mut self, bytes: &[u8]) {
+        let bytes = match self.overflow {
+            Overflow::DropNewest => {
+                let room = N - self.len;
+                if bytes.len() > room {
+                    self.dropped += (bytes.len() - room) as u32;
+                }
+                &bytes[..bytes.len().min(room)]
+            }
+            Overflow::DropOldest => {
+                // Only the last `N` can survive
+                let skip = bytes.len().saturating_sub(N);
+                let bytes = &bytes[skip..];
+                let old = (self.len + bytes.len()).saturating_sub(N);
+                self.head = (self.head + old) % N;
+                self.len -= old;
+                self.dropped += (skip + old) as u32;
+                bytes
+            }
+        };
+        for b in bytes {
+            self.bytes[(self.head + self.len) % N] = *b;
+            self.len += 1;
+        }
+    }
+
+    /// Oldest byte, stays queued until `pop`
+    pub fn peek(&self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        Some(self.bytes[self.head])
+    }
+
+    /// Drops the oldest byte, the one `peek` returns
+    pub fn pop(&mut self) {
+        if self.len > 0 {
+            self.head = (self.head + 1) % N;
+            self.len -= 1;
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! USART2 TX sent a byte at a time from its TXE interrupt, text framed
+//! with COBS one line per frame.
+
+use core::fmt;
+
+use ehal::serial::Write as _;
+
+use super::cobs::Encoder;
+use super::ring::{Overflow, Ring};
+
+type USART = hal::pac::USART2;
+type TxUsart = hal::serial::Tx<USART>;
+
+/// Bytes of text waiting for the port
+pub const CONSOLE_LEN: usize = 1024;
+
+struct Console {
+    tx: TxUsart,
+    ring: Ring<CONSOLE_LEN>,
+    enc: Encoder,
+    /// Bytes went into the ring since the last frame ended
+    open: bool,
+}
+
+impl Console {
+    fn push(&mut self, b: u8) {
+        let ring = &mut self.ring;
+        let _ = self.enc.push(b, &mut |e| {
+            ring.push(&[e]);
+            Ok::<_, ()>(())
+        });
+        self.open = true;
+        if b == b'\n' {
+            self.end_frame();
+        }
+    }
+
+    fn end_frame(&mut self) {
+        if !self.open {
+            return;
+        }
+        self.open = false;
+        let ring = &mut self.ring;
+        let _ = self.enc.finish(&mut |e| {
+            ring.push(&[e]);
+            Ok::<_, ()>(())
+        });
+    }
+}
+
+static mut CONSOLE: Option<Console> = None;
+
+/// Takes TX over, output before it is lost
+pub fn init(tx: TxUsart, overflow: Overflow) {
+    let mut ring = Ring::new(overflow);
+    // Ends whatever the receiver caught before reset
+    ring.push(&[0]);
+    cortex_m::interrupt::free(|_| unsafe {
+        CONSOLE = Some(Console {
+            tx,
+            ring,
+            enc: Encoder::new(),
+            open: false,
+        })
+    });
+    listen_txe(true);
+}
+
+// Writers and the interrupt handler take turns
+fn with<R>(f: impl FnOnce(&mut Console) -> R) -> Option<R> {
+    cortex_m::interrupt::free(|_| unsafe { CONSOLE.as_mut().map(f) })
+}
+
+fn listen_txe(on: bool) {
+    // HAL only has it for the whole serial port
+    let usart = unsafe { &*USART::ptr() };
+    usart.cr1.modify(|_, w| w.txeie().bit(on));
+}
+
+/// Queues bytes for the interrupt to send, never waits. A frame ends after
+/// each `\n`.
+pub fn write(bytes: &[u8]) {
+    with(|c| {
+        for b in bytes {
+            c.push(*b);
+        }
+        listen_txe(!c.ring.is_empty());
+    });
+}
+
+/// Sends the next byte, call it from the USART2 interrupt. Stops the
+/// interrupt once everything is out.
+pub fn on_interrupt() {
+    with(|c| match c.ring.peek() {
+        Some(b) => {
+            if c.tx.write(b).is_ok() {
+                c.ring.pop();
+            }
+        }
+        None => listen_txe(false),
+    });
+}
+
+/// Ends the open frame and sends everything queued, waiting for the port.
+/// For panic and fault handlers, where the interrupt won't run anymore.
+pub fn flush() {
+    with(|c| {
+        listen_txe(false);
+        c.end_frame();
+        while let Some(b) = c.ring.peek() {
+            let _ = nb::block!(c.tx.write(b));
+            c.ring.pop();
+        }
+        let _ = nb::block!(c.tx.flush());
+    });
+}
+
+/// Bytes lost to a full ring so far
+pub fn dropped() -> u32 {
+    with(|c| c.ring.dropped()).unwrap_or(0)
+}
+
+/// Console as a `fmt::Write`, what `print!` and `println!` write to
+pub struct Stdout;
+
+impl fmt::Write for Stdout {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write(s.as_bytes());
+        Ok(())
+    }
+}
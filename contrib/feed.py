@@ -4,6 +4,10 @@ import sys
 import time
 from serial.tools import list_ports
 
+# Passes framed telemetry through to stdout, surviving reconnects. Pipe it
+# into the telemetry decoder:
+#     python3 feed.py /dev/ttyACM0 | decode --csv attitude
+
 device = sys.argv[1]
 if not device.startswith("/"):
     x = list(list_ports.grep(device))
@@ -15,15 +19,12 @@ port = serial.Serial(device, baudrate=460800)
 i = 0
 while True:
     try:
-        line = port.read_until()
+        frame = port.read_until(b'\x00')
         i = 0
-        if line.strip():
-            print(line.strip().decode('ascii', 'ignore'))
-            sys.stdout.flush()
-        else:
-            print("fail")
+        sys.stdout.buffer.write(frame)
+        sys.stdout.buffer.flush()
     except serial.serialutil.SerialException as e:
-        print(f"exception: {e}; sleeping for 1s or until device ({device}) is there...")
+        print(f"exception: {e}; sleeping for 1s or until device ({device}) is there...", file=sys.stderr)
         time.sleep(1)
         while not list(list_ports.grep(device)):
             time.sleep(1)
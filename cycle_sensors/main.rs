@@ -5,8 +5,17 @@
 #[allow(unused)]
 use panic_abort;
 
+#[cfg(feature = "log_usart")]
+#[path = "../console/mod.rs"]
+mod console;
+#[macro_use]
+#[path = "../logging/mod.rs"]
+mod logging;
+
 use cortex_m::asm;
 use cortex_m_rt::{entry, exception, ExceptionFrame};
+#[cfg(feature = "log_usart")]
+use hal::pac::interrupt;
 use hal::prelude::*;
 use hal::time::Bps;
 use hal::{delay, serial};
@@ -61,7 +70,21 @@ fn main() -> ! {
     core.DCB.enable_trace();
     core.DWT.enable_cycle_counter();
 
-    cortex_m_semihosting::hprintln!("start").unwrap();
+    // `log_usart` sink, the pins mpu-calib uses
+    #[cfg(feature = "log_usart")]
+    {
+        let serial =
+            device
+                .USART2
+                .serial((gpioa.pa2, gpioa.pa15), Bps(115200), clocks);
+        let ser_int = serial.get_interrupt();
+        let (tx, _) = serial.split();
+        console::init(tx, console::Overflow::DropNewest);
+        unsafe { cortex_m::peripheral::NVIC::unmask(ser_int) };
+    }
+    logging::init();
+    logging::start_clock(&mut core.SYST, clocks.sysclk().0);
+    info!("start");
     loop {
         pa1.toggle();
         let n1 = cortex_m::peripheral::DWT::get_cycle_count() as i32;
@@ -74,13 +97,20 @@ fn main() -> ! {
         s += diff;
         i += 1;
         if i == 100 {
-            cortex_m_semihosting::hprintln!(
-                "100: {} // {}",
-                s,
-                (s as f32) / 100f32
-            );
+            info!("100: {} // {}", s, (s as f32) / 100f32);
             i = 0;
             s = 0;
         }
     }
 }
+
+#[cfg(feature = "log_usart")]
+#[interrupt]
+fn USART2_EXTI26() {
+    console::on_interrupt();
+}
+
+#[exception]
+fn SysTick() {
+    logging::tick();
+}
@@ -0,0 +1,425 @@
+// Fills the ping-pong buffers as producers and DMA would, decodes what
+// would go out and checks overflow handling. Pushes into the queue from
+// threads while draining it, checks order, priorities and drop counts.
+// Plays DMA writing into a circular buffer, checks frames that wrap around
+// and DMA running over unread ones:
+//     rustc --edition 2021 -O check.rs && ./check
+// Panics on failure.
+
+#![allow(dead_code)]
+
+// Framing only, the blocking writer needs the HAL
+#[path = "../cobs"]
+mod cobs {
+    mod crc;
+    mod decode;
+    mod encode;
+
+    pub use decode::Decoder;
+    pub use encode::{encode_in_place, max_payload};
+}
+#[path = "outbox.rs"]
+mod outbox;
+#[path = "queue.rs"]
+mod queue;
+#[path = "ring.rs"]
+mod ring;
+
+use core::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use cobs::Decoder;
+use outbox::{Buffer, Outbox, Stats};
+use queue::{Priority, Queue, Stream, StreamStats};
+use ring::{Ring, RxStats};
+
+const N: usize = 64;
+
+fn outbox() -> Outbox<N> {
+    let a = Box::leak(Box::new(Buffer::new()));
+    let b = Box::leak(Box::new(Buffer::new()));
+    Outbox::new(a, b)
+}
+
+/// Payloads of the frames in a taken buffer
+fn frames(bytes: &[u8]) -> Vec<Vec<u8>> {
+    let mut dec = Decoder::<{ N + 2 }>::new();
+    let mut out = Vec::new();
+    for b in bytes {
+        if let Some(r) = dec.push(*b) {
+            out.push(r.expect("broken frame").to_vec());
+        }
+    }
+    out
+}
+
+fn stats(frames: u32, truncated: u32, dropped: u32) -> Stats {
+    Stats {
+        frames,
+        truncated,
+        dropped,
+    }
+}
+
+fn ping_pong() {
+    let mut out = outbox();
+    assert!(out.take().is_none(), "nothing to send");
+    out.write_frame(b"one");
+    let first = out.take().expect("first");
+    assert_eq!(frames(first), [b"one".to_vec()]);
+    // DMA is busy with `first`, frames pile up in the other one
+    out.write_frame(b"two");
+    out.write_frame(&[0, 1, 0]);
+    assert!(out.take().is_none(), "both buffers out");
+    out.give_back(first);
+    let second = out.take().expect("second");
+    assert_eq!(frames(second), [b"two".to_vec(), vec![0, 1, 0]]);
+    out.give_back(second);
+    assert!(out.take().is_none(), "sent everything");
+    assert_eq!(out.stats(), stats(3, 0, 0));
+    println!("ping-pong ok");
+}
+
+fn overflow() {
+    let mut out = outbox();
+    let payload = [7u8; 20];
+    while out.stats().dropped == 0 {
+        out.write_frame(&payload);
+    }
+    let queued = out.stats().frames;
+    assert!(queued >= 2, "{} frames", queued);
+    let full = out.take().unwrap();
+    assert!(full.len() <= N);
+    assert_eq!(frames(full).len(), queued as usize);
+    out.give_back(full);
+
+    // Can never fit whole, goes out cut
+    let long = [3u8; 2 * N];
+    out.write_frame(&long);
+    let cut = out.take().unwrap();
+    let got = frames(cut);
+    assert_eq!(got.len(), 1);
+    assert_eq!(got[0], long[..cobs::max_payload(N)]);
+    out.give_back(cut);
+    assert_eq!(out.stats(), stats(queued, 1, 1));
+
+    // Raw bytes are never cut
+    out.write_raw(&[1; N - 4]);
+    out.write_raw(&[2; 8]);
+    out.write_raw(&[3; 4]);
+    let raw = out.take().unwrap();
+    assert_eq!(raw.len(), N);
+    assert_eq!(&raw[N - 4..], &[3; 4]);
+    assert_eq!(out.stats(), stats(queued + 2, 1, 2));
+    println!("overflow ok");
+}
+
+fn text() {
+    let mut out = outbox();
+    write!(out, "x = {}\r\n", 42).unwrap();
+    write!(out, "partial").unwrap();
+    assert!(out.take().is_none(), "line is open");
+    writeln!(out, " line").unwrap();
+    let lines = out.take().unwrap();
+    assert_eq!(
+        frames(lines),
+        [b"x = 42\r\n".to_vec(), b"partial line\n".to_vec()]
+    );
+
+    out.give_back(lines);
+
+    // Open line is cut at the space left, the rest is lost
+    let long = "y".repeat(N);
+    write!(out, "{}", long).unwrap();
+    out.end_frame();
+    let cut = out.take().unwrap();
+    let got = frames(cut);
+    assert_eq!(got[0].len(), cobs::max_payload(N));
+    assert_eq!(out.stats(), stats(2, 1, 0));
+
+    // DMA still has `cut`, the line after a full one finds no room
+    writeln!(out, "{}", long).unwrap();
+    writeln!(out, "lost").unwrap();
+    assert_eq!(out.stats(), stats(2, 2, 1));
+    out.give_back(cut);
+    println!("text ok");
+}
+
+const SAMPLES: Stream = Stream::framed(0, Priority::Low);
+const EVENTS: Stream = Stream::framed(1, Priority::High);
+const MAVLINK: Stream = Stream::raw(2, Priority::Low);
+
+fn leak<T>(t: T) -> &'static T {
+    Box::leak(Box::new(t))
+}
+
+fn priorities() {
+    let q = leak(Queue::<4, 8, 16>::new());
+    let mut drain = q.take_drain().unwrap();
+    assert!(q.take_drain().is_none(), "one drain only");
+    for i in 0..4 {
+        assert!(q.push_bytes(SAMPLES, &[i]));
+    }
+    assert!(!q.push_bytes(SAMPLES, &[4]), "rest is kept for events");
+    // High priority has slots of its own and jumps the line
+    assert!(q.push_bytes(EVENTS, b"fault"));
+    assert!(!q.push_bytes(EVENTS, &[0; 17]), "longer than a slot");
+    let mut got = Vec::new();
+    drain.drain(|stream, payload| {
+        got.push((stream, payload.to_vec()));
+        got.len() < 3
+    });
+    assert_eq!(got[0], (EVENTS, b"fault".to_vec()));
+    assert_eq!(got[1], (SAMPLES, vec![0]));
+    // Refused one stays queued
+    assert_eq!(got[2], (SAMPLES, vec![1]));
+    got.clear();
+    q.push(EVENTS, |_| 0);
+    q.push_bytes(MAVLINK, &[0xfd]);
+    q.push_bytes(EVENTS, b"result");
+    drain.drain(|stream, payload| {
+        got.push((stream, payload.to_vec()));
+        true
+    });
+    assert_eq!(got[0], (EVENTS, b"result".to_vec()), "empty skipped");
+    assert_eq!(got[1], (SAMPLES, vec![1]));
+    assert_eq!(got.last(), Some(&(MAVLINK, vec![0xfd])));
+    let stats = |sent, dropped| StreamStats { sent, dropped };
+    assert_eq!(q.stats(SAMPLES), stats(4, 1));
+    assert_eq!(q.stats(EVENTS), stats(2, 1));
+    assert_eq!(q.stats(MAVLINK), stats(1, 0));
+    println!("priorities ok");
+}
+
+/// High priority takes low slots once its own are full, in order
+fn spill() {
+    let q = leak(Queue::<2, 4, 16>::new());
+    let mut drain = q.take_drain().unwrap();
+    let mut got = Vec::new();
+    let take = |drain: &mut queue::Drain<2, 4, 16>, n| {
+        let mut got = Vec::new();
+        drain.drain(|stream, payload: &[u8]| {
+            let fits = got.len() < n;
+            if fits {
+                got.push((stream, payload[0]));
+            }
+            fits
+        });
+        got
+    };
+    for i in 0..2 {
+        assert!(q.push_bytes(SAMPLES, &[i]));
+    }
+    assert!(!q.push_bytes(SAMPLES, &[2]), "rest is kept for events");
+    for i in 0..4 {
+        assert!(q.push_bytes(EVENTS, &[i]));
+    }
+    assert!(!q.push_bytes(EVENTS, &[4]), "every slot taken");
+    got.extend(take(&mut drain, 1));
+    assert_eq!(got, [(EVENTS, 0)]);
+    // Low lane still full, own lane has room again
+    assert!(q.push_bytes(EVENTS, &[5]));
+    got = take(&mut drain, usize::MAX);
+    // Not ahead of the ones that spilled, samples queued before them go
+    // first
+    assert_eq!(
+        got,
+        [
+            (EVENTS, 1),
+            (SAMPLES, 0),
+            (SAMPLES, 1),
+            (EVENTS, 2),
+            (EVENTS, 3),
+            (EVENTS, 5)
+        ]
+    );
+    assert!(q.push_bytes(SAMPLES, &[3]));
+    assert!(q.push_bytes(EVENTS, &[6]));
+    got = take(&mut drain, usize::MAX);
+    assert_eq!(got, [(EVENTS, 6), (SAMPLES, 3)]);
+    let stats = |sent, dropped| StreamStats { sent, dropped };
+    assert_eq!(q.stats(EVENTS), stats(6, 1));
+    assert_eq!(q.stats(SAMPLES), stats(3, 1));
+    println!("spill ok");
+}
+
+/// Samples stream without pause, a burst of events still gets through
+fn burst() {
+    type Q = Queue<4, 8, 16>;
+    let q = leak(Q::new());
+    let mut drain = q.take_drain().unwrap();
+    let mut samples = 0;
+    while q.push_bytes(SAMPLES, &[samples]) {
+        samples += 1;
+    }
+    assert_eq!(samples as usize, 8 - Q::RESERVED);
+    for i in 0..5 {
+        assert!(q.push_bytes(EVENTS, &[i]), "event {}", i);
+        assert!(!q.push_bytes(SAMPLES, &[samples]), "samples stay out");
+    }
+    let mut events = Vec::new();
+    drain.drain(|stream, payload| {
+        if stream == EVENTS {
+            events.push(payload[0]);
+        }
+        true
+    });
+    assert_eq!(events, [0, 1, 2, 3, 4]);
+    // Own lane and the whole reserve, not one more
+    while q.push_bytes(SAMPLES, &[samples]) {
+        samples += 1;
+    }
+    for i in 0..4 + Q::RESERVED {
+        assert!(q.push_bytes(EVENTS, &[i as u8]), "event {}", i);
+    }
+    assert!(!q.push_bytes(EVENTS, &[0]), "every slot taken");
+    assert_eq!(q.stats(EVENTS).dropped, 1);
+    println!("burst ok");
+}
+
+/// Producers spin on a full lane, every message gets through in order
+fn threads() {
+    const PER: u32 = 20_000;
+    let q = leak(Queue::<8, 8, 8>::new());
+    let done = leak(AtomicBool::new(false));
+    let mut drain = q.take_drain().unwrap();
+    let consumer = thread::spawn(move || {
+        let mut next = [0u32; 4];
+        let mut high = 0;
+        while !done.load(Ordering::Relaxed) || next.iter().any(|n| *n < PER) {
+            drain.drain(|stream, payload| {
+                let who = payload[0] as usize;
+                let n = u32::from_le_bytes(payload[1..5].try_into().unwrap());
+                assert_eq!(stream.id as usize, who);
+                assert_eq!(n, next[who], "producer {}", who);
+                next[who] += 1;
+                high += (stream.priority == Priority::High) as u32;
+                true
+            });
+            thread::yield_now();
+        }
+        high
+    });
+    let producers: Vec<_> = (0..4u8)
+        .map(|who| {
+            thread::spawn(move || {
+                let priority = match who {
+                    0 => Priority::High,
+                    _ => Priority::Low,
+                };
+                let stream = Stream::framed(who, priority);
+                for n in 0..PER {
+                    let mut msg = [who; 5];
+                    msg[1..].copy_from_slice(&n.to_le_bytes());
+                    while !q.push_bytes(stream, &msg) {
+                        thread::yield_now();
+                    }
+                }
+            })
+        })
+        .collect();
+    for p in producers {
+        p.join().unwrap();
+    }
+    done.store(true, Ordering::Relaxed);
+    assert_eq!(consumer.join().unwrap(), PER);
+    for who in 0..4 {
+        assert_eq!(q.stats(Stream::framed(who, Priority::Low)).sent, PER);
+    }
+    println!("threads ok");
+}
+
+/// DMA writing a circular buffer with its half and full transfer flags
+struct Dma {
+    buf: [u8; 16],
+    pos: usize,
+    half: bool,
+    full: bool,
+}
+
+impl Dma {
+    fn receive(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.buf[self.pos] = *b;
+            self.pos = (self.pos + 1) % 16;
+            self.half |= self.pos == 8;
+            self.full |= self.pos == 0;
+        }
+    }
+
+    /// What the interrupt handler does, `idle` line or not
+    fn poll(&mut self, ring: &mut Ring<16>, idle: bool) -> Option<Vec<u8>> {
+        let (half, full) = (self.half, self.full);
+        self.half = false;
+        self.full = false;
+        ring.advance(self.pos, half, full);
+        if !idle && !ring.is_full() {
+            return None;
+        }
+        ring.end(&self.buf).map(|(a, b)| [a, b].concat())
+    }
+}
+
+fn circular() {
+    let mut ring = Ring::<16>::new();
+    let mut dma = Dma {
+        buf: [0; 16],
+        pos: 0,
+        half: false,
+        full: false,
+    };
+    dma.receive(b"hello");
+    assert_eq!(dma.poll(&mut ring, false), None, "line still busy");
+    assert_eq!(dma.poll(&mut ring, true), Some(b"hello".to_vec()));
+    assert_eq!(dma.poll(&mut ring, true), None, "nothing new");
+    // Crosses the middle and the end of the buffer
+    dma.receive(b"wrapped around");
+    assert_eq!(dma.poll(&mut ring, true), Some(b"wrapped around".to_vec()));
+    // Half the buffer goes out before the line is idle
+    dma.receive(b"0123456789");
+    assert_eq!(dma.poll(&mut ring, false), Some(b"0123456789".to_vec()));
+    dma.receive(b"ab");
+    assert_eq!(dma.poll(&mut ring, true), Some(b"ab".to_vec()));
+    let stats = |frames, overruns| RxStats { frames, overruns };
+    assert_eq!(ring.stats(), stats(4, 0));
+
+    // Handler late by a whole lap: position alone looks like 3 bytes
+    dma.receive(&[7; 19]);
+    assert_eq!(dma.poll(&mut ring, true), None, "lapped");
+    dma.receive(b"next");
+    assert_eq!(dma.poll(&mut ring, true), Some(b"next".to_vec()));
+    // Longer than the buffer before anyone looked
+    dma.receive(&[7; 6]);
+    dma.poll(&mut ring, false);
+    dma.receive(&[7; 12]);
+    assert_eq!(dma.poll(&mut ring, true), None, "written over");
+    // USART dropped a byte
+    dma.receive(b"xy");
+    ring.lose();
+    assert_eq!(dma.poll(&mut ring, true), None, "overrun");
+    assert_eq!(ring.stats(), stats(5, 3));
+
+    // DMA crossed the end between reading flags and position, the flag
+    // shows up a read late and is no overrun
+    let mut ring = Ring::<16>::new();
+    let buf = [0; 16];
+    ring.advance(14, false, false);
+    ring.end(&buf);
+    ring.advance(2, false, false);
+    ring.advance(4, false, true);
+    assert_eq!(ring.end(&buf).map(|(a, b)| a.len() + b.len()), Some(6));
+    assert_eq!(ring.stats(), stats(2, 0));
+    println!("circular ok");
+}
+
+fn main() {
+    ping_pong();
+    overflow();
+    text();
+    priorities();
+    spill();
+    burst();
+    threads();
+    circular();
+}
@@ -0,0 +1,40 @@
+//! Serial output and input over DMA for the RTIC binaries.
+//!
+//! `DmaTelemetry` owns USART2 TX with DMA1 channel 7 and two buffers:
+//! producers fill one while DMA sends the other, nobody waits for a
+//! transfer. Buffers hold whole frames, COBS framed (see `cobs/`) or raw
+//! ones that carry framing of their own, like MAVLink. A frame that doesn't
+//! fit the space left is dropped, one longer than a whole buffer is cut,
+//! `Stats` counts both.
+//!
+//! `Queue` feeds it from several tasks and interrupts without locks: each
+//! message belongs to a `Stream` with a priority, high priority messages
+//! have slots of their own and go out before any low priority one. Once
+//! those are full they take low priority slots that samples leave free for
+//! them, so a burst of events gets through a lane full of samples. Drops
+//! are counted per stream.
+//!
+//! `DmaReceiver` is the other direction: USART2 RX with DMA1 channel 6
+//! filling a circular buffer. Frames end when the line goes idle and come
+//! out exactly as long as they were sent, without an interrupt per byte,
+//! bursts longer than half the buffer come in pieces.
+//! `RxStats` counts frames lost to DMA coming round over unread bytes or to
+//! a USART overrun.
+//!
+//! Include with `#[path = "../dma/mod.rs"] mod dma;`, it needs `cobs` next
+//! to it. `check.rs` tests the buffering on the host.
+
+// Binaries use only some of it
+#![allow(dead_code, unused_imports)]
+
+mod outbox;
+mod queue;
+mod ring;
+mod rx;
+mod tx;
+
+pub use outbox::{Buffer, Outbox, Stats};
+pub use queue::{Drain, Priority, Queue, Stream, StreamStats, STREAMS};
+pub use ring::{Ring, RxStats};
+pub use rx::{DmaReceiver, RX_CAPACITY};
+pub use tx::{DmaTelemetry, CAPACITY};
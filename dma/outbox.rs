@@ -0,0 +1,212 @@
+//! Ping-pong buffering, no hardware involved.
+
+use core::fmt;
+use core::mem;
+use core::ops::Deref;
+
+use crate::cobs::{encode_in_place, max_payload};
+
+/// Frames waiting for DMA, up to `N` bytes
+pub struct Buffer<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Buffer<N> {
+    pub const fn new() -> Self {
+        Buffer {
+            bytes: [0; N],
+            len: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    fn free(&self) -> usize {
+        N - self.len
+    }
+}
+
+impl<const N: usize> Deref for Buffer<N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for Buffer<N> {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    /// Frames queued whole
+    pub frames: u32,
+    /// Frames queued cut to the space left
+    pub truncated: u32,
+    /// Frames left out for lack of space
+    pub dropped: u32,
+}
+
+/// Two buffers: producers append frames to one, the other is either out
+/// with DMA or spare
+pub struct Outbox<const N: usize> {
+    fill: &'static mut Buffer<N>,
+    spare: Option<&'static mut Buffer<N>>,
+    /// Payload of a text frame so far, staged past the end of `fill`
+    open: Option<usize>,
+    /// Text didn't fit the open frame
+    cut: bool,
+    stats: Stats,
+}
+
+impl<const N: usize> Outbox<N> {
+    pub fn new(a: &'static mut Buffer<N>, b: &'static mut Buffer<N>) -> Self {
+        a.clear();
+        b.clear();
+        Outbox {
+            fill: a,
+            spare: Some(b),
+            open: None,
+            cut: false,
+            stats: Stats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Queues `payload` as a COBS frame. Payload longer than a buffer can
+    /// ever carry is cut to the space left.
+    pub fn write_frame(&mut self, payload: &[u8]) {
+        self.end_frame();
+        let room = max_payload(self.fill.free());
+        let len = if payload.len() <= room {
+            self.stats.frames += 1;
+            payload.len()
+        } else if payload.len() > max_payload(N) && room > 0 {
+            self.stats.truncated += 1;
+            room
+        } else {
+            self.stats.dropped += 1;
+            return;
+        };
+        let start = self.fill.len;
+        self.fill.bytes[start..start + len].copy_from_slice(&payload[..len]);
+        self.seal(len);
+    }
+
+    /// Queues a COBS frame `fill` writes in place: it gets the space left
+    /// and returns payload length. Nothing is queued for empty payload.
+    pub fn frame_with(&mut self, fill: impl FnOnce(&mut [u8]) -> usize) {
+        self.end_frame();
+        let room = max_payload(self.fill.free());
+        let start = self.fill.len;
+        let len = fill(&mut self.fill.bytes[start..start + room]).min(room);
+        if len > 0 {
+            self.stats.frames += 1;
+            self.seal(len);
+        }
+    }
+
+    /// Queues bytes as they are, a frame of some other protocol. Never
+    /// cut, dropped whole if it doesn't fit.
+    pub fn write_raw(&mut self, bytes: &[u8]) {
+        self.end_frame();
+        if bytes.len() > self.fill.free() {
+            self.stats.dropped += 1;
+            return;
+        }
+        let start = self.fill.len;
+        self.fill.bytes[start..start + bytes.len()].copy_from_slice(bytes);
+        self.fill.len += bytes.len();
+        self.stats.frames += 1;
+    }
+
+    /// Queues a frame only if it fits whole, COBS framed unless `raw`.
+    /// Nothing is counted when it doesn't.
+    pub fn offer(&mut self, raw: bool, payload: &[u8]) -> bool {
+        self.end_frame();
+        let room = self.fill.free();
+        if raw && payload.len() <= room {
+            self.write_raw(payload);
+        } else if !raw && payload.len() <= max_payload(room) {
+            self.write_frame(payload);
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// Ends the text frame started without a trailing newline, if any
+    pub fn end_frame(&mut self) {
+        let len = match self.open.take() {
+            Some(len) => len,
+            None => return,
+        };
+        let cut = mem::replace(&mut self.cut, false);
+        match (len, cut) {
+            (0, true) => self.stats.dropped += 1,
+            (_, true) => self.stats.truncated += 1,
+            _ => self.stats.frames += 1,
+        }
+        if len > 0 {
+            self.seal(len);
+        }
+    }
+
+    /// Buffer to hand to DMA. `None` while the other one is still out,
+    /// when there is nothing to send or a text frame is open.
+    pub fn take(&mut self) -> Option<&'static mut Buffer<N>> {
+        if self.fill.is_empty() || self.open.is_some() {
+            return None;
+        }
+        let spare = self.spare.take()?;
+        Some(mem::replace(&mut self.fill, spare))
+    }
+
+    /// Takes back the buffer DMA is done with
+    pub fn give_back(&mut self, buffer: &'static mut Buffer<N>) {
+        buffer.clear();
+        self.spare = Some(buffer);
+    }
+
+    /// Frames `len` payload bytes staged at the end of `fill`
+    fn seal(&mut self, len: usize) {
+        let start = self.fill.len;
+        // Callers keep `len` within `max_payload` of the space left
+        let n = encode_in_place(&mut self.fill.bytes[start..], len).unwrap();
+        self.fill.len += n;
+    }
+
+    fn push_text(&mut self, b: u8) {
+        let len = self.open.unwrap_or(0);
+        if len < max_payload(self.fill.free()) {
+            self.fill.bytes[self.fill.len + len] = b;
+            self.open = Some(len + 1);
+        } else {
+            self.open = Some(len);
+            self.cut = true;
+        }
+        if b == b'\n' {
+            self.end_frame();
+        }
+    }
+}
+
+/// Text goes out one frame per line, a frame ends after each `\n`. Lines
+/// longer than the space left are cut, never an error.
+impl<const N: usize> fmt::Write for Outbox<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for b in s.bytes() {
+            self.push_text(b);
+        }
+        Ok(())
+    }
+}
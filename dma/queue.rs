@@ -0,0 +1,338 @@
+//! Lock-free message queue for many producers and the DMA interrupt.
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+/// Streams a queue keeps statistics for
+pub const STREAMS: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Priority {
+    /// Periodic samples, the next one replaces a lost one
+    Low,
+    /// Events that have to get through: faults, results, replies
+    High,
+}
+
+/// Where a message belongs: statistics slot, lane and framing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stream {
+    pub id: u8,
+    pub priority: Priority,
+    /// Payload goes out as is, it has framing of its own
+    pub raw: bool,
+}
+
+impl Stream {
+    /// Payload goes out COBS framed
+    pub const fn framed(id: u8, priority: Priority) -> Self {
+        assert!((id as usize) < STREAMS);
+        Stream {
+            id,
+            priority,
+            raw: false,
+        }
+    }
+
+    pub const fn raw(id: u8, priority: Priority) -> Self {
+        assert!((id as usize) < STREAMS);
+        Stream {
+            id,
+            priority,
+            raw: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StreamStats {
+    /// Handed to DMA
+    pub sent: u32,
+    /// Lost to a full lane
+    pub dropped: u32,
+}
+
+struct Slot<const LEN: usize> {
+    /// Position the slot is free for, one past it once written
+    seq: AtomicUsize,
+    stream: UnsafeCell<Stream>,
+    /// Order of high priority messages across lanes
+    stamp: UnsafeCell<u32>,
+    len: UnsafeCell<usize>,
+    data: UnsafeCell<[u8; LEN]>,
+}
+
+impl<const LEN: usize> Slot<LEN> {
+    const fn empty() -> Self {
+        Slot {
+            seq: AtomicUsize::new(0),
+            stream: UnsafeCell::new(Stream::framed(0, Priority::Low)),
+            stamp: UnsafeCell::new(0),
+            len: UnsafeCell::new(0),
+            data: UnsafeCell::new([0; LEN]),
+        }
+    }
+}
+
+/// Bounded ring of `SLOTS` messages: producers claim a slot by moving
+/// `tail` and publish it through its `seq`, so a producer interrupted half
+/// way holds up only the consumer, never other producers.
+struct Lane<const SLOTS: usize, const LEN: usize> {
+    slots: [Slot<LEN>; SLOTS],
+    tail: AtomicUsize,
+    head: AtomicUsize,
+}
+
+impl<const SLOTS: usize, const LEN: usize> Lane<SLOTS, LEN> {
+    const fn new() -> Self {
+        // Positions wrap around, slot index has to follow
+        assert!(SLOTS.is_power_of_two());
+        let mut slots = [const { Slot::empty() }; SLOTS];
+        let mut i = 0;
+        while i < SLOTS {
+            slots[i].seq = AtomicUsize::new(i);
+            i += 1;
+        }
+        Lane {
+            slots,
+            tail: AtomicUsize::new(0),
+            head: AtomicUsize::new(0),
+        }
+    }
+
+    /// Hands `fill` back when the lane is full
+    fn push<F: FnOnce(&mut [u8]) -> usize>(
+        &self,
+        stream: Stream,
+        stamp: u32,
+        fill: F,
+    ) -> Result<(), F> {
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % SLOTS];
+            let seq = slot.seq.load(Ordering::Acquire);
+            let lag = seq.wrapping_sub(pos) as isize;
+            if lag < 0 {
+                // Consumer hasn't freed it yet
+                return Err(fill);
+            }
+            if lag > 0 {
+                pos = self.tail.load(Ordering::Relaxed);
+                continue;
+            }
+            let next = pos.wrapping_add(1);
+            match self.tail.compare_exchange_weak(
+                pos,
+                next,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    // Ours alone until `seq` moves on
+                    unsafe {
+                        let len = fill(&mut *slot.data.get());
+                        *slot.len.get() = len.min(LEN);
+                        *slot.stream.get() = stream;
+                        *slot.stamp.get() = stamp;
+                    }
+                    slot.seq.store(next, Ordering::Release);
+                    return Ok(());
+                }
+                Err(now) => pos = now,
+            }
+        }
+    }
+
+    /// Slots neither claimed nor waiting for the consumer, can be stale by
+    /// the time it returns
+    fn free(&self) -> usize {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Relaxed);
+        SLOTS.saturating_sub(tail.wrapping_sub(head))
+    }
+
+    /// Oldest message, if its producer is done with it. Consumer only.
+    fn peek(&self) -> Option<(Stream, u32, &[u8])> {
+        let pos = self.head.load(Ordering::Relaxed);
+        let slot = self.written(pos)?;
+        unsafe {
+            let data = &*slot.data.get();
+            Some((
+                *slot.stream.get(),
+                *slot.stamp.get(),
+                &data[..*slot.len.get()],
+            ))
+        }
+    }
+
+    /// Stamp of the oldest high priority message queued here, skipping
+    /// ones still being written. Consumer only.
+    fn oldest_high(&self) -> Option<u32> {
+        let head = self.head.load(Ordering::Relaxed);
+        (0..SLOTS).find_map(|i| {
+            let slot = self.written(head.wrapping_add(i))?;
+            let stream = unsafe { *slot.stream.get() };
+            match stream.priority {
+                Priority::High => Some(unsafe { *slot.stamp.get() }),
+                Priority::Low => None,
+            }
+        })
+    }
+
+    /// Slot at `pos` if its producer published it there
+    fn written(&self, pos: usize) -> Option<&Slot<LEN>> {
+        let slot = &self.slots[pos % SLOTS];
+        let seq = slot.seq.load(Ordering::Acquire);
+        (seq == pos.wrapping_add(1)).then_some(slot)
+    }
+
+    /// Frees the slot `peek` returned. Consumer only.
+    fn pop(&self) {
+        let pos = self.head.load(Ordering::Relaxed);
+        let slot = &self.slots[pos % SLOTS];
+        slot.seq.store(pos.wrapping_add(SLOTS), Ordering::Release);
+        self.head.store(pos.wrapping_add(1), Ordering::Relaxed);
+    }
+}
+
+/// Two lanes of messages up to `LEN` bytes: `HIGH` slots for high priority
+/// streams, `LOW` for the rest. Anything can push, one `Drain` takes
+/// messages out, high priority ones first. Low priority messages leave
+/// `RESERVED` of their slots free: when its lane is full a high priority
+/// message takes one of those, so a burst of `HIGH + RESERVED` gets through
+/// however many samples stream. Slot counts are powers of two.
+pub struct Queue<const HIGH: usize, const LOW: usize, const LEN: usize> {
+    high: Lane<HIGH, LEN>,
+    low: Lane<LOW, LEN>,
+    /// Next high priority stamp, keeps them in order whatever lane they
+    /// land in
+    stamp: AtomicU32,
+    sent: [AtomicU32; STREAMS],
+    dropped: [AtomicU32; STREAMS],
+    drain: AtomicBool,
+}
+
+// Slots are handed between producers and the drain through `seq`
+unsafe impl<const HIGH: usize, const LOW: usize, const LEN: usize> Sync
+    for Queue<HIGH, LOW, LEN>
+{
+}
+
+impl<const HIGH: usize, const LOW: usize, const LEN: usize>
+    Queue<HIGH, LOW, LEN>
+{
+    /// Low slots only high priority messages take
+    pub const RESERVED: usize = LOW / 2;
+
+    pub const fn new() -> Self {
+        Queue {
+            high: Lane::new(),
+            low: Lane::new(),
+            stamp: AtomicU32::new(0),
+            sent: [const { AtomicU32::new(0) }; STREAMS],
+            dropped: [const { AtomicU32::new(0) }; STREAMS],
+            drain: AtomicBool::new(false),
+        }
+    }
+
+    /// Queues a message `fill` writes in place: it gets `LEN` bytes and
+    /// returns payload length, empty payload isn't sent. Never blocks,
+    /// `false` and counted as dropped when there's no slot for it.
+    pub fn push(
+        &self,
+        stream: Stream,
+        fill: impl FnOnce(&mut [u8]) -> usize,
+    ) -> bool {
+        let queued = match stream.priority {
+            Priority::Low => {
+                self.low.free() > Self::RESERVED
+                    && self.low.push(stream, 0, fill).is_ok()
+            }
+            Priority::High => {
+                let stamp = self.stamp.fetch_add(1, Ordering::AcqRel);
+                match self.high.push(stream, stamp, fill) {
+                    Ok(()) => true,
+                    Err(fill) => self.low.push(stream, stamp, fill).is_ok(),
+                }
+            }
+        };
+        if !queued {
+            self.dropped[stream.id as usize].fetch_add(1, Ordering::Relaxed);
+        }
+        queued
+    }
+
+    /// Queues a copy of `bytes`, dropped if longer than `LEN`
+    pub fn push_bytes(&self, stream: Stream, bytes: &[u8]) -> bool {
+        if bytes.len() > LEN {
+            self.dropped[stream.id as usize].fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        self.push(stream, |b| {
+            b[..bytes.len()].copy_from_slice(bytes);
+            bytes.len()
+        })
+    }
+
+    pub fn stats(&self, stream: Stream) -> StreamStats {
+        let id = stream.id as usize;
+        StreamStats {
+            sent: self.sent[id].load(Ordering::Relaxed),
+            dropped: self.dropped[id].load(Ordering::Relaxed),
+        }
+    }
+
+    /// The consumer end, `None` after the first call
+    pub fn take_drain(&'static self) -> Option<Drain<HIGH, LOW, LEN>> {
+        if self.drain.swap(true, Ordering::Relaxed) {
+            return None;
+        }
+        Some(Drain { queue: self })
+    }
+}
+
+pub struct Drain<const HIGH: usize, const LOW: usize, const LEN: usize> {
+    queue: &'static Queue<HIGH, LOW, LEN>,
+}
+
+impl<const HIGH: usize, const LOW: usize, const LEN: usize>
+    Drain<HIGH, LOW, LEN>
+{
+    /// Hands messages to `send` oldest first, high priority ones before
+    /// any low one, until `send` refuses one. That one stays queued. High
+    /// priority ones that took low slots go out in order with the rest:
+    /// low ones queued ahead of them go first.
+    pub fn drain(&mut self, mut send: impl FnMut(Stream, &[u8]) -> bool) {
+        let q = self.queue;
+        loop {
+            let next = q.high.peek();
+            let high = match next {
+                Some((_, stamp, _)) => q
+                    .low
+                    .oldest_high()
+                    .is_none_or(|spilled| before(stamp, spilled)),
+                None => false,
+            };
+            let next = if high { next } else { q.low.peek() };
+            let Some((stream, _, payload)) = next else {
+                return;
+            };
+            if !payload.is_empty() {
+                if !send(stream, payload) {
+                    return;
+                }
+                q.sent[stream.id as usize].fetch_add(1, Ordering::Relaxed);
+            }
+            if high {
+                q.high.pop();
+            } else {
+                q.low.pop();
+            }
+        }
+    }
+}
+
+/// Whether stamp `a` was handed out before `b`, they wrap around
+fn before(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
@@ -0,0 +1,104 @@
+//! Frames out of a circular DMA buffer, no hardware involved.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RxStats {
+    /// Frames handed out
+    pub frames: u32,
+    /// Frames lost to DMA lapping the reader or to a USART overrun
+    pub overruns: u32,
+}
+
+/// Follows DMA around a circular buffer of `N` bytes. Fed the DMA position
+/// and transfer flags on every interrupt, it keeps track of the frame being
+/// received and notices when DMA went all the way around, over bytes nobody
+/// read yet.
+pub struct Ring<const N: usize> {
+    /// First byte of the frame being received
+    start: usize,
+    /// Bytes of it so far
+    len: usize,
+    /// DMA position at the previous update
+    last: usize,
+    /// How far DMA moved up to the previous update
+    moved: usize,
+    /// Some of the frame was written over or never made it
+    lost: bool,
+    stats: RxStats,
+}
+
+impl<const N: usize> Ring<N> {
+    pub const fn new() -> Self {
+        // Half transfer flag marks the middle
+        assert!(N.is_multiple_of(2));
+        Ring {
+            start: 0,
+            len: 0,
+            last: 0,
+            moved: 0,
+            lost: false,
+            stats: RxStats {
+                frames: 0,
+                overruns: 0,
+            },
+        }
+    }
+
+    pub fn stats(&self) -> RxStats {
+        self.stats
+    }
+
+    /// DMA is at `pos`, `half` and `full` are the half and full transfer
+    /// flags read and cleared right before `pos`. A flag no move of DMA
+    /// explains means it went around at least once, the frame is lost.
+    pub fn advance(&mut self, pos: usize, half: bool, full: bool) {
+        let delta = (pos + N - self.last) % N;
+        // A crossing just after the previous flag read shows up now, flags
+        // cover the previous move too
+        let span = self.moved + delta;
+        let from = (self.last + N - self.moved) % N;
+        let crossed = |at: usize| (at + N - from - 1) % N < span;
+        if (half && !crossed(N / 2)) || (full && !crossed(0)) {
+            self.lost = true;
+        }
+        self.len += delta;
+        if self.len > N {
+            self.lost = true;
+        }
+        self.last = pos;
+        self.moved = delta;
+    }
+
+    /// Marks the frame being received as lost
+    pub fn lose(&mut self) {
+        self.lost = true;
+    }
+
+    /// Frame being received takes half the buffer, hand it out before DMA
+    /// gets to it again
+    pub fn is_full(&self) -> bool {
+        self.len >= N / 2
+    }
+
+    /// Ends the frame being received, the next one starts where DMA is.
+    /// Its bytes in `buf` as the two parts they may wrap into, `None` when
+    /// there are none or it's lost.
+    pub fn end<'a>(
+        &mut self,
+        buf: &'a [u8; N],
+    ) -> Option<(&'a [u8], &'a [u8])> {
+        let (start, len, lost) = (self.start, self.len, self.lost);
+        self.start = self.last;
+        self.len = 0;
+        self.lost = false;
+        if lost {
+            self.stats.overruns += 1;
+            return None;
+        }
+        if len == 0 {
+            return None;
+        }
+        self.stats.frames += 1;
+        let first = len.min(N - start);
+        Some((&buf[start..start + first], &buf[..len - first]))
+    }
+}
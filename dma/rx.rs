@@ -0,0 +1,92 @@
+//! USART2 RX over DMA1 channel 6, frames end when the line goes idle.
+
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use super::ring::{Ring, RxStats};
+
+type USART = hal::pac::USART2;
+type RxUsart = hal::serial::Rx<USART>;
+type RxCh = hal::dma::dma1::C6;
+type RxBusy = hal::dma::CircBuffer<[u8; RX_HALF], RxCh>;
+
+/// Bytes the circular buffer holds
+pub const RX_CAPACITY: usize = 2 * RX_HALF;
+const RX_HALF: usize = 64;
+
+static mut RX_BUFFER: [[u8; RX_HALF]; 2] = [[0; RX_HALF]; 2];
+
+/// DMA fills the buffer round and round, `poll` hands out what came in
+/// since the previous frame once the line goes idle. Half and full transfer
+/// interrupts keep track of DMA and pass bursts longer than half the buffer
+/// in pieces.
+pub struct DmaReceiver {
+    ring: Ring<RX_CAPACITY>,
+    // DMA writes it, frames are read through this
+    buffer: *const [u8; RX_CAPACITY],
+    frame: [u8; RX_CAPACITY],
+    // Never stopped
+    _transfer: RxBusy,
+}
+
+// Only DMA shares the buffer
+unsafe impl Send for DmaReceiver {}
+
+impl DmaReceiver {
+    /// Takes the static buffer, create only one. Reception starts right
+    /// away.
+    pub fn create(mut ch: RxCh, rx: RxUsart) -> Self {
+        ch.listen(hal::dma::Event::HalfTransfer);
+        ch.listen(hal::dma::Event::TransferComplete);
+        // HAL has no event for an idle line
+        let usart = unsafe { &*USART::ptr() };
+        usart.icr.write(|w| w.idlecf().set_bit());
+        usart.cr1.modify(|_, w| w.idleie().set_bit());
+        let halves = unsafe { &mut RX_BUFFER };
+        let buffer = halves.as_ptr() as *const [u8; RX_CAPACITY];
+        DmaReceiver {
+            ring: Ring::new(),
+            buffer,
+            frame: [0; RX_CAPACITY],
+            _transfer: rx.circ_read(ch, halves),
+        }
+    }
+
+    pub fn stats(&self) -> RxStats {
+        self.ring.stats()
+    }
+
+    /// Hands the frame received so far to `frame` if the line went idle
+    /// or it takes half the buffer. Run it from both DMA1 channel 6 and
+    /// USART2 interrupts, it clears their flags.
+    pub fn poll(&mut self, frame: impl FnOnce(&[u8])) {
+        let dma = unsafe { &*hal::pac::DMA1::ptr() };
+        let usart = unsafe { &*USART::ptr() };
+        // Flags before position, see `Ring::advance`
+        let flags = dma.isr.read();
+        let half = flags.htif6().bit_is_set();
+        let full = flags.tcif6().bit_is_set();
+        dma.ifcr.write(|w| w.chtif6().set_bit().ctcif6().set_bit());
+        let status = usart.isr.read();
+        let idle = status.idle().bit_is_set();
+        if status.ore().bit_is_set() {
+            // A byte never made it to the buffer
+            self.ring.lose();
+        }
+        usart.icr.write(|w| w.idlecf().set_bit().orecf().set_bit());
+        // Counts down, reloads at zero
+        let left = dma.ch6.ndtr.read().ndt().bits() as usize;
+        let pos = (RX_CAPACITY - left) % RX_CAPACITY;
+        self.ring.advance(pos, half, full);
+        if !idle && !self.ring.is_full() {
+            return;
+        }
+        compiler_fence(Ordering::Acquire);
+        let buffer = unsafe { &*self.buffer };
+        if let Some((a, b)) = self.ring.end(buffer) {
+            let len = a.len() + b.len();
+            self.frame[..a.len()].copy_from_slice(a);
+            self.frame[a.len()..len].copy_from_slice(b);
+            frame(&self.frame[..len]);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! USART2 TX over DMA1 channel 7.
+
+use core::fmt::{self, Write};
+
+use super::outbox::{Buffer, Outbox, Stats};
+use super::queue::Drain;
+
+type USART = hal::pac::USART2;
+type TxUsart = hal::serial::Tx<USART>;
+type TxCh = hal::dma::dma1::C7;
+type TxBusy = hal::dma::Transfer<
+    hal::dma::R,
+    &'static mut Buffer<CAPACITY>,
+    TxCh,
+    TxUsart,
+>;
+
+/// Bytes each of the two buffers holds
+pub const CAPACITY: usize = 256;
+
+static mut BUFFERS: [Buffer<CAPACITY>; 2] = [Buffer::new(), Buffer::new()];
+
+enum TransferState {
+    Ready(TxCh, TxUsart),
+    Busy(TxBusy),
+}
+
+/// Frames go into a buffer right away, `poll` hands it to DMA when the
+/// previous transfer is done. Every write polls, so what piled up during a
+/// transfer goes out with the next write or an explicit `poll`.
+pub struct DmaTelemetry {
+    outbox: Outbox<CAPACITY>,
+    // `None` only while `poll` switches states
+    state: Option<TransferState>,
+}
+
+impl DmaTelemetry {
+    /// Takes the static buffers, create only one
+    pub fn create(ch: TxCh, tx: TxUsart) -> Self {
+        let [a, b] = unsafe { &mut BUFFERS };
+        DmaTelemetry {
+            outbox: Outbox::new(a, b),
+            state: Some(TransferState::Ready(ch, tx)),
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.outbox.stats()
+    }
+
+    /// Sends `payload` as a COBS frame
+    pub fn write_frame(&mut self, payload: &[u8]) {
+        self.outbox.write_frame(payload);
+        self.poll();
+    }
+
+    /// Sends a COBS frame `fill` writes in place, see `Outbox::frame_with`
+    pub fn frame_with(&mut self, fill: impl FnOnce(&mut [u8]) -> usize) {
+        self.outbox.frame_with(fill);
+        self.poll();
+    }
+
+    /// Sends bytes as they are, for protocols framed on their own
+    pub fn write_raw(&mut self, bytes: &[u8]) {
+        self.outbox.write_raw(bytes);
+        self.poll();
+    }
+
+    /// Ends the text frame started without a trailing newline, if any
+    pub fn end_frame(&mut self) {
+        self.outbox.end_frame();
+        self.poll();
+    }
+
+    /// Moves queued messages into the buffer while they fit, see
+    /// `Drain::drain`, and keeps DMA busy. Run it from the DMA1 channel 7
+    /// interrupt with transfer complete events on, producers pend that
+    /// interrupt after pushing.
+    pub fn send_queued<
+        const HIGH: usize,
+        const LOW: usize,
+        const LEN: usize,
+    >(
+        &mut self,
+        drain: &mut Drain<HIGH, LOW, LEN>,
+    ) {
+        self.poll();
+        let outbox = &mut self.outbox;
+        drain.drain(|stream, payload| outbox.offer(stream.raw, payload));
+        self.poll();
+    }
+
+    /// Takes back the buffer of a finished transfer and starts the next
+    /// one if there is something to send. Never waits.
+    pub fn poll(&mut self) {
+        let state = match self.state.take() {
+            Some(TransferState::Busy(transfer)) if transfer.is_done() => {
+                let (buffer, ch, tx) = transfer.wait();
+                self.outbox.give_back(buffer);
+                TransferState::Ready(ch, tx)
+            }
+            Some(state) => state,
+            None => return,
+        };
+        self.state = Some(match state {
+            TransferState::Ready(ch, tx) => match self.outbox.take() {
+                Some(buffer) => TransferState::Busy(tx.write_all(ch, buffer)),
+                None => TransferState::Ready(ch, tx),
+            },
+            busy => busy,
+        });
+    }
+}
+
+impl fmt::Write for DmaTelemetry {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.outbox.write_str(s)?;
+        self.poll();
+        Ok(())
+    }
+}
@@ -1,6 +1,5 @@
 #![no_std]
 #![no_main]
-#![feature(unboxed_closures)]
 
 #[allow(unused)]
 use panic_abort;
@@ -11,71 +10,13 @@ use rtic::app;
 use hal::gpio::{Input, LowSpeed, Output, PullNone, PullUp, PushPull};
 use hal::prelude::*;
 use hal::time::Bps;
-use heapless::Vec;
 
-type USART = hal::pac::USART2;
-type TxUsart = hal::serial::Tx<USART>;
-type TxCh = hal::dma::dma1::C7;
-type TxBuffer = Vec<u8, 256>;
-type TxReady = (&'static mut TxBuffer, TxCh, TxUsart);
-type TxBusy =
-    hal::dma::Transfer<hal::dma::R, &'static mut TxBuffer, TxCh, TxUsart>;
-static mut BUFFER: TxBuffer = Vec::new();
+#[path = "../cobs/mod.rs"]
+mod cobs;
+#[path = "../dma/mod.rs"]
+mod dma;
 
-enum TransferState {
-    Ready(TxReady),
-    MaybeBusy(TxBusy),
-}
-
-pub struct DmaTelemetry {
-    state: TransferState,
-}
-
-impl DmaTelemetry {
-    fn with_state(ns: TransferState) -> Self {
-        DmaTelemetry { state: ns }
-    }
-}
-
-impl DmaTelemetry {
-    pub fn create(ch: TxCh, tx: TxUsart) -> Self {
-        let bf = unsafe { &mut BUFFER };
-        let state = TransferState::Ready((bf, ch, tx));
-        DmaTelemetry::with_state(state)
-    }
-
-    fn send<F>(self, mut buffer_filler: F) -> Self
-    where
-        F: for<'a> FnMut<(&'a mut TxBuffer,), Output = ()>,
-    {
-        let ns = match self.state {
-            TransferState::Ready((mut buffer, ch, tx)) => {
-                buffer_filler(&mut buffer);
-                TransferState::MaybeBusy(tx.write_all(ch, buffer))
-            }
-            TransferState::MaybeBusy(transfer) => {
-                if transfer.is_done() {
-                    let (buffer, ch, tx) = transfer.wait();
-                    buffer.clear();
-                    TransferState::Ready((buffer, ch, tx))
-                } else {
-                    TransferState::MaybeBusy(transfer)
-                }
-            }
-        };
-
-        match ns {
-            TransferState::MaybeBusy(_) => DmaTelemetry::with_state(ns),
-            TransferState::Ready(_) => {
-                DmaTelemetry::with_state(ns).send(buffer_filler)
-            }
-        }
-    }
-}
-
-fn fill_with_str(buffer: &mut TxBuffer, arg: &str) {
-    buffer.extend_from_slice(arg.as_bytes()).unwrap();
-}
+use dma::DmaTelemetry;
 
 #[app(device = hal::pac, peripherals = true)]
 mod app {
@@ -88,11 +29,14 @@ mod app {
             hal::gpio::PA0<PullUp, Input>,
             hal::exti::EXTI1,
         >,
-        tele: Option<DmaTelemetry>,
     }
 
+    // Both handlers run at the same priority
     #[shared]
-    struct Shared {}
+    struct Shared {
+        #[lock_free]
+        tele: DmaTelemetry,
+    }
 
     #[init]
     fn init(ctx: init::Context) -> (Shared, Local, init::Monotonics) {
@@ -122,29 +66,30 @@ mod app {
 
         let dma_channels = device.DMA1.split(&mut rcc.ahb);
         write!(tx, "dma...\r\n").unwrap();
-        let tele = DmaTelemetry::create(dma_channels.7, tx);
-        let new_tele = tele.send(|b| fill_with_str(b, "Dma ok!\r\n"));
+        let mut tx_ch = dma_channels.7;
+        tx_ch.listen(hal::dma::Event::TransferComplete);
+        let mut tele = DmaTelemetry::create(tx_ch, tx);
+        tele.write_frame(b"Dma ok!\r\n");
         let mut led = gpioa.pa5.output().pull_type(PullNone);
         let _ = led.set_high();
 
         (
-            Shared {},
-            Local {
-                led,
-                extih: handle,
-                tele: Some(new_tele),
-            },
+            Shared { tele },
+            Local { led, extih: handle },
             init::Monotonics(),
         )
     }
 
-    #[task(binds=EXTI0, local = [led, tele, extih])]
+    #[task(binds=EXTI0, local = [led, extih], shared = [tele])]
     fn handle_mpu(ctx: handle_mpu::Context) {
         let _ = ctx.local.led.set_low();
-        if let Some(tele) = ctx.local.tele.take() {
-            let new_tele = tele.send(|b| fill_with_str(b, "interrupt!\n"));
-            *ctx.local.tele = Some(new_tele);
-        }
+        ctx.shared.tele.write_frame(b"interrupt!\n");
         ctx.local.extih.unpend();
     }
+
+    /// Transfer done, sends what piled up meanwhile
+    #[task(binds = DMA1_CH7, shared = [tele])]
+    fn handle_sent(ctx: handle_sent::Context) {
+        ctx.shared.tele.poll();
+    }
 }
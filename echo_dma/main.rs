@@ -1,6 +1,5 @@
 #![no_std]
 #![no_main]
-#![feature(unboxed_closures)]
 
 #[allow(unused)]
 use panic_abort;
@@ -10,77 +9,13 @@ use rtic::app;
 use hal::gpio::{LowSpeed, Output, PullNone, PushPull};
 use hal::prelude::*;
 use hal::time::Bps;
-use heapless::Vec;
 
-type USART = hal::pac::USART2;
-type TxUsart = hal::serial::Tx<USART>;
-type TxCh = hal::dma::dma1::C7;
-type TxBuffer = Vec<u8, 256>;
-type TxReady = (&'static mut TxBuffer, TxCh, TxUsart);
-type TxBusy =
-    hal::dma::Transfer<hal::dma::R, &'static mut TxBuffer, TxCh, TxUsart>;
-static mut OUT_BUFFER: TxBuffer = Vec::new();
-const HALF: usize = 32;
-static mut IN_BUFFER: [[u8; crate::HALF]; 2] = [[0; HALF]; 2];
+#[path = "../cobs/mod.rs"]
+mod cobs;
+#[path = "../dma/mod.rs"]
+mod dma;
 
-enum TransferState {
-    Ready(TxReady),
-    MaybeBusy(TxBusy),
-}
-
-pub struct DmaTelemetry {
-    state: TransferState,
-}
-
-impl DmaTelemetry {
-    fn with_state(ns: TransferState) -> Self {
-        DmaTelemetry { state: ns }
-    }
-}
-
-impl DmaTelemetry {
-    pub fn create(ch: TxCh, tx: TxUsart) -> Self {
-        let bf = unsafe { &mut OUT_BUFFER };
-        let state = TransferState::Ready((bf, ch, tx));
-        DmaTelemetry::with_state(state)
-    }
-
-    fn send<F>(self, mut buffer_filler: F) -> Self
-    where
-        F: for<'a> FnMut<(&'a mut TxBuffer,), Output = ()>,
-    {
-        let ns = match self.state {
-            TransferState::Ready((mut buffer, ch, tx)) => {
-                buffer_filler(&mut buffer);
-                TransferState::MaybeBusy(tx.write_all(ch, buffer))
-            }
-            TransferState::MaybeBusy(transfer) => {
-                if transfer.is_done() {
-                    let (buffer, ch, tx) = transfer.wait();
-                    buffer.clear();
-                    TransferState::Ready((buffer, ch, tx))
-                } else {
-                    TransferState::MaybeBusy(transfer)
-                }
-            }
-        };
-
-        match ns {
-            TransferState::MaybeBusy(_) => DmaTelemetry::with_state(ns),
-            TransferState::Ready(_) => {
-                DmaTelemetry::with_state(ns).send(buffer_filler)
-            }
-        }
-    }
-}
-
-fn fill_with_bytes(buffer: &mut TxBuffer, arg: &[u8]) {
-    buffer.extend_from_slice(arg).unwrap();
-}
-
-fn fill_with_str(buffer: &mut TxBuffer, arg: &str) {
-    buffer.extend_from_slice(arg.as_bytes()).unwrap();
-}
+use dma::{DmaReceiver, DmaTelemetry};
 
 #[app(device = hal::pac, peripherals = true)]
 mod app {
@@ -89,12 +24,16 @@ mod app {
     #[local]
     struct Local {
         led: hal::gpio::PA5<PullNone, Output<PushPull, LowSpeed>>,
-        tele: Option<DmaTelemetry>,
-        cb: hal::dma::CircBuffer<[u8; HALF], hal::dma::dma1::C6>,
     }
 
+    // All handlers run at the same priority
     #[shared]
-    struct Shared {}
+    struct Shared {
+        #[lock_free]
+        rx: DmaReceiver,
+        #[lock_free]
+        tele: DmaTelemetry,
+    }
 
     #[init()]
     fn init(ctx: init::Context) -> (Shared, Local, init::Monotonics) {
@@ -113,47 +52,34 @@ mod app {
                 .serial((gpioa.pa2, gpioa.pa15), Bps(460800), clocks);
         let (tx, rx) = serial.split();
         let dma_channels = device.DMA1.split(&mut rcc.ahb);
-        let tele = DmaTelemetry::create(dma_channels.7, tx);
-        let new_tele = tele.send(|b| fill_with_str(b, "Dma ok!\r\n"));
+        let mut tx_ch = dma_channels.7;
+        tx_ch.listen(hal::dma::Event::TransferComplete);
+        let mut tele = DmaTelemetry::create(tx_ch, tx);
+        tele.write_frame(b"Dma ok!\r\n");
         let mut led = gpioa.pa5.output().pull_type(PullNone);
         let _ = led.set_low();
-        let mut rx_chan = dma_channels.6;
-        rx_chan.listen(hal::dma::Event::HalfTransfer);
-        rx_chan.listen(hal::dma::Event::TransferComplete);
-        let ib = unsafe { &mut IN_BUFFER };
-        let cb = rx.circ_read(rx_chan, ib);
-        (
-            Shared {},
-            Local {
-                led,
-                cb,
-                tele: Some(new_tele),
-            },
-            init::Monotonics(),
-        )
+        let rx = DmaReceiver::create(dma_channels.6, rx);
+        (Shared { rx, tele }, Local { led }, init::Monotonics())
     }
 
-    #[task(binds = DMA1_CH6, local = [led, cb, tele], shared = [])]
-    fn handle_read(ctx: handle_read::Context) {
-        let led = ctx.local.led;
-        let _ = led.toggle();
-        let maybe_tele = ctx.local.tele.take();
-        let cb = ctx.local.cb;
+    /// Half or full buffer
+    #[task(binds = DMA1_CH6, shared = [rx, tele])]
+    fn handle_dma(ctx: handle_dma::Context) {
+        let tele = ctx.shared.tele;
+        ctx.shared.rx.poll(|frame| tele.write_frame(frame));
+    }
+
+    /// Line went idle, sender is done with a message
+    #[task(binds = USART2_EXTI26, local = [led], shared = [rx, tele])]
+    fn handle_idle(ctx: handle_idle::Context) {
+        let _ = ctx.local.led.toggle();
+        let tele = ctx.shared.tele;
+        ctx.shared.rx.poll(|frame| tele.write_frame(frame));
+    }
 
-        if let Some(tele) = maybe_tele {
-            let mut msg = [0u8; 32];
-            let ret = cb.peek(|buf, _half| {
-                msg.copy_from_slice(buf);
-            });
-            match ret {
-                Ok(()) => {
-                    let new_tele = tele.send(|b| fill_with_bytes(b, &msg));
-                    *ctx.local.tele = Some(new_tele);
-                }
-                Err(_e) => {
-                    // err
-                }
-            }
-        }
+    /// Transfer done, sends what piled up meanwhile
+    #[task(binds = DMA1_CH7, shared = [tele])]
+    fn handle_sent(ctx: handle_sent::Context) {
+        ctx.shared.tele.poll();
     }
 }
@@ -2,7 +2,7 @@
 #![no_main]
 #![feature(type_alias_impl_trait)]
 
-use defmt;
+use cortex_m_rt::exception;
 use embassy_executor::Spawner;
 use embassy_stm32::time::Hertz;
 use embassy_stm32::Config;
@@ -10,6 +10,13 @@ use embassy_time::{Duration, Timer};
 use embassy_stm32::gpio::{Level, Output, Speed};
 use {defmt_rtt as _, panic_probe as _};
 
+#[macro_use]
+#[path = "../logging/mod.rs"]
+mod logging;
+
+#[cfg(feature = "log_usart")]
+compile_error!("no console/ here for log_usart, embassy owns the peripherals");
+
 #[embassy_executor::main]
 async fn main(_spawner: Spawner) -> ! {
     let mut config = Config::default();
@@ -18,11 +25,15 @@ async fn main(_spawner: Spawner) -> ! {
     config.rcc.pclk1 = Some(Hertz(32_000_000));
     config.rcc.pclk2 = Some(Hertz(32_000_000));
     let p = embassy_stm32::init(config);
-    defmt::info!("embassy init");
+    // The time driver runs on a timer, SysTick is free
+    let mut core = cortex_m::Peripherals::take().unwrap();
+    logging::init();
+    logging::start_clock(&mut core.SYST, 64_000_000);
+    info!("embassy init");
     let mut led = Output::new(p.PB3, Level::Low, Speed::Low);
     led.set_high();
     let mut b = true;
-    defmt::info!("embassy led");
+    info!("embassy led");
     loop {
         Timer::after(Duration::from_secs(3)).await;
         if b {
@@ -31,6 +42,11 @@ async fn main(_spawner: Spawner) -> ! {
             led.set_low();
         }
         b = !b;
-        defmt::error!("tick; next {}", if b { "high" } else { "low" });
+        error!("tick; next {}", if b { "high" } else { "low" });
     }
 }
+
+#[exception]
+fn SysTick() {
+    logging::tick();
+}
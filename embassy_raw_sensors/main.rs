@@ -6,9 +6,23 @@
 
 use asm_delay::AsmDelay;
 use core::fmt::Write;
-use defmt;
+use cortex_m_rt::exception;
 use {defmt_rtt as _, panic_probe as _};
 
+// COBS encoder alone, `cobs/` wants the HAL's serial traits
+#[allow(dead_code)]
+#[path = "../cobs/crc.rs"]
+mod crc;
+#[allow(dead_code)]
+#[path = "../cobs/encode.rs"]
+mod encode;
+#[macro_use]
+#[path = "../logging/mod.rs"]
+mod logging;
+
+#[cfg(feature = "log_usart")]
+compile_error!("no console/ here for log_usart, embassy owns the peripherals");
+
 use embassy_executor::Spawner;
 // use embassy_stm32::exti::Channel;
 use embassy_stm32::exti::ExtiInput;
@@ -25,15 +39,22 @@ use mpu9250::Mpu9250;
 struct ToggleQuiet;
 static QUIET: Signal<CriticalSectionRawMutex, ToggleQuiet> = Signal::new();
 const TOGGLE_QUIET: u8 = 'q' as u8;
+// Longest line sent over USART2
+const LOG_LEN: usize = 128;
 
 bind_interrupts!(struct Irqs {
     USART2 => usart::InterruptHandler<peripherals::USART2>;
 });
 
+// Each call goes out as one COBS frame
 macro_rules! log_to_usart {
     ($sink: ident, $buf: ident, $($args:tt)+) => ({
         core::write!(&mut $buf, $($args)+).unwrap();
-        defmt::unwrap!($sink.blocking_write($buf.as_bytes()));
+        let mut frame = [0u8; encode::frame_len(LOG_LEN)];
+        let len = $buf.len();
+        frame[..len].copy_from_slice($buf.as_bytes());
+        let len = defmt::unwrap!(encode::encode_in_place(&mut frame, len));
+        defmt::unwrap!($sink.blocking_write(&frame[..len]));
         // TODO: don't clear until full? %)
         $buf.clear();
     });
@@ -41,21 +62,21 @@ macro_rules! log_to_usart {
 
 #[embassy_executor::task]
 async fn reader(mut rx: usart::UartRx<'static, peripherals::USART2, peripherals::DMA1_CH6>) {
-    defmt::info!("starting reader loop");
+    info!("starting reader loop");
     let mut msg: [u8; 1] = [0; 1];
     loop {
         match rx.read(&mut msg).await {
             Ok(_) => {
-                defmt::info!("received: {}", msg[0]);
+                info!("received: {}", msg[0]);
                 if msg[0] == TOGGLE_QUIET {
-                    defmt::info!("toggling quiet");
+                    info!("toggling quiet");
                     QUIET.signal(ToggleQuiet);
                 } else {
                     // echo byte as is
                 }
             },
             Err(e) => {
-                defmt::error!("read error: {:?}", defmt::Debug2Format(&e));
+                error!("read error: {:?}", e);
                     // TODO: log to usart too
             },
         };
@@ -64,8 +85,6 @@ async fn reader(mut rx: usart::UartRx<'static, peripherals::USART2, peripherals:
 
 #[embassy_executor::main]
 async fn main(spawner: Spawner) -> ! {
-    defmt::info!("Starting MPU Embassy demo!");
-
     let mut config = embassy_stm32::Config::default();
     let sysclk = 64_000_000;
     config.rcc.hse = None;
@@ -73,9 +92,14 @@ async fn main(spawner: Spawner) -> ! {
     config.rcc.pclk1 = Some(Hertz(32_000_000));
     config.rcc.pclk2 = Some(Hertz(32_000_000));
     let device = embassy_stm32::init(config);
-    defmt::info!("Device initialized!");
+    // The time driver runs on a timer, SysTick is free
+    let mut core = cortex_m::Peripherals::take().unwrap();
+    logging::init();
+    logging::start_clock(&mut core.SYST, sysclk);
+    info!("Starting MPU Embassy demo!");
+    info!("Device initialized!");
 
-    let mut log_buf: String<128> = String::new();
+    let mut log_buf: String<LOG_LEN> = String::new();
 
     let mut usart_config = usart::Config::default();
     usart_config.detect_previous_overrun = false;
@@ -91,11 +115,14 @@ async fn main(spawner: Spawner) -> ! {
         usart_config,
     );
     let (mut tx, rx) = usart.split();
-    defmt::info!("Usart initialized!");
+    // Ends whatever the receiver caught before reset, lines go out as
+    // COBS frames after it
+    defmt::unwrap!(tx.blocking_write(&[0]));
+    info!("Usart initialized!");
     log_to_usart!(tx, log_buf, "usart ok!\r\n");
     log_to_usart!(tx, log_buf, "starting USART interrupt reader task!\r\n");
     defmt::unwrap!(spawner.spawn(reader(rx)));
-    defmt::info!("Started reader!");
+    info!("Started reader!");
 
     let mut spi_config = spi::Config::default();
     spi_config.mode = mpu9250::MODE;
@@ -111,7 +138,7 @@ async fn main(spawner: Spawner) -> ! {
     );
 
     log_to_usart!(tx, log_buf, "spi ok!\r\n");
-    defmt::info!("Spi ok!");
+    info!("Spi ok!");
 
     // TODO: use embassy impl of Delay
     let mut delay = AsmDelay::new(asm_delay::bitrate::Hertz(sysclk));
@@ -134,21 +161,22 @@ async fn main(spawner: Spawner) -> ! {
         Ok(ab) => ab,
         Err(e) => {
             log_to_usart!(tx, log_buf, "mpu calib err  {:?}!\r\n", e);
-            defmt::panic!("mpu calib error: {:?}", defmt::Debug2Format(&e));
+            error!("mpu calib error: {:?}", e);
+            panic!("mpu calib error");
         }
     };
     log_to_usart!(tx, log_buf, "calib ok  {:?}!\r\n", accel_biases);
-    defmt::info!("calib ok!");
+    info!("calib ok!");
 
     mpu.enable_interrupts(mpu9250::InterruptEnable::RAW_RDY_EN).unwrap();
     let enabled_int = mpu.get_enabled_interrupts();
-    defmt::info!("mpu int enabled; now: {:?}", defmt::Debug2Format(&enabled_int));
+    info!("mpu int enabled; now: {:?}", enabled_int);
 
     let drdy = Input::new(device.PA11, Pull::Up);
     let mut drdy = ExtiInput::new(drdy, device.EXTI11);
-    defmt::info!("mpupin enabled");
+    info!("mpupin enabled");
     let mut led = Output::new(device.PB3, Level::Low, Speed::Low);
-    defmt::info!("led ready");
+    info!("led ready");
 
     let mut prev_t_ms = Instant::now().as_millis();
 
@@ -158,7 +186,7 @@ async fn main(spawner: Spawner) -> ! {
         "All ok, now: {:?}; Press 'q' to toggle verbosity!\r\n",
         prev_t_ms
     );
-    defmt::info!("all ok, starting loop!");
+    info!("all ok, starting loop!");
 
     let mut quiet = true;
     let mut c = 0u16;
@@ -190,11 +218,11 @@ async fn main(spawner: Spawner) -> ! {
                         accel[1],
                         accel[2]
                     );
-                    defmt::trace!("Measured mpu");
+                    trace!("Measured mpu");
                 }
                 if QUIET.signaled() {
                     quiet = !quiet;
-                    defmt::info!("Signaled quiet: new state: {}", quiet);
+                    info!("Signaled quiet: new state: {}", quiet);
                     QUIET.reset();
                     if quiet {
                         led.set_high();
@@ -204,9 +232,14 @@ async fn main(spawner: Spawner) -> ! {
                 }
             }
             Err(e) => {
-                log_to_usart!(tx, log_buf, "Err: {:?}; {:?}", t_ms, e);
-                defmt::error!("mpu error!");
+                log_to_usart!(tx, log_buf, "Err: {:?}; {:?}\r\n", t_ms, e);
+                error!("mpu error: {:?}", e);
             }
         }
     }
 }
+
+#[exception]
+fn SysTick() {
+    logging::tick();
+}
@@ -1,134 +1,61 @@
 #![no_std]
 #![no_main]
-#![feature(unboxed_closures)]
 
 #[allow(unused)]
 use panic_semihosting;
 
 use core::str::FromStr;
 use cortex_m_rt::{entry, exception, ExceptionFrame};
-use cortex_m_semihosting::hprintln;
 use dcmimu::DCMIMU;
 use hal::prelude::*;
 use hal::serial;
 use hal::time::Bps;
-use heapless::consts::*;
-use heapless::Vec;
 use nb;
+use telemetry::{Attitude, Estimator, Message};
 
-const BUFFER_SIZE: usize = 512;
-const CR: u8 = '\r' as u8;
-const LF: u8 = '\n' as u8;
-
-pub struct Cmd {
-    buffer: [u8; BUFFER_SIZE],
-    pos: usize,
-}
-
-impl Cmd {
-    #[inline]
-    const fn new() -> Cmd {
-        Cmd {
-            buffer: [0; BUFFER_SIZE],
-            pos: 0,
-        }
-    }
-
-    #[inline]
-    fn push(&mut self, b: u8) -> Option<&[u8]> {
-        if b == CR || b == LF {
-            if self.pos == 0 {
-                None
-            } else {
-                let result = &self.buffer[0..self.pos];
-                self.pos = 0;
-                Some(result)
-            }
-        } else {
-            self.buffer[self.pos] = b;
-            self.pos = (self.pos + 1) & (BUFFER_SIZE - 1);
-            None
-        }
-    }
-}
-
-type USART = hal::pac::USART2;
-type TxUsart = hal::serial::Tx<USART>;
-type TxCh = hal::dma::dma1::C7;
-type TxBuffer = Vec<u8, U256>;
-type TxReady = (&'static mut TxBuffer, TxCh, TxUsart);
-type TxBusy =
-    hal::dma::Transfer<hal::dma::R, &'static mut TxBuffer, TxCh, TxUsart>;
-static mut BUFFER: TxBuffer = Vec::new();
-
-enum TransferState {
-    Ready(TxReady),
-    MaybeBusy(TxBusy),
-}
-
-struct DmaTelemetry {
-    state: TransferState,
-}
+#[path = "../cobs/mod.rs"]
+mod cobs;
+#[path = "../dma/mod.rs"]
+mod dma;
+#[macro_use]
+#[path = "../logging/mod.rs"]
+mod logging;
+#[path = "../shell/mod.rs"]
+mod shell;
 
-impl DmaTelemetry {
-    fn with_state(ns: TransferState) -> Self {
-        DmaTelemetry { state: ns }
-    }
-}
+#[cfg(feature = "log_usart")]
+compile_error!("no console/ here for log_usart, USART2 carries the feed");
 
-impl DmaTelemetry {
-    pub fn create(ch: TxCh, tx: TxUsart) -> Self {
-        let bf = unsafe { &mut BUFFER };
-        let state = TransferState::Ready((bf, ch, tx));
-        DmaTelemetry::with_state(state)
-    }
+use dma::DmaTelemetry;
+use shell::Cmd;
 
-    fn send<F>(self, mut buffer_filler: F) -> Self
-    where
-        F: for<'a> FnMut<(&'a mut TxBuffer,), Output = ()>,
-    {
-        let ns = match self.state {
-            TransferState::Ready((mut buffer, ch, tx)) => {
-                buffer_filler(&mut buffer);
-                TransferState::MaybeBusy(tx.write_all(ch, buffer))
-            }
-            TransferState::MaybeBusy(transfer) => {
-                if transfer.is_done() {
-                    let (buffer, ch, tx) = transfer.wait();
-                    buffer.clear();
-                    TransferState::Ready((buffer, ch, tx))
-                } else {
-                    TransferState::MaybeBusy(transfer)
-                }
-            }
-        };
+const BUFFER_SIZE: usize = 512;
 
-        match ns {
-            TransferState::MaybeBusy(_) => DmaTelemetry::with_state(ns),
-            TransferState::Ready(_) => {
-                DmaTelemetry::with_state(ns).send(buffer_filler)
-            }
+/// Encodes messages back to back into frame payload, the ones that don't
+/// fit are left out whole
+fn encode_messages(payload: &mut [u8], msgs: &[Message]) -> usize {
+    let mut len = 0;
+    for msg in msgs {
+        if let Ok(n) = telemetry::append(payload, len, msg) {
+            len = n;
         }
     }
+    len
 }
 
-fn fill_with_bytes(buffer: &mut TxBuffer, arg: &[u8]) {
-    buffer.extend_from_slice(arg).unwrap();
-}
-
-fn fill_with_floats(buffer: &mut TxBuffer, arg: &[f32]) {
-    for f in arg.into_iter() {
-        let mut b = ryu::Buffer::new();
-        let s = b.format(*f);
-        buffer.extend_from_slice(s.as_bytes()).unwrap();
-        buffer.push(',' as u8).unwrap();
-    }
-    buffer.push('\n' as u8).unwrap();
+fn euler(estimator: Estimator, ypr: [f32; 3]) -> Message<'static> {
+    Message::Attitude(Attitude {
+        estimator,
+        quat: None,
+        ypr: Some(ypr),
+        gyro_bias: None,
+    })
 }
 
 #[entry]
 fn main() -> ! {
     let device = hal::pac::Peripherals::take().unwrap();
+    let mut core = cortex_m::Peripherals::take().unwrap();
     let mut rcc = device.RCC.constrain();
     let mut flash = device.FLASH.constrain();
     let clocks = rcc
@@ -146,26 +73,39 @@ fn main() -> ! {
     serial.listen(serial::Event::Rxne);
     let (tx, mut rx) = serial.split();
     let dma_channels = device.DMA1.split(&mut rcc.ahb);
-    let mut cmd = Cmd::new();
+    let mut cmd = Cmd::<BUFFER_SIZE>::new();
     let mut tele = DmaTelemetry::create(dma_channels.7, tx);
     let mut dcm = DCMIMU::new();
-    hprintln!("ready...").unwrap();
+    logging::init();
+    logging::start_clock(&mut core.SYST, clocks.sysclk().0);
+    info!("ready...");
     loop {
-        match nb::block!(rx.read()) {
+        // Sends what piled up during the previous transfer
+        tele.poll();
+        match rx.read() {
             Ok(b) => {
-                if let Some(word) = cmd.push(b) {
-                    let v = unsafe { core::str::from_utf8_unchecked(word) };
-                    let (acc, gyro, dt_s, (oy, op, or)) = parse(v);
-                    let (ypr, _biased_gyro) = dcm.update(gyro, acc, dt_s);
-                    let to_send = [ypr.yaw, ypr.pitch, ypr.roll, oy, op, or];
-                    // let (ax, ay, az) = acc;
-                    // let (gx, gy, gz) = gyro;
-                    // let to_send = [ax, ay, az, gx, gy, gz, dt_s, oy, op, or];
-                    // tele = tele.send(|b| fill_with_bytes(b, word))
-                    tele = tele.send(|b| fill_with_floats(b, &to_send));
-                }
+                let word = match cmd.push(b) {
+                    None => continue,
+                    Some(Ok(word)) => word,
+                    Some(Err(e)) => {
+                        warn!("skipped: {}", e);
+                        continue;
+                    }
+                };
+                let v = unsafe { core::str::from_utf8_unchecked(word) };
+                let (acc, gyro, dt_s, (oy, op, or)) = parse(v);
+                let (ypr, _biased_gyro) = dcm.update(gyro, acc, dt_s);
+                // Computed attitude next to the logged one
+                let computed = [ypr.yaw, ypr.pitch, ypr.roll];
+                let to_send = [
+                    euler(Estimator::Dcmimu, computed),
+                    euler(Estimator::Reference, [oy, op, or]),
+                ];
+                // tele.write_frame(word);
+                tele.frame_with(|b| encode_messages(b, &to_send));
             }
-            Err(e) => match e {
+            Err(nb::Error::WouldBlock) => {}
+            Err(nb::Error::Other(e)) => match e {
                 serial::Error::Overrun => {
                     rx.clear_overrun_error();
                 }
@@ -176,7 +116,7 @@ fn main() -> ! {
                     rx.clear_noise_error();
                 }
                 _ => {
-                    hprintln!("re: {:?}", e).unwrap();
+                    error!("re: {:?}", e);
                 }
             },
         };
@@ -223,6 +163,11 @@ fn parse(
     return ((ax, ay, az), (gx, gy, gz), dt_s, (y, p, r));
 }
 
+#[exception]
+fn SysTick() {
+    logging::tick();
+}
+
 #[exception]
 fn HardFault(ef: &ExceptionFrame) -> ! {
     panic!("HardFault at {:#?}", ef);
@@ -0,0 +1,56 @@
+// Filters levels against each maximum and formats lines, checks
+// timestamps and cutting of long lines:
+//     rustc --edition 2021 -O check.rs && ./check
+// Panics on failure.
+
+#![allow(dead_code)]
+
+#[path = "record.rs"]
+mod record;
+
+use record::{format, passes, Level, Line};
+
+use core::fmt::Write;
+
+fn filtering() {
+    use Level::*;
+    let all = [Error, Warn, Info, Debug, Trace];
+    for (i, max) in all.iter().enumerate() {
+        for (j, level) in all.iter().enumerate() {
+            assert_eq!(
+                passes(*level, Some(*max)),
+                j <= i,
+                "{} at {}",
+                level,
+                max
+            );
+        }
+        assert!(!passes(*max, None), "off");
+    }
+    println!("filtering ok");
+}
+
+fn lines() {
+    let line = format::<64>(Level::Info, 1234, format_args!("mpu {}", "ok"));
+    assert_eq!(line.as_str(), "     1.234 INFO  mpu ok");
+    let line = format::<64>(Level::Error, 5, format_args!("x"));
+    assert_eq!(line.as_str(), "     0.005 ERROR x");
+    // A day and then some
+    let line = format::<64>(Level::Debug, 90_061_001, format_args!(""));
+    assert_eq!(line.as_str(), " 90061.001 DEBUG ");
+    // Cut at a char boundary, nothing after the cut
+    let mut line = Line::<4>::new();
+    write!(line, "ab°c").unwrap();
+    write!(line, "d").unwrap();
+    assert_eq!(line.as_str(), "ab°");
+    let mut line = Line::<4>::new();
+    write!(line, "abc°").unwrap();
+    write!(line, "d").unwrap();
+    assert_eq!(line.as_str(), "abc");
+    println!("lines ok");
+}
+
+fn main() {
+    filtering();
+    lines();
+}
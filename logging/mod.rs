@@ -0,0 +1,124 @@
+//! Leveled log lines with millisecond timestamps, sent where the build
+//! says.
+//!
+//! `error!`, `warn!`, `info!`, `debug!` and `trace!` take `format!`
+//! arguments and log a line like `     1.234 INFO  mpu ok`. Cargo features
+//! pick the sinks, the same code logs through any of them without edits:
+//!
+//! * `log_usart`: `console/` on USART2, the binary includes it under the
+//!   feature and calls `console::init`. Binaries that can't give USART2
+//!   away stop the build with `compile_error!` instead
+//! * `log_semihosting`: debugger console, halts the core on every line
+//! * `log_rtt`: RTT up channel 0, call `logging::init` first
+//! * `log_defmt`: defmt over RTT at the matching level, `DEFMT_LOG` filters
+//!   too. Lines are formatted on the target
+//!
+//! Several can be on at once, except `log_rtt` with `log_defmt`: both want
+//! RTT. With none logging compiles to nothing, so each binary requires the
+//! sink it always printed through in its `required-features`. One of `max_level_off`,
+//! `max_level_error`, `max_level_warn`, `max_level_info` or
+//! `max_level_debug` leaves out less severe lines at compile time,
+//! everything is logged without them.
+//!
+//! Timestamps count `tick` calls: call it from a SysTick handler firing
+//! every millisecond, `start_clock` sets SysTick up for that.
+//!
+//! Include with `#[macro_use] #[path = "../logging/mod.rs"] mod logging;`.
+//! `check.rs` tests filtering and line format on the host.
+
+// Binaries use only some of it
+#![allow(dead_code, unused_imports, unused_macros)]
+
+mod record;
+mod sinks;
+
+use core::fmt;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use cortex_m::peripheral::syst::SystClkSource;
+use cortex_m::peripheral::SYST;
+
+pub use record::{format, Level, Line, Stamp};
+pub use sinks::LINE_LEN;
+
+/// Least severe level built in, `None` for no logging at all
+pub const MAX_LEVEL: Option<Level> = if cfg!(feature = "max_level_off") {
+    None
+} else if cfg!(feature = "max_level_error") {
+    Some(Level::Error)
+} else if cfg!(feature = "max_level_warn") {
+    Some(Level::Warn)
+} else if cfg!(feature = "max_level_info") {
+    Some(Level::Info)
+} else if cfg!(feature = "max_level_debug") {
+    Some(Level::Debug)
+} else {
+    Some(Level::Trace)
+};
+
+static NOW_MS: AtomicU32 = AtomicU32::new(0);
+
+/// Sets up sinks that need it, before the first line
+pub fn init() {
+    #[cfg(feature = "log_rtt")]
+    rtt_target::rtt_init_print!();
+}
+
+/// SysTick every millisecond with its interrupt on, the binary's handler
+/// calls `tick`
+pub fn start_clock(syst: &mut SYST, sysclk_hz: u32) {
+    syst.set_reload(sysclk_hz / 1000 - 1);
+    syst.clear_current();
+    syst.set_clock_source(SystClkSource::Core);
+    syst.enable_interrupt();
+    syst.enable_counter();
+}
+
+/// One more millisecond
+pub fn tick() {
+    NOW_MS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn now_ms() -> u32 {
+    NOW_MS.load(Ordering::Relaxed)
+}
+
+/// Evaluated at compile time, lines filtered out don't make it into the
+/// binary
+pub const fn enabled(level: Level) -> bool {
+    record::passes(level, MAX_LEVEL)
+}
+
+/// What the macros call, use them instead
+pub fn log(level: Level, args: fmt::Arguments) {
+    sinks::write(level, now_ms(), args);
+}
+
+macro_rules! log_at {
+    ($level:expr, $($tt:tt)*) => ({
+        const ON: bool = crate::logging::enabled($level);
+        if ON {
+            crate::logging::log($level, format_args!($($tt)*));
+        }
+    });
+}
+
+macro_rules! error {
+    ($($tt:tt)*) => (log_at!(crate::logging::Level::Error, $($tt)*));
+}
+
+macro_rules! warn {
+    ($($tt:tt)*) => (log_at!(crate::logging::Level::Warn, $($tt)*));
+}
+
+macro_rules! info {
+    ($($tt:tt)*) => (log_at!(crate::logging::Level::Info, $($tt)*));
+}
+
+macro_rules! debug {
+    ($($tt:tt)*) => (log_at!(crate::logging::Level::Debug, $($tt)*));
+}
+
+macro_rules! trace {
+    ($($tt:tt)*) => (log_at!(crate::logging::Level::Trace, $($tt)*));
+}
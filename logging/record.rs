@@ -0,0 +1,98 @@
+//! Levels and line format, no hardware involved.
+
+use core::fmt::{self, Write};
+
+/// Most severe first
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// Whether `level` gets logged when `max` is the least severe one that
+/// does, `None` logs nothing
+pub const fn passes(level: Level, max: Option<Level>) -> bool {
+    match max {
+        Some(max) => level as u8 <= max as u8,
+        None => false,
+    }
+}
+
+/// Milliseconds shown as seconds
+pub struct Stamp(pub u32);
+
+impl fmt::Display for Stamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:6}.{:03}", self.0 / 1000, self.0 % 1000)
+    }
+}
+
+/// Text formatted on the stack, what doesn't fit `N` bytes is cut
+pub struct Line<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+    /// Text after a cut is left out too
+    cut: bool,
+}
+
+impl<const N: usize> Line<N> {
+    pub const fn new() -> Self {
+        Line {
+            bytes: [0; N],
+            len: 0,
+            cut: false,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Cut at char boundaries only
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+impl<const N: usize> fmt::Write for Line<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            let n = c.len_utf8();
+            self.cut |= self.len + n > N;
+            if self.cut {
+                break;
+            }
+            c.encode_utf8(&mut self.bytes[self.len..]);
+            self.len += n;
+        }
+        Ok(())
+    }
+}
+
+/// `     1.234 INFO  text`, no line ending
+pub fn format<const N: usize>(
+    level: Level,
+    ms: u32,
+    args: fmt::Arguments,
+) -> Line<N> {
+    let mut line = Line::new();
+    let _ = write!(line, "{} {:5} {}", Stamp(ms), level, args);
+    line
+}
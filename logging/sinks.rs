@@ -0,0 +1,71 @@
+//! Where lines go, one sink per cargo feature.
+
+use core::fmt;
+
+#[cfg(feature = "log_defmt")]
+use core::fmt::Write;
+
+use super::record::{format, Level};
+#[cfg(feature = "log_defmt")]
+use super::record::{Line, Stamp};
+
+// Global logger for defmt
+#[cfg(feature = "log_defmt")]
+use defmt_rtt as _;
+
+/// Bytes of a line, longer ones are cut
+pub const LINE_LEN: usize = 128;
+
+/// Formats the line once for the text sinks, defmt gets the level apart
+pub fn write(level: Level, ms: u32, args: fmt::Arguments) {
+    let line = format::<LINE_LEN>(level, ms, args);
+    let text = line.as_str();
+    #[cfg(feature = "log_usart")]
+    usart(text);
+    #[cfg(feature = "log_semihosting")]
+    semihosting(text);
+    #[cfg(feature = "log_rtt")]
+    rtt(text);
+    #[cfg(feature = "log_defmt")]
+    defmt(level, ms, args);
+    let _ = text;
+}
+
+/// Through `console/`, the binary sets it up
+#[cfg(feature = "log_usart")]
+fn usart(text: &str) {
+    crate::console::write(text.as_bytes());
+    crate::console::write(b"\r\n");
+}
+
+/// Host stdout through the debugger, halts the core while it writes
+#[cfg(feature = "log_semihosting")]
+fn semihosting(text: &str) {
+    if let Ok(mut out) = cortex_m_semihosting::hio::hstdout() {
+        let _ = out.write_all(text.as_bytes());
+        let _ = out.write_all(b"\n");
+    }
+}
+
+/// RTT up channel 0, see `logging::init`
+#[cfg(feature = "log_rtt")]
+fn rtt(text: &str) {
+    rtt_target::rprintln!("{}", text);
+}
+
+/// At the matching defmt level, so `DEFMT_LOG` filters on top of
+/// `max_level_*`. defmt shows the level itself, the text carries the
+/// timestamp. `format!` arguments are formatted here, on the target.
+#[cfg(feature = "log_defmt")]
+fn defmt(level: Level, ms: u32, args: fmt::Arguments) {
+    let mut line = Line::<LINE_LEN>::new();
+    let _ = write!(line, "{} {}", Stamp(ms), args);
+    let text = line.as_str();
+    match level {
+        Level::Error => defmt::error!("{=str}", text),
+        Level::Warn => defmt::warn!("{=str}", text),
+        Level::Info => defmt::info!("{=str}", text),
+        Level::Debug => defmt::debug!("{=str}", text),
+        Level::Trace => defmt::trace!("{=str}", text),
+    }
+}
@@ -16,7 +16,11 @@ use nb;
 
 use lsm303c::Lsm303c;
 
-static mut TX: Option<hal::serial::Tx<hal::pac::USART1>> = None;
+#[path = "../cobs/mod.rs"]
+mod cobs;
+use cobs::FrameWriter;
+
+static mut TX: Option<FrameWriter<hal::serial::Tx<hal::pac::USART1>>> = None;
 static mut RX: Option<hal::serial::Rx<hal::pac::USART1>> = None;
 static mut QUIET: bool = true;
 const TURN_QUIET: u8 = 'q' as u8;
@@ -40,11 +44,9 @@ fn main() -> ! {
             .serial((gpioa.pa9, gpioa.pa10), Bps(115200), clocks);
     let ser_int = serial.get_interrupt();
     serial.listen(serial::Event::Rxne);
-    let (mut tx, rx) = serial.split();
-    // COBS frame
-    tx.write(0x00).unwrap();
+    let (tx, rx) = serial.split();
     unsafe {
-        TX = Some(tx);
+        TX = Some(FrameWriter::start(tx).unwrap());
         RX = Some(rx);
     };
     let l = unsafe { extract(&mut TX) };
@@ -176,6 +178,7 @@ fn panic(panic_info: &PanicInfo) -> ! {
                     write!(l, "panic occured, no info available").unwrap();
                 }
             }
+            let _ = l.end_frame();
         }
         None => {}
     }
@@ -0,0 +1,71 @@
+//! Keeping streams inside what the link carries.
+
+/// 8N1: start and stop bit around every byte
+pub const fn bytes_per_s(baud: u32) -> u32 {
+    baud / 10
+}
+
+/// Token bucket over the link rate. Blocking writers check it before a
+/// frame and skip the frame instead of stalling the loop behind the port.
+pub struct Budget {
+    bytes_per_s: u32,
+    /// In thousandths of a byte, ms times bytes per second
+    credit: u32,
+    max_credit: u32,
+    last_ms: u32,
+}
+
+impl Budget {
+    /// Up to `burst` bytes can go out back to back after a quiet spell
+    pub fn new(baud: u32, burst: usize, now_ms: u32) -> Self {
+        let max_credit = burst as u32 * 1000;
+        Budget {
+            bytes_per_s: bytes_per_s(baud),
+            credit: max_credit,
+            max_credit,
+            last_ms: now_ms,
+        }
+    }
+
+    /// Takes `len` bytes if there is that much, `false` means drop it
+    pub fn spend(&mut self, now_ms: u32, len: usize) -> bool {
+        // Longer gaps fill the bucket anyway, this keeps it from overflowing
+        let dt_ms = now_ms.wrapping_sub(self.last_ms).min(10_000);
+        self.last_ms = now_ms;
+        self.credit = self
+            .credit
+            .saturating_add(dt_ms * self.bytes_per_s)
+            .min(self.max_credit);
+        let cost = len as u32 * 1000;
+        if cost > self.credit {
+            return false;
+        }
+        self.credit -= cost;
+        true
+    }
+}
+
+/// Fixed rate stream on a millisecond clock
+pub struct Interval {
+    period_ms: u32,
+    next_ms: u32,
+}
+
+impl Interval {
+    /// Due right away
+    pub fn new(period_ms: u32, now_ms: u32) -> Self {
+        Interval {
+            period_ms,
+            next_ms: now_ms,
+        }
+    }
+
+    /// True once per period, missed periods are not made up
+    pub fn due(&mut self, now_ms: u32) -> bool {
+        if (now_ms.wrapping_sub(self.next_ms) as i32) < 0 {
+            return false;
+        }
+        self.next_ms = now_ms.wrapping_add(self.period_ms);
+        true
+    }
+}
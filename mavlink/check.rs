@@ -0,0 +1,428 @@
+// Round trips every message through encoder and parser, checks CRC_EXTRA
+// against the field definitions, damages the stream to see the parser
+// recover, and keeps the link budget honest:
+//     rustc --edition 2021 -O check.rs && ./check
+// Panics on failure.
+
+#[path = "mod.rs"]
+mod mavlink;
+
+use mavlink::*;
+
+/// xorshift64*
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() >> 33) as usize % n
+    }
+
+    fn f32(&mut self) -> f32 {
+        (self.below(20001) as f32 - 10000.) / 100.
+    }
+
+    fn i16(&mut self) -> i16 {
+        self.next() as i16
+    }
+}
+
+/// How the generator derives it: name and base fields sorted as on the
+/// wire, extensions excluded
+fn crc_extra_of(name: &str, fields: &[(&str, &str, u8)]) -> u8 {
+    let mut crc = x25(format!("{} ", name).as_bytes());
+    let more = |crc: u16, s: &[u8]| s.iter().fold(crc, |c, b| upd(c, *b));
+    for (ty, field, array) in fields {
+        crc = more(crc, format!("{} {} ", ty, field).as_bytes());
+        if *array > 0 {
+            crc = upd(crc, *array);
+        }
+    }
+    ((crc & 0xff) ^ (crc >> 8)) as u8
+}
+
+fn upd(crc: u16, b: u8) -> u16 {
+    let mut t = b ^ (crc & 0xff) as u8;
+    t ^= t << 4;
+    let t = t as u16;
+    (crc >> 8) ^ (t << 8) ^ (t << 3) ^ (t >> 4)
+}
+
+fn crc() {
+    assert_eq!(x25(b"123456789"), 0x6f91);
+    let heartbeat = [
+        ("uint32_t", "custom_mode", 0),
+        ("uint8_t", "type", 0),
+        ("uint8_t", "autopilot", 0),
+        ("uint8_t", "base_mode", 0),
+        ("uint8_t", "system_status", 0),
+        ("uint8_t", "mavlink_version", 0),
+    ];
+    let attitude = [
+        ("uint32_t", "time_boot_ms", 0),
+        ("float", "q1", 0),
+        ("float", "q2", 0),
+        ("float", "q3", 0),
+        ("float", "q4", 0),
+        ("float", "rollspeed", 0),
+        ("float", "pitchspeed", 0),
+        ("float", "yawspeed", 0),
+    ];
+    let mut raw_imu = vec![("uint64_t", "time_usec", 0)];
+    for f in [
+        "xacc", "yacc", "zacc", "xgyro", "ygyro", "zgyro", "xmag", "ymag",
+        "zmag",
+    ] {
+        raw_imu.push(("int16_t", f, 0));
+    }
+    let pressure = [
+        ("uint32_t", "time_boot_ms", 0),
+        ("float", "press_abs", 0),
+        ("float", "press_diff", 0),
+        ("int16_t", "temperature", 0),
+    ];
+    let distance = [
+        ("uint32_t", "time_boot_ms", 0),
+        ("uint16_t", "min_distance", 0),
+        ("uint16_t", "max_distance", 0),
+        ("uint16_t", "current_distance", 0),
+        ("uint8_t", "type", 0),
+        ("uint8_t", "id", 0),
+        ("uint8_t", "orientation", 0),
+        ("uint8_t", "covariance", 0),
+    ];
+    let cases = [
+        ("HEARTBEAT", &heartbeat[..], Heartbeat::CRC_EXTRA),
+        (
+            "ATTITUDE_QUATERNION",
+            &attitude[..],
+            AttitudeQuaternion::CRC_EXTRA,
+        ),
+        ("RAW_IMU", &raw_imu[..], RawImu::CRC_EXTRA),
+        ("SCALED_PRESSURE", &pressure[..], ScaledPressure::CRC_EXTRA),
+        ("DISTANCE_SENSOR", &distance[..], DistanceSensor::CRC_EXTRA),
+    ];
+    for (name, fields, extra) in cases {
+        assert_eq!(crc_extra_of(name, fields), extra, "{}", name);
+    }
+    println!("crc ok");
+}
+
+fn encode<M: Message>(enc: &mut Encoder, msg: &M) -> Vec<u8> {
+    let mut buf = vec![0xaa; frame_len::<M>()];
+    let n = enc.encode(msg, &mut buf).expect("fits");
+    buf.truncate(n);
+    buf
+}
+
+fn parse_one<M: Message>(frame: &[u8]) -> (M, u8) {
+    let mut p = Parser::new();
+    let mut out = None;
+    for (i, b) in frame.iter().enumerate() {
+        if let Some(r) = p.push(*b) {
+            assert_eq!(i, frame.len() - 1, "frame ended early");
+            let f = r.expect("good frame");
+            assert_eq!((f.sysid, f.compid), (1, 1));
+            out = Some((f.decode::<M>().expect("same message"), f.seq));
+        }
+    }
+    out.expect("no frame")
+}
+
+fn roundtrip_one<M: Message + PartialEq + std::fmt::Debug>(
+    enc: &mut Encoder,
+    msg: M,
+) {
+    let frame = encode(enc, &msg);
+    assert_eq!(frame[0], 0xfd);
+    assert!(frame.len() <= frame_len::<M>());
+    let (back, _) = parse_one::<M>(&frame);
+    assert_eq!(back, msg);
+}
+
+fn random_messages(rng: &mut Rng, enc: &mut Encoder) -> Vec<(u32, Vec<u8>)> {
+    let mut out = Vec::new();
+    let t = rng.next() as u32;
+    let i3 = |rng: &mut Rng| [rng.i16(), rng.i16(), rng.i16()];
+    let hb = Heartbeat::quadrotor(rng.below(8) as u8);
+    out.push((Heartbeat::ID, encode(enc, &hb)));
+    let att = AttitudeQuaternion {
+        time_boot_ms: t,
+        q: [rng.f32(), rng.f32(), rng.f32(), rng.f32()],
+        rollspeed: rng.f32(),
+        pitchspeed: rng.f32(),
+        yawspeed: 0.,
+    };
+    out.push((AttitudeQuaternion::ID, encode(enc, &att)));
+    let imu = RawImu {
+        time_usec: rng.next(),
+        acc: i3(rng),
+        gyro: i3(rng),
+        mag: i3(rng),
+        id: 0,
+        temperature: rng.i16(),
+    };
+    out.push((RawImu::ID, encode(enc, &imu)));
+    let pressure = ScaledPressure {
+        time_boot_ms: t,
+        press_abs: 900. + rng.f32(),
+        press_diff: 0.,
+        temperature: rng.i16(),
+    };
+    out.push((ScaledPressure::ID, encode(enc, &pressure)));
+    let distance = DistanceSensor {
+        time_boot_ms: t,
+        min_distance: 3,
+        max_distance: 200,
+        current_distance: rng.below(202) as u16,
+        sensor_type: MAV_DISTANCE_SENSOR_LASER,
+        id: 0,
+        orientation: MAV_SENSOR_ROTATION_PITCH_270,
+        covariance: 255,
+    };
+    out.push((DistanceSensor::ID, encode(enc, &distance)));
+    out
+}
+
+fn roundtrip() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    let mut enc = Encoder::new(1, 1);
+    for _ in 0..200 {
+        roundtrip_one(&mut enc, Heartbeat::quadrotor(MAV_STATE_ACTIVE));
+        roundtrip_one(
+            &mut enc,
+            AttitudeQuaternion {
+                time_boot_ms: rng.next() as u32,
+                q: [rng.f32(), rng.f32(), rng.f32(), rng.f32()],
+                rollspeed: rng.f32(),
+                pitchspeed: rng.f32(),
+                yawspeed: rng.f32(),
+            },
+        );
+        roundtrip_one(
+            &mut enc,
+            RawImu {
+                time_usec: rng.next(),
+                acc: [rng.i16(), rng.i16(), rng.i16()],
+                gyro: [rng.i16(), rng.i16(), rng.i16()],
+                mag: [rng.i16(), rng.i16(), rng.i16()],
+                id: rng.below(3) as u8,
+                temperature: rng.i16(),
+            },
+        );
+        roundtrip_one(
+            &mut enc,
+            ScaledPressure {
+                time_boot_ms: rng.next() as u32,
+                press_abs: rng.f32(),
+                press_diff: rng.f32(),
+                temperature: rng.i16(),
+            },
+        );
+        roundtrip_one(
+            &mut enc,
+            DistanceSensor {
+                time_boot_ms: rng.next() as u32,
+                min_distance: rng.below(100) as u16,
+                max_distance: rng.below(1000) as u16,
+                current_distance: rng.below(1000) as u16,
+                sensor_type: rng.below(5) as u8,
+                id: rng.below(3) as u8,
+                orientation: rng.below(40) as u8,
+                covariance: rng.below(256) as u8,
+            },
+        );
+    }
+    // Trailing zeros are cut, down to one byte
+    let zero = RawImu {
+        time_usec: 0,
+        acc: [0; 3],
+        gyro: [0; 3],
+        mag: [0; 3],
+        id: 0,
+        temperature: 0,
+    };
+    let frame = encode(&mut enc, &zero);
+    assert_eq!(frame[1], 1);
+    assert_eq!(frame.len(), 10 + 1 + 2);
+    assert_eq!(parse_one::<RawImu>(&frame).0, zero);
+    let hb = encode(&mut enc, &Heartbeat::quadrotor(0));
+    // mavlink_version is the last byte
+    assert_eq!(hb[1], 9);
+
+    // Sequence counts frames and wraps
+    let mut enc = Encoder::new(1, 1);
+    for i in 0..600u32 {
+        let frame = encode(&mut enc, &Heartbeat::quadrotor(0));
+        assert_eq!(frame[4], i as u8);
+    }
+    let mut short = [0; 20];
+    assert_eq!(enc.encode(&Heartbeat::quadrotor(0), &mut short[..8]), None);
+    let frame = encode(&mut enc, &Heartbeat::quadrotor(0));
+    assert_eq!(frame[4], (600 % 256) as u8, "failed encode took a seq");
+    println!("roundtrip ok");
+}
+
+fn units() {
+    let imu = RawImu::from_si(
+        5,
+        [0., 0., 9.80665],
+        [0.1, -0.2, 0.],
+        [20., -40., 1e6],
+        24.5,
+    );
+    assert!((imu.acc[2] - 1000).abs() <= 1);
+    assert_eq!(imu.gyro, [100, -200, 0]);
+    assert_eq!(imu.mag, [200, -400, i16::MAX]);
+    assert_eq!(imu.temperature, 2450);
+    let cold = RawImu::from_si(0, [0.; 3], [0.; 3], [0.; 3], 0.);
+    assert_eq!(cold.temperature, 1, "0 would read as no sensor");
+    println!("units ok");
+}
+
+/// A broken frame costs at most itself and the frames its bogus length
+/// covers, the parser never returns a frame that wasn't sent
+fn resync() {
+    let mut rng = Rng(42);
+    let mut enc = Encoder::new(1, 1);
+    let mut frames = Vec::new();
+    for _ in 0..100 {
+        frames.extend(random_messages(&mut rng, &mut enc));
+    }
+    let stream: Vec<u8> = frames.iter().flat_map(|f| f.1.clone()).collect();
+    let decode_all = |bytes: &[u8]| {
+        let mut p = Parser::new();
+        let mut good = Vec::new();
+        let mut errors = 0;
+        // Padding lets frames queued behind a broken one come out
+        for b in bytes.iter().chain(&[0; MAX_FRAME]) {
+            match p.push(*b) {
+                Some(Ok(f)) => {
+                    let mut raw = vec![0xfd, f.payload.len() as u8, 0, 0];
+                    raw.extend([f.seq, f.sysid, f.compid]);
+                    raw.extend(&f.id.to_le_bytes()[..3]);
+                    raw.extend(f.payload);
+                    good.push(raw);
+                }
+                Some(Err(_)) => errors += 1,
+                None => {}
+            }
+        }
+        (good, errors)
+    };
+    let (good, errors) = decode_all(&stream);
+    assert_eq!(errors, 0);
+    assert_eq!(good.len(), frames.len());
+
+    let mut lost_total = 0;
+    for round in 0..2000 {
+        let mut bad = stream.clone();
+        let at = rng.below(bad.len());
+        match round % 3 {
+            0 => bad[at] ^= 1 << rng.below(8),
+            1 => {
+                bad.remove(at);
+            }
+            _ => bad.insert(at, rng.below(256) as u8),
+        }
+        let (good, _) = decode_all(&bad);
+        let mut it = frames.iter();
+        for g in &good {
+            let sent = it.any(|f| f.1[..f.1.len() - 2] == g[..]);
+            assert!(sent, "bogus frame in round {}", round);
+        }
+        let lost = frames.len() - good.len();
+        // Bogus length is at most 255 bytes, frames are at least 13
+        assert!(lost <= 1 + 255 / 13, "lost {} in round {}", lost, round);
+        lost_total += lost;
+    }
+
+    // Other systems' traffic is skipped whole
+    let mut p = Parser::new();
+    let mut other = vec![0xfd, 3, 0, 0, 0, 9, 9, 22, 0, 0, 0xfd, 0xfd, 0xfd];
+    other.extend([0x12, 0x34]);
+    other.extend(&frames[0].1);
+    let results: Vec<_> = other
+        .iter()
+        .filter_map(|b| p.push(*b).map(|r| r.map(|f| f.id)))
+        .collect();
+    assert_eq!(results, vec![Err(Error::Unknown(22)), Ok(frames[0].0)]);
+    println!("resync ok, {} frames lost in 2000 corruptions", lost_total);
+}
+
+/// What `calibrating-ahrs` sends every 20 ms plus a heartbeat a second
+fn budget() {
+    let link = bytes_per_s(460800);
+    let per_cycle = frame_len::<AttitudeQuaternion>() + frame_len::<RawImu>();
+    let stream = per_cycle * 50 + frame_len::<Heartbeat>();
+    assert_eq!((per_cycle, stream), (85, 4271));
+    assert!(stream * 10 < link as usize, "over a tenth of the link");
+
+    // A loop faster than the port gets the link rate and no more
+    let mut b = Budget::new(115200, 128, 0);
+    let mut sent = 0;
+    for now_ms in 0..10_000 {
+        for _ in 0..10 {
+            if b.spend(now_ms, 44) {
+                sent += 44;
+            }
+        }
+    }
+    let allowed = bytes_per_s(115200) as usize * 10 + 128;
+    assert!(
+        sent <= allowed && sent > allowed * 95 / 100,
+        "sent {}",
+        sent
+    );
+    // Refill survives the clock wrapping
+    let mut b = Budget::new(115200, 128, u32::MAX - 5);
+    assert!(b.spend(u32::MAX, 128));
+    assert!(!b.spend(u32::MAX, 128));
+    assert!(b.spend(20, 128));
+
+    // Link drops what doesn't fit without leaving sequence gaps
+    let mut slow = Link::new(1, 1, 115200, 0);
+    let mut out = Vec::new();
+    let hb = Heartbeat::quadrotor(MAV_STATE_ACTIVE);
+    let mut dropped = 0;
+    for now_ms in 0..1000 {
+        let write = |f: &[u8]| -> Result<(), ()> {
+            out.extend_from_slice(f);
+            Ok(())
+        };
+        if !slow.send(now_ms, &hb, write).unwrap() {
+            dropped += 1;
+        }
+    }
+    assert!(dropped > 0 && out.len() <= 11520 + MAX_FRAME);
+    let mut p = Parser::new();
+    let seqs: Vec<u8> = out
+        .iter()
+        .filter_map(|b| p.push(*b).map(|r| r.unwrap().seq))
+        .collect();
+    assert_eq!(seqs.len(), 1000 - dropped);
+    assert!(seqs.iter().enumerate().all(|(i, s)| *s == i as u8));
+
+    let mut hb = Interval::new(1000, u32::MAX - 500);
+    let due: Vec<u32> = (0..3000u32)
+        .map(|t| t.wrapping_add(u32::MAX - 500))
+        .filter(|t| hb.due(*t))
+        .collect();
+    assert_eq!(due.len(), 3);
+    assert_eq!(due[1].wrapping_sub(due[0]), 1000);
+    println!("budget ok, {} of {} bytes/s at 460800", stream, link);
+}
+
+fn main() {
+    crc();
+    roundtrip();
+    units();
+    resync();
+    budget();
+}
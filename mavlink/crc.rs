@@ -0,0 +1,14 @@
+//! CRC-16/MCRF4XX, what MAVLink calls X.25.
+
+pub const INIT: u16 = 0xffff;
+
+pub fn x25(data: &[u8]) -> u16 {
+    data.iter().fold(INIT, |crc, b| update(crc, *b))
+}
+
+pub fn update(crc: u16, b: u8) -> u16 {
+    let mut t = b ^ (crc & 0xff) as u8;
+    t ^= t << 4;
+    let t = t as u16;
+    (crc >> 8) ^ (t << 8) ^ (t << 3) ^ (t >> 4)
+}
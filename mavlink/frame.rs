@@ -0,0 +1,177 @@
+//! v2 frame layout and the encoder.
+
+use super::crc::{self, INIT};
+
+pub const STX: u8 = 0xfd;
+/// STX, len, incompat and compat flags, seq, sysid, compid, 3 byte msgid
+pub const HEADER_LEN: usize = 10;
+pub const CHECKSUM_LEN: usize = 2;
+pub const MAX_PAYLOAD: usize = 255;
+pub const MAX_FRAME: usize = HEADER_LEN + MAX_PAYLOAD + CHECKSUM_LEN;
+
+/// Fields are laid out as the XML definition sorts them: by size, largest
+/// first, extensions after in declaration order.
+pub trait Message: Sized {
+    const ID: u32;
+    /// Seeds the checksum so both ends agree on the layout
+    const CRC_EXTRA: u8;
+    /// Payload with extensions, before trailing zeros are cut off
+    const LEN: usize;
+
+    /// Fills `LEN` bytes
+    fn write(&self, payload: &mut [u8]);
+
+    /// Reads `LEN` bytes, cut off zeros restored
+    fn read(payload: &[u8]) -> Self;
+}
+
+/// Longest frame of `M`, what to budget for
+pub const fn frame_len<M: Message>() -> usize {
+    HEADER_LEN + M::LEN + CHECKSUM_LEN
+}
+
+/// Numbers frames from one component
+pub struct Encoder {
+    sysid: u8,
+    compid: u8,
+    seq: u8,
+}
+
+impl Encoder {
+    pub const fn new(sysid: u8, compid: u8) -> Self {
+        Encoder {
+            sysid,
+            compid,
+            seq: 0,
+        }
+    }
+
+    /// Writes the frame of `msg` to the start of `buf`, returns its length.
+    /// `None` when it doesn't fit, the sequence number is not used up then.
+    pub fn encode<M: Message>(
+        &mut self,
+        msg: &M,
+        buf: &mut [u8],
+    ) -> Option<usize> {
+        if buf.len() < frame_len::<M>() {
+            return None;
+        }
+        let payload = &mut buf[HEADER_LEN..HEADER_LEN + M::LEN];
+        msg.write(payload);
+        // v2 drops trailing zeros, one byte stays
+        let len = match payload.iter().rposition(|b| *b != 0) {
+            Some(last) => last + 1,
+            None => 1,
+        };
+        let id = M::ID.to_le_bytes();
+        let header = [
+            STX,
+            len as u8,
+            0,
+            0,
+            self.seq,
+            self.sysid,
+            self.compid,
+            id[0],
+            id[1],
+            id[2],
+        ];
+        buf[..HEADER_LEN].copy_from_slice(&header);
+        let end = HEADER_LEN + len;
+        let crc = checksum(&buf[1..end], M::CRC_EXTRA);
+        buf[end..end + CHECKSUM_LEN].copy_from_slice(&crc.to_le_bytes());
+        self.seq = self.seq.wrapping_add(1);
+        Some(end + CHECKSUM_LEN)
+    }
+}
+
+/// Over the frame without STX, then CRC_EXTRA
+pub fn checksum(bytes: &[u8], extra: u8) -> u16 {
+    let crc = bytes.iter().fold(INIT, |crc, b| crc::update(crc, *b));
+    crc::update(crc, extra)
+}
+
+/// Little endian fields in wire order
+pub struct Put<'a> {
+    buf: &'a mut [u8],
+    at: usize,
+}
+
+impl<'a> Put<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Put { buf, at: 0 }
+    }
+
+    fn bytes<const N: usize>(&mut self, b: [u8; N]) -> &mut Self {
+        self.buf[self.at..self.at + N].copy_from_slice(&b);
+        self.at += N;
+        self
+    }
+
+    pub fn u8(&mut self, v: u8) -> &mut Self {
+        self.bytes(v.to_le_bytes())
+    }
+
+    pub fn u16(&mut self, v: u16) -> &mut Self {
+        self.bytes(v.to_le_bytes())
+    }
+
+    pub fn i16(&mut self, v: i16) -> &mut Self {
+        self.bytes(v.to_le_bytes())
+    }
+
+    pub fn u32(&mut self, v: u32) -> &mut Self {
+        self.bytes(v.to_le_bytes())
+    }
+
+    pub fn u64(&mut self, v: u64) -> &mut Self {
+        self.bytes(v.to_le_bytes())
+    }
+
+    pub fn f32(&mut self, v: f32) -> &mut Self {
+        self.bytes(v.to_le_bytes())
+    }
+}
+
+/// Reads back what `Put` wrote
+pub struct Get<'a> {
+    buf: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Get<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Get { buf, at: 0 }
+    }
+
+    fn bytes<const N: usize>(&mut self) -> [u8; N] {
+        let mut b = [0; N];
+        b.copy_from_slice(&self.buf[self.at..self.at + N]);
+        self.at += N;
+        b
+    }
+
+    pub fn u8(&mut self) -> u8 {
+        u8::from_le_bytes(self.bytes())
+    }
+
+    pub fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.bytes())
+    }
+
+    pub fn i16(&mut self) -> i16 {
+        i16::from_le_bytes(self.bytes())
+    }
+
+    pub fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.bytes())
+    }
+
+    pub fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.bytes())
+    }
+
+    pub fn f32(&mut self) -> f32 {
+        f32::from_le_bytes(self.bytes())
+    }
+}
@@ -0,0 +1,38 @@
+//! Encoder and budget together for blocking writers.
+
+use super::budget::Budget;
+use super::frame::{frame_len, Encoder, Message, MAX_FRAME};
+
+pub struct Link {
+    enc: Encoder,
+    budget: Budget,
+    buf: [u8; MAX_FRAME],
+}
+
+impl Link {
+    pub fn new(sysid: u8, compid: u8, baud: u32, now_ms: u32) -> Self {
+        Link {
+            enc: Encoder::new(sysid, compid),
+            budget: Budget::new(baud, MAX_FRAME, now_ms),
+            buf: [0; MAX_FRAME],
+        }
+    }
+
+    /// Hands the frame of `msg` to `write` if the link has room for it,
+    /// `Ok(false)` when it was dropped. Dropped frames don't take a
+    /// sequence number, the receiver sees no loss.
+    pub fn send<M: Message, E>(
+        &mut self,
+        now_ms: u32,
+        msg: &M,
+        write: impl FnOnce(&[u8]) -> Result<(), E>,
+    ) -> Result<bool, E> {
+        if !self.budget.spend(now_ms, frame_len::<M>()) {
+            return Ok(false);
+        }
+        match self.enc.encode(msg, &mut self.buf) {
+            Some(n) => write(&self.buf[..n]).map(|_| true),
+            None => Ok(false),
+        }
+    }
+}
@@ -0,0 +1,276 @@
+//! Messages from the common dialect, with the enum values they need.
+
+use super::frame::{Get, Message, Put};
+
+pub const MAV_TYPE_QUADROTOR: u8 = 2;
+pub const MAV_AUTOPILOT_GENERIC: u8 = 0;
+pub const MAV_STATE_CALIBRATING: u8 = 2;
+pub const MAV_STATE_ACTIVE: u8 = 4;
+pub const MAV_DISTANCE_SENSOR_LASER: u8 = 0;
+pub const MAV_SENSOR_ROTATION_PITCH_270: u8 = 25;
+
+const G: f32 = 9.80665;
+
+/// Ground stations list the system after the first one, send at 1 Hz
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Heartbeat {
+    pub custom_mode: u32,
+    pub mav_type: u8,
+    pub autopilot: u8,
+    pub base_mode: u8,
+    pub system_status: u8,
+    pub mavlink_version: u8,
+}
+
+impl Heartbeat {
+    pub fn quadrotor(system_status: u8) -> Self {
+        Heartbeat {
+            custom_mode: 0,
+            mav_type: MAV_TYPE_QUADROTOR,
+            autopilot: MAV_AUTOPILOT_GENERIC,
+            base_mode: 0,
+            system_status,
+            mavlink_version: 3,
+        }
+    }
+}
+
+impl Message for Heartbeat {
+    const ID: u32 = 0;
+    const CRC_EXTRA: u8 = 50;
+    const LEN: usize = 9;
+
+    fn write(&self, payload: &mut [u8]) {
+        Put::new(payload)
+            .u32(self.custom_mode)
+            .u8(self.mav_type)
+            .u8(self.autopilot)
+            .u8(self.base_mode)
+            .u8(self.system_status)
+            .u8(self.mavlink_version);
+    }
+
+    fn read(payload: &[u8]) -> Self {
+        let mut g = Get::new(payload);
+        Heartbeat {
+            custom_mode: g.u32(),
+            mav_type: g.u8(),
+            autopilot: g.u8(),
+            base_mode: g.u8(),
+            system_status: g.u8(),
+            mavlink_version: g.u8(),
+        }
+    }
+}
+
+/// Without the `repr_offset_q` extension
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AttitudeQuaternion {
+    pub time_boot_ms: u32,
+    /// w, x, y, z
+    pub q: [f32; 4],
+    /// rad/s
+    pub rollspeed: f32,
+    pub pitchspeed: f32,
+    pub yawspeed: f32,
+}
+
+impl Message for AttitudeQuaternion {
+    const ID: u32 = 31;
+    const CRC_EXTRA: u8 = 246;
+    const LEN: usize = 32;
+
+    fn write(&self, payload: &mut [u8]) {
+        let mut p = Put::new(payload);
+        p.u32(self.time_boot_ms);
+        for q in self.q {
+            p.f32(q);
+        }
+        p.f32(self.rollspeed)
+            .f32(self.pitchspeed)
+            .f32(self.yawspeed);
+    }
+
+    fn read(payload: &[u8]) -> Self {
+        let mut g = Get::new(payload);
+        AttitudeQuaternion {
+            time_boot_ms: g.u32(),
+            q: [g.f32(), g.f32(), g.f32(), g.f32()],
+            rollspeed: g.f32(),
+            pitchspeed: g.f32(),
+            yawspeed: g.f32(),
+        }
+    }
+}
+
+/// With `id` and `temperature` extensions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RawImu {
+    pub time_usec: u64,
+    /// mG
+    pub acc: [i16; 3],
+    /// mrad/s
+    pub gyro: [i16; 3],
+    /// mgauss
+    pub mag: [i16; 3],
+    pub id: u8,
+    /// cdegC, 0 when unknown
+    pub temperature: i16,
+}
+
+impl RawImu {
+    /// From driver units: m/s², rad/s, µT and °C. Out of range values
+    /// saturate.
+    pub fn from_si(
+        time_usec: u64,
+        accel: [f32; 3],
+        gyro: [f32; 3],
+        mag: [f32; 3],
+        temp: f32,
+    ) -> Self {
+        let scale = |v: [f32; 3], k: f32| v.map(|v| (v * k) as i16);
+        // 0 means no reading
+        let temperature = match (temp * 100.) as i16 {
+            0 => 1,
+            t => t,
+        };
+        RawImu {
+            time_usec,
+            acc: scale(accel, 1000. / G),
+            gyro: scale(gyro, 1000.),
+            mag: scale(mag, 10.),
+            id: 0,
+            temperature,
+        }
+    }
+}
+
+impl Message for RawImu {
+    const ID: u32 = 27;
+    const CRC_EXTRA: u8 = 144;
+    const LEN: usize = 29;
+
+    fn write(&self, payload: &mut [u8]) {
+        let mut p = Put::new(payload);
+        p.u64(self.time_usec);
+        for v in self.acc.iter().chain(&self.gyro).chain(&self.mag) {
+            p.i16(*v);
+        }
+        p.u8(self.id).i16(self.temperature);
+    }
+
+    fn read(payload: &[u8]) -> Self {
+        let mut g = Get::new(payload);
+        let time_usec = g.u64();
+        let mut three = || [g.i16(), g.i16(), g.i16()];
+        let (acc, gyro, mag) = (three(), three(), three());
+        RawImu {
+            time_usec,
+            acc,
+            gyro,
+            mag,
+            id: g.u8(),
+            temperature: g.i16(),
+        }
+    }
+}
+
+/// Without the `temperature_press_diff` extension
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScaledPressure {
+    pub time_boot_ms: u32,
+    /// hPa
+    pub press_abs: f32,
+    /// hPa
+    pub press_diff: f32,
+    /// cdegC
+    pub temperature: i16,
+}
+
+impl Message for ScaledPressure {
+    const ID: u32 = 29;
+    const CRC_EXTRA: u8 = 115;
+    const LEN: usize = 14;
+
+    fn write(&self, payload: &mut [u8]) {
+        Put::new(payload)
+            .u32(self.time_boot_ms)
+            .f32(self.press_abs)
+            .f32(self.press_diff)
+            .i16(self.temperature);
+    }
+
+    fn read(payload: &[u8]) -> Self {
+        let mut g = Get::new(payload);
+        ScaledPressure {
+            time_boot_ms: g.u32(),
+            press_abs: g.f32(),
+            press_diff: g.f32(),
+            temperature: g.i16(),
+        }
+    }
+}
+
+/// Without field of view and quaternion extensions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DistanceSensor {
+    pub time_boot_ms: u32,
+    /// cm
+    pub min_distance: u16,
+    /// cm
+    pub max_distance: u16,
+    /// cm, `max_distance + 1` when nothing is in range
+    pub current_distance: u16,
+    pub sensor_type: u8,
+    pub id: u8,
+    pub orientation: u8,
+    /// cm², 255 when unknown
+    pub covariance: u8,
+}
+
+impl Message for DistanceSensor {
+    const ID: u32 = 132;
+    const CRC_EXTRA: u8 = 85;
+    const LEN: usize = 14;
+
+    fn write(&self, payload: &mut [u8]) {
+        Put::new(payload)
+            .u32(self.time_boot_ms)
+            .u16(self.min_distance)
+            .u16(self.max_distance)
+            .u16(self.current_distance)
+            .u8(self.sensor_type)
+            .u8(self.id)
+            .u8(self.orientation)
+            .u8(self.covariance);
+    }
+
+    fn read(payload: &[u8]) -> Self {
+        let mut g = Get::new(payload);
+        DistanceSensor {
+            time_boot_ms: g.u32(),
+            min_distance: g.u16(),
+            max_distance: g.u16(),
+            current_distance: g.u16(),
+            sensor_type: g.u8(),
+            id: g.u8(),
+            orientation: g.u8(),
+            covariance: g.u8(),
+        }
+    }
+}
+
+/// For the parser, `None` for messages not implemented here
+pub fn crc_extra(id: u32) -> Option<u8> {
+    let known = [
+        (Heartbeat::ID, Heartbeat::CRC_EXTRA),
+        (AttitudeQuaternion::ID, AttitudeQuaternion::CRC_EXTRA),
+        (RawImu::ID, RawImu::CRC_EXTRA),
+        (ScaledPressure::ID, ScaledPressure::CRC_EXTRA),
+        (DistanceSensor::ID, DistanceSensor::CRC_EXTRA),
+    ];
+    known
+        .iter()
+        .find(|(i, _)| *i == id)
+        .map(|(_, extra)| *extra)
+}
@@ -0,0 +1,26 @@
+//! MAVLink v2 messages for ground stations and log viewers.
+//!
+//! Frames are written straight to the port, MAVLink has its own start byte
+//! and checksum so they don't go through `cobs/`. Only messages the fusion
+//! binaries have data for are implemented, without signing and without
+//! optional extensions unless noted.
+//!
+//! Include with `#[path = "../mavlink/mod.rs"] mod mavlink;`. `check.rs`
+//! round trips every message through the parser on the host.
+
+// Binaries use only some of it
+#![allow(dead_code, unused_imports)]
+
+mod budget;
+mod crc;
+mod frame;
+mod link;
+mod messages;
+mod parse;
+
+pub use budget::{bytes_per_s, Budget, Interval};
+pub use crc::x25;
+pub use frame::{frame_len, Encoder, Message, MAX_FRAME};
+pub use link::Link;
+pub use messages::*;
+pub use parse::{Error, Frame, Parser};
@@ -0,0 +1,118 @@
+//! Streaming parser, byte by byte from a port or a capture.
+
+use super::frame::{checksum, Message, CHECKSUM_LEN, HEADER_LEN, MAX_FRAME};
+use super::frame::{MAX_PAYLOAD, STX};
+use super::messages::crc_extra;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /// Checksum mismatch, or a start byte that wasn't one
+    Crc,
+    /// No CRC_EXTRA to check it with, the frame is skipped by its length
+    Unknown(u32),
+    /// Signed or otherwise flagged frame
+    Incompatible,
+}
+
+/// A checked frame, payload as received: trailing zeros cut off
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frame<'a> {
+    pub seq: u8,
+    pub sysid: u8,
+    pub compid: u8,
+    pub id: u32,
+    pub payload: &'a [u8],
+}
+
+impl<'a> Frame<'a> {
+    /// `None` if it is some other message
+    pub fn decode<M: Message>(&self) -> Option<M> {
+        if self.id != M::ID || self.payload.len() > M::LEN {
+            return None;
+        }
+        let mut full = [0; MAX_PAYLOAD];
+        full[..self.payload.len()].copy_from_slice(self.payload);
+        Some(M::read(&full[..M::LEN]))
+    }
+}
+
+/// Holds at most one frame. After a broken frame scanning resumes right
+/// after its start byte, so a good frame inside the damaged one's length is
+/// still found.
+pub struct Parser {
+    buf: [u8; MAX_FRAME],
+    len: usize,
+    /// Returned last time, dropped on next push
+    consumed: usize,
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Parser {
+            buf: [0; MAX_FRAME],
+            len: 0,
+            consumed: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.consumed = 0;
+    }
+
+    /// Feeds a received byte, returns a frame or an error once one is
+    /// complete
+    pub fn push(&mut self, b: u8) -> Option<Result<Frame<'_>, Error>> {
+        self.drop_front(self.consumed);
+        self.consumed = 0;
+        self.buf[self.len] = b;
+        self.len += 1;
+
+        match self.buf[..self.len].iter().position(|b| *b == STX) {
+            Some(at) => self.drop_front(at),
+            None => {
+                self.len = 0;
+                return None;
+            }
+        }
+        if self.len < HEADER_LEN {
+            return None;
+        }
+        let total = HEADER_LEN + self.buf[1] as usize + CHECKSUM_LEN;
+        if self.len < total {
+            return None;
+        }
+        let h = &self.buf[..HEADER_LEN];
+        let id = u32::from_le_bytes([h[7], h[8], h[9], 0]);
+        if h[2] != 0 {
+            self.consumed = 1;
+            return Some(Err(Error::Incompatible));
+        }
+        let extra = match crc_extra(id) {
+            Some(extra) => extra,
+            None => {
+                self.consumed = total;
+                return Some(Err(Error::Unknown(id)));
+            }
+        };
+        let end = total - CHECKSUM_LEN;
+        let crc = u16::from_le_bytes([self.buf[end], self.buf[end + 1]]);
+        if checksum(&self.buf[1..end], extra) != crc {
+            self.consumed = 1;
+            return Some(Err(Error::Crc));
+        }
+        self.consumed = total;
+        Some(Ok(Frame {
+            seq: h[4],
+            sysid: h[5],
+            compid: h[6],
+            id,
+            payload: &self.buf[HEADER_LEN..end],
+        }))
+    }
+
+    fn drop_front(&mut self, n: usize) {
+        self.buf.copy_within(n..self.len, 0);
+        self.len -= n;
+    }
+}
@@ -5,7 +5,6 @@
 #[allow(unused)]
 use panic_abort;
 
-use core::f32::{INFINITY, NEG_INFINITY};
 use core::fmt::Write;
 
 use cortex_m_rt::{entry, exception, ExceptionFrame};
@@ -15,6 +14,24 @@ use hal::time::Bps;
 
 use mpu9250::Mpu9250;
 
+#[allow(dead_code)]
+#[path = "../ahrs-ekf/ellipsoid.rs"]
+mod ellipsoid;
+#[path = "../ahrs-ekf/solve.rs"]
+mod solve;
+use ellipsoid::StreamingFit;
+#[path = "../cobs/mod.rs"]
+mod cobs;
+use cobs::FrameWriter;
+#[path = "../storage/mod.rs"]
+mod storage;
+use storage::stm32::InternalFlash;
+use storage::{records, Store};
+
+// Give up after a minute of 5 ms reads
+const MAX_READS: u32 = 12000;
+const REPORT_EVERY: u32 = 200;
+
 #[entry]
 fn main() -> ! {
     let device = hal::pac::Peripherals::take().unwrap();
@@ -32,17 +49,15 @@ fn main() -> ! {
 
     let serial =
         device
-            .USART1
-            .serial((gpioa.pa9, gpioa.pa10), Bps(115200), clocks);
-    let (mut tx, _rx) = serial.split();
-    // COBS frame
-    tx.write(0x00).unwrap();
-    let mut l = tx;
+            .USART2
+            .serial((gpioa.pa2, gpioa.pa15), Bps(115200), clocks);
+    let (tx, _rx) = serial.split();
+    let mut l = FrameWriter::start(tx).unwrap();
     write!(l, "logger ok\r\n").unwrap();
     let mut delay = delay::Delay::new(core.SYST, clocks);
-    // SPI1
-    let ncs = gpiob.pb9.output().push_pull();
-    let scl_sck = gpiob.pb3;
+    // SPI1, the pins ahrs-ekf and calibrating-ahrs use
+    let ncs = gpiob.pb0.output().push_pull();
+    let scl_sck = gpioa.pa5;
     let sda_sdi_mosi = gpiob.pb5;
     let ad0_sdo_miso = gpiob.pb4;
     let spi = device.SPI1.spi(
@@ -53,13 +68,7 @@ fn main() -> ! {
     );
     let mut mpu = Mpu9250::marg_default(spi, ncs, &mut delay).unwrap();
 
-    let sample_count = 500;
-    let mut mag_max_x: f32 = NEG_INFINITY;
-    let mut mag_max_y: f32 = NEG_INFINITY;
-    let mut mag_max_z: f32 = NEG_INFINITY;
-    let mut mag_min_x: f32 = INFINITY;
-    let mut mag_min_y: f32 = INFINITY;
-    let mut mag_min_z: f32 = INFINITY;
+    let mut fit = StreamingFit::new();
 
     let [mag_sensitivity_x, mag_sensitivity_y, mag_sensitivity_z] =
         mpu.mag_sensitivity_adjustments::<[f32; 3]>();
@@ -72,79 +81,53 @@ fn main() -> ! {
 
     write!(
         l,
-        "Mag Calibration: Wave device in a figure eight until done!\r\n"
+        "Mag Calibration: Rotate device every way until done!\r\n"
     )
     .unwrap();
     delay.delay_ms(200u32);
 
-    for _ in 0..sample_count {
+    let mut reads = 0;
+    while !fit.is_done() && reads < MAX_READS {
         match mpu.mag::<[f32; 3]>() {
-            Ok([mx, my, mz]) => {
-                // x
-                if mx > mag_max_x {
-                    mag_max_x = mx;
-                }
-                if mx < mag_min_x {
-                    mag_min_x = mx;
-                }
-                // y
-                if my > mag_max_y {
-                    mag_max_y = my;
-                }
-                if my < mag_min_y {
-                    mag_min_y = my;
-                }
-
-                // z
-                if mz > mag_max_z {
-                    mag_max_z = mz;
-                }
-                if mz < mag_min_z {
-                    mag_min_z = mz;
-                }
+            Ok(mag) => {
+                fit.push(mag);
             }
             Err(e) => {
                 write!(l, "err: {:?}\r\n", e).unwrap();
             }
         }
+        reads += 1;
+        if reads % REPORT_EVERY == 0 {
+            write!(
+                l,
+                "samples: {}, rejected: {}, coverage: {}, residual: {:?}\r\n",
+                fit.len(),
+                fit.rejected(),
+                fit.coverage(),
+                fit.calibration().map(|c| c.residual)
+            )
+            .unwrap();
+        }
         delay.delay_ms(5u32);
     }
 
-    // Get hard iron correction
-    let mag_avg_bias_x = ((mag_max_x + mag_min_x) as f32) / 2.; // get average x mag bias in counts
-    let mag_avg_bias_y = ((mag_max_y + mag_min_y) as f32) / 2.; // get average y mag bias in counts
-    let mag_avg_bias_z = ((mag_max_z + mag_min_z) as f32) / 2.; // get average z mag bias in counts
-
-    let mag_res = mpu.mag_resolution();
-
-    // save mag biases in G for main program
-    let mag_bias_x = mag_avg_bias_x * mag_res * mag_sensitivity_x;
-    let mag_bias_y = mag_avg_bias_y * mag_res * mag_sensitivity_y;
-    let mag_bias_z = mag_avg_bias_z * mag_res * mag_sensitivity_z;
-
-    // Get soft iron correction estimate
-    let mag_scale_x = ((mag_max_x - mag_min_x) as f32) / 2.; // get average x axis max chord length in counts
-    let mag_scale_y = ((mag_max_y - mag_min_y) as f32) / 2.; // get average y axis max chord length in counts
-    let mag_scale_z = ((mag_max_z - mag_min_z) as f32) / 2.; // get average z axis max chord length in counts
-
-    let mut avg_rad = mag_scale_x + mag_scale_y + mag_scale_z;
-    avg_rad /= 3.0;
-
-    let final_mag_scale_x = avg_rad / (mag_scale_x);
-    let final_mag_scale_y = avg_rad / (mag_scale_y);
-    let final_mag_scale_z = avg_rad / (mag_scale_z);
-
-    write!(
-        l,
-        "loop done; bias: ({}, {}, {}); scale: ({}, {}, {})\r\n",
-        mag_bias_x,
-        mag_bias_y,
-        mag_bias_z,
-        final_mag_scale_x,
-        final_mag_scale_y,
-        final_mag_scale_z
-    )
-    .unwrap();
+    match fit.calibration() {
+        Some(cal) if fit.is_done() => {
+            write!(l, "loop done; {:?}\r\n", cal).unwrap();
+            let mut v = [0.0; 12];
+            v[..9].copy_from_slice(&cal.a_1);
+            v[9..].copy_from_slice(&cal.b);
+            let saved = Store::new(unsafe { InternalFlash::new() })
+                .and_then(|mut s| s.write_f32s(records::MAG, &v));
+            write!(l, "saving calibration: {:?}\r\n", saved).unwrap();
+        }
+        Some(cal) => {
+            write!(l, "not enough coverage, not saving {:?}\r\n", cal).unwrap();
+        }
+        None => {
+            write!(l, "no fit, rotate more and restart\r\n").unwrap();
+        }
+    }
     loop {}
 }
 
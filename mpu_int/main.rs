@@ -5,10 +5,16 @@
 #[allow(unused)]
 use panic_abort as _;
 
+#[cfg(feature = "log_usart")]
+#[path = "../console/mod.rs"]
+mod console;
+#[macro_use]
+#[path = "../logging/mod.rs"]
+mod logging;
+
 #[rtic::app(device = hal::pac, peripherals = true)]
 mod app {
     use asm_delay::AsmDelay;
-    use cortex_m_semihosting::hprintln;
     use hal::gpio::{
         self, AltFn, HighSpeed, Input, Output, PullNone, PullUp, PushPull, AF5,
     };
@@ -44,6 +50,7 @@ mod app {
     #[init()]
     fn init(ctx: init::Context) -> init::LateResources {
         let device = ctx.device;
+        let mut core = ctx.core;
         let mut rcc = device.RCC.constrain();
         let mut flash = device.FLASH.constrain();
         let clocks = rcc
@@ -57,7 +64,22 @@ mod app {
         let mut syscfg = device.SYSCFG.constrain(&mut rcc.apb2);
         let exti = device.EXTI.constrain();
         let interrupt_pin = gpioa.pa0.pull_type(PullUp).input();
-        hprintln!("init ok").unwrap();
+        // `log_usart` sink, the pins mpu-calib uses
+        #[cfg(feature = "log_usart")]
+        {
+            let serial = device.USART2.serial(
+                (gpioa.pa2, gpioa.pa15),
+                hal::time::Bps(115200),
+                clocks,
+            );
+            let ser_int = serial.get_interrupt();
+            let (tx, _) = serial.split();
+            crate::console::init(tx, crate::console::Overflow::DropNewest);
+            unsafe { cortex_m::peripheral::NVIC::unmask(ser_int) };
+        }
+        crate::logging::init();
+        crate::logging::start_clock(&mut core.SYST, clocks.sysclk().0);
+        info!("init ok");
 
         // SPI1
         let ncs_pin = gpiob.pb0.output().push_pull().output_speed(HighSpeed);
@@ -70,7 +92,7 @@ mod app {
             1.mhz(),
             clocks,
         );
-        hprintln!("spi ok").unwrap();
+        info!("spi ok");
         // MPU
         // 8Hz
         let gyro_rate = mpu9250::GyroTempDataRate::DlpfConf(mpu9250::Dlpf::_2);
@@ -95,15 +117,15 @@ mod app {
             },
         )
         .unwrap();
-        hprintln!("mpu ok").unwrap();
+        info!("mpu ok");
 
         mpu9250
             .enable_interrupts(mpu9250::InterruptEnable::RAW_RDY_EN)
             .unwrap();
-        hprintln!("int enabled").unwrap();
+        info!("int enabled");
 
         let extih = exti.EXTI1.bind(interrupt_pin, &mut syscfg);
-        hprintln!("int bound").unwrap();
+        info!("int bound");
 
         init::LateResources {
             extih,
@@ -115,7 +137,7 @@ mod app {
     fn handle_mpu(mut ctx: handle_mpu::Context) {
         ctx.resources.mpu.lock(|mpu| match mpu.all::<[f32; 3]>() {
             Ok(a) => {
-                hprintln!(
+                debug!(
                     "[a:({:?},{:?},{:?}),g:({:?},{:?},{:?}),t:{:?}]",
                     a.accel[0],
                     a.accel[1],
@@ -124,14 +146,25 @@ mod app {
                     a.gyro[1],
                     a.gyro[2],
                     a.temp,
-                )
-                .unwrap();
+                );
             }
             Err(e) => {
-                hprintln!("e: {:?}", e).unwrap();
+                warn!("read: {:?}", e);
             }
         });
 
         ctx.resources.extih.lock(|extih| extih.unpend());
     }
+
+    // Only unmasked for the `log_usart` sink
+    #[task(binds = USART2_EXTI26)]
+    fn usart2(_: usart2::Context) {
+        #[cfg(feature = "log_usart")]
+        crate::console::on_interrupt();
+    }
+
+    #[task(binds = SysTick)]
+    fn tick(_: tick::Context) {
+        crate::logging::tick();
+    }
 }
@@ -16,7 +16,11 @@ use hal::time::Bps;
 
 use mpu9250::Mpu9250;
 
-static mut L: Option<hal::serial::Tx<hal::pac::USART2>> = None;
+#[path = "../cobs/mod.rs"]
+mod cobs;
+use cobs::FrameWriter;
+
+static mut L: Option<FrameWriter<hal::serial::Tx<hal::pac::USART2>>> = None;
 static mut RX: Option<hal::serial::Rx<hal::pac::USART2>> = None;
 static mut QUIET: bool = true;
 static mut NOW_MS: u32 = 0;
@@ -42,13 +46,13 @@ fn main() -> ! {
             .serial((gpioa.pa2, gpioa.pa15), Bps(460800), clocks);
     let ser_int = serial.get_interrupt();
     serial.listen(serial::Event::Rxne);
-    let (mut tx, rx) = serial.split();
-    writeln!(tx, "tx ok").unwrap();
+    let (tx, rx) = serial.split();
     unsafe {
-        L = Some(tx);
+        L = Some(FrameWriter::start(tx).unwrap());
         RX = Some(rx);
     };
     let l = unsafe { extract(&mut L) };
+    writeln!(l, "tx ok").unwrap();
     writeln!(l, "logger ok").unwrap();
     // SPI1
     let ncs = gpiob.pb0.output().push_pull();
@@ -127,7 +131,7 @@ fn main() -> ! {
                 }
             }
             Err(e) => {
-                write!(l, "Err: {:?}; {:?}", t_ms, e).unwrap();
+                write!(l, "Err: {:?}; {:?}\r\n", t_ms, e).unwrap();
             }
         }
     }
@@ -167,7 +171,7 @@ fn USART2_EXTI26() {
                 rx.clear_noise_error();
             }
             _ => {
-                write!(l, "read error: {:?}", e).unwrap();
+                write!(l, "read error: {:?}\r\n", e).unwrap();
             }
         },
     };
@@ -185,14 +189,14 @@ unsafe fn SysTick() {
 #[exception]
 unsafe fn HardFault(ef: &ExceptionFrame) -> ! {
     let l = extract(&mut L);
-    write!(l, "hard fault at {:?}", ef).unwrap();
+    write!(l, "hard fault at {:?}\r\n", ef).unwrap();
     panic!("HardFault at {:#?}", ef);
 }
 
 #[exception]
 unsafe fn DefaultHandler(irqn: i16) {
     let l = extract(&mut L);
-    write!(l, "Interrupt: {}", irqn).unwrap();
+    write!(l, "Interrupt: {}\r\n", irqn).unwrap();
 }
 
 #[panic_handler]
@@ -227,6 +231,7 @@ fn panic(panic_info: &PanicInfo) -> ! {
                     write!(l, "panic occured, no info available").unwrap();
                 }
             }
+            let _ = l.end_frame();
         }
         None => {}
     }
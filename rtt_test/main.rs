@@ -3,41 +3,74 @@
 #![no_main]
 #![feature(core_intrinsics)]
 
-use cortex_m_rt::entry;
-use hal::prelude::*;
+use cortex_m_rt::{entry, exception};
 use hal::gpio;
+#[cfg(feature = "log_usart")]
+use hal::pac::interrupt;
+use hal::prelude::*;
 
-//use rtt_target::{rtt_init_print, rprintln};
 use panic_abort as _;
 
+#[cfg(feature = "log_usart")]
+#[path = "../console/mod.rs"]
+mod console;
+#[macro_use]
+#[path = "../logging/mod.rs"]
+mod logging;
+
 #[entry]
 fn main() -> ! {
-//    rtt_init_print!();
+    logging::init();
     let device = hal::pac::Peripherals::take().unwrap();
-    // let core = cortex_m::Peripherals::take().unwrap();
+    let mut core = cortex_m::Peripherals::take().unwrap();
     let mut rcc = device.RCC.constrain();
-    // let mut flash = device.FLASH.constrain();
-    // let clocks = rcc
-    //     .cfgr
-    //     .sysclk(64.mhz())
-    //     .pclk1(32.mhz())
-    //     .pclk2(32.mhz())
-    //     .freeze(&mut flash.acr);
-    //rprintln!("device ok!");
+    let mut flash = device.FLASH.constrain();
+    let clocks = rcc
+        .cfgr
+        .sysclk(64.mhz())
+        .pclk1(32.mhz())
+        .pclk2(32.mhz())
+        .freeze(&mut flash.acr);
+    // `log_usart` sink, the pins mpu-calib uses
+    #[cfg(feature = "log_usart")]
+    {
+        let gpioa = device.GPIOA.split(&mut rcc.ahb);
+        let serial = device.USART2.serial(
+            (gpioa.pa2, gpioa.pa15),
+            hal::time::Bps(115200),
+            clocks,
+        );
+        let ser_int = serial.get_interrupt();
+        let (tx, _) = serial.split();
+        console::init(tx, console::Overflow::DropNewest);
+        unsafe { cortex_m::peripheral::NVIC::unmask(ser_int) };
+    }
+    logging::start_clock(&mut core.SYST, clocks.sysclk().0);
+    info!("device ok!");
     let gpiob = device.GPIOB.split(&mut rcc.ahb);
-    // rprintln!("gpiob ok!");
+    debug!("gpiob ok!");
 
     let mut beeper = gpiob
         .pb3
         .pull_type(gpio::PullNone)
         .output()
         .output_type(gpio::PushPull);
-    // rprintln!("beeper ok!");
+    debug!("beeper ok!");
     let _ = beeper.set_high();
-    //rprintln!("set high!");
+    info!("set high!");
     loop {
-        //rprintln!("in the loop!");
+        // SysTick wakes it every millisecond
         cortex_m::asm::wfi();
-        //rprintln!("should not !");
     }
 }
+
+#[cfg(feature = "log_usart")]
+#[interrupt]
+fn USART2_EXTI26() {
+    console::on_interrupt();
+}
+
+#[exception]
+fn SysTick() {
+    logging::tick();
+}
@@ -7,12 +7,21 @@
 use panic_semihosting;
 
 use cortex_m_rt::{entry, exception, ExceptionFrame};
-use cortex_m_semihosting::hprintln;
+#[cfg(feature = "log_usart")]
+use hal::pac::interrupt;
 use hal::prelude::*;
 
+#[cfg(feature = "log_usart")]
+#[path = "../console/mod.rs"]
+mod console;
+#[macro_use]
+#[path = "../logging/mod.rs"]
+mod logging;
+
 #[entry]
 fn main() -> ! {
     let device = hal::pac::Peripherals::take().unwrap();
+    let mut core = cortex_m::Peripherals::take().unwrap();
     let rcc = device.RCC.constrain();
     let mut flash = device.FLASH.constrain();
     let clocks = rcc
@@ -21,24 +30,53 @@ fn main() -> ! {
         .pclk1(32.mhz())
         .pclk2(32.mhz())
         .freeze(&mut flash.acr);
-    hprintln!(
+    // `log_usart` sink, the pins mpu-calib uses
+    #[cfg(feature = "log_usart")]
+    {
+        let mut ahb = rcc.ahb;
+        let gpioa = device.GPIOA.split(&mut ahb);
+        let serial = device.USART2.serial(
+            (gpioa.pa2, gpioa.pa15),
+            hal::time::Bps(115200),
+            clocks,
+        );
+        let ser_int = serial.get_interrupt();
+        let (tx, _) = serial.split();
+        console::init(tx, console::Overflow::DropNewest);
+        unsafe { cortex_m::peripheral::NVIC::unmask(ser_int) };
+    }
+    logging::init();
+    logging::start_clock(&mut core.SYST, clocks.sysclk().0);
+    info!(
         "main: sysclk: {:?}; hclck: {:?}",
         clocks.sysclk(),
         clocks.hclk()
-    )
-    .unwrap();
+    );
     loop {}
 }
 
+#[cfg(feature = "log_usart")]
+#[interrupt]
+fn USART2_EXTI26() {
+    console::on_interrupt();
+}
+
+#[exception]
+fn SysTick() {
+    logging::tick();
+}
+
 #[exception]
 fn HardFault(ef: &ExceptionFrame) -> ! {
-    hprintln!("hardfault").unwrap();
+    error!("hardfault");
+    #[cfg(feature = "log_usart")]
+    console::flush();
 
     panic!("HardFault at {:#?}", ef);
 }
 
 #[exception]
 fn DefaultHandler(irqn: i16) {
-    hprintln!("unh interrult").unwrap();
+    error!("unh interrult");
     panic!("Unhandled exception (IRQn = {})", irqn);
 }
@@ -5,11 +5,19 @@
 #[allow(unused)]
 use panic_abort;
 
+#[cfg(feature = "log_usart")]
+#[path = "../console/mod.rs"]
+mod console;
+#[macro_use]
+#[path = "../logging/mod.rs"]
+mod logging;
+
 // use core::fmt::{self, Write};
 
 use asm_delay::AsmDelay;
 use cortex_m_rt::{entry, exception, ExceptionFrame};
-use cortex_m_semihosting::hprintln;
+#[cfg(feature = "log_usart")]
+use hal::pac::interrupt;
 use hal::prelude::*;
 use hal::serial;
 use hal::time::Bps;
@@ -21,7 +29,7 @@ use mpu9250::{Mpu9250, MpuConfig};
 #[inline(never)]
 fn main() -> ! {
     let device = hal::pac::Peripherals::take().unwrap();
-    let core = cortex_m::Peripherals::take().unwrap();
+    let mut core = cortex_m::Peripherals::take().unwrap();
     let mut rcc = device.RCC.constrain();
     let mut flash = device.FLASH.constrain();
     let clocks = rcc
@@ -37,7 +45,22 @@ fn main() -> ! {
         .output()
         .output_speed(hal::gpio::HighSpeed)
         .pull_type(hal::gpio::PullDown);
-    hprintln!("start").unwrap();
+    // `log_usart` sink, the pins mpu-calib uses
+    #[cfg(feature = "log_usart")]
+    {
+        let serial =
+            device
+                .USART2
+                .serial((gpioa.pa2, gpioa.pa15), Bps(115200), clocks);
+        let ser_int = serial.get_interrupt();
+        let (tx, _) = serial.split();
+        // Readings keep coming, what's queued goes out whole
+        console::init(tx, console::Overflow::DropNewest);
+        unsafe { cortex_m::peripheral::NVIC::unmask(ser_int) };
+    }
+    logging::init();
+    logging::start_clock(&mut core.SYST, clocks.sysclk().0);
+    info!("start");
     // SPI1
     let ncs = gpiob.pb0.output().push_pull();
     let scl_sck = gpiob.pb3;
@@ -49,9 +72,9 @@ fn main() -> ! {
         1.mhz(),
         clocks,
     );
-    hprintln!("spi ok").unwrap();
+    info!("spi ok");
     let mut delay = AsmDelay::new(clocks.sysclk());
-    hprintln!("delay ok").unwrap();
+    debug!("delay ok");
     // MPU
     let mmpu = Mpu9250::marg_with_reinit(
         spi,
@@ -69,18 +92,18 @@ fn main() -> ! {
     let mut mpu = match mmpu {
         Ok(m) => m,
         Err(e) => {
-            hprintln!("err: {:?}", e);
+            error!("mpu init: {:?}", e);
             panic!("oops")
         }
     };
-    hprintln!("mpu ok").unwrap();
+    info!("mpu ok");
 
     pa1.set_low();
     for _ in 1..10 {
         pa1.toggle();
         match mpu.all::<[f32; 3]>() {
             Ok(a) => {
-                hprintln!(
+                debug!(
                     "[a:({:?},{:?},{:?}),g:({:?},{:?},{:?}),m:({:?},{:?},{:?}),t:{:?}]",
                     a.accel[0],
                     a.accel[1],
@@ -92,24 +115,23 @@ fn main() -> ! {
                     a.mag[1],
                     a.mag[2],
                     a.temp,
-                )
-                .unwrap();
+                );
             }
             Err(e) => {
-                hprintln!("e");
+                warn!("read: {:?}", e);
             }
         }
     }
 
-    hprintln!("running calibration...").unwrap();
+    info!("running calibration...");
     let accel_biases: [f32; 3] = mpu.calibrate_at_rest(&mut delay).unwrap();
-    hprintln!("calibration ok: {:?}", accel_biases).unwrap();
+    info!("calibration ok: {:?}", accel_biases);
 
     loop {
         pa1.toggle();
         match mpu.all::<[f32; 3]>() {
             Ok(a) => {
-                hprintln!(
+                debug!(
                     "[a:({:?},{:?},{:?}),g:({:?},{:?},{:?}),m:({:?},{:?},{:?}),t:{}]",
                     a.accel[0],
                     a.accel[1],
@@ -121,11 +143,10 @@ fn main() -> ! {
                     a.mag[1],
                     a.mag[2],
                     a.temp,
-                )
-                .unwrap();
+                );
             }
             Err(e) => {
-                hprintln!("e");
+                warn!("read: {:?}", e);
             }
         };
     }
@@ -137,3 +158,14 @@ unsafe fn extract<T>(opt: &'static mut Option<T>) -> &'static mut T {
         None => panic!("extract"),
     }
 }
+
+#[cfg(feature = "log_usart")]
+#[interrupt]
+fn USART2_EXTI26() {
+    console::on_interrupt();
+}
+
+#[exception]
+fn SysTick() {
+    logging::tick();
+}
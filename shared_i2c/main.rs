@@ -17,7 +17,11 @@ use bmp280::{self, BMP280};
 use lsm303c::Lsm303c;
 use shared_bus::CortexMBusManager as SharedBus;
 
-static mut L: Option<hal::serial::Tx<hal::pac::USART1>> = None;
+#[path = "../cobs/mod.rs"]
+mod cobs;
+use cobs::FrameWriter;
+
+static mut L: Option<FrameWriter<hal::serial::Tx<hal::pac::USART1>>> = None;
 static mut RX: Option<hal::serial::Rx<hal::pac::USART1>> = None;
 static mut QUIET: bool = true;
 const TURN_QUIET: u8 = 'q' as u8;
@@ -42,11 +46,9 @@ fn main() -> ! {
             .serial((gpioa.pa9, gpioa.pa10), Bps(115200), clocks);
     let ser_int = serial.get_interrupt();
     serial.listen(serial::Event::Rxne);
-    let (mut tx, rx) = serial.split();
-    // COBS frame
-    tx.write(0x00).unwrap();
+    let (tx, rx) = serial.split();
     unsafe {
-        L = Some(tx);
+        L = Some(FrameWriter::start(tx).unwrap());
         RX = Some(rx);
     };
     let l = unsafe { extract(&mut L) };
@@ -194,6 +196,7 @@ fn panic(panic_info: &PanicInfo) -> ! {
                     write!(l, "panic occured, no info available").unwrap();
                 }
             }
+            let _ = l.end_frame();
         }
         None => {}
     }
@@ -0,0 +1,50 @@
+//! Whitespace separated arguments of a command.
+
+use core::str::{FromStr, SplitAsciiWhitespace};
+
+use super::error::Error;
+
+#[derive(Clone)]
+pub struct Args<'a> {
+    words: SplitAsciiWhitespace<'a>,
+}
+
+impl<'a> Args<'a> {
+    pub fn new(s: &'a str) -> Self {
+        Args {
+            words: s.split_ascii_whitespace(),
+        }
+    }
+
+    pub fn word(&mut self) -> Result<&'a str, Error> {
+        self.words.next().ok_or(Error::MissingArg)
+    }
+
+    pub fn parse<T: FromStr>(&mut self) -> Result<T, Error> {
+        self.word()?.parse().map_err(|_| Error::BadValue)
+    }
+
+    /// `None` when there are no more arguments
+    pub fn maybe<T: FromStr>(&mut self) -> Result<Option<T>, Error> {
+        match self.words.next() {
+            Some(w) => w.parse().map(Some).map_err(|_| Error::BadValue),
+            None => Ok(None),
+        }
+    }
+
+    /// Fails if anything is left
+    pub fn end(&mut self) -> Result<(), Error> {
+        match self.words.next() {
+            Some(_) => Err(Error::ExtraArg),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a> Iterator for Args<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.words.next()
+    }
+}
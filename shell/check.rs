@@ -0,0 +1,304 @@
+// Runs the shell on the host against a small context, checks replies,
+// errors and typed parameters:
+//     rustc --edition 2021 -O check.rs && ./check
+// Panics on failure.
+
+#[path = "mod.rs"]
+mod shell;
+
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use shell::{
+    Args, Command, Error, Flags, Params, Registry, Shell, Spec, Stored, Value,
+};
+
+struct Ctx {
+    led: bool,
+    rate: u32,
+    gain: f32,
+    runs: u32,
+}
+
+impl Params for Ctx {
+    fn names(&self) -> &[&'static str] {
+        &["led", "rate", "gain", "runs"]
+    }
+
+    fn get(&self, name: &str) -> Option<Value> {
+        Some(match name {
+            "led" => Value::Bool(self.led),
+            "rate" => Value::U32(self.rate),
+            "gain" => Value::F32(self.gain),
+            "runs" => Value::U32(self.runs),
+            _ => return None,
+        })
+    }
+
+    fn set(&mut self, name: &str, value: Value) -> Result<(), Error> {
+        match (name, value) {
+            ("led", Value::Bool(v)) => self.led = v,
+            ("rate", Value::U32(v)) if v <= 1000 => self.rate = v,
+            ("rate", _) => return Err(Error::OutOfRange),
+            ("gain", Value::F32(v)) => self.gain = v,
+            ("runs", _) => return Err(Error::ReadOnly),
+            _ => return Err(Error::UnknownParam),
+        }
+        Ok(())
+    }
+}
+
+fn add(
+    ctx: &mut Ctx,
+    args: &mut Args,
+    out: &mut dyn Write,
+) -> Result<(), Error> {
+    let a: i32 = args.parse()?;
+    let b: i32 = args.maybe()?.unwrap_or(1);
+    args.end()?;
+    ctx.runs += 1;
+    write!(out, "{}\r\n", a + b)?;
+    Ok(())
+}
+
+fn echo(
+    ctx: &mut Ctx,
+    args: &mut Args,
+    out: &mut dyn Write,
+) -> Result<(), Error> {
+    ctx.runs += 1;
+    for w in args {
+        write!(out, "{} ", w)?;
+    }
+    write!(out, "\r\n")?;
+    Ok(())
+}
+
+static COMMANDS: [Command<Ctx>; 2] = [
+    Command {
+        name: "add",
+        usage: "<a> [b]",
+        help: "adds b or 1 to a",
+        run: add,
+    },
+    Command {
+        name: "echo",
+        usage: "<words>",
+        help: "says it back",
+        run: echo,
+    },
+];
+
+fn feed(sh: &mut Shell<Ctx, 32>, ctx: &mut Ctx, input: &str) -> String {
+    let mut out = String::new();
+    for b in input.bytes() {
+        sh.push(b, ctx, &mut out).unwrap();
+    }
+    out
+}
+
+static SPECS: [Spec; 3] = [
+    Spec::f32("qval", 0.001, 1e-9, 1.0),
+    Spec::u32("divisor", 3, 0, 255),
+    Spec::bool("verbose", false),
+];
+
+fn registry() {
+    let mut reg = Registry::new(&SPECS);
+    assert_eq!((reg.f32(0), reg.u32(1), reg.bool(2)), (0.001, 3, false));
+    static NONE: [Command<Registry<3>>; 0] = [];
+    let mut sh = Shell::<Registry<3>, 32>::new(&NONE);
+    let mut run = |reg: &mut Registry<3>, input: &str| {
+        let mut out = String::new();
+        for b in input.bytes() {
+            sh.push(b, reg, &mut out).unwrap();
+        }
+        out
+    };
+    let g = reg.generation();
+    assert_eq!(
+        run(&mut reg, "params\n"),
+        "qval = 0.001\r\ndivisor = 3\r\nverbose = false\r\n"
+    );
+    assert_eq!(run(&mut reg, "set qval 2\n"), "error: out of range\r\n");
+    assert_eq!(run(&mut reg, "set qval 0\n"), "error: out of range\r\n");
+    assert_eq!(run(&mut reg, "set qval nan\n"), "error: out of range\r\n");
+    assert_eq!(
+        run(&mut reg, "set divisor 256\n"),
+        "error: out of range\r\n"
+    );
+    assert_eq!(run(&mut reg, "set divisor x\n"), "error: bad value\r\n");
+    assert_eq!(reg.generation(), g, "failed sets change nothing");
+    assert_eq!(run(&mut reg, "set qval 0.01\n"), "qval = 0.01\r\n");
+    assert_eq!(run(&mut reg, "set divisor 7\n"), "divisor = 7\r\n");
+    assert_eq!(run(&mut reg, "set verbose on\n"), "verbose = true\r\n");
+    assert_ne!(reg.generation(), g);
+    assert_eq!((reg.f32(0), reg.u32(1), reg.bool(2)), (0.01, 7, true));
+    // Typed setter refuses other types
+    assert_eq!(reg.set_at(1, Value::F32(1.0)), Err(Error::BadValue));
+    assert_eq!(reg.set_at(2, Value::U32(1)), Err(Error::BadValue));
+
+    // Through the store and back
+    let saved = reg.to_f32s();
+    assert_eq!(saved, [0.01, 7.0, 1.0]);
+    reg.reset();
+    assert_eq!((reg.f32(0), reg.u32(1), reg.bool(2)), (0.001, 3, false));
+    assert_eq!(reg.load_f32s(&saved), 0);
+    assert_eq!((reg.f32(0), reg.u32(1), reg.bool(2)), (0.01, 7, true));
+    // Out of range or not integral stays as is
+    assert_eq!(reg.load_f32s(&[5.0, 2.5, 0.5]), 3);
+    assert_eq!(reg.load_f32s(&[0.2, -1.0, 0.0]), 1);
+    assert_eq!((reg.f32(0), reg.u32(1), reg.bool(2)), (0.2, 7, false));
+    println!("registry ok");
+}
+
+// Record key and values, like the flash store keeps them
+type Saved = Vec<(u16, Vec<f32>)>;
+type Tuning = Stored<Saved, &'static str, 3>;
+static STORED: [Command<Tuning>; 2] = [Tuning::SAVE, Tuning::DEFAULTS];
+
+fn stored() {
+    let write = |s: &mut Saved, v: &[f32]| {
+        s.push((7, v.to_vec()));
+        Ok(())
+    };
+    let mut tuning =
+        Tuning::new(Registry::new(&SPECS), Some(Vec::new()), write);
+    let mut sh = Shell::<Tuning, 32>::new(&STORED);
+    let mut run = |t: &mut Tuning, input: &str| {
+        let mut out = String::new();
+        for b in input.bytes() {
+            sh.push(b, t, &mut out).unwrap();
+        }
+        out
+    };
+    assert_eq!(run(&mut tuning, "set divisor 9\n"), "divisor = 9\r\n");
+    assert_eq!(run(&mut tuning, "save\n"), "saved\r\n");
+    assert_eq!(tuning.store, Some(vec![(7, vec![0.001, 9.0, 0.0])]));
+    assert_eq!(
+        run(&mut tuning, "defaults\n"),
+        "defaults, `save` to keep them\r\n"
+    );
+    assert_eq!(tuning.params.u32(1), 3);
+    assert_eq!(
+        run(&mut tuning, "save now\n"),
+        "error: too many arguments\r\n"
+    );
+    let mut full =
+        Tuning::new(Registry::new(&SPECS), Some(Vec::new()), |_, _| {
+            Err("full")
+        });
+    assert_eq!(run(&mut full, "save\n"), "can't save: \"full\"\r\n");
+    let mut none = Tuning::new(Registry::new(&SPECS), None, write);
+    assert_eq!(run(&mut none, "save\n"), "no storage\r\n");
+    println!("stored ok");
+}
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+static MAVLINK: AtomicBool = AtomicBool::new(true);
+
+fn flags() {
+    let mut flags = Flags::new(["quiet", "mavlink"], [&QUIET, &MAVLINK]);
+    static NONE: [Command<Flags<2>>; 0] = [];
+    let mut sh = Shell::<Flags<2>, 32>::new(&NONE);
+    let mut input = "params\nset quiet on\nset mavlink 0\nset quiet 2\n"
+        .bytes()
+        .chain("get led\n".bytes());
+    let mut out = String::new();
+    sh.drain(|| input.next(), &mut flags, &mut out).unwrap();
+    assert_eq!(
+        out,
+        "quiet = false\r\nmavlink = true\r\nquiet = true\r\n\
+         mavlink = false\r\nerror: bad value\r\n\
+         error: unknown parameter, try params\r\n"
+    );
+    assert!(QUIET.load(Ordering::Relaxed));
+    assert!(!MAVLINK.load(Ordering::Relaxed));
+    assert_eq!(flags.set("quiet", Value::U32(0)), Err(Error::BadValue));
+    println!("flags ok");
+}
+
+fn main() {
+    registry();
+    stored();
+    flags();
+    let mut sh = Shell::<Ctx, 32>::new(&COMMANDS);
+    let mut ctx = Ctx {
+        led: false,
+        rate: 50,
+        gain: 0.5,
+        runs: 0,
+    };
+    let mut run = |input: &str| feed(&mut sh, &mut ctx, input);
+
+    assert_eq!(run("add 2 3\r\n"), "5\r\n");
+    assert_eq!(run("add 41\n"), "42\r\n");
+    assert_eq!(run("  echo  a b\r"), "a b \r\n");
+    // Empty lines, CR LF and lines without a command say nothing
+    assert_eq!(run("\r\n\r\n   \n"), "");
+    // Commands from a partial line run once it ends
+    assert_eq!(run("add 1"), "");
+    assert_eq!(run(" 1\n"), "2\r\n");
+
+    let help = run("help\n");
+    assert!(help.starts_with("help - list commands\r\n"), "{}", help);
+    assert!(help.contains("set <name> <value> - change a parameter\r\n"));
+    assert!(help.ends_with("echo <words> - says it back\r\n"));
+    assert_eq!(help.lines().count(), 6);
+
+    assert_eq!(run("nope\n"), "error: unknown command, try help\r\n");
+    assert_eq!(run("add\n"), "error: missing argument\r\n");
+    assert_eq!(run("add x\n"), "error: bad value\r\n");
+    assert_eq!(run("add 1 2 3\n"), "error: too many arguments\r\n");
+    assert_eq!(run("help me\n"), "error: too many arguments\r\n");
+
+    // Overlong line is dropped whole, next one works
+    let long = format!("echo {}\n", "x".repeat(40));
+    assert_eq!(run(&long), "error: line too long\r\n");
+    assert_eq!(run(&"y".repeat(100)), "");
+    assert_eq!(run("\nadd 0\n"), "error: line too long\r\n1\r\n");
+    // Exactly full still fits
+    let full = format!("echo {}\n", "z".repeat(32 - 5));
+    assert_eq!(run(&full), format!("{} \r\n", "z".repeat(27)));
+
+    // Typed parameters
+    assert_eq!(
+        run("params\n"),
+        "led = false\r\nrate = 50\r\ngain = 0.5\r\nruns = 6\r\n"
+    );
+    assert_eq!(run("get rate\n"), "rate = 50\r\n");
+    assert_eq!(run("set rate 200\n"), "rate = 200\r\n");
+    assert_eq!(run("set rate 2000\n"), "error: out of range\r\n");
+    assert_eq!(run("set rate -1\n"), "error: bad value\r\n");
+    assert_eq!(run("set rate 1.5\n"), "error: bad value\r\n");
+    assert_eq!(run("set gain 1e-3\n"), "gain = 0.001\r\n");
+    assert_eq!(run("set led on\n"), "led = true\r\n");
+    assert_eq!(run("set led 0\n"), "led = false\r\n");
+    assert_eq!(run("set led maybe\n"), "error: bad value\r\n");
+    assert_eq!(run("set runs 0\n"), "error: read only\r\n");
+    assert_eq!(
+        run("get nope\n"),
+        "error: unknown parameter, try params\r\n"
+    );
+    assert_eq!(run("set rate\n"), "error: missing argument\r\n");
+    assert_eq!(run("get rate 1\n"), "error: too many arguments\r\n");
+    assert_eq!(ctx.rate, 200);
+
+    let mut out = String::new();
+    for b in b"echo \xff\n" {
+        sh.push(*b, &mut ctx, &mut out).unwrap();
+    }
+    assert_eq!(out, "error: not text\r\n");
+
+    // Context without parameters
+    struct Plain;
+    impl Params for Plain {}
+    static NONE: [Command<Plain>; 0] = [];
+    let mut sh = Shell::<Plain, 8>::new(&NONE);
+    let mut out = String::new();
+    for b in "params\nget x\n".bytes() {
+        sh.push(b, &mut Plain, &mut out).unwrap();
+    }
+    assert_eq!(out, "error: unknown parameter, try params\r\n");
+    println!("shell ok");
+}
@@ -0,0 +1,43 @@
+use core::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /// Line didn't fit the buffer and was dropped
+    Overlong,
+    /// Line isn't UTF-8
+    NotText,
+    UnknownCommand,
+    UnknownParam,
+    MissingArg,
+    ExtraArg,
+    /// Argument doesn't parse as the type expected
+    BadValue,
+    OutOfRange,
+    ReadOnly,
+    /// Reply couldn't be written
+    Write,
+}
+
+impl From<fmt::Error> for Error {
+    fn from(_: fmt::Error) -> Self {
+        Error::Write
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Error::Overlong => "line too long",
+            Error::NotText => "not text",
+            Error::UnknownCommand => "unknown command, try help",
+            Error::UnknownParam => "unknown parameter, try params",
+            Error::MissingArg => "missing argument",
+            Error::ExtraArg => "too many arguments",
+            Error::BadValue => "bad value",
+            Error::OutOfRange => "out of range",
+            Error::ReadOnly => "read only",
+            Error::Write => "write failed",
+        };
+        f.write_str(s)
+    }
+}
@@ -0,0 +1,48 @@
+//! On/off switches the main loop polls, as shell parameters.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::error::Error;
+use super::params::{Params, Value};
+
+/// `N` named flags backed by statics, `set quiet on` stores into the one
+/// named `quiet`
+pub struct Flags<const N: usize> {
+    names: [&'static str; N],
+    flags: [&'static AtomicBool; N],
+}
+
+impl<const N: usize> Flags<N> {
+    pub const fn new(
+        names: [&'static str; N],
+        flags: [&'static AtomicBool; N],
+    ) -> Self {
+        Flags { names, flags }
+    }
+
+    fn flag(&self, name: &str) -> Option<&'static AtomicBool> {
+        let i = self.names.iter().position(|n| *n == name)?;
+        Some(self.flags[i])
+    }
+}
+
+impl<const N: usize> Params for Flags<N> {
+    fn names(&self) -> &[&'static str] {
+        &self.names
+    }
+
+    fn get(&self, name: &str) -> Option<Value> {
+        let flag = self.flag(name)?;
+        Some(Value::Bool(flag.load(Ordering::Relaxed)))
+    }
+
+    fn set(&mut self, name: &str, value: Value) -> Result<(), Error> {
+        let v = match value {
+            Value::Bool(v) => v,
+            _ => return Err(Error::BadValue),
+        };
+        let flag = self.flag(name).ok_or(Error::UnknownParam)?;
+        flag.store(v, Ordering::Relaxed);
+        Ok(())
+    }
+}
@@ -0,0 +1,48 @@
+//! Line buffer, splits on CR or LF.
+
+use super::error::Error;
+
+const CR: u8 = b'\r';
+const LF: u8 = b'\n';
+
+/// Collects a line of up to `N` bytes. A longer one is dropped whole and
+/// reported once its end comes in.
+pub struct Cmd<const N: usize> {
+    buffer: [u8; N],
+    pos: usize,
+    overflow: bool,
+}
+
+impl<const N: usize> Cmd<N> {
+    pub const fn new() -> Self {
+        Cmd {
+            buffer: [0; N],
+            pos: 0,
+            overflow: false,
+        }
+    }
+
+    /// Returns the line at CR or LF, empty lines are skipped
+    pub fn push(&mut self, b: u8) -> Option<Result<&[u8], Error>> {
+        if b == CR || b == LF {
+            let len = self.pos;
+            self.pos = 0;
+            if self.overflow {
+                self.overflow = false;
+                Some(Err(Error::Overlong))
+            } else if len == 0 {
+                None
+            } else {
+                Some(Ok(&self.buffer[..len]))
+            }
+        } else {
+            if self.pos == N {
+                self.overflow = true;
+            } else if !self.overflow {
+                self.buffer[self.pos] = b;
+                self.pos += 1;
+            }
+            None
+        }
+    }
+}
@@ -0,0 +1,32 @@
+//! Line based command shell for serial ports.
+//!
+//! Bytes from the RX path go into `Shell::push`, a complete line runs the
+//! command named by its first word, replies are written to a
+//! `core::fmt::Write` one `\r\n` terminated line each. Built in are `help`,
+//! `params`, `get NAME` and `set NAME VALUE`, the latter over whatever the
+//! context exposes through `Params`. `Registry` is a ready `Params` of
+//! typed, range checked values for tuning without reflashing, `Stored`
+//! adds `save` and `defaults` commands keeping them in flash, `Flags` is
+//! one of on/off switches in statics.
+//!
+//! Include with `#[path = "../shell/mod.rs"] mod shell;`. `check.rs` runs
+//! the shell on the host.
+
+// Binaries use only some of it
+#![allow(dead_code, unused_imports)]
+
+mod args;
+mod error;
+mod flags;
+mod line;
+mod params;
+mod registry;
+mod session;
+
+pub use args::Args;
+pub use error::Error;
+pub use flags::Flags;
+pub use line::Cmd;
+pub use params::{Params, Value};
+pub use registry::{Registry, Spec, Stored};
+pub use session::{exec, Command, Shell};
@@ -0,0 +1,60 @@
+//! Named runtime parameters the shell can read and write.
+
+use core::fmt;
+
+use super::error::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    U32(u32),
+    F32(f32),
+}
+
+impl Value {
+    /// Parses `s` as the same type as `self`
+    pub fn parse_like(self, s: &str) -> Result<Value, Error> {
+        let v = match self {
+            Value::Bool(_) => match s {
+                "1" | "on" | "true" => Value::Bool(true),
+                "0" | "off" | "false" => Value::Bool(false),
+                _ => return Err(Error::BadValue),
+            },
+            Value::U32(_) => {
+                Value::U32(s.parse().map_err(|_| Error::BadValue)?)
+            }
+            Value::F32(_) => {
+                Value::F32(s.parse().map_err(|_| Error::BadValue)?)
+            }
+        };
+        Ok(v)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Bool(v) => write!(f, "{}", v),
+            Value::U32(v) => write!(f, "{}", v),
+            Value::F32(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// What `get`, `set` and `params` work on. Defaults have no parameters,
+/// `impl Params for Ctx {}` is enough for commands only.
+pub trait Params {
+    /// In listing order
+    fn names(&self) -> &[&'static str] {
+        &[]
+    }
+
+    fn get(&self, _name: &str) -> Option<Value> {
+        None
+    }
+
+    /// `value` has the type `get` returned
+    fn set(&mut self, _name: &str, _value: Value) -> Result<(), Error> {
+        Err(Error::UnknownParam)
+    }
+}
@@ -0,0 +1,269 @@
+//! Typed, range checked parameters that live code reads each cycle.
+
+use core::fmt::{self, Write};
+
+use super::args::Args;
+use super::error::Error;
+use super::params::{Params, Value};
+use super::session::Command;
+
+/// Name, type and range of a parameter. Type comes from `default`, `min`
+/// and `max` are inclusive and of the same type.
+#[derive(Clone, Copy)]
+pub struct Spec {
+    pub name: &'static str,
+    pub default: Value,
+    pub min: Value,
+    pub max: Value,
+}
+
+impl Spec {
+    pub const fn f32(
+        name: &'static str,
+        default: f32,
+        min: f32,
+        max: f32,
+    ) -> Self {
+        Spec {
+            name,
+            default: Value::F32(default),
+            min: Value::F32(min),
+            max: Value::F32(max),
+        }
+    }
+
+    pub const fn u32(
+        name: &'static str,
+        default: u32,
+        min: u32,
+        max: u32,
+    ) -> Self {
+        Spec {
+            name,
+            default: Value::U32(default),
+            min: Value::U32(min),
+            max: Value::U32(max),
+        }
+    }
+
+    pub const fn bool(name: &'static str, default: bool) -> Self {
+        Spec {
+            name,
+            default: Value::Bool(default),
+            min: Value::Bool(false),
+            max: Value::Bool(true),
+        }
+    }
+
+    fn check(&self, value: Value) -> Result<Value, Error> {
+        let in_range = match (self.min, value, self.max) {
+            (Value::F32(lo), Value::F32(v), Value::F32(hi)) => {
+                lo <= v && v <= hi
+            }
+            (Value::U32(lo), Value::U32(v), Value::U32(hi)) => {
+                lo <= v && v <= hi
+            }
+            (_, Value::Bool(_), _) => {
+                matches!(self.default, Value::Bool(_))
+            }
+            _ => return Err(Error::BadValue),
+        };
+        if in_range {
+            Ok(value)
+        } else {
+            Err(Error::OutOfRange)
+        }
+    }
+}
+
+/// Current values of `N` parameters, index is the position in the spec
+/// table. Readers poll `generation` to notice changes.
+pub struct Registry<const N: usize> {
+    specs: &'static [Spec; N],
+    names: [&'static str; N],
+    values: [Value; N],
+    generation: u32,
+}
+
+impl<const N: usize> Registry<N> {
+    pub fn new(specs: &'static [Spec; N]) -> Self {
+        Registry {
+            specs,
+            names: specs.map(|s| s.name),
+            values: specs.map(|s| s.default),
+            generation: 0,
+        }
+    }
+
+    /// Changes on every successful set, load or reset
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    pub fn f32(&self, i: usize) -> f32 {
+        match self.values[i] {
+            Value::F32(v) => v,
+            Value::U32(v) => v as f32,
+            Value::Bool(v) => v as u32 as f32,
+        }
+    }
+
+    pub fn u32(&self, i: usize) -> u32 {
+        match self.values[i] {
+            Value::F32(v) => v as u32,
+            Value::U32(v) => v,
+            Value::Bool(v) => v as u32,
+        }
+    }
+
+    pub fn bool(&self, i: usize) -> bool {
+        self.u32(i) != 0
+    }
+
+    pub fn set_at(&mut self, i: usize, value: Value) -> Result<(), Error> {
+        self.values[i] = self.specs[i].check(value)?;
+        self.generation = self.generation.wrapping_add(1);
+        Ok(())
+    }
+
+    pub fn reset(&mut self) {
+        for (v, s) in self.values.iter_mut().zip(self.specs.iter()) {
+            *v = s.default;
+        }
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    /// For the store: every value as f32, booleans as 0 and 1. Integers
+    /// are exact up to 2^24.
+    pub fn to_f32s(&self) -> [f32; N] {
+        let mut out = [0.0; N];
+        for (i, o) in out.iter_mut().enumerate() {
+            *o = self.f32(i);
+        }
+        out
+    }
+
+    /// Takes stored values back, the ones out of range keep their current
+    /// value. Returns how many were rejected.
+    pub fn load_f32s(&mut self, stored: &[f32; N]) -> usize {
+        let mut rejected = 0;
+        for (i, v) in stored.iter().enumerate() {
+            let value = match self.specs[i].default {
+                Value::F32(_) => Value::F32(*v),
+                Value::U32(_) if *v >= 0.0 && *v == (*v as u32) as f32 => {
+                    Value::U32(*v as u32)
+                }
+                Value::Bool(_) if *v == 0.0 || *v == 1.0 => {
+                    Value::Bool(*v != 0.0)
+                }
+                _ => {
+                    rejected += 1;
+                    continue;
+                }
+            };
+            match self.specs[i].check(value) {
+                Ok(value) => self.values[i] = value,
+                Err(_) => rejected += 1,
+            }
+        }
+        self.generation = self.generation.wrapping_add(1);
+        rejected
+    }
+}
+
+impl<const N: usize> Params for Registry<N> {
+    fn names(&self) -> &[&'static str] {
+        &self.names
+    }
+
+    fn get(&self, name: &str) -> Option<Value> {
+        let i = self.names.iter().position(|n| *n == name)?;
+        Some(self.values[i])
+    }
+
+    fn set(&mut self, name: &str, value: Value) -> Result<(), Error> {
+        let i = self.names.iter().position(|n| *n == name);
+        self.set_at(i.ok_or(Error::UnknownParam)?, value)
+    }
+}
+
+/// Shell context of a registry and the storage it's kept in, `SAVE` and
+/// `DEFAULTS` are ready commands for it
+pub struct Stored<S, E, const N: usize> {
+    pub params: Registry<N>,
+    /// `None` when storage failed to open, `save` says so
+    pub store: Option<S>,
+    write: fn(&mut S, &[f32]) -> Result<(), E>,
+}
+
+impl<S, E: fmt::Debug, const N: usize> Stored<S, E, N> {
+    /// `write` puts values under the record of this registry, like
+    /// `|s, v| s.write_f32s(records::FILTER, v)`
+    pub fn new(
+        params: Registry<N>,
+        store: Option<S>,
+        write: fn(&mut S, &[f32]) -> Result<(), E>,
+    ) -> Self {
+        Stored {
+            params,
+            store,
+            write,
+        }
+    }
+
+    pub const SAVE: Command<Self> = Command {
+        name: "save",
+        usage: "",
+        help: "keep parameters in flash",
+        run: Self::save,
+    };
+
+    pub const DEFAULTS: Command<Self> = Command {
+        name: "defaults",
+        usage: "",
+        help: "restore default parameters",
+        run: Self::defaults,
+    };
+
+    fn save(
+        &mut self,
+        args: &mut Args,
+        out: &mut dyn Write,
+    ) -> Result<(), Error> {
+        args.end()?;
+        let values = self.params.to_f32s();
+        match self.store.as_mut() {
+            Some(store) => match (self.write)(store, &values) {
+                Ok(()) => write!(out, "saved\r\n")?,
+                Err(e) => write!(out, "can't save: {:?}\r\n", e)?,
+            },
+            None => write!(out, "no storage\r\n")?,
+        }
+        Ok(())
+    }
+
+    fn defaults(
+        &mut self,
+        args: &mut Args,
+        out: &mut dyn Write,
+    ) -> Result<(), Error> {
+        args.end()?;
+        self.params.reset();
+        write!(out, "defaults, `save` to keep them\r\n")?;
+        Ok(())
+    }
+}
+
+impl<S, E, const N: usize> Params for Stored<S, E, N> {
+    fn names(&self) -> &[&'static str] {
+        self.params.names()
+    }
+
+    fn get(&self, name: &str) -> Option<Value> {
+        self.params.get(name)
+    }
+
+    fn set(&mut self, name: &str, value: Value) -> Result<(), Error> {
+        self.params.set(name, value)
+    }
+}
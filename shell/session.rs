@@ -0,0 +1,140 @@
+//! Command table and dispatch.
+
+use core::fmt::{self, Write};
+
+use super::args::Args;
+use super::error::Error;
+use super::line::Cmd;
+use super::params::Params;
+
+pub type Run<C> = fn(&mut C, &mut Args, &mut dyn Write) -> Result<(), Error>;
+
+pub struct Command<C> {
+    pub name: &'static str,
+    /// Arguments as `help` shows them, like `<on|off>`
+    pub usage: &'static str,
+    pub help: &'static str,
+    pub run: Run<C>,
+}
+
+/// Commands acting on context `C`, lines up to `N` bytes
+pub struct Shell<C: 'static, const N: usize> {
+    line: Cmd<N>,
+    commands: &'static [Command<C>],
+}
+
+impl<C: Params, const N: usize> Shell<C, N> {
+    pub const fn new(commands: &'static [Command<C>]) -> Self {
+        Shell {
+            line: Cmd::new(),
+            commands,
+        }
+    }
+
+    /// Feeds a received byte, runs the line once it's complete. Errors are
+    /// replied to, only failing to write is returned.
+    pub fn push(
+        &mut self,
+        b: u8,
+        ctx: &mut C,
+        out: &mut dyn Write,
+    ) -> fmt::Result {
+        let result = match self.line.push(b) {
+            None => return Ok(()),
+            Some(Ok(line)) => match core::str::from_utf8(line) {
+                Ok(line) => exec(self.commands, line, ctx, out),
+                Err(_) => Err(Error::NotText),
+            },
+            Some(Err(e)) => Err(e),
+        };
+        reply(result, out)
+    }
+
+    /// Pushes bytes from `next` until it runs dry, for the main loop to
+    /// call between its own lines and frames so replies never go out in
+    /// the middle of one. The RX interrupt that fills the input drops bytes
+    /// once it's full, the line comes out garbled and gets an error reply.
+    pub fn drain(
+        &mut self,
+        mut next: impl FnMut() -> Option<u8>,
+        ctx: &mut C,
+        out: &mut dyn Write,
+    ) -> fmt::Result {
+        while let Some(b) = next() {
+            self.push(b, ctx, out)?;
+        }
+        Ok(())
+    }
+}
+
+fn reply(result: Result<(), Error>, out: &mut dyn Write) -> fmt::Result {
+    match result {
+        Ok(()) => Ok(()),
+        Err(Error::Write) => Err(fmt::Error),
+        Err(e) => write!(out, "error: {}\r\n", e),
+    }
+}
+
+const BUILTIN: [(&str, &str, &str); 4] = [
+    ("help", "", "list commands"),
+    ("params", "", "list parameters with values"),
+    ("get", "<name>", "show a parameter"),
+    ("set", "<name> <value>", "change a parameter"),
+];
+
+/// Runs one line without the line buffer, for RX paths that deliver
+/// whole lines
+pub fn exec<C: Params>(
+    commands: &[Command<C>],
+    line: &str,
+    ctx: &mut C,
+    out: &mut dyn Write,
+) -> Result<(), Error> {
+    let mut args = Args::new(line);
+    let name = match args.next() {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+    match name {
+        "help" => {
+            args.end()?;
+            let own = commands.iter().map(|c| (c.name, c.usage, c.help));
+            for (name, usage, help) in BUILTIN.iter().copied().chain(own) {
+                if usage.is_empty() {
+                    write!(out, "{} - {}\r\n", name, help)?;
+                } else {
+                    write!(out, "{} {} - {}\r\n", name, usage, help)?;
+                }
+            }
+        }
+        "params" => {
+            args.end()?;
+            for name in ctx.names() {
+                if let Some(v) = ctx.get(name) {
+                    write!(out, "{} = {}\r\n", name, v)?;
+                }
+            }
+        }
+        "get" => {
+            let name = args.word()?;
+            args.end()?;
+            let v = ctx.get(name).ok_or(Error::UnknownParam)?;
+            write!(out, "{} = {}\r\n", name, v)?;
+        }
+        "set" => {
+            let name = args.word()?;
+            let text = args.word()?;
+            args.end()?;
+            let current = ctx.get(name).ok_or(Error::UnknownParam)?;
+            ctx.set(name, current.parse_like(text)?)?;
+            let v = ctx.get(name).ok_or(Error::UnknownParam)?;
+            write!(out, "{} = {}\r\n", name, v)?;
+        }
+        _ => {
+            let cmd = commands.iter().find(|c| c.name == name);
+            let cmd = cmd.ok_or(Error::UnknownCommand)?;
+            (cmd.run)(ctx, &mut args, out)?;
+        }
+    }
+    Ok(())
+}
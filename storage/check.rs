@@ -0,0 +1,289 @@
+// Exercises the store against in-memory flash, including power loss at every
+// program and erase step:
+//     rustc --edition 2021 -O check.rs && ./check
+// Panics on failure.
+
+#![allow(dead_code)]
+
+#[path = "records.rs"]
+mod records;
+#[path = "store.rs"]
+mod store;
+
+use records::{FILTER, IMU, MAG, TEMP_BIAS};
+use store::{Error, Flash, Key, Store, MAX_LEN};
+
+const PAGE: usize = 512;
+
+/// Two pages of RAM acting like flash that loses power after `budget`
+/// operations, the interrupted one is left half done
+#[derive(Clone)]
+struct MemFlash {
+    mem: Vec<u8>,
+    erases: [usize; 2],
+    budget: Option<usize>,
+}
+
+impl MemFlash {
+    fn new() -> Self {
+        MemFlash {
+            mem: vec![0xff; PAGE * 2],
+            erases: [0; 2],
+            budget: None,
+        }
+    }
+
+    /// Counts the operation, false once power is gone
+    fn spend(&mut self) -> Option<bool> {
+        match self.budget {
+            None => Some(true),
+            Some(0) => None,
+            Some(n) => {
+                self.budget = Some(n - 1);
+                Some(n > 1)
+            }
+        }
+    }
+}
+
+impl Flash for MemFlash {
+    const PAGE_SIZE: usize = PAGE;
+
+    fn read(&self, page: usize, offset: usize, buf: &mut [u8]) {
+        let start = page * PAGE + offset;
+        buf.copy_from_slice(&self.mem[start..start + buf.len()]);
+    }
+
+    fn erase(&mut self, page: usize) -> Result<(), Error> {
+        let whole = self.spend().ok_or(Error::Flash)?;
+        self.erases[page] += 1;
+        // Torn erase clears only the tail, header keeps what it had
+        let from = if whole { 0 } else { PAGE / 2 };
+        for b in &mut self.mem[page * PAGE + from..(page + 1) * PAGE] {
+            *b = 0xff;
+        }
+        if whole {
+            Ok(())
+        } else {
+            Err(Error::Flash)
+        }
+    }
+
+    fn program(
+        &mut self,
+        page: usize,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        assert!(
+            offset.is_multiple_of(2) && data.len().is_multiple_of(2),
+            "unaligned program"
+        );
+        assert!(offset + data.len() <= PAGE, "program past page end");
+        for (i, pair) in data.chunks_exact(2).enumerate() {
+            let at = page * PAGE + offset + i * 2;
+            let old = [self.mem[at], self.mem[at + 1]];
+            if pair != [0, 0] && old != [0xff, 0xff] {
+                panic!("program over {:?} at {}:{}", old, page, offset);
+            }
+            let whole = self.spend().ok_or(Error::Flash)?;
+            if whole {
+                self.mem[at] = old[0] & pair[0];
+                self.mem[at + 1] = old[1] & pair[1];
+            } else {
+                // Only some bits made it
+                self.mem[at] = old[0] & (pair[0] | 0x5a);
+                self.mem[at + 1] = old[1] & (pair[1] | 0xa5);
+                return Err(Error::Flash);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn mount(flash: MemFlash) -> Store<MemFlash> {
+    Store::new(flash).expect("mount failed")
+}
+
+fn values(seed: u32, n: usize) -> Vec<f32> {
+    (0..n).map(|i| seed as f32 + i as f32 * 0.25).collect()
+}
+
+fn read(store: &Store<MemFlash>, key: Key) -> Option<Vec<f32>> {
+    let mut buf = [0u8; MAX_LEN];
+    let len = store.read(key, &mut buf)?;
+    Some(
+        buf[..len]
+            .chunks_exact(4)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect(),
+    )
+}
+
+fn fresh() {
+    let store = mount(MemFlash::new());
+    assert_eq!(read(&store, MAG), None);
+    assert_eq!(store.free(), PAGE - 8);
+    // Mounting again finds the same empty store
+    let store = mount(store.release());
+    assert_eq!(store.free(), PAGE - 8);
+    println!("fresh ok");
+}
+
+fn roundtrip() {
+    let mut store = mount(MemFlash::new());
+    store.write_f32s(MAG, &values(1, 12)).unwrap();
+    store.write_f32s(IMU, &values(2, 6)).unwrap();
+    store.write(Key::new(9, 1), &[1, 2, 3]).unwrap();
+    store.write(Key::new(10, 1), &[]).unwrap();
+    let store = mount(store.release());
+    assert_eq!(store.read_f32s::<12>(MAG).unwrap().to_vec(), values(1, 12));
+    assert_eq!(store.read_f32s::<6>(IMU).unwrap().to_vec(), values(2, 6));
+    // Wrong length reads as missing
+    assert_eq!(store.read_f32s::<5>(IMU), None);
+    let mut buf = [0u8; 8];
+    assert_eq!(store.read(Key::new(9, 1), &mut buf), Some(3));
+    assert_eq!(buf[..3], [1, 2, 3]);
+    assert_eq!(store.read(Key::new(10, 1), &mut buf), Some(0));
+    // Newer layout doesn't pick up old records
+    assert_eq!(store.read(Key::new(MAG.id, 2), &mut buf), None);
+    println!("roundtrip ok");
+}
+
+fn latest_wins_and_wear() {
+    let mut store = mount(MemFlash::new());
+    store.write_f32s(FILTER, &values(0, 3)).unwrap();
+    for i in 0..2000 {
+        store.write_f32s(MAG, &values(i, 12)).unwrap();
+        store.write_f32s(TEMP_BIAS, &values(i * 7, 3)).unwrap();
+        assert_eq!(read(&store, MAG), Some(values(i, 12)));
+    }
+    let store = mount(store.release());
+    assert_eq!(read(&store, MAG), Some(values(1999, 12)));
+    assert_eq!(read(&store, TEMP_BIAS), Some(values(1999 * 7, 3)));
+    assert_eq!(read(&store, FILTER), Some(values(0, 3)));
+    let flash = store.release();
+    let [a, b] = flash.erases;
+    println!("erases per page {} {}", a, b);
+    assert!(a > 10 && (a as i64 - b as i64).abs() <= 1, "uneven wear");
+    println!("latest wins and wear ok");
+}
+
+fn errors() {
+    let mut store = mount(MemFlash::new());
+    assert_eq!(store.write(MAG, &[0; MAX_LEN + 1]), Err(Error::TooLong));
+    assert_eq!(
+        store.write(Key::new(0xffff, 1), &[0; 2]),
+        Err(Error::TooLong)
+    );
+    // Distinct keys fill the page, the next one can't fit even compacted
+    let mut id = 100;
+    let full = loop {
+        match store.write(Key::new(id, 1), &[id as u8; 100]) {
+            Ok(()) => id += 1,
+            Err(e) => break e,
+        }
+    };
+    assert_eq!(full, Error::Full);
+    // Old copy stays until the new one is written, so no room to rewrite
+    let rewrite = store.write(Key::new(100, 1), &[7; 100]);
+    assert_eq!(rewrite, Err(Error::Full));
+    let mut store = mount(store.release());
+    let mut buf = [0u8; 100];
+    assert_eq!(store.read(Key::new(100, 1), &mut buf), Some(100));
+    assert_eq!(buf, [100; 100]);
+    // Small records still go in
+    store.write(Key::new(100, 1), &[7; 10]).unwrap();
+    assert_eq!(store.read(Key::new(100, 1), &mut buf), Some(10));
+    assert_eq!(buf[..10], [7; 10]);
+    println!("errors ok");
+}
+
+fn corruption() {
+    let mut store = mount(MemFlash::new());
+    store.write_f32s(MAG, &values(1, 12)).unwrap();
+    store.write_f32s(MAG, &values(2, 12)).unwrap();
+    let mut flash = store.release();
+    // Flip a data bit of the second record
+    let second = 8 + 6 + 48 + 6;
+    flash.mem[second + 5] ^= 0x10;
+    let mut store = mount(flash);
+    assert_eq!(read(&store, MAG), Some(values(1, 12)));
+    store.write_f32s(MAG, &values(3, 12)).unwrap();
+    let store = mount(store.release());
+    assert_eq!(read(&store, MAG), Some(values(3, 12)));
+
+    // Garbage in both pages formats the store
+    let mut flash = MemFlash::new();
+    for (i, b) in flash.mem.iter_mut().enumerate() {
+        *b = i as u8;
+    }
+    let store = mount(flash);
+    assert_eq!(read(&store, MAG), None);
+    println!("corruption ok");
+}
+
+/// Runs the same write sequence cutting power after every operation, after
+/// remount every key holds its old or new value and the store takes writes
+fn power_loss() {
+    let mut store = mount(MemFlash::new());
+    store.write_f32s(FILTER, &values(100, 3)).unwrap();
+    // Close to a compaction so cuts land in it too
+    let mut last = 0;
+    while store.free() > 200 {
+        last += 1;
+        store.write_f32s(MAG, &values(last, 12)).unwrap();
+    }
+    let base = store.release();
+
+    let mut cuts = 0;
+    for budget in 0.. {
+        let mut flash = base.clone();
+        flash.budget = Some(budget);
+        let mut store = match Store::new(flash) {
+            Ok(store) => store,
+            Err(_) => continue,
+        };
+        let mut done = true;
+        let mut written = last;
+        for i in 1..=8 {
+            if store.write_f32s(MAG, &values(last + i, 12)).is_err() {
+                done = false;
+                break;
+            }
+            written = last + i;
+        }
+        if done {
+            break;
+        }
+        cuts += 1;
+
+        let mut flash = store.release();
+        flash.budget = None;
+        let mut store = mount(flash);
+        let mag = read(&store, MAG).expect("MAG lost");
+        let ok = (written..=written + 1).any(|i| mag == values(i, 12));
+        assert!(
+            ok,
+            "MAG is {:?} after cut {}, wrote {}",
+            mag, budget, written
+        );
+        assert_eq!(read(&store, FILTER), Some(values(100, 3)));
+        for i in 0..40 {
+            store.write_f32s(TEMP_BIAS, &values(i, 3)).unwrap();
+        }
+        let store = mount(store.release());
+        assert_eq!(read(&store, TEMP_BIAS), Some(values(39, 3)));
+        assert_eq!(read(&store, FILTER), Some(values(100, 3)));
+    }
+    println!("power loss ok after {} cuts", cuts);
+}
+
+fn main() {
+    fresh();
+    roundtrip();
+    latest_wins_and_wear();
+    errors();
+    corruption();
+    power_loss();
+}
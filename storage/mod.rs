@@ -0,0 +1,13 @@
+//! Calibration and parameter store in the last two pages of internal flash.
+//!
+//! Include with `#[path = "../storage/mod.rs"] mod storage;`, `check.rs`
+//! tests the store on the host against in-memory flash.
+
+// Binaries use only some of the records
+#![allow(dead_code, unused_imports)]
+
+pub mod records;
+pub mod stm32;
+mod store;
+
+pub use store::{Error, Flash, Key, Store, MAX_LEN};
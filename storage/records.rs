@@ -0,0 +1,51 @@
+//! Records kept in the store, data is little endian f32 arrays.
+
+use super::store::{Error, Flash, Key, Store};
+
+/// Magnetometer soft iron `a_1` (row-major) then hard iron `b`
+pub const MAG: Key = Key::new(3, 1);
+/// Six-position IMU calibration from wonca, see `wonca/imu.rs` for the
+/// layout
+pub const IMU: Key = Key::new(5, 2);
+/// Accel and gyro bias polynomials of temperature from temp-calib, see
+/// `temp_calib/bias.rs` for the layout
+pub const TEMP_BIAS: Key = Key::new(6, 1);
+/// Attitude filter tuning from calibrating-ahrs, its `PARAMS` in order:
+/// initial covariance, process noise, measurement noise, MPU sample rate
+/// divisor, gyro DLPF, sampling period (ms), MAVLink output
+pub const FILTER: Key = Key::new(4, 3);
+/// Altitude filter tuning and output mode from altitude-fusion, its
+/// `PARAMS` in order
+pub const ALTITUDE: Key = Key::new(7, 2);
+
+impl<F: Flash> Store<F> {
+    pub fn write_f32s(
+        &mut self,
+        key: Key,
+        values: &[f32],
+    ) -> Result<(), Error> {
+        let mut buf = [0u8; super::store::MAX_LEN];
+        let len = values.len() * 4;
+        if len > buf.len() {
+            return Err(Error::TooLong);
+        }
+        for (chunk, v) in buf.chunks_exact_mut(4).zip(values.iter()) {
+            chunk.copy_from_slice(&v.to_le_bytes());
+        }
+        self.write(key, &buf[..len])
+    }
+
+    /// Latest `key` record, `None` if missing or of different length
+    pub fn read_f32s<const N: usize>(&self, key: Key) -> Option<[f32; N]> {
+        let mut buf = [0u8; super::store::MAX_LEN];
+        match self.read(key, &mut buf) {
+            Some(len) if len == N * 4 => {}
+            _ => return None,
+        }
+        let mut values = [0.0; N];
+        for (v, chunk) in values.iter_mut().zip(buf.chunks_exact(4)) {
+            *v = f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        Some(values)
+    }
+}
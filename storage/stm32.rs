@@ -0,0 +1,119 @@
+//! STM32F30x internal flash driven through FLASH registers.
+
+use core::ptr;
+
+use super::store::{Error, Flash};
+
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xcdef_89ab;
+// FLASH_SR
+const BSY: u32 = 1 << 0;
+const PGERR: u32 = 1 << 2;
+const WRPRTERR: u32 = 1 << 4;
+const EOP: u32 = 1 << 5;
+// FLASH_CR
+const PG: u32 = 1 << 0;
+const PER: u32 = 1 << 1;
+const STRT: u32 = 1 << 6;
+const LOCK: u32 = 1 << 7;
+
+extern "C" {
+    // Right past FLASH region of memory.x
+    static _storage_start: u8;
+}
+
+/// Two 2K pages past the program
+pub struct InternalFlash {
+    base: usize,
+}
+
+impl InternalFlash {
+    /// Pages reserved by memory.x, there must be only one instance
+    pub unsafe fn new() -> Self {
+        InternalFlash {
+            base: &_storage_start as *const u8 as usize,
+        }
+    }
+
+    fn regs(&self) -> &hal::pac::flash::RegisterBlock {
+        unsafe { &*hal::pac::FLASH::ptr() }
+    }
+
+    fn unlock(&self) {
+        let regs = self.regs();
+        if regs.cr.read().bits() & LOCK != 0 {
+            regs.keyr.write(|w| unsafe { w.bits(KEY1) });
+            regs.keyr.write(|w| unsafe { w.bits(KEY2) });
+        }
+    }
+
+    fn lock(&self) {
+        let regs = self.regs();
+        regs.cr.modify(|r, w| unsafe { w.bits(r.bits() | LOCK) });
+    }
+
+    /// Wait for operation to finish, clear and check its status
+    fn wait(&self) -> Result<(), Error> {
+        let regs = self.regs();
+        while regs.sr.read().bits() & BSY != 0 {}
+        let sr = regs.sr.read().bits();
+        regs.sr.write(|w| unsafe { w.bits(PGERR | WRPRTERR | EOP) });
+        if sr & (PGERR | WRPRTERR) != 0 {
+            Err(Error::Flash)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn address(&self, page: usize, offset: usize) -> usize {
+        self.base + page * Self::PAGE_SIZE + offset
+    }
+}
+
+impl Flash for InternalFlash {
+    const PAGE_SIZE: usize = 2048;
+
+    fn read(&self, page: usize, offset: usize, buf: &mut [u8]) {
+        let start = self.address(page, offset) as *const u8;
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = unsafe { ptr::read_volatile(start.add(i)) };
+        }
+    }
+
+    fn erase(&mut self, page: usize) -> Result<(), Error> {
+        let regs = self.regs();
+        self.unlock();
+        regs.cr.modify(|r, w| unsafe { w.bits(r.bits() | PER) });
+        regs.ar
+            .write(|w| unsafe { w.bits(self.address(page, 0) as u32) });
+        regs.cr.modify(|r, w| unsafe { w.bits(r.bits() | STRT) });
+        let result = self.wait();
+        regs.cr.modify(|r, w| unsafe { w.bits(r.bits() & !PER) });
+        self.lock();
+        result
+    }
+
+    fn program(
+        &mut self,
+        page: usize,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        let regs = self.regs();
+        self.unlock();
+        regs.cr.modify(|r, w| unsafe { w.bits(r.bits() | PG) });
+        let mut result = Ok(());
+        for (i, pair) in data.chunks_exact(2).enumerate() {
+            let at = self.address(page, offset + i * 2) as *mut u16;
+            let half = u16::from_le_bytes([pair[0], pair[1]]);
+            unsafe { ptr::write_volatile(at, half) };
+            result = self.wait();
+            if result.is_err() {
+                break;
+            }
+        }
+        regs.cr.modify(|r, w| unsafe { w.bits(r.bits() & !PG) });
+        self.lock();
+        result
+    }
+}
@@ -0,0 +1,340 @@
+//! Log structured record store over two flash pages.
+//!
+//! Records are appended to the active page, the latest valid record of a key
+//! wins. When the page fills up live records are copied to the other page,
+//! which then becomes active, so erases alternate between the two pages.
+//!
+//! Page layout: header `[seq: u32, format: u16, magic: u16]` followed by
+//! records `[id: u16, version: u8, len: u8, crc: u16, data, padding]`.
+//!
+//! Power loss safety comes from write order: record data goes first and its
+//! header last, page header is written after all records are copied and its
+//! magic is the last half-word. Torn records fail CRC, torn pages miss the
+//! magic. Both are skipped on mount and the next write compacts them away.
+//! Page about to be erased loses its magic first.
+
+/// Flash memory with two pages of `PAGE_SIZE` bytes for the store.
+///
+/// Programming goes in half-words, erased flash reads as 0xff.
+pub trait Flash {
+    const PAGE_SIZE: usize;
+
+    fn read(&self, page: usize, offset: usize, buf: &mut [u8]);
+
+    fn erase(&mut self, page: usize) -> Result<(), Error>;
+
+    /// Program even number of bytes at even `offset`, destination must be
+    /// erased unless data is all zeros
+    fn program(
+        &mut self,
+        page: usize,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<(), Error>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /// Flash reported program or erase failure
+    Flash,
+    /// Live records don't fit into a page
+    Full,
+    /// Record data longer than `MAX_LEN`
+    TooLong,
+}
+
+/// Record identity and version of its data layout, records of other
+/// versions read as missing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Key {
+    pub id: u16,
+    pub version: u8,
+}
+
+impl Key {
+    pub const fn new(id: u16, version: u8) -> Self {
+        Key { id, version }
+    }
+}
+
+/// Longest record data, bytes
+pub const MAX_LEN: usize = 255;
+/// Store layout version, pages of other formats are discarded
+const FORMAT: u16 = 1;
+const MAGIC: u16 = 0x5a17;
+const PAGE_HEADER: usize = 8;
+const RECORD_HEADER: usize = 6;
+const ERASED_ID: u16 = 0xffff;
+
+struct Record {
+    id: u16,
+    version: u8,
+    len: usize,
+    offset: usize,
+}
+
+pub struct Store<F: Flash> {
+    flash: F,
+    active: usize,
+    seq: u32,
+    // First free byte of the active page
+    free: usize,
+    // Active page has garbage that has to be compacted before next append
+    dirty: bool,
+}
+
+impl<F: Flash> Store<F> {
+    /// Mount store, formats flash if neither page holds a valid one
+    pub fn new(flash: F) -> Result<Self, Error> {
+        let mut store = Store {
+            flash,
+            active: 0,
+            seq: 0,
+            free: PAGE_HEADER,
+            dirty: false,
+        };
+        match (store.page_seq(0), store.page_seq(1)) {
+            (Some(a), Some(b)) if (b.wrapping_sub(a) as i32) > 0 => {
+                store.active = 1;
+                store.seq = b;
+            }
+            (Some(a), _) => store.seq = a,
+            (None, Some(b)) => {
+                store.active = 1;
+                store.seq = b;
+            }
+            (None, None) => {
+                store.flash.erase(0)?;
+                store.write_page_header(0, 1)?;
+                store.seq = 1;
+            }
+        }
+        store.scan();
+        Ok(store)
+    }
+
+    /// Give the flash back
+    pub fn release(self) -> F {
+        self.flash
+    }
+
+    /// Copy data of the latest `key` record into `buf`, returns its length
+    pub fn read(&self, key: Key, buf: &mut [u8]) -> Option<usize> {
+        let r = self.find(key.id).filter(|r| r.version == key.version)?;
+        let len = r.len.min(buf.len());
+        self.flash
+            .read(self.active, r.offset + RECORD_HEADER, &mut buf[..len]);
+        Some(r.len)
+    }
+
+    /// Append new record for `key`, compacts the store when needed
+    pub fn write(&mut self, key: Key, data: &[u8]) -> Result<(), Error> {
+        if data.len() > MAX_LEN || key.id == ERASED_ID {
+            return Err(Error::TooLong);
+        }
+        if self.dirty || self.free + record_size(data.len()) > F::PAGE_SIZE {
+            self.compact(data.len())?;
+        }
+        let (page, offset) = (self.active, self.free);
+        self.free += record_size(data.len());
+        let result = self.write_record(page, offset, key, data);
+        // Half written record has to go with the next compaction
+        self.dirty |= result.is_err();
+        result
+    }
+
+    /// Bytes left in the active page before compaction
+    pub fn free(&self) -> usize {
+        F::PAGE_SIZE - self.free
+    }
+
+    fn page_seq(&self, page: usize) -> Option<u32> {
+        let mut h = [0u8; PAGE_HEADER];
+        self.flash.read(page, 0, &mut h);
+        let seq = u32::from_le_bytes([h[0], h[1], h[2], h[3]]);
+        let format = u16::from_le_bytes([h[4], h[5]]);
+        let magic = u16::from_le_bytes([h[6], h[7]]);
+        if magic == MAGIC && format == FORMAT {
+            Some(seq)
+        } else {
+            None
+        }
+    }
+
+    fn write_page_header(
+        &mut self,
+        page: usize,
+        seq: u32,
+    ) -> Result<(), Error> {
+        self.flash.program(page, 0, &seq.to_le_bytes())?;
+        self.flash.program(page, 4, &FORMAT.to_le_bytes())?;
+        self.flash.program(page, 6, &MAGIC.to_le_bytes())
+    }
+
+    /// Find end of records in the active page and check the rest is erased
+    fn scan(&mut self) {
+        let mut offset = PAGE_HEADER;
+        let mut dirty = false;
+        loop {
+            match slot(&self.flash, self.active, offset) {
+                Slot::Record(r) => offset += record_size(r.len),
+                Slot::End => break,
+                Slot::Torn => {
+                    dirty = true;
+                    break;
+                }
+            }
+        }
+        self.free = offset;
+        let mut buf = [0u8; 32];
+        while !dirty && offset < F::PAGE_SIZE {
+            let n = buf.len().min(F::PAGE_SIZE - offset);
+            self.flash.read(self.active, offset, &mut buf[..n]);
+            dirty = buf[..n].iter().any(|&b| b != 0xff);
+            offset += n;
+        }
+        self.dirty = dirty;
+    }
+
+    /// Latest valid record of `id` in the active page
+    fn find(&self, id: u16) -> Option<Record> {
+        let mut found = None;
+        let mut offset = PAGE_HEADER;
+        while let Slot::Record(r) = slot(&self.flash, self.active, offset) {
+            offset += record_size(r.len);
+            if r.id == id {
+                found = Some(r);
+            }
+        }
+        found
+    }
+
+    /// Copy latest record of every key to the other page and switch to it,
+    /// leaving room for a record of `extra` bytes
+    fn compact(&mut self, extra: usize) -> Result<(), Error> {
+        let (from, to) = (self.active, 1 - self.active);
+        let mut live = 0;
+        let mut offset = PAGE_HEADER;
+        while let Slot::Record(r) = slot(&self.flash, from, offset) {
+            offset += record_size(r.len);
+            if self.is_latest(&r) {
+                live += record_size(r.len);
+            }
+        }
+        if PAGE_HEADER + live + record_size(extra) > F::PAGE_SIZE {
+            return Err(Error::Full);
+        }
+
+        // Interrupted erase may leave old header looking valid and newer
+        self.flash.program(to, 6, &[0, 0])?;
+        self.flash.erase(to)?;
+        let mut buf = [0u8; MAX_LEN];
+        let mut src = PAGE_HEADER;
+        let mut dst = PAGE_HEADER;
+        while let Slot::Record(r) = slot(&self.flash, from, src) {
+            src += record_size(r.len);
+            if !self.is_latest(&r) {
+                continue;
+            }
+            let data = &mut buf[..r.len];
+            self.flash.read(from, r.offset + RECORD_HEADER, data);
+            self.write_record(to, dst, Key::new(r.id, r.version), data)?;
+            dst += record_size(r.len);
+        }
+        let seq = self.seq.wrapping_add(1);
+        self.write_page_header(to, seq)?;
+        self.active = to;
+        self.seq = seq;
+        self.free = dst;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Whether no later valid record of the same key follows `r`
+    fn is_latest(&self, r: &Record) -> bool {
+        self.find(r.id).is_none_or(|l| l.offset == r.offset)
+    }
+
+    fn write_record(
+        &mut self,
+        page: usize,
+        offset: usize,
+        key: Key,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        let start = offset + RECORD_HEADER;
+        let even = data.len() & !1;
+        self.flash.program(page, start, &data[..even])?;
+        if even < data.len() {
+            self.flash
+                .program(page, start + even, &[data[even], 0xff])?;
+        }
+        let crc = crc16(key, data).to_le_bytes();
+        let len = data.len() as u8;
+        self.flash.program(page, offset + 2, &[key.version, len])?;
+        self.flash.program(page, offset + 4, &crc)?;
+        // Id goes last, erased id marks the end of records
+        self.flash.program(page, offset, &key.id.to_le_bytes())
+    }
+}
+
+/// Record header and data rounded up to half-words
+fn record_size(len: usize) -> usize {
+    RECORD_HEADER + ((len + 1) & !1)
+}
+
+enum Slot {
+    Record(Record),
+    /// Erased header, no more records
+    End,
+    /// Torn or corrupted record
+    Torn,
+}
+
+/// Record at `offset` of `page`
+fn slot<F: Flash>(flash: &F, page: usize, offset: usize) -> Slot {
+    if offset + RECORD_HEADER > F::PAGE_SIZE {
+        return Slot::End;
+    }
+    let mut h = [0u8; RECORD_HEADER];
+    flash.read(page, offset, &mut h);
+    let id = u16::from_le_bytes([h[0], h[1]]);
+    if id == ERASED_ID {
+        return Slot::End;
+    }
+    let (version, len) = (h[2], h[3] as usize);
+    let crc = u16::from_le_bytes([h[4], h[5]]);
+    if offset + record_size(len) > F::PAGE_SIZE {
+        return Slot::Torn;
+    }
+    let mut buf = [0u8; MAX_LEN];
+    let data = &mut buf[..len];
+    flash.read(page, offset + RECORD_HEADER, data);
+    if crc16(Key::new(id, version), data) != crc {
+        return Slot::Torn;
+    }
+    Slot::Record(Record {
+        id,
+        version,
+        len,
+        offset,
+    })
+}
+
+/// CRC-16/CCITT-FALSE over record key, length and data
+fn crc16(key: Key, data: &[u8]) -> u16 {
+    let id = key.id.to_le_bytes();
+    let head = [id[0], id[1], key.version, data.len() as u8];
+    let mut crc: u16 = 0xffff;
+    for &b in head.iter().chain(data.iter()) {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
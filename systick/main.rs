@@ -12,9 +12,13 @@ use cortex_m_rt::{entry, exception, ExceptionFrame};
 use hal::prelude::*;
 use hal::time::Bps;
 
+#[path = "../cobs/mod.rs"]
+mod cobs;
+use cobs::FrameWriter;
+
 static mut NOW_MS: u32 = 0;
 static mut LAST_SNAPSHOT_MS: u32 = 0;
-static mut L: Option<hal::serial::Tx<hal::pac::USART2>> = None;
+static mut L: Option<FrameWriter<hal::serial::Tx<hal::pac::USART2>>> = None;
 
 #[entry]
 fn main() -> ! {
@@ -33,11 +37,9 @@ fn main() -> ! {
         device
             .USART2
             .serial((gpioa.pa2, gpioa.pa15), Bps(460800), clocks);
-    let (mut tx, _rx) = serial.split();
-    // COBS frame
-    tx.write(0x00).unwrap();
+    let (tx, _rx) = serial.split();
     unsafe {
-        L = Some(tx);
+        L = Some(FrameWriter::start(tx).unwrap());
     }
     let l = unsafe { extract(&mut L) };
     write!(l, "logger ok\r\n").unwrap();
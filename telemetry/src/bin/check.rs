@@ -0,0 +1,139 @@
+// Round trips every message kind through the board side path (postcard
+// into a fixed buffer, COBS framing) and back:
+//     cargo run --target x86_64-unknown-linux-gnu --features std --bin check
+// Panics on failure.
+
+#[allow(dead_code)]
+#[path = "../../../cobs/crc.rs"]
+mod crc;
+#[allow(dead_code)]
+#[path = "../../../cobs/decode.rs"]
+mod decode;
+#[allow(dead_code)]
+#[path = "../../../cobs/encode.rs"]
+mod encode;
+
+use decode::Decoder;
+use encode::{encode_in_place, max_payload};
+use telemetry::{
+    Altitude, Attitude, Estimator, Health, Level, Message, RawMarg,
+};
+
+// dma::CAPACITY of the boards
+const BUFFER: usize = 256;
+
+fn raw(i: u32) -> RawMarg {
+    let f = i as f32;
+    RawMarg {
+        accel: [0.01 * f, -0.02, 9.80665],
+        gyro: [1e-4, -2e-4 * f, 0.0],
+        mag: [21.5, -3.25, f32::NAN],
+        temp: 31.5,
+        dt_s: 0.00201,
+    }
+}
+
+fn attitude(i: u32) -> Attitude {
+    Attitude {
+        estimator: Estimator::QuatEkf,
+        quat: Some([1.0, 0.0, -1e-3 * i as f32, 0.0]),
+        ypr: None,
+        gyro_bias: Some([1e-5, 0.0, -1e-5]),
+    }
+}
+
+/// Fills a board buffer like `fill_with_messages` does, messages that don't
+/// fit are left out
+fn frame(msgs: &[Message]) -> (Vec<u8>, usize) {
+    let mut buf = [0u8; BUFFER];
+    let cap = max_payload(BUFFER);
+    let mut len = 0;
+    let mut sent = 0;
+    for m in msgs {
+        if let Ok(n) = telemetry::append(&mut buf[..cap], len, m) {
+            len = n;
+            sent += 1;
+        }
+    }
+    let n = encode_in_place(&mut buf, len).unwrap();
+    (buf[..n].to_vec(), sent)
+}
+
+fn same(a: &Message, b: &Message) -> bool {
+    // NaN doesn't equal itself, compare encodings
+    postcard::to_stdvec(a).unwrap() == postcard::to_stdvec(b).unwrap()
+}
+
+fn main() {
+    let log = format!("mpu err {}", 7);
+    let frames: Vec<Vec<Message>> = vec![
+        vec![Message::log(Level::Info, "Dma ok!")],
+        vec![Message::RawMarg(raw(1)), Message::Attitude(attitude(1))],
+        vec![Message::Altitude(Altitude {
+            t_ms: 123_456,
+            pressure_pa: None,
+            range_mm: Some(812),
+            agl_m: 0.81,
+            climb_mps: -0.02,
+        })],
+        vec![
+            Message::Health(Health {
+                uptime_ms: u32::MAX,
+                samples: 0,
+                sensor_errors: 3,
+            }),
+            Message::log(Level::Error, &log),
+        ],
+    ];
+    let mut stream = vec![0u8];
+    for msgs in &frames {
+        let (bytes, sent) = frame(msgs);
+        assert_eq!(sent, msgs.len());
+        stream.extend(bytes);
+    }
+    let (bytes, _) = frame(&[Message::RawMarg(raw(0))]);
+    println!("raw marg frame: {} bytes", bytes.len());
+
+    let mut dec = Decoder::<BUFFER>::new();
+    let mut got = Vec::new();
+    for b in &stream {
+        if let Some(payload) = dec.push(*b) {
+            let payload = payload.expect("bad frame").to_vec();
+            let mut msgs = Vec::new();
+            let mut rest: &[u8] = &payload;
+            while !rest.is_empty() {
+                let (m, r) = telemetry::take(rest).expect("bad message");
+                msgs.push(format!("{:?}", m));
+                assert!(frames.iter().flatten().any(|f| same(f, &m)));
+                rest = r;
+            }
+            got.push(msgs);
+        }
+    }
+    let expected: Vec<Vec<String>> = frames
+        .iter()
+        .map(|f| f.iter().map(|m| format!("{:?}", m)).collect())
+        .collect();
+    assert_eq!(got, expected);
+
+    // Whole messages are dropped when the buffer is full, never cut
+    let many: Vec<Message> =
+        (0..10).map(|i| Message::RawMarg(raw(i))).collect();
+    let (bytes, sent) = frame(&many);
+    assert!(sent > 0 && sent < many.len());
+    let mut payload = Vec::new();
+    for b in &bytes {
+        if let Some(p) = dec.push(*b) {
+            payload = p.expect("bad frame").to_vec();
+        }
+    }
+    let mut rest = &payload[..];
+    let mut n = 0;
+    while !rest.is_empty() {
+        rest = telemetry::take(rest).unwrap().1;
+        n += 1;
+    }
+    assert_eq!(n, sent);
+    println!("{} raw marg per frame", sent);
+    println!("telemetry ok");
+}
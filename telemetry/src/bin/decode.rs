@@ -0,0 +1,197 @@
+// Decodes framed telemetry from a capture or a port into JSON lines, or CSV
+// of one message kind:
+//     cargo run --target x86_64-unknown-linux-gnu --features std \
+//         --bin decode -- [--csv raw_marg|attitude|altitude|health|log] [FILE]
+// Reads stdin without FILE. Broken frames are counted on stderr.
+
+#[allow(dead_code)]
+#[path = "../../../cobs/crc.rs"]
+mod crc;
+#[allow(dead_code)]
+#[path = "../../../cobs/decode.rs"]
+mod decode;
+
+use std::io::{self, BufReader, Read, Write};
+
+use decode::Decoder;
+use telemetry::{Attitude, Message};
+
+const MAX_FRAME: usize = 4096;
+
+enum Output {
+    Json,
+    Csv(Kind),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    RawMarg,
+    Attitude,
+    Altitude,
+    Health,
+    Log,
+}
+
+impl Kind {
+    fn parse(s: &str) -> Option<Kind> {
+        Some(match s {
+            "raw_marg" => Kind::RawMarg,
+            "attitude" => Kind::Attitude,
+            "altitude" => Kind::Altitude,
+            "health" => Kind::Health,
+            "log" => Kind::Log,
+            _ => return None,
+        })
+    }
+
+    fn of(msg: &Message) -> Kind {
+        match msg {
+            Message::RawMarg(_) => Kind::RawMarg,
+            Message::Attitude(_) => Kind::Attitude,
+            Message::Altitude(_) => Kind::Altitude,
+            Message::Health(_) => Kind::Health,
+            Message::Log(_) => Kind::Log,
+        }
+    }
+
+    fn header(self) -> &'static str {
+        match self {
+            Kind::RawMarg => "ax,ay,az,gx,gy,gz,mx,my,mz,temp,dt_s",
+            Kind::Attitude => "estimator,q0,q1,q2,q3,yaw,pitch,roll,bx,by,bz",
+            Kind::Altitude => "t_ms,pressure_pa,range_mm,agl_m,climb_mps",
+            Kind::Health => "uptime_ms,samples,sensor_errors",
+            Kind::Log => "level,text",
+        }
+    }
+}
+
+fn fields<T: ToString>(row: &mut Vec<String>, values: &[T]) {
+    row.extend(values.iter().map(|v| v.to_string()));
+}
+
+/// Missing values stay empty
+fn maybe<const N: usize>(row: &mut Vec<String>, values: Option<[f32; N]>) {
+    match values {
+        Some(v) => fields(row, &v),
+        None => row.extend((0..N).map(|_| String::new())),
+    }
+}
+
+fn opt<T: ToString>(v: Option<T>) -> String {
+    v.map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn csv_row(msg: &Message) -> String {
+    let mut row = Vec::new();
+    match msg {
+        Message::RawMarg(m) => {
+            fields(&mut row, &m.accel);
+            fields(&mut row, &m.gyro);
+            fields(&mut row, &m.mag);
+            fields(&mut row, &[m.temp, m.dt_s]);
+        }
+        Message::Attitude(Attitude {
+            estimator,
+            quat,
+            ypr,
+            gyro_bias,
+        }) => {
+            row.push(format!("{:?}", estimator));
+            maybe(&mut row, *quat);
+            maybe(&mut row, *ypr);
+            maybe(&mut row, *gyro_bias);
+        }
+        Message::Altitude(a) => {
+            row.push(a.t_ms.to_string());
+            row.push(opt(a.pressure_pa));
+            row.push(opt(a.range_mm));
+            fields(&mut row, &[a.agl_m, a.climb_mps]);
+        }
+        Message::Health(h) => {
+            fields(&mut row, &[h.uptime_ms, h.samples, h.sensor_errors]);
+        }
+        Message::Log(l) => {
+            row.push(format!("{:?}", l.level));
+            row.push(format!("\"{}\"", l.text.trim_end().replace('"', "\"\"")));
+        }
+    }
+    row.join(",")
+}
+
+fn main() -> io::Result<()> {
+    let mut output = Output::Json;
+    let mut path = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--csv" {
+            let kind = args.next().as_deref().and_then(Kind::parse);
+            match kind {
+                Some(kind) => output = Output::Csv(kind),
+                None => {
+                    eprintln!("--csv raw_marg|attitude|altitude|health|log");
+                    std::process::exit(2);
+                }
+            }
+        } else {
+            path = Some(arg);
+        }
+    }
+    let input: Box<dyn Read> = match path {
+        Some(path) => Box::new(std::fs::File::open(path)?),
+        None => Box::new(io::stdin()),
+    };
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if let Output::Csv(kind) = output {
+        writeln!(out, "{}", kind.header())?;
+    }
+
+    let mut dec = Decoder::<MAX_FRAME>::new();
+    let (mut frames, mut bad_frames, mut bad_messages) = (0, 0, 0);
+    let mut line = String::new();
+    for b in BufReader::new(input).bytes() {
+        let mut payload = match dec.push(b?) {
+            None => continue,
+            Some(Ok(payload)) => payload,
+            Some(Err(_)) => {
+                bad_frames += 1;
+                continue;
+            }
+        };
+        frames += 1;
+        while !payload.is_empty() {
+            let msg = match telemetry::take(payload) {
+                Ok((msg, rest)) => {
+                    payload = rest;
+                    msg
+                }
+                Err(e) => {
+                    // Text or a newer schema, rest of the frame is lost
+                    eprintln!("frame {}: {:?}", frames, e);
+                    bad_messages += 1;
+                    break;
+                }
+            };
+            line.clear();
+            match output {
+                Output::Json => {
+                    let json = serde_json::to_string(&msg)
+                        .expect("messages serialize");
+                    line.push_str(&json);
+                }
+                Output::Csv(kind) if Kind::of(&msg) == kind => {
+                    line.push_str(&csv_row(&msg));
+                }
+                Output::Csv(_) => continue,
+            }
+            line.push('\n');
+            out.write_all(line.as_bytes())?;
+        }
+        out.flush()?;
+    }
+    eprintln!(
+        "{} frames, {} broken frames, {} undecodable payloads",
+        frames, bad_frames, bad_messages
+    );
+    Ok(())
+}
@@ -0,0 +1,121 @@
+//! Telemetry messages shared by the boards and host tools.
+//!
+//! Messages are postcard encoded back to back into a frame payload, frames
+//! are COBS framed with CRC as everything else on the wire (see `cobs/`).
+//! `decode` turns a capture or a port back into CSV or JSON lines.
+//!
+//! Changing a message changes its encoding, boards and host tools have to
+//! be rebuilt together.
+
+#![no_std]
+
+use serde::{Deserialize, Serialize};
+
+pub use postcard::Error;
+
+/// One read of the MPU, as it came out of the driver
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct RawMarg {
+    /// m/s²
+    pub accel: [f32; 3],
+    /// rad/s
+    pub gyro: [f32; 3],
+    /// µT
+    pub mag: [f32; 3],
+    /// °C
+    pub temp: f32,
+    /// Time since previous sample, s
+    pub dt_s: f32,
+}
+
+/// What produced an `Attitude`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Estimator {
+    Dcmimu,
+    QuatEkf,
+    MargEkf,
+    /// Replayed from a log for comparison
+    Reference,
+}
+
+/// Attitude estimate, estimators fill in what they have
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Attitude {
+    pub estimator: Estimator,
+    /// w, x, y, z
+    pub quat: Option<[f32; 4]>,
+    /// Yaw, pitch, roll, rad
+    pub ypr: Option<[f32; 3]>,
+    /// rad/s
+    pub gyro_bias: Option<[f32; 3]>,
+}
+
+/// Barometer and range finder fusion
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Altitude {
+    pub t_ms: u32,
+    /// Pa, when read this cycle
+    pub pressure_pa: Option<f32>,
+    /// When the range finder had a reading
+    pub range_mm: Option<u16>,
+    /// Above ground, m
+    pub agl_m: f32,
+    /// m/s
+    pub climb_mps: f32,
+}
+
+/// Periodic counters of the sender
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Health {
+    pub uptime_ms: u32,
+    pub samples: u32,
+    /// Sensor reads that failed
+    pub sensor_errors: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Log<'a> {
+    pub level: Level,
+    pub text: &'a str,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Message<'a> {
+    RawMarg(RawMarg),
+    Attitude(Attitude),
+    Altitude(Altitude),
+    Health(Health),
+    #[serde(borrow)]
+    Log(Log<'a>),
+}
+
+impl<'a> Message<'a> {
+    pub fn log(level: Level, text: &'a str) -> Self {
+        Message::Log(Log { level, text })
+    }
+}
+
+/// Encodes `msg` into `buf` after the first `len` bytes, returns the new
+/// length. On error nothing past `len` is meaningful.
+pub fn append(
+    buf: &mut [u8],
+    len: usize,
+    msg: &Message,
+) -> Result<usize, Error> {
+    let used = postcard::to_slice(msg, &mut buf[len..])?.len();
+    Ok(len + used)
+}
+
+/// Splits the first message off a frame payload
+pub fn take(payload: &[u8]) -> Result<(Message<'_>, &[u8]), Error> {
+    postcard::take_from_bytes(payload)
+}
@@ -0,0 +1,183 @@
+// Temperature model of gyro and accelerometer biases, no_std. `BiasFit`
+// fits it to a warm-up sweep at rest, `Compensate` removes modelled biases
+// from measurements before they reach the filters. Mount
+// `ahrs-ekf/solve.rs` as `crate::solve` next to it.
+
+use mpu9250::{ImuMeasurements, MargMeasurements};
+
+use crate::solve::solve;
+
+/// Degree of the bias(T) polynomials
+pub const DEGREE: usize = 2;
+const TERMS: usize = DEGREE + 1;
+/// Floats in a stored model: `t0`, accel then gyro coefficients
+pub const MODEL_LEN: usize = 1 + 6 * TERMS;
+/// Temperature span a sweep has to cover, °C
+pub const MIN_SPAN: f32 = 5.0;
+/// Gravity the sweep sees along +z
+pub const G: f32 = 9.80665;
+
+/// Per axis bias polynomials of `T - t0`, lowest power first
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BiasModel {
+    /// Reference temperature, °C
+    pub t0: f32,
+    /// m/s²
+    pub accel: [[f32; TERMS]; 3],
+    /// rad/s
+    pub gyro: [[f32; TERMS]; 3],
+}
+
+impl BiasModel {
+    /// No correction
+    pub const fn zero() -> Self {
+        BiasModel {
+            t0: 0.0,
+            accel: [[0.0; TERMS]; 3],
+            gyro: [[0.0; TERMS]; 3],
+        }
+    }
+
+    pub fn accel_bias(&self, temp: f32) -> [f32; 3] {
+        self.accel.map(|c| poly(&c, temp - self.t0))
+    }
+
+    pub fn gyro_bias(&self, temp: f32) -> [f32; 3] {
+        self.gyro.map(|c| poly(&c, temp - self.t0))
+    }
+
+    /// Subtract biases at `temp` from raw readings
+    pub fn correct(
+        &self,
+        accel: &mut [f32; 3],
+        gyro: &mut [f32; 3],
+        temp: f32,
+    ) {
+        let (ab, gb) = (self.accel_bias(temp), self.gyro_bias(temp));
+        for i in 0..3 {
+            accel[i] -= ab[i];
+            gyro[i] -= gb[i];
+        }
+    }
+
+    pub fn to_f32s(self) -> [f32; MODEL_LEN] {
+        let mut v = [0.0; MODEL_LEN];
+        v[0] = self.t0;
+        let coeffs = self.accel.iter().chain(self.gyro.iter()).flatten();
+        for (v, c) in v[1..].iter_mut().zip(coeffs) {
+            *v = *c;
+        }
+        v
+    }
+
+    pub fn from_f32s(v: &[f32; MODEL_LEN]) -> Self {
+        let mut model = BiasModel::zero();
+        model.t0 = v[0];
+        let coeffs = model.accel.iter_mut().chain(model.gyro.iter_mut());
+        for (i, c) in coeffs.flatten().enumerate() {
+            *c = v[1 + i];
+        }
+        model
+    }
+}
+
+/// Measurements that can have modelled biases removed in place
+pub trait Compensate {
+    fn compensate(&mut self, model: &BiasModel);
+}
+
+impl Compensate for ImuMeasurements<[f32; 3]> {
+    fn compensate(&mut self, model: &BiasModel) {
+        model.correct(&mut self.accel, &mut self.gyro, self.temp);
+    }
+}
+
+impl Compensate for MargMeasurements<[f32; 3]> {
+    fn compensate(&mut self, model: &BiasModel) {
+        model.correct(&mut self.accel, &mut self.gyro, self.temp);
+    }
+}
+
+/// Least squares fit of `BiasModel` to readings at rest with +z up,
+/// accumulated in constant memory. Temperatures are taken relative to the
+/// first one.
+pub struct BiasFit {
+    t0: Option<f32>,
+    /// Σ dT^k for k up to 2 * DEGREE
+    powers: [f64; 2 * DEGREE + 1],
+    /// Σ bias dT^k per channel, accel then gyro
+    moments: [[f64; TERMS]; 6],
+    min: f32,
+    max: f32,
+}
+
+impl BiasFit {
+    pub const fn new() -> Self {
+        BiasFit {
+            t0: None,
+            powers: [0.0; 2 * DEGREE + 1],
+            moments: [[0.0; TERMS]; 6],
+            min: f32::MAX,
+            max: f32::MIN,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.powers[0] as usize
+    }
+
+    /// Covered temperature range, °C
+    pub fn span(&self) -> f32 {
+        if self.t0.is_some() {
+            self.max - self.min
+        } else {
+            0.0
+        }
+    }
+
+    /// Add readings averaged at `temp`
+    pub fn push(&mut self, temp: f32, accel: [f32; 3], gyro: [f32; 3]) {
+        let t0 = *self.t0.get_or_insert(temp);
+        self.min = self.min.min(temp);
+        self.max = self.max.max(temp);
+        let dt = (temp - t0) as f64;
+        let mut p = 1.0;
+        for k in 0..self.powers.len() {
+            self.powers[k] += p;
+            p *= dt;
+        }
+        let bias =
+            [accel[0], accel[1], accel[2] - G, gyro[0], gyro[1], gyro[2]];
+        for (m, b) in self.moments.iter_mut().zip(bias.iter()) {
+            let mut p = 1.0;
+            for mk in m.iter_mut() {
+                *mk += *b as f64 * p;
+                p *= dt;
+            }
+        }
+    }
+
+    /// `None` until the sweep spans `MIN_SPAN`
+    pub fn fit(&self) -> Option<BiasModel> {
+        let t0 = self.t0?;
+        if self.span() < MIN_SPAN {
+            return None;
+        }
+        let mut a = [[0.0; TERMS]; TERMS];
+        for (i, row) in a.iter_mut().enumerate() {
+            row.copy_from_slice(&self.powers[i..i + TERMS]);
+        }
+        let mut model = BiasModel::zero();
+        model.t0 = t0;
+        let coeffs = model.accel.iter_mut().chain(model.gyro.iter_mut());
+        for (c, m) in coeffs.zip(self.moments.iter()) {
+            let x = solve(a, *m)?;
+            *c = x.map(|x| x as f32);
+        }
+        Some(model)
+    }
+}
+
+fn poly(c: &[f32; TERMS], x: f32) -> f32 {
+    c.iter().rev().fold(0.0, |acc, c| acc * x + c)
+}
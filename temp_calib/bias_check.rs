@@ -0,0 +1,154 @@
+// Fits bias(T) to a simulated warm-up sweep with known drift and checks the
+// correction removes it:
+//     rustc --edition 2021 -O bias_check.rs && ./bias_check
+// Panics on mismatch.
+
+#![allow(dead_code)]
+
+mod bias {
+    // mpu9250 on the target
+    pub mod mpu9250 {
+        pub struct ImuMeasurements<T> {
+            pub accel: T,
+            pub gyro: T,
+            pub temp: f32,
+        }
+        pub struct MargMeasurements<T> {
+            pub accel: T,
+            pub gyro: T,
+            pub mag: T,
+            pub temp: f32,
+        }
+    }
+    include!("bias.rs");
+}
+#[path = "../ahrs-ekf/solve.rs"]
+mod solve;
+
+use bias::mpu9250::MargMeasurements;
+use bias::{BiasFit, BiasModel, Compensate, G, MODEL_LEN};
+
+// Warm-up from 24 °C towards 41 °C with 4 minute time constant, sampled
+// every second for 15 minutes
+const START: f64 = 24.0;
+const END: f64 = 41.0;
+const TAU_S: f64 = 240.0;
+const SECONDS: usize = 900;
+// Noise of one second averages
+const ACCEL_NOISE: f64 = 0.002;
+const GYRO_NOISE: f64 = 0.0002;
+const MAX_ACCEL_ERROR: f32 = 0.003;
+const MAX_GYRO_ERROR: f32 = 0.0003;
+
+/// True drift, quadratic in °C around 25 °C
+const ACCEL_DRIFT: [[f64; 3]; 3] = [
+    [0.12, 0.004, -0.0002],
+    [-0.08, -0.006, 0.0001],
+    [0.25, 0.011, 0.0003],
+];
+const GYRO_DRIFT: [[f64; 3]; 3] = [
+    [0.010, 0.0004, 0.00002],
+    [-0.020, 0.0011, -0.00001],
+    [0.004, -0.0007, 0.00003],
+];
+
+/// xorshift64*, uniform noise is good enough here
+struct Rng(u64);
+
+impl Rng {
+    fn sym(&mut self, a: f64) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let v = self.0.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        ((v >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0) * a
+    }
+}
+
+fn drift(c: &[[f64; 3]; 3], temp: f64) -> [f64; 3] {
+    let x = temp - 25.0;
+    c.map(|c| c[0] + c[1] * x + c[2] * x * x)
+}
+
+fn sweep(rng: &mut Rng, seconds: usize, end: f64) -> BiasFit {
+    let mut fit = BiasFit::new();
+    for s in 0..seconds {
+        let temp = end + (START - end) * (-(s as f64) / TAU_S).exp();
+        let a = drift(&ACCEL_DRIFT, temp);
+        let g = drift(&GYRO_DRIFT, temp);
+        let accel = [
+            (a[0] + rng.sym(ACCEL_NOISE)) as f32,
+            (a[1] + rng.sym(ACCEL_NOISE)) as f32,
+            (a[2] + G as f64 + rng.sym(ACCEL_NOISE)) as f32,
+        ];
+        let gyro = [0, 1, 2].map(|i| (g[i] + rng.sym(GYRO_NOISE)) as f32);
+        fit.push(temp as f32, accel, gyro);
+    }
+    fit
+}
+
+fn main() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    let fit = sweep(&mut rng, SECONDS, END);
+    println!("{} samples over {} °C", fit.len(), fit.span());
+    let model = fit.fit().expect("fit failed");
+    println!("{:?}", model);
+
+    // Inside the swept range the model follows the drift
+    let mut t = START;
+    while t <= END {
+        let a = drift(&ACCEL_DRIFT, t);
+        let g = drift(&GYRO_DRIFT, t);
+        let (ma, mg) = (model.accel_bias(t as f32), model.gyro_bias(t as f32));
+        for i in 0..3 {
+            let ea = (ma[i] - a[i] as f32).abs();
+            let eg = (mg[i] - g[i] as f32).abs();
+            if ea > MAX_ACCEL_ERROR || eg > MAX_GYRO_ERROR {
+                panic!(
+                    "axis {} at {} °C: accel off {}, gyro off {}",
+                    i, t, ea, eg
+                );
+            }
+        }
+        t += 0.5;
+    }
+
+    // Correction leaves gravity and zero rate
+    let t = 33.0;
+    let a = drift(&ACCEL_DRIFT, t);
+    let g = drift(&GYRO_DRIFT, t);
+    let mut m = MargMeasurements {
+        accel: [a[0] as f32, a[1] as f32, (a[2] + G as f64) as f32],
+        gyro: g.map(|g| g as f32),
+        mag: [1.0, 2.0, 3.0],
+        temp: t as f32,
+    };
+    m.compensate(&model);
+    println!("compensated at {} °C: {:?} {:?}", t, m.accel, m.gyro);
+    for i in 0..3 {
+        let expected = if i == 2 { G } else { 0.0 };
+        if (m.accel[i] - expected).abs() > MAX_ACCEL_ERROR
+            || m.gyro[i].abs() > MAX_GYRO_ERROR
+        {
+            panic!("axis {} not compensated", i);
+        }
+    }
+    if m.mag != [1.0, 2.0, 3.0] {
+        panic!("mag changed");
+    }
+
+    let v: [f32; MODEL_LEN] = model.to_f32s();
+    if BiasModel::from_f32s(&v) != model {
+        panic!("model doesn't survive storage layout");
+    }
+
+    // Barely warming up is not a sweep
+    let short = sweep(&mut rng, SECONDS, START + 3.0);
+    if short.fit().is_some() {
+        panic!("fit over {} °C", short.span());
+    }
+    if BiasFit::new().fit().is_some() {
+        panic!("fit without samples");
+    }
+    println!("bias ok");
+}
@@ -0,0 +1,180 @@
+#![deny(warnings)]
+#![no_std]
+#![no_main]
+
+#[allow(unused)]
+use panic_abort;
+
+use core::fmt::Write;
+
+use cortex_m_rt::{entry, exception, ExceptionFrame};
+use hal::delay;
+use hal::prelude::*;
+use hal::time::Bps;
+
+use mpu9250::Mpu9250;
+
+#[allow(dead_code)]
+mod bias;
+#[path = "../ahrs-ekf/solve.rs"]
+mod solve;
+use bias::{BiasFit, BiasModel, MIN_SPAN, MODEL_LEN};
+#[path = "../cobs/mod.rs"]
+mod cobs;
+use cobs::FrameWriter;
+#[path = "../storage/mod.rs"]
+mod storage;
+use storage::stm32::InternalFlash;
+use storage::{records, Store};
+
+// One line per second of 10 ms reads
+const SAMPLES: u32 = 100;
+// Settled once temperature moves less than this in a minute, °C
+const SETTLED: f32 = 0.1;
+const MINUTE: u32 = 60;
+// Give up after an hour
+const MAX_SECONDS: u32 = 3600;
+
+#[entry]
+fn main() -> ! {
+    let device = hal::pac::Peripherals::take().unwrap();
+    let core = cortex_m::Peripherals::take().unwrap();
+    let mut rcc = device.RCC.constrain();
+    let mut flash = device.FLASH.constrain();
+    let clocks = rcc
+        .cfgr
+        .sysclk(64.mhz())
+        .pclk1(32.mhz())
+        .pclk2(36.mhz())
+        .freeze(&mut flash.acr);
+    let gpioa = device.GPIOA.split(&mut rcc.ahb);
+    let gpiob = device.GPIOB.split(&mut rcc.ahb);
+
+    let serial =
+        device
+            .USART2
+            .serial((gpioa.pa2, gpioa.pa15), Bps(115200), clocks);
+    let (tx, _rx) = serial.split();
+    let mut l = FrameWriter::start(tx).unwrap();
+    write!(l, "logger ok\r\n").unwrap();
+    let mut delay = delay::Delay::new(core.SYST, clocks);
+    // SPI1, the pins ahrs-ekf and calibrating-ahrs use
+    let ncs = gpiob.pb0.output().push_pull();
+    let scl_sck = gpioa.pa5;
+    let sda_sdi_mosi = gpiob.pb5;
+    let ad0_sdo_miso = gpiob.pb4;
+    let spi = device.SPI1.spi(
+        (scl_sck, ad0_sdo_miso, sda_sdi_mosi),
+        mpu9250::MODE,
+        1.mhz(),
+        clocks,
+    );
+    let mut mpu = Mpu9250::imu_default(spi, ncs, &mut delay).unwrap();
+
+    write!(
+        l,
+        "Temperature sweep: power up cold, keep the board still, z up\r\n"
+    )
+    .unwrap();
+    write!(l, "temp,ax,ay,az,gx,gy,gz\r\n").unwrap();
+
+    let mut fit = BiasFit::new();
+    let mut model: Option<BiasModel> = None;
+    let mut minute_temp = None;
+    let mut seconds = 0;
+    loop {
+        let mut accel = [0.0; 3];
+        let mut gyro = [0.0; 3];
+        let mut temp = 0.0;
+        let mut n = 0;
+        for _ in 0..SAMPLES {
+            match mpu.all::<[f32; 3]>() {
+                Ok(meas) => {
+                    for i in 0..3 {
+                        accel[i] += meas.accel[i];
+                        gyro[i] += meas.gyro[i];
+                    }
+                    temp += meas.temp;
+                    n += 1;
+                }
+                Err(e) => {
+                    write!(l, "err: {:?}\r\n", e).unwrap();
+                }
+            }
+            delay.delay_ms(10u32);
+        }
+        if n == 0 {
+            continue;
+        }
+        let n = n as f32;
+        temp /= n;
+        for i in 0..3 {
+            accel[i] /= n;
+            gyro[i] /= n;
+        }
+
+        // Once fitted, log what is left after correction
+        if let Some(ref model) = model {
+            model.correct(&mut accel, &mut gyro, temp);
+            accel[2] -= bias::G;
+        } else {
+            fit.push(temp, accel, gyro);
+        }
+        write!(
+            l,
+            "{},{},{},{},{},{},{}\r\n",
+            temp, accel[0], accel[1], accel[2], gyro[0], gyro[1], gyro[2]
+        )
+        .unwrap();
+        if model.is_some() {
+            continue;
+        }
+
+        seconds += 1;
+        if seconds % MINUTE != 0 {
+            continue;
+        }
+        let settled = match minute_temp.replace(temp) {
+            Some(prev) => abs(temp - prev) < SETTLED,
+            None => false,
+        };
+        if !settled && seconds < MAX_SECONDS {
+            continue;
+        }
+        write!(l, "{} samples over {} °C\r\n", fit.len(), fit.span()).unwrap();
+        match fit.fit() {
+            Some(fitted) => {
+                write!(l, "bias model: {:?}\r\n", fitted).unwrap();
+                let v: [f32; MODEL_LEN] = fitted.to_f32s();
+                let saved = Store::new(unsafe { InternalFlash::new() })
+                    .and_then(|mut s| s.write_f32s(records::TEMP_BIAS, &v));
+                write!(l, "saving model: {:?}\r\n", saved).unwrap();
+                write!(l, "residual temp,ax,ay,az,gx,gy,gz\r\n").unwrap();
+                model = Some(fitted);
+            }
+            None => {
+                write!(l, "sweep under {} °C, restart from cold\r\n", MIN_SPAN)
+                    .unwrap();
+                loop {}
+            }
+        }
+    }
+}
+
+fn abs(x: f32) -> f32 {
+    if x < 0.0 {
+        -x
+    } else {
+        x
+    }
+}
+
+#[exception]
+unsafe fn HardFault(ef: &ExceptionFrame) -> ! {
+    panic!("HardFault at {:#?}", ef);
+}
+
+#[exception]
+unsafe fn DefaultHandler(irqn: i16) {
+    panic!("Unhandled exception (IRQn = {})", irqn);
+}
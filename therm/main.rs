@@ -14,6 +14,10 @@ use hal::{delay, serial};
 
 use mpu9250::Mpu9250;
 
+#[path = "../cobs/mod.rs"]
+mod cobs;
+use cobs::FrameWriter;
+
 #[entry]
 fn main() -> ! {
     let device = hal::pac::Peripherals::take().unwrap();
@@ -33,10 +37,8 @@ fn main() -> ! {
             .USART1
             .serial((gpioa.pa9, gpioa.pa10), Bps(115200), clocks);
     serial.listen(serial::Event::Rxne);
-    let (mut tx, _rx) = serial.split();
-    // COBS frame
-    tx.write(0x00).unwrap();
-    let mut l = tx;
+    let (tx, _rx) = serial.split();
+    let mut l = FrameWriter::start(tx).unwrap();
     write!(l, "logger ok\r\n").unwrap();
     let mut delay = delay::Delay::new(core.SYST, clocks);
     // SPI1
@@ -16,7 +16,11 @@ use nb;
 
 use vl53l0x;
 
-static mut L: Option<hal::serial::Tx<hal::pac::USART1>> = None;
+#[path = "../cobs/mod.rs"]
+mod cobs;
+use cobs::FrameWriter;
+
+static mut L: Option<FrameWriter<hal::serial::Tx<hal::pac::USART1>>> = None;
 static mut RX: Option<hal::serial::Rx<hal::pac::USART1>> = None;
 
 static mut QUIET: bool = false;
@@ -43,10 +47,9 @@ fn main() -> ! {
             .serial((gpioa.pa9, gpioa.pa10), Bps(115200), clocks);
     let ser_int = serial.get_interrupt();
     serial.listen(serial::Event::Rxne);
-    let (mut tx, rx) = serial.split();
-    tx.write(0x00).unwrap();
+    let (tx, rx) = serial.split();
     unsafe {
-        L = Some(Logger { tx });
+        L = Some(FrameWriter::start(tx).unwrap());
         RX = Some(rx);
     };
     let l = unsafe { extract(&mut L) };
@@ -116,7 +119,7 @@ fn USART1_EXTI25() {
                 rx.clear_noise_error();
             }
             _ => {
-                write!(l, "read error: {:?}", e).unwrap();
+                write!(l, "read error: {:?}\r\n", e).unwrap();
             }
         },
     };
@@ -164,6 +167,7 @@ fn panic(panic_info: &PanicInfo) -> ! {
                     write!(l, "panic occured, no info available").unwrap();
                 }
             }
+            let _ = l.end_frame();
         }
         None => {}
     }
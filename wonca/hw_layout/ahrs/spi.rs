@@ -0,0 +1,8 @@
+// Board of ahrs-ekf and calibrating-ahrs
+conf! {
+    dev: device.SPI1,
+    scl: gpioa.pa5,
+    miso: gpiob.pb4,
+    mosi: gpiob.pb5,
+    cs_mpu: gpiob.pb0,
+};
@@ -0,0 +1,6 @@
+conf! {
+    dev: device.USART2,
+    tx: gpioa.pa2,
+    rx: gpioa.pa15,
+    bps: 460800,
+}
@@ -0,0 +1,89 @@
+// Six-position IMU calibration from wonca, no_std. wonca saves it under
+// `records::IMU`, `ImuCal::correct` applies it to measurements.
+
+/// Rest positions the calibration goes through
+pub const POSITIONS: usize = 6;
+/// Floats in a stored record: accel scales and offsets, gyro scales, then
+/// gyro zero rate and die temperature per rest position
+pub const IMU_LEN: usize = 9 + 4 * POSITIONS;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImuCal {
+    /// Corrected accel is `scale * raw + offset`
+    pub accel_scale: [f32; 3],
+    pub accel_offset: [f32; 3],
+    /// Corrected rate is `scale * (raw - bias)`
+    pub gyro_scale: [f32; 3],
+    /// Gyro zero rate at each rest position, rad/s. Kept apart with its
+    /// temperature, the mean is only good near these temperatures.
+    pub rest_gyro: [[f32; 3]; POSITIONS],
+    /// Die temperature at each rest position, °C
+    pub rest_temp: [f32; POSITIONS],
+}
+
+impl ImuCal {
+    /// Mean zero rate over the rest positions, rad/s
+    pub fn gyro_bias(&self) -> [f32; 3] {
+        let mut bias = [0.0; 3];
+        for g in self.rest_gyro.iter() {
+            for i in 0..3 {
+                bias[i] += g[i] / POSITIONS as f32;
+            }
+        }
+        bias
+    }
+
+    /// Mean die temperature over the rest positions, °C
+    pub fn temp(&self) -> f32 {
+        self.rest_temp.iter().sum::<f32>() / POSITIONS as f32
+    }
+
+    /// Corrects raw readings. With `unbiased` the biases are already gone
+    /// (temp-calib's model took them), only scales are applied.
+    pub fn correct(
+        &self,
+        accel: &mut [f32; 3],
+        gyro: &mut [f32; 3],
+        unbiased: bool,
+    ) {
+        let bias = self.gyro_bias();
+        for i in 0..3 {
+            accel[i] *= self.accel_scale[i];
+            if !unbiased {
+                accel[i] += self.accel_offset[i];
+                gyro[i] -= bias[i];
+            }
+            gyro[i] *= self.gyro_scale[i];
+        }
+    }
+
+    pub fn to_f32s(&self) -> [f32; IMU_LEN] {
+        let mut v = [0.0; IMU_LEN];
+        v[0..3].copy_from_slice(&self.accel_scale);
+        v[3..6].copy_from_slice(&self.accel_offset);
+        v[6..9].copy_from_slice(&self.gyro_scale);
+        for (pos, rest) in v[9..].chunks_exact_mut(4).enumerate() {
+            rest[..3].copy_from_slice(&self.rest_gyro[pos]);
+            rest[3] = self.rest_temp[pos];
+        }
+        v
+    }
+
+    pub fn from_f32s(v: &[f32; IMU_LEN]) -> Self {
+        let mut cal = ImuCal {
+            accel_scale: [0.0; 3],
+            accel_offset: [0.0; 3],
+            gyro_scale: [0.0; 3],
+            rest_gyro: [[0.0; 3]; POSITIONS],
+            rest_temp: [0.0; POSITIONS],
+        };
+        cal.accel_scale.copy_from_slice(&v[0..3]);
+        cal.accel_offset.copy_from_slice(&v[3..6]);
+        cal.gyro_scale.copy_from_slice(&v[6..9]);
+        for (pos, rest) in v[9..].chunks_exact(4).enumerate() {
+            cal.rest_gyro[pos].copy_from_slice(&rest[..3]);
+            cal.rest_temp[pos] = rest[3];
+        }
+        cal
+    }
+}
@@ -4,23 +4,57 @@
 #[macro_use]
 mod utils;
 #[macro_use]
+#[path = "../console/mod.rs"]
+mod console;
+#[allow(dead_code)]
+mod imu;
 mod logger;
+#[path = "../storage/mod.rs"]
+mod storage;
 
 #[allow(unused)]
 use panic_abort;
 
 use cortex_m_rt::{entry, exception, ExceptionFrame};
 use hal::delay;
+use hal::pac::interrupt;
 use hal::prelude::*;
 use hal::time::Bps;
 
-use libm::F32Ext;
+use core::f32::consts::PI;
+use cortex_m::peripheral::DWT;
+use libm::{atan2f, fabsf, sqrtf};
 use mpu9250::Mpu9250;
 use nalgebra::Vector3;
 
-use logger::{Vs, Write};
+use console::Overflow;
+use imu::{ImuCal, POSITIONS};
+use logger::Vs;
+use storage::stm32::InternalFlash;
+use storage::{records, Store};
 
 const G: f32 = 9.80665;
+const AXES: [&str; 3] = ["x", "y", "z"];
+// Gyro rates telling a turn from rest, rad/s
+const TURN_RATE: f32 = 0.5;
+const STILL_RATE: f32 = 0.05;
+// Gyro turn above which it was a full turn, below a quarter turn
+const FULL_TURN_MIN: f32 = 1.25 * PI;
+const QUARTER_TURN_MIN: f32 = 0.25 * PI;
+// How far from the start gravity may end up after a full turn
+const FULL_TURN_BACK: f32 = 0.05 * PI;
+// Share of gravity in the plane of the turn for it to be measurable
+const LEVEL: f32 = 0.9;
+
+/// Averages at a rest position
+#[derive(Clone, Copy)]
+struct Rest {
+    accel: Vector3<f32>,
+    /// Gyro zero rate, rad/s
+    gyro: Vector3<f32>,
+    /// Die temperature, °C
+    temp: f32,
+}
 
 fn accel_error<Dev, Imu>(
     mpu: &mut Mpu9250<Dev, Imu>,
@@ -45,7 +79,7 @@ fn wait_for_measurement<Dev, Imu>(
     delay: &mut delay::Delay,
     rest: f32,
     noise_level: f32,
-) -> Result<Vector3<f32>, Dev::Error>
+) -> Result<Rest, Dev::Error>
 where
     Dev: mpu9250::Device,
 {
@@ -67,24 +101,116 @@ where
         }
     }
 
+    measure(mpu, delay)
+}
+
+fn measure<Dev, Imu>(
+    mpu: &mut Mpu9250<Dev, Imu>,
+    delay: &mut delay::Delay,
+) -> Result<Rest, Dev::Error>
+where
+    Dev: mpu9250::Device,
+{
     println!("\r- measuring, stay put");
 
-    let mut r = Vector3::zeros();
+    let mut r = Rest {
+        accel: Vector3::zeros(),
+        gyro: Vector3::zeros(),
+        temp: 0.0,
+    };
     for _ in 0..50 {
         let accel: Vector3<_> = mpu.accel()?;
-        r += accel;
+        let gyro: Vector3<_> = mpu.gyro()?;
+        r.accel += accel;
+        r.gyro += gyro;
+        r.temp += mpu.temp()?;
         delay.delay_ms(20u8);
     }
 
-    r *= 0.02;
+    r.accel *= 0.02;
+    r.gyro *= 0.02;
+    r.temp *= 0.02;
 
     Ok(r)
 }
 
+/// Waits for the device to settle, then integrates gyro `axis` over the
+/// turn until it settles again. Returns rests around the turn and the turn
+/// angle by gyro, radians.
+fn measure_turn<Dev, Imu>(
+    mpu: &mut Mpu9250<Dev, Imu>,
+    delay: &mut delay::Delay,
+    rest: f32,
+    noise_level: f32,
+    bias: &Vector3<f32>,
+    axis: usize,
+    hz: f32,
+) -> Result<(Rest, f32, Rest), Dev::Error>
+where
+    Dev: mpu9250::Device,
+{
+    let start = wait_for_measurement(mpu, delay, rest, noise_level)?;
+    println!(
+        "\r- turn by 90 or 360 degrees around {} axis and hold still",
+        AXES[axis]
+    );
+    let mut angle = 0.0;
+    let mut mov = 0.0;
+    let mut turning = false;
+    let mut last = DWT::get_cycle_count();
+    loop {
+        let gyro: Vector3<_> = mpu.gyro()?;
+        let now = DWT::get_cycle_count();
+        let dt = now.wrapping_sub(last) as f32 / hz;
+        last = now;
+        let rate = gyro - bias;
+        angle += rate[axis] * dt;
+        mov = lerp(0.01, mov, rate.norm());
+        turning |= mov > TURN_RATE;
+        if turning && mov < STILL_RATE {
+            break;
+        }
+    }
+    let end = measure(mpu, delay)?;
+    Ok((start, angle, end))
+}
+
+/// Gyro scale from a turn around `axis` of `turned` radians by gyro. Turn
+/// is either full, back where gravity started, or measured by the change
+/// of gravity direction.
+fn turn_scale(
+    axis: usize,
+    start: &Rest,
+    turned: f32,
+    end: &Rest,
+) -> Option<f32> {
+    let turned = fabsf(turned);
+    let (j, k) = ((axis + 1) % 3, (axis + 2) % 3);
+    let (a, b) = (&start.accel, &end.accel);
+    let level =
+        |v: &Vector3<f32>| sqrtf(v[j] * v[j] + v[k] * v[k]) > LEVEL * v.norm();
+    if !level(a) || !level(b) {
+        return None;
+    }
+    let cross = a[j] * b[k] - a[k] * b[j];
+    let dot = a[j] * b[j] + a[k] * b[k];
+    let by_gravity = fabsf(atan2f(cross, dot));
+    if turned > FULL_TURN_MIN {
+        if by_gravity > FULL_TURN_BACK {
+            return None;
+        }
+        return Some(2.0 * PI / turned);
+    }
+    if by_gravity < QUARTER_TURN_MIN || turned < QUARTER_TURN_MIN {
+        return None;
+    }
+    Some(by_gravity / turned)
+}
+
 #[entry]
 fn main() -> ! {
     let device = hal::pac::Peripherals::take().unwrap();
-    let core = cortex_m::Peripherals::take().unwrap();
+    let mut core = cortex_m::Peripherals::take().unwrap();
     let mut rcc = device.RCC.constrain();
     let mut flash = device.FLASH.constrain();
     let clocks = rcc
@@ -107,13 +233,19 @@ fn main() -> ! {
         Bps(usart_conf.bps),
         clocks,
     );
+    let ser_int = serial.get_interrupt();
     let (tx, _) = serial.split();
 
-    logger::set_stdout(tx);
+    // Progress lines get rewritten, the latest one matters
+    console::init(tx, Overflow::DropOldest);
+    unsafe { cortex_m::peripheral::NVIC::unmask(ser_int) };
 
     print!("\x1b[H\x1b[J");
     println!("Getting ready");
 
+    core.DCB.enable_trace();
+    core.DWT.enable_cycle_counter();
+    let hz = clocks.sysclk().0 as f32;
     let mut delay = delay::Delay::new(core.SYST, clocks);
 
     // SPI1
@@ -135,8 +267,6 @@ fn main() -> ! {
         }
     };
 
-    println!("- not calibrating mpu");
-
     println!("- calibrating rest position");
 
     let mut rest = 1.0;
@@ -144,7 +274,7 @@ fn main() -> ! {
         let prev = rest;
         let gyro: Vector3<_> = mpu.gyro().unwrap();
         rest = lerp(0.1, rest, gyro.norm());
-        if (prev - rest).abs() < 0.0001 {
+        if fabsf(prev - rest) < 0.0001 {
             break;
         }
     }
@@ -153,7 +283,14 @@ fn main() -> ! {
 
     println!("- mpu ok");
 
-    let mut readings = [[0.0f32; 3]; 6];
+    let mut readings = [[0.0f32; 3]; POSITIONS];
+    let mut cal = ImuCal {
+        accel_scale: [0.0; 3],
+        accel_offset: [0.0; 3],
+        gyro_scale: [1.0; 3],
+        rest_gyro: [[0.0; 3]; POSITIONS],
+        rest_temp: [0.0; POSITIONS],
+    };
 
     println!("Calibrating using g0 = {}", G);
 
@@ -161,27 +298,34 @@ fn main() -> ! {
     let noise_level = accel_error(&mut mpu, &mut delay).unwrap();
     println!(" = {}", noise_level);
 
-    for pos in 0..6 {
+    for pos in 0..POSITIONS {
         println!("Put device in position {}", pos);
 
-        let r = wait_for_measurement(&mut mpu, &mut delay, rest, noise_level)
+        let m = wait_for_measurement(&mut mpu, &mut delay, rest, noise_level)
             .unwrap();
+        let r = m.accel;
 
         println!("\r- ok, readings: {} = {:8.3}", Vs(r), r.norm());
+        println!("- gyro bias: {} at {:.2} C", Vs(m.gyro), m.temp);
 
         readings[pos] = [r[0], r[1], r[2]];
+        cal.rest_gyro[pos] = [m.gyro[0], m.gyro[1], m.gyro[2]];
+        cal.rest_temp[pos] = m.temp;
     }
 
+    let gyro_bias = Vector3::from(cal.gyro_bias());
+    println!("Gyro bias: {} at {:.2} C", Vs(gyro_bias), cal.temp());
+
     if let Some(adj) = estimate(&readings) {
         println!("Calibration result: {:?}", adj);
-        for pos in 0..6 {
+        for pos in 0..POSITIONS {
             let r = Vector3::from(readings[pos]);
             let a = Vector3::new(
                 adj[0].estimate(r[0]),
                 adj[1].estimate(r[1]),
                 adj[2].estimate(r[2]),
             );
-            let err = (G - a.norm()).abs();
+            let err = fabsf(G - a.norm());
             println!(" - orig reading: {} = {}", Vs(r), r.norm());
             println!(
                 "   adjusted:     {} = {}, error: {}",
@@ -191,18 +335,55 @@ fn main() -> ! {
             );
         }
 
+        for axis in 0..3 {
+            cal.gyro_scale[axis] = loop {
+                println!("Hold device with {} axis level", AXES[axis]);
+                let (start, turned, end) = measure_turn(
+                    &mut mpu,
+                    &mut delay,
+                    rest,
+                    noise_level,
+                    &gyro_bias,
+                    axis,
+                    hz,
+                )
+                .unwrap();
+                let degrees = turned * 180.0 / PI;
+                match turn_scale(axis, &start, turned, &end) {
+                    Some(scale) => {
+                        println!("- turned {:.1}, scale {}", degrees, scale);
+                        break scale;
+                    }
+                    None => {
+                        println!("- can't use {:.1} degree turn", degrees);
+                    }
+                }
+            };
+        }
+
+        // Adjustment is linear, store it as scale * raw + offset
+        for i in 0..3 {
+            cal.accel_offset[i] = adj[i].estimate(0.0);
+            cal.accel_scale[i] = adj[i].estimate(1.0) - cal.accel_offset[i];
+        }
+        println!("Calibration record: {:?}", cal);
+        let saved = Store::new(unsafe { InternalFlash::new() })
+            .and_then(|mut s| s.write_f32s(records::IMU, &cal.to_f32s()));
+        println!("Saving calibration: {:?}", saved);
+
         loop {
             println!("Put device in new position");
 
             let r =
                 wait_for_measurement(&mut mpu, &mut delay, rest, noise_level)
-                    .unwrap();
+                    .unwrap()
+                    .accel;
             let a = Vector3::new(
                 adj[0].estimate(r[0]),
                 adj[1].estimate(r[1]),
                 adj[2].estimate(r[2]),
             );
-            let err = (G - a.norm()).abs();
+            let err = fabsf(G - a.norm());
             println!("\r- readings: {} = {:8.3}", Vs(r), r.norm());
             println!(
                 "\r- adjusted: {} = {:8.3} err = {}",
@@ -234,14 +415,21 @@ fn estimate(vals: &[[f32; 3]]) -> Option<[won2010::Adj; 3]> {
     None
 }
 
+#[interrupt]
+fn USART2_EXTI26() {
+    console::on_interrupt();
+}
+
 #[exception]
 unsafe fn HardFault(ef: &ExceptionFrame) -> ! {
     println!("hard fault at {:?}", ef);
+    console::flush();
     panic!("HardFault at {:#?}", ef);
 }
 
 #[exception]
 unsafe fn DefaultHandler(irqn: i16) {
     println!("Interrupt: {}", irqn);
+    console::flush();
     panic!("Unhandled exception (IRQn = {})", irqn);
 }